@@ -6,6 +6,17 @@ import { fetch_client } from "./client_fetch";
 
 export const ctx = createContextId<Signal<Schema>>("schema");
 
+// `#[cms(min_len = 1)]` and friends, forms check these before sending
+export const rule_schema = v.variant("rule", [
+    v.object({ rule: v.literal("min_len"), min: v.number() }),
+    v.object({ rule: v.literal("max_len"), max: v.number() }),
+    v.object({ rule: v.literal("range"), min: v.nullable(v.number()), max: v.nullable(v.number()) }),
+    v.object({ rule: v.literal("regex"), pattern: v.string() }),
+    v.object({ rule: v.literal("email") }),
+    v.object({ rule: v.literal("url") }),
+    v.object({ rule: v.literal("one_of"), values: v.array(v.string()) }),
+]);
+
 export const one_schema = v.object({
     name: v.string(),
     fields: v.array(v.object({
@@ -18,7 +29,12 @@ export const one_schema = v.object({
             v.literal("string"),
             v.literal("number"),
             v.literal("boolean"),
-        ])
+        ]),
+        rules: v.optional(v.array(rule_schema), []),
+        // `#[derive(TextEnum)]` members, the values they accept
+        variants: v.optional(v.nullable(v.array(v.string())), null),
+        // json documents, dates, uuids and decimals
+        kind: v.optional(v.picklist(["plain", "json", "date_time", "date", "uuid", "decimal"]), "plain"),
     }))
 });
 
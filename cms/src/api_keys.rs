@@ -0,0 +1,702 @@
+//! api keys let services call the collection routes without
+//! logging in, they are sent in the `X-Api-Key` header instead
+//! of a Bearer token and handled by `permissions::authorize`.
+//!
+//! a key runs as a role, and can be limited to some
+//! collections. keys look like `cms_<prefix>_<secret>`, only
+//! the prefix (to tell keys apart) and the sha256 of the whole
+//! key are stored in `_cms_api_keys`. keys are cached in
+//! `Backend::api_keys` like the permissions are.
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware::from_fn,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
+
+use crate::{
+    auth::session::{hash_token, random_token},
+    backend::Backend,
+    error::{self, ClientError},
+    permissions::{need_admin, role_exists},
+};
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// `last_used_at` is written at most once per this many seconds
+const TOUCH_EVERY: i64 = 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub role: String,
+    /// empty for every collection
+    pub collections: Vec<String>,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub created_at: i64,
+}
+
+impl ApiKey {
+    pub fn allows_collection(&self, collection: &str) -> bool {
+        self.collections.is_empty()
+            || self.collections.iter().any(|e| e == collection)
+    }
+
+    /// what `permissions::OWNER_COLUMN` stores for rows inserted
+    /// with this key
+    pub fn owner(&self) -> String {
+        format!("api_key:{}", self.id)
+    }
+}
+
+type Touch = Arc<dyn Fn(i64, i64) + Send + Sync>;
+
+/// keys by the hash of the key
+#[derive(Default)]
+pub struct ApiKeys {
+    by_hash: HashMap<String, ApiKey>,
+    /// records `last_used_at` in the database the keys were
+    /// loaded from
+    touch: Option<Touch>,
+}
+
+pub fn migration_st<S: Backend>() -> String {
+    format!(
+        "
+    CREATE TABLE IF NOT EXISTS _cms_api_keys (
+        id {},
+        name TEXT NOT NULL,
+        prefix TEXT NOT NULL UNIQUE,
+        hash TEXT NOT NULL UNIQUE,
+        role TEXT NOT NULL,
+        collections TEXT,
+        expires_at {i64},
+        last_used_at {i64},
+        created_at {i64} NOT NULL
+    );
+    ",
+        S::id_column(),
+        i64 = S::i64_column(),
+    )
+}
+
+pub async fn init_api_keys<S>(
+    db: &Pool<S>,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(&migration_st::<S>()).execute(db).await?;
+    reload(db).await
+}
+
+type Row = (
+    i64,
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<i64>,
+    Option<i64>,
+    i64,
+);
+
+async fn fetch_all<S>(
+    db: &Pool<S>,
+) -> Result<Vec<(String, ApiKey)>, sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S>,
+    String: Type<S> + for<'d> Decode<'d, S>,
+{
+    let rows: Vec<Row> = sqlx::query_as(
+        "
+    SELECT id, name, prefix, hash, role, collections,
+        expires_at, last_used_at, created_at
+    FROM _cms_api_keys ORDER BY id;
+    ",
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let collections = row
+                .5
+                .map(|e| {
+                    e.split(',').map(String::from).collect()
+                })
+                .unwrap_or_default();
+            let key = ApiKey {
+                id: row.0,
+                name: row.1,
+                prefix: row.2,
+                role: row.4,
+                collections,
+                expires_at: row.6,
+                last_used_at: row.7,
+                created_at: row.8,
+            };
+            (row.3, key)
+        })
+        .collect())
+}
+
+async fn reload<S>(db: &Pool<S>) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let by_hash = fetch_all(db).await?.into_iter().collect();
+
+    let pool = db.clone();
+    let touch: Touch = Arc::new(move |id, now| {
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            let res = sqlx::query(
+                "UPDATE _cms_api_keys SET last_used_at = $1 WHERE id = $2;",
+            )
+            .bind(now)
+            .bind(id)
+            .execute(&pool)
+            .await;
+            if let Err(err) = res {
+                tracing::warn!(
+                    "api key {id} not touched: {err}"
+                );
+            }
+        });
+    });
+
+    *S::api_keys().write().await = ApiKeys {
+        by_hash,
+        touch: Some(touch),
+    };
+
+    Ok(())
+}
+
+fn unauthorized(code: &str, dev_hint: &str) -> ClientError {
+    ClientError {
+        status_code: StatusCode::UNAUTHORIZED,
+        dev_hint: dev_hint.to_string(),
+        user_error: None,
+    }
+    .add_user_error(code, "api key is not valid", |_| {})
+}
+
+/// the key the value of `X-Api-Key` belongs to
+pub async fn authenticate<S: Backend>(
+    key: &str,
+) -> Result<ApiKey, ClientError> {
+    let hash = hash_token(key);
+    let now = Utc::now().timestamp();
+
+    let mut keys = S::api_keys().write().await;
+    let touch = keys.touch.clone();
+    let Some(found) = keys.by_hash.get_mut(&hash) else {
+        return Err(unauthorized(
+            "invalid_api_key",
+            "api key does not exist or was revoked",
+        ));
+    };
+
+    if found.expires_at.is_some_and(|e| e < now) {
+        return Err(unauthorized(
+            "api_key_expired",
+            "api key has expired",
+        ));
+    }
+
+    if found.last_used_at.is_none_or(|e| now - e >= TOUCH_EVERY)
+    {
+        found.last_used_at = Some(now);
+        if let Some(touch) = touch {
+            touch(found.id, now);
+        }
+    }
+
+    Ok(found.clone())
+}
+
+pub fn api_keys_router<S>() -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    Router::new()
+        .route("/", get(list::<S>))
+        .route("/create", post(create::<S>))
+        .route("/revoke", post(revoke::<S>))
+        .route_layer(from_fn(need_admin::<S>))
+}
+
+async fn list<S>(
+    db: State<Pool<S>>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let keys: Vec<ApiKey> = fetch_all(&db.0)
+        .await
+        .map_err(error::server_error)?
+        .into_iter()
+        .map(|e| e.1)
+        .collect();
+
+    Ok(Json(json!({"data": keys})))
+}
+
+#[derive(Deserialize)]
+pub struct CreateInput {
+    pub name: String,
+    pub role: String,
+    #[serde(default)]
+    pub collections: Vec<String>,
+    /// unix timestamp, the key never expires when missing
+    pub expires_at: Option<i64>,
+}
+
+/// the key is in the response, it can't be seen again
+async fn create<S>(
+    db: State<Pool<S>>,
+    input: Json<CreateInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    role_exists::<S>(&input.role).await?;
+
+    {
+        let collections = S::collections().read().await;
+        if let Some(unknown) = input
+            .collections
+            .iter()
+            .find(|e| !collections.contains_key(e.as_str()))
+        {
+            return Err(ClientError {
+                status_code: StatusCode::NOT_FOUND,
+                dev_hint: format!(
+                    "collection {unknown} does not exist"
+                ),
+                user_error: None,
+            });
+        }
+    }
+
+    // the prefix only has to tell keys apart, `-` and `_` are
+    // avoided so it can be read back from the key
+    let prefix: String = random_token()
+        .chars()
+        .filter(|e| e.is_ascii_alphanumeric())
+        .take(8)
+        .collect();
+    let key = format!("cms_{prefix}_{}", random_token());
+
+    // nullable columns are set apart, `Option` is not `Encode`
+    // for every backend
+    let mut tx = db
+        .0
+        .begin()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    let (id,): (i64,) = sqlx::query_as(
+        "
+    INSERT INTO _cms_api_keys
+        (name, prefix, hash, role, created_at)
+    VALUES ($1, $2, $3, $4, $5)
+    RETURNING id;
+    ",
+    )
+    .bind(input.0.name)
+    .bind(prefix.clone())
+    .bind(hash_token(&key))
+    .bind(input.0.role)
+    .bind(Utc::now().timestamp())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    if !input.0.collections.is_empty() {
+        sqlx::query(
+            "UPDATE _cms_api_keys SET collections = $1 WHERE id = $2;",
+        )
+        .bind(input.0.collections.join(","))
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+    }
+
+    if let Some(expires_at) = input.0.expires_at {
+        sqlx::query(
+            "UPDATE _cms_api_keys SET expires_at = $1 WHERE id = $2;",
+        )
+        .bind(expires_at)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": {
+        "id": id,
+        "prefix": prefix,
+        "key": key,
+    }})))
+}
+
+#[derive(Deserialize)]
+pub struct RevokeInput {
+    pub id: i64,
+}
+
+async fn revoke<S>(
+    db: State<Pool<S>>,
+    input: Json<RevokeInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query("DELETE FROM _cms_api_keys WHERE id = $1;")
+        .bind(input.id)
+        .execute(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+        middleware::from_fn,
+        routing::post,
+        Extension, Router,
+    };
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use sqlx::{Pool, Sqlite};
+    use tower::ServiceExt;
+
+    use crate::{
+        auth::ijwt,
+        permissions::{
+            assign_role, authorize, init_permissions,
+            permissions_router,
+            test::{keys, SERIAL},
+            ADMIN,
+        },
+    };
+
+    use super::*;
+
+    fn request(
+        uri: &str,
+        auth: &[(&str, &str)],
+        body: Value,
+    ) -> Request<Body> {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .header("content-type", "application/json")
+            .uri(uri);
+        for (name, value) in auth {
+            req = req.header(*name, *value);
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_scoped_expire_and_revoke() {
+        let _serial = SERIAL.lock().await;
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        std::env::set_var("JWT_SALT", "test");
+
+        init_permissions(&pool).await.unwrap();
+        assign_role(&pool, 1, ADMIN).await.unwrap();
+        init_api_keys(&pool).await.unwrap();
+
+        let collections = Router::new()
+            .route(
+                "/{collection}/get_many",
+                post(|| async { "ok" }),
+            )
+            .route(
+                "/{collection}/insert_one",
+                post(|| async { "ok" }),
+            )
+            .route_layer(from_fn(authorize::<Sqlite>));
+
+        let app = Router::new()
+            .nest("/collection", collections)
+            .nest("/permissions", permissions_router::<Sqlite>())
+            .nest("/api_keys", api_keys_router::<Sqlite>())
+            .layer(Extension(keys()))
+            .with_state(pool.clone());
+
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                let status = res.status();
+                let body =
+                    res.into_body().collect().await.unwrap();
+                let body = serde_json::from_slice::<Value>(
+                    &body.to_bytes(),
+                )
+                .unwrap_or_default();
+                (status, body)
+            }
+        };
+
+        let admin = format!(
+            "Bearer {}",
+            ijwt::sign_for(
+                &keys(),
+                "1",
+                chrono::Duration::hours(1),
+            )
+        );
+        let admin = [("authorization", admin.as_str())];
+
+        send(request(
+            "/permissions/create_role",
+            &admin,
+            json!({"name": "service"}),
+        ))
+        .await;
+        send(request(
+            "/permissions/grant",
+            &admin,
+            json!({
+                "role": "service",
+                "collection": "*",
+                "operation": "get_many",
+            }),
+        ))
+        .await;
+
+        let (status, _) = send(request(
+            "/api_keys/create",
+            &admin,
+            json!({"name": "nope", "role": "missing"}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = send(request(
+            "/api_keys/create",
+            &admin,
+            json!({
+                "name": "sync",
+                "role": "service",
+                "collections": ["Todo"],
+            }),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let id = body["data"]["id"].as_i64().unwrap();
+        let key =
+            body["data"]["key"].as_str().unwrap().to_string();
+        let prefix = body["data"]["prefix"].as_str().unwrap();
+        assert!(key.starts_with(&format!("cms_{prefix}_")));
+
+        let with_key = [(API_KEY_HEADER, key.as_str())];
+
+        let (status, _) = send(request(
+            "/collection/todo/get_many",
+            &with_key,
+            json!({}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(request(
+            "/collection/todo/insert_one",
+            &with_key,
+            json!({}),
+        ))
+        .await;
+        assert_eq!(
+            status,
+            StatusCode::FORBIDDEN,
+            "the role of the key is not granted insert_one"
+        );
+
+        let (status, _) = send(request(
+            "/collection/category/get_many",
+            &with_key,
+            json!({}),
+        ))
+        .await;
+        assert_eq!(
+            status,
+            StatusCode::FORBIDDEN,
+            "Category is granted but not in the scope of the key"
+        );
+
+        let (status, _) = send(request(
+            "/collection/todo/get_many",
+            &[with_key[0], admin[0]],
+            json!({}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = send(request(
+            "/collection/todo/get_many",
+            &[(API_KEY_HEADER, "cms_nothing_here")],
+            json!({}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body["error"]["user_error"]["code"],
+            "invalid_api_key"
+        );
+
+        // last_used_at is written in the background
+        let mut listed = Value::Null;
+        for _ in 0..50 {
+            let res = app
+                .clone()
+                .oneshot(
+                    Request::get("/api_keys")
+                        .header(admin[0].0, admin[0].1)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let body = res.into_body().collect().await.unwrap();
+            listed = serde_json::from_slice::<Value>(
+                &body.to_bytes(),
+            )
+            .unwrap();
+            if !listed["data"][0]["last_used_at"].is_null() {
+                break;
+            }
+            tokio::time::sleep(
+                std::time::Duration::from_millis(10),
+            )
+            .await;
+        }
+        assert_eq!(listed["data"][0]["id"], id);
+        assert_eq!(
+            listed["data"][0]["collections"],
+            json!(["Todo"])
+        );
+        assert!(listed["data"][0]["last_used_at"].is_i64());
+        assert!(listed["data"][0].get("hash").is_none());
+
+        let (_, body) = send(request(
+            "/api_keys/create",
+            &admin,
+            json!({
+                "name": "old",
+                "role": "service",
+                "expires_at": Utc::now().timestamp() - 1,
+            }),
+        ))
+        .await;
+        let expired = body["data"]["key"].as_str().unwrap();
+        let (status, body) = send(request(
+            "/collection/todo/get_many",
+            &[(API_KEY_HEADER, expired)],
+            json!({}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body["error"]["user_error"]["code"],
+            "api_key_expired"
+        );
+
+        send(request(
+            "/api_keys/revoke",
+            &admin,
+            json!({"id": id}),
+        ))
+        .await;
+        let (status, _) = send(request(
+            "/collection/todo/get_many",
+            &with_key,
+            json!({}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
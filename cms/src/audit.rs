@@ -0,0 +1,565 @@
+//! who changed what and when. every insert_one, update_one and
+//! delete_one of the collection routes is recorded in
+//! `_cms_audit`, with the owner of the request as the actor and
+//! the fields before and after the change.
+//!
+//! updates only keep the fields that changed, inserts have no
+//! `before` and deletes no `after`. relation changes are kept
+//! as they were sent, at `relations`.
+//!
+//! recording starts once `init_audit` ran (`init_auth` runs
+//! it). the entry is written in the transaction of the change
+//! it is about, a change that can't be recorded is rolled back.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::{
+    extract::{Query, State},
+    middleware::from_fn,
+    routing::get,
+    Json, Router,
+};
+use case::CaseExt;
+use chrono::Utc;
+use queries_for_sqlx::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
+
+use crate::{
+    backend::Backend,
+    dynamic_schema::DynCollection,
+    error::{self, ClientError},
+    permissions::{current_owner, need_admin},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// the actor of writes made outside of a request, or by the
+/// public role
+pub const ANONYMOUS: &str = "anonymous";
+
+pub fn migration_st<S: Backend>() -> String {
+    format!(
+        "
+    CREATE TABLE IF NOT EXISTS _cms_audit (
+        id {},
+        created_at {i64} NOT NULL,
+        actor TEXT NOT NULL,
+        collection TEXT NOT NULL,
+        record_id {i64} NOT NULL,
+        operation TEXT NOT NULL,
+        before TEXT NOT NULL,
+        after TEXT NOT NULL,
+        relations TEXT NOT NULL
+    );
+    ",
+        S::id_column(),
+        i64 = S::i64_column(),
+    )
+}
+
+pub async fn init_audit<S>(
+    db: &Pool<S>,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+{
+    sqlx::query(&migration_st::<S>()).execute(db).await?;
+    sqlx::query(
+        "
+    CREATE INDEX IF NOT EXISTS _cms_audit_record
+    ON _cms_audit (collection, record_id);
+    ",
+    )
+    .execute(db)
+    .await?;
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Change {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Insert => "insert_one",
+            Change::Update => "update_one",
+            Change::Delete => "delete_one",
+        }
+    }
+}
+
+/// the fields of `before` and `after` that differ, in both
+pub fn diff(before: &Value, after: &Value) -> (Value, Value) {
+    let (Some(before), Some(after)) =
+        (before.as_object(), after.as_object())
+    else {
+        return (before.clone(), after.clone());
+    };
+
+    let mut old = Map::new();
+    let mut new = Map::new();
+    for (key, value) in after {
+        let prev = before.get(key).unwrap_or(&Value::Null);
+        if prev != value {
+            old.insert(key.clone(), prev.clone());
+            new.insert(key.clone(), value.clone());
+        }
+    }
+    for (key, prev) in before {
+        if !after.contains_key(key) {
+            old.insert(key.clone(), prev.clone());
+            new.insert(key.clone(), Value::Null);
+        }
+    }
+
+    (Value::Object(old), Value::Object(new))
+}
+
+/// the row as it is before an update or a delete, `None` when
+/// recording is off
+pub(crate) async fn snapshot<S>(
+    conn: &mut S::Connection,
+    collection: &dyn DynCollection<S>,
+    id: i64,
+) -> Result<Option<Value>, sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+{
+    if !is_enabled() {
+        return Ok(None);
+    }
+
+    let mut st = stmt::SelectSt::init(collection.table_name());
+    collection.on_select(&mut st);
+    st.where_(
+        scoped(collection.table_name().to_string(), "id").eq(id),
+    );
+
+    st.fetch_optional(conn, |r| {
+        Ok(collection.from_row_scoped(&r))
+    })
+    .await
+}
+
+/// `before` and `after` are whole rows, updates are diffed here
+pub(crate) async fn record<S>(
+    conn: &mut S::Connection,
+    change: Change,
+    collection: &str,
+    record_id: i64,
+    before: Option<Value>,
+    after: Option<Value>,
+    relations: Map<String, Value>,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    if !is_enabled() {
+        return Ok(());
+    }
+
+    let before = before.unwrap_or_default();
+    let after = after.unwrap_or_default();
+    let (before, after) = match change {
+        Change::Update => diff(&before, &after),
+        _ => (before, after),
+    };
+
+    sqlx::query(
+        "
+    INSERT INTO _cms_audit
+        (created_at, actor, collection, record_id, operation,
+        before, after, relations)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8);
+    ",
+    )
+    .bind(Utc::now().timestamp())
+    .bind(
+        current_owner().unwrap_or_else(|| ANONYMOUS.to_string()),
+    )
+    .bind(collection.to_string())
+    .bind(record_id)
+    .bind(change.as_str().to_string())
+    .bind(before.to_string())
+    .bind(after.to_string())
+    .bind(Value::Object(relations).to_string())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// nested in `admin_router`
+pub fn audit_router<S>() -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    Router::new()
+        .route("/", get(list::<S>))
+        .route_layer(from_fn(need_admin::<S>))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub collection: Option<String>,
+    pub record_id: Option<i64>,
+    pub actor: Option<String>,
+    /// entries older than this id, to page back from the
+    /// last `id` of a response
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub created_at: i64,
+    pub actor: String,
+    pub collection: String,
+    pub record_id: i64,
+    pub operation: String,
+    pub before: Value,
+    pub after: Value,
+    pub relations: Value,
+}
+
+type AuditRow = (
+    i64,
+    i64,
+    String,
+    String,
+    i64,
+    String,
+    String,
+    String,
+    String,
+);
+
+/// newest first
+async fn list<S>(
+    db: State<Pool<S>>,
+    query: Query<AuditQuery>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let query = query.0;
+
+    let mut conditions = vec![];
+    for (column, given) in [
+        ("collection =", query.collection.is_some()),
+        ("record_id =", query.record_id.is_some()),
+        ("actor =", query.actor.is_some()),
+        ("id <", query.before_id.is_some()),
+    ] {
+        if given {
+            let param = conditions.len() + 1;
+            conditions.push(format!("{column} ${param}"));
+        }
+    }
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+    let limit_param = conditions.len() + 1;
+    let limit =
+        query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let sql = format!(
+        "
+    SELECT id, created_at, actor, collection, record_id,
+        operation, before, after, relations
+    FROM _cms_audit
+    {where_clause}
+    ORDER BY id DESC
+    LIMIT ${limit_param};
+    "
+    );
+
+    let mut st = sqlx::query_as::<_, AuditRow>(&sql);
+    if let Some(collection) = query.collection {
+        st = st.bind(collection.to_camel());
+    }
+    if let Some(record_id) = query.record_id {
+        st = st.bind(record_id);
+    }
+    if let Some(actor) = query.actor {
+        st = st.bind(actor);
+    }
+    if let Some(before_id) = query.before_id {
+        st = st.bind(before_id);
+    }
+    let rows = st
+        .bind(limit)
+        .fetch_all(&db.0)
+        .await
+        .map_err(error::server_error)?;
+
+    let parse = |e: String| {
+        serde_json::from_str::<Value>(&e).unwrap_or_default()
+    };
+    let entries: Vec<AuditEntry> = rows
+        .into_iter()
+        .map(|e| AuditEntry {
+            id: e.0,
+            created_at: e.1,
+            actor: e.2,
+            collection: e.3,
+            record_id: e.4,
+            operation: e.5,
+            before: parse(e.6),
+            after: parse(e.7),
+            relations: parse(e.8),
+        })
+        .collect();
+
+    Ok(Json(json!({ "data": entries })))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+        Extension,
+    };
+    use http_body_util::BodyExt;
+    use queries_for_sqlx::ident_safety::define_schema;
+    use sqlx::Sqlite;
+    use tower::ServiceExt;
+
+    use crate::{
+        auth::ijwt,
+        axum_router::collections_router,
+        permissions::{
+            assign_role, init_permissions,
+            test::{keys, SERIAL},
+            ADMIN, OWNER_COLUMN,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn diff_keeps_changed_fields() {
+        let before =
+            json!({"title": "a", "done": false, "n": 1});
+        let after = json!({"title": "b", "done": false, "m": 2});
+        assert_eq!(
+            diff(&before, &after),
+            (
+                json!({"title": "a", "m": null, "n": 1}),
+                json!({"title": "b", "m": 2, "n": null}),
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_are_recorded() {
+        let _serial = SERIAL.lock().await;
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        std::env::set_var("JWT_SALT", "test");
+
+        sqlx::query(
+            "
+        CREATE TABLE Category (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            cat_title TEXT NOT NULL,
+            _owner TEXT
+        );
+        ",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        define_schema(&[(
+            "Category",
+            &["id", "cat_title", OWNER_COLUMN],
+        )]);
+
+        init_permissions(&pool).await.unwrap();
+        assign_role(&pool, 1, ADMIN).await.unwrap();
+        init_audit(&pool).await.unwrap();
+
+        let app = Router::new()
+            .nest("/collection", collections_router::<Sqlite>())
+            .nest("/audit", audit_router::<Sqlite>())
+            .layer(Extension(keys()))
+            .with_state(pool.clone());
+
+        let admin = format!(
+            "Bearer {}",
+            ijwt::sign_for(
+                &keys(),
+                "1",
+                chrono::Duration::hours(1),
+            )
+        );
+        let send = |method: Method, uri: &str, body: Value| {
+            let req = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("authorization", &admin)
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                let body =
+                    res.into_body().collect().await.unwrap();
+                serde_json::from_slice::<Value>(&body.to_bytes())
+                    .unwrap_or_default()
+            }
+        };
+
+        for title in ["a", "b"] {
+            send(
+                Method::POST,
+                "/collection/category/insert_one",
+                json!({"input": {"cat_title": title}, "relation": {}}),
+            )
+            .await;
+        }
+        send(
+            Method::POST,
+            "/collection/category/update_one",
+            json!({
+                "id": 1,
+                "partial": {"cat_title": ["set", "renamed"]},
+                "relations": {},
+            }),
+        )
+        .await;
+        send(
+            Method::POST,
+            "/collection/category/delete_one",
+            json!({
+                "id": 1,
+                "return_attr": false,
+                "return_residual": [],
+            }),
+        )
+        .await;
+
+        let res = send(
+            Method::GET,
+            "/audit?collection=category&record_id=1",
+            Value::Null,
+        )
+        .await;
+        let entries = res["data"].as_array().unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let operations: Vec<_> =
+            entries.iter().map(|e| &e["operation"]).collect();
+        assert_eq!(
+            operations,
+            ["delete_one", "update_one", "insert_one"]
+        );
+        assert!(entries.iter().all(|e| e["actor"] == "1"
+            && e["collection"] == "Category"));
+
+        assert_eq!(entries[2]["before"], Value::Null);
+        assert_eq!(entries[2]["after"]["cat_title"], "a");
+        assert_eq!(
+            (&entries[1]["before"], &entries[1]["after"]),
+            (
+                &json!({"cat_title": "a"}),
+                &json!({"cat_title": "renamed"})
+            )
+        );
+        assert_eq!(entries[0]["before"]["cat_title"], "renamed");
+        assert_eq!(entries[0]["after"], Value::Null);
+
+        let res =
+            send(Method::GET, "/audit?limit=1", Value::Null)
+                .await;
+        assert_eq!(res["data"].as_array().unwrap().len(), 1);
+        let last = res["data"][0]["id"].as_i64().unwrap();
+
+        let res = send(
+            Method::GET,
+            &format!("/audit?actor=1&before_id={last}"),
+            Value::Null,
+        )
+        .await;
+        assert_eq!(res["data"].as_array().unwrap().len(), 3);
+
+        let res =
+            send(Method::GET, "/audit?actor=2", Value::Null)
+                .await;
+        assert_eq!(res["data"], json!([]));
+
+        // a change that can't be recorded is rolled back
+        sqlx::query("DROP TABLE _cms_audit;")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let res = send(
+            Method::POST,
+            "/collection/category/update_one",
+            json!({
+                "id": 2,
+                "partial": {"cat_title": ["set", "lost"]},
+                "relations": {},
+            }),
+        )
+        .await;
+        assert!(res["error"].is_object());
+        let (title,): (String,) = sqlx::query_as(
+            "SELECT cat_title FROM Category WHERE id = 2;",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(title, "b");
+
+        // the databases of other tests have no audit table
+        ENABLED.store(false, Ordering::Relaxed);
+    }
+}
@@ -1,19 +1,48 @@
 mod email_password;
-mod ijwt;
+pub(crate) mod ijwt;
+pub mod invitations;
+pub mod keys;
 mod other;
+pub mod password;
+pub mod rate_limit;
+pub mod session;
+pub mod totp;
+pub mod users;
 use axum::{
-    http::StatusCode, middleware::from_fn, routing::post, Router,
+    http::StatusCode,
+    middleware::from_fn,
+    routing::{get, post},
+    Json, Router,
 };
+pub use ijwt::{IClaims, Principal};
 pub use other::*;
 
-use sqlx::{Pool, Sqlite};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode, Executor,
+    IntoArguments, Pool, Type,
+};
+
+use crate::backend::Backend;
 
-pub fn auth_router() -> Router<Pool<Sqlite>> {
+/// the tokens are signed with the `keys::Keys` extension, its
+/// public keys are served at `/jwks`
+pub fn auth_router<S>() -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments: IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
     Router::new()
         .nest(
             "/init",
             Router::new()
-                .route("/sign_in_first", post(sign_in_existing))
+                .route(
+                    "/sign_in_first",
+                    post(sign_in_existing::<S>),
+                )
                 .route(
                     "/set_up_db",
                     post(|| async {
@@ -24,19 +53,31 @@ pub fn auth_router() -> Router<Pool<Sqlite>> {
                     }),
                 )
                 .layer((
+                    from_fn(rate_limit::limit_attempts),
                     from_fn(need_super_user),
                     from_fn(other::can_init),
                 )),
         )
         .route(
             "/sign_in_invited",
-            post(sign_in_existing)
-                .route_layer(from_fn(need_super_user)),
+            post(invitations::accept::<S>).route_layer(
+                from_fn(rate_limit::limit_attempts),
+            ),
         )
         .route(
             "/login",
-            post(login)
-                .route_layer(from_fn(extend_for_authinticated)),
+            post(login::<S>).route_layer(from_fn(
+                rate_limit::limit_attempts,
+            )),
+        )
+        .nest("/totp", totp::totp_router::<S>())
+        .route("/refresh", post(session::refresh::<S>))
+        .route("/logout", post(session::logout::<S>))
+        .route(
+            "/jwks",
+            get(|keys: keys::Keys| async move {
+                Json(keys.jwks())
+            }),
         )
 }
 
@@ -44,28 +85,54 @@ pub fn auth_router() -> Router<Pool<Sqlite>> {
 mod auth_router_test {
     use axum::{
         body::Body,
-        http::{Method, Request},
+        http::{Method, Request, StatusCode},
+        Extension,
     };
-    use http_body_util::BodyExt;
+    use jwt::ToBase64;
     use serde_json::json;
     use tower::ServiceExt;
 
     use super::*;
+    use crate::permissions::test::keys as test_keys;
+
+    fn login_request(
+        email: &str,
+        password: &str,
+    ) -> Request<Body> {
+        let basic = json!({"email": email, "password": password})
+            .to_base64()
+            .unwrap()
+            .to_string();
+
+        Request::builder()
+            .method(Method::POST)
+            .header("authorization", format!("Basic {}", basic))
+            .uri("/login")
+            .body(Body::empty())
+            .unwrap()
+    }
 
     #[tokio::test]
     async fn test_auth_router() {
-        let pool = sqlx::Pool::connect("sqlite::memory:")
+        // admins can require TOTP for every super user
+        let _serial =
+            crate::permissions::test::SERIAL.lock().await;
+        let pool = sqlx::Pool::<sqlx::Sqlite>::connect("sqlite::memory:")
             .await
             .unwrap();
         std::env::set_var("JWT_SALT", "test");
 
         let token = create_super_user_if_not_exist_and_return_init_token(
             pool.clone(),
+            &test_keys(),
         )
         .await
         .expect("new db therfore token should be generated");
+        session::init_sessions(&pool).await.unwrap();
+        totp::init_totp(&pool).await.unwrap();
 
-        let res = auth_router()
+        let res = auth_router::<sqlx::Sqlite>()
+            .layer(Extension(test_keys()))
             .with_state(pool.clone())
             .oneshot(
                 Request::builder()
@@ -89,12 +156,226 @@ mod auth_router_test {
             .await
             .unwrap();
 
-        // res body as text
-        let res_text =
-            res.into_body().collect().await.unwrap().to_bytes();
-        let res_text =
-            String::from_utf8(res_text.into()).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let (stored,): (String,) =
+            sqlx::query_as("SELECT password FROM _super_users")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+
+        assert_ne!(stored, "test");
+        assert_eq!(
+            password::verify("test", Some(&stored)),
+            password::Verify::Valid
+        );
+
+        let res = auth_router::<sqlx::Sqlite>()
+            .layer(Extension(test_keys()))
+            .with_state(pool.clone())
+            .oneshot(login_request("test@example.com", "test"))
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let token = res
+            .headers()
+            .get("X-Cms-Token")
+            .expect("login should issue a token")
+            .to_str()
+            .unwrap();
+        let claims = ijwt::verify_exp(
+            &test_keys(),
+            token,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(claims.id, "1");
+
+        for (email, password) in [
+            ("test@example.com", "wrong"),
+            ("nobody@example.com", "test"),
+        ] {
+            let res = auth_router::<sqlx::Sqlite>()
+                .layer(Extension(test_keys()))
+                .with_state(pool.clone())
+                .oneshot(login_request(email, password))
+                .await
+                .unwrap();
+
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+            assert!(res.headers().get("X-Cms-Token").is_none());
+        }
+
+        // a router without the keys can't sign, it doesn't panic
+        let res = auth_router::<sqlx::Sqlite>()
+            .with_state(pool.clone())
+            .oneshot(login_request("test@example.com", "test"))
+            .await
+            .unwrap();
+        assert_eq!(
+            res.status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    fn json_request(
+        uri: &str,
+        token: Option<&str>,
+        body: serde_json::Value,
+    ) -> Request<Body> {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .header("content-type", "application/json")
+            .uri(uri);
+        if let Some(token) = token {
+            req = req
+                .header("authorization", format!("Bearer {}", token));
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
 
-        panic!("why {:?}", res_text);
+    /// the access token and the refresh token
+    async fn tokens(
+        res: axum::http::Response<Body>,
+    ) -> (String, String) {
+        use http_body_util::BodyExt;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let access = res.headers()["X-Cms-Token"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = res.into_body().collect().await.unwrap();
+        let body: serde_json::Value =
+            serde_json::from_slice(&body.to_bytes()).unwrap();
+        let refresh = body["data"]["refresh_token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        (access, refresh)
+    }
+
+    async fn user_error(res: axum::http::Response<Body>) -> String {
+        use http_body_util::BodyExt;
+
+        let body = res.into_body().collect().await.unwrap();
+        let body: serde_json::Value =
+            serde_json::from_slice(&body.to_bytes()).unwrap();
+        body["error"]["user_error"]["code"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_and_logout_revokes() {
+        // admins can require TOTP for every super user
+        let _serial =
+            crate::permissions::test::SERIAL.lock().await;
+        let pool = sqlx::Pool::<sqlx::Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        std::env::set_var("JWT_SALT", "test");
+
+        create_super_user_if_not_exist_and_return_init_token(
+            pool.clone(),
+            &test_keys(),
+        )
+        .await
+        .unwrap();
+        session::init_sessions(&pool).await.unwrap();
+        totp::init_totp(&pool).await.unwrap();
+        sqlx::query(
+            "UPDATE _super_users SET email = $1, password = $2;",
+        )
+        .bind("test@example.com")
+        .bind(password::hash("test"))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let app = auth_router::<sqlx::Sqlite>()
+            .layer(Extension(test_keys()))
+            .with_state(pool);
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+
+        let (access, refresh) =
+            tokens(send(login_request("test@example.com", "test")).await)
+                .await;
+        let claims = ijwt::verify_exp(
+            &test_keys(),
+            &access,
+        )
+        .ok()
+        .unwrap();
+        assert!(claims.exp - claims.iat <= 15 * 60);
+
+        let (access_2, refresh_2) = tokens(
+            send(json_request(
+                "/refresh",
+                None,
+                json!({"refresh_token": refresh}),
+            ))
+            .await,
+        )
+        .await;
+        assert_ne!(refresh, refresh_2);
+
+        // the first refresh token leaked, using it again ends the
+        // session for everyone holding its tokens
+        let res = send(json_request(
+            "/refresh",
+            None,
+            json!({"refresh_token": refresh}),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(user_error(res).await, "refresh_token_reused");
+
+        let res = send(json_request(
+            "/refresh",
+            None,
+            json!({"refresh_token": refresh_2}),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        for access in [&access, &access_2] {
+            let res =
+                send(json_request("/logout", Some(access), json!({})))
+                    .await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(user_error(res).await, "token_revoked");
+        }
+
+        // a new session, logging out revokes the access token and
+        // the refresh token
+        let (access, refresh) =
+            tokens(send(login_request("test@example.com", "test")).await)
+                .await;
+        let res =
+            send(json_request("/logout", Some(&access), json!({})))
+                .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let claims = ijwt::verify_exp(
+            &test_keys(),
+            &access,
+        )
+        .ok()
+        .unwrap();
+        assert!(session::is_revoked(&claims));
+        let res = send(json_request(
+            "/refresh",
+            None,
+            json!({"refresh_token": refresh}),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
     }
 }
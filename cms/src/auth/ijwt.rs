@@ -1,38 +1,65 @@
 use axum::http::StatusCode;
 use chrono::DateTime;
 use chrono::Utc;
-use hmac::Hmac;
-use hmac::Mac;
-use jwt::SignWithKey;
-use jwt::VerifyWithKey;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::HashMap;
 
+use super::keys::JwtKeys;
+use super::session::random_token;
 use crate::error::ClientError;
 use crate::error::UserError;
 
+/// who a token was issued to, super users and end users have
+/// separate id spaces
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Principal {
+    /// tokens issued before end users existed have no
+    /// `principal` claim
+    #[default]
+    SuperUser,
+    User,
+}
+
+impl Principal {
+    pub fn owner(&self, id: &str) -> String {
+        match self {
+            Principal::SuperUser => id.to_string(),
+            Principal::User => format!("user:{id}"),
+        }
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct IClaims {
     pub id: String,
     pub iat: i64,
     pub exp: i64,
+    #[serde(default)]
+    pub principal: Principal,
+    /// id of this token, to revoke it
+    #[serde(default)]
+    pub jti: Option<String>,
+    /// the session the token was refreshed in, see
+    /// `session::start`
+    #[serde(default)]
+    pub sid: Option<String>,
     #[serde(flatten)]
     pub todos: HashMap<String, serde_json::Value>,
 }
 
-fn sign<S: Serialize>(data: S) -> String {
-    let env =
-        std::env::var("JWT_SALT").expect("JWT_SALT not set");
-
-    let key =
-        Hmac::<sha2::Sha256>::new_from_slice(env.as_bytes())
-            .expect("HMAC can take key of any size");
-
-    data.sign_with_key(&key).unwrap()
-}
-
 pub fn sign_and(
+    keys: &JwtKeys,
     id: &str,
     until: chrono::TimeDelta,
     todos: HashMap<String, serde_json::Value>,
@@ -44,41 +71,67 @@ pub fn sign_and(
         id: id.to_owned(),
         iat: iat.timestamp(),
         exp: exp.timestamp(),
+        principal: Principal::SuperUser,
+        jti: Some(random_token()),
+        sid: None,
         todos,
     })
     .unwrap();
 
-    sign(map)
+    keys.sign(&map)
+}
+
+pub fn sign_for(
+    keys: &JwtKeys,
+    id: &str,
+    until: chrono::TimeDelta,
+) -> String {
+    sign_as(keys, Principal::SuperUser, id, until)
+}
+
+pub fn sign_as(
+    keys: &JwtKeys,
+    principal: Principal,
+    id: &str,
+    until: chrono::TimeDelta,
+) -> String {
+    sign_in_session(keys, principal, id, None, until)
 }
 
-pub fn sign_for(id: &str, until: chrono::TimeDelta) -> String {
+pub fn sign_in_session(
+    keys: &JwtKeys,
+    principal: Principal,
+    id: &str,
+    sid: Option<&str>,
+    until: chrono::TimeDelta,
+) -> String {
     let iat = Utc::now();
     let exp = iat + until;
 
-    sign(IClaims {
+    keys.sign(&IClaims {
         id: id.to_owned(),
         iat: iat.timestamp(),
         exp: exp.timestamp(),
+        principal,
+        jti: Some(random_token()),
+        sid: sid.map(String::from),
         todos: HashMap::new(),
     })
 }
 
-fn verify_or(data: &str) -> Option<()> {
-    let env =
-        std::env::var("JWT_SALT").expect("JWT_SALT not set");
-
-    let key =
-        Hmac::<sha2::Sha256>::new_from_slice(env.as_bytes())
-            .expect("HMAC can take key of any size");
-
-    let _: IClaims = data.verify_with_key(&key).ok()?;
-
-    Some(())
+impl IClaims {
+    /// what `permissions::OWNER_COLUMN` stores for rows this
+    /// principal inserts, end user ids are prefixed so they
+    /// don't collide with super user ids
+    pub fn owner(&self) -> String {
+        self.principal.owner(&self.id)
+    }
 }
 
 pub enum TokenError {
     TokenExpired,
     TokenInvalid,
+    TokenRevoked,
 }
 
 impl From<TokenError> for ClientError {
@@ -100,21 +153,28 @@ impl From<TokenError> for ClientError {
                 dev_hint: "invalid signature".to_owned(),
                 user_error: None,
             },
+            TokenError::TokenRevoked => ClientError {
+                status_code: StatusCode::UNAUTHORIZED,
+                dev_hint: "token or its session was revoked"
+                    .to_owned(),
+                user_error: Some(UserError {
+                    code: "token_revoked".to_owned(),
+                    user_hint: "session has ended".to_owned(),
+                    structured_hint: None,
+                    server_suggest: None,
+                }),
+            },
         }
     }
 }
 
-pub fn verify_exp(data: &str) -> Result<IClaims, TokenError> {
-    let env =
-        std::env::var("JWT_SALT").expect("JWT_SALT not set");
-
-    let key =
-        Hmac::<sha2::Sha256>::new_from_slice(env.as_bytes())
-            .expect("HMAC can take key of any size");
-
-    let claims: IClaims = data
-        .verify_with_key(&key)
-        .map_err(|_| TokenError::TokenInvalid)?;
+pub fn verify_exp(
+    keys: &JwtKeys,
+    data: &str,
+) -> Result<IClaims, TokenError> {
+    let claims: IClaims = keys
+        .verify(data)
+        .ok_or(TokenError::TokenInvalid)?;
 
     let exp = DateTime::from_timestamp(claims.exp, 0).unwrap();
     let now = Utc::now();
@@ -0,0 +1,713 @@
+//! admins invite new super users by email, with the role they
+//! will have. the invite token works once and expires, like the
+//! init token of the first super user. whoever has it picks a
+//! name and a password at `/auth/sign_in_invited` and is logged
+//! in, through TOTP when it's required.
+//!
+//! invitations are kept in `_cms_invitations`, with the token
+//! hashed.
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{Response, StatusCode},
+    middleware::from_fn,
+    response::IntoResponse,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use chrono::{TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
+
+use super::{
+    ijwt::{IClaims, Principal},
+    keys::Keys,
+    password,
+    session::{self, hash_token, random_token},
+    totp::login_challenge,
+    users::{check_password, invalid_token, normalize_email},
+};
+use crate::{
+    backend::Backend,
+    error::{self, ClientError},
+    permissions::{need_admin, reload, role_exists, store_role},
+};
+
+pub const INVITE_TTL: TimeDelta = TimeDelta::days(7);
+
+pub fn migration_st<S: Backend>() -> String {
+    format!(
+        "
+    CREATE TABLE IF NOT EXISTS _cms_invitations (
+        id {},
+        token TEXT NOT NULL UNIQUE,
+        email TEXT NOT NULL,
+        role TEXT NOT NULL,
+        invited_by TEXT NOT NULL,
+        expires_at {i64} NOT NULL
+    );
+    ",
+        S::id_column(),
+        i64 = S::i64_column(),
+    )
+}
+
+pub async fn init_invitations<S>(
+    db: &Pool<S>,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(&migration_st::<S>()).execute(db).await?;
+    sqlx::query(
+        "DELETE FROM _cms_invitations WHERE expires_at < $1;",
+    )
+    .bind(Utc::now().timestamp())
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// nested in `admin_router`
+pub fn invitations_router<S>() -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    Router::new()
+        .route("/", get(list::<S>))
+        .route("/create", post(create::<S>))
+        .route("/revoke", post(revoke::<S>))
+        .route_layer(from_fn(need_admin::<S>))
+}
+
+#[derive(Serialize)]
+pub struct Invitation {
+    pub id: i64,
+    pub email: String,
+    pub role: String,
+    pub invited_by: String,
+    pub expires_at: i64,
+}
+
+async fn list<S>(
+    db: State<Pool<S>>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let rows: Vec<(i64, String, String, String, i64)> =
+        sqlx::query_as(
+            "
+    SELECT id, email, role, invited_by, expires_at
+    FROM _cms_invitations
+    WHERE expires_at >= $1
+    ORDER BY id;
+    ",
+        )
+        .bind(Utc::now().timestamp())
+        .fetch_all(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    let invitations: Vec<Invitation> = rows
+        .into_iter()
+        .map(|(id, email, role, invited_by, expires_at)| {
+            Invitation {
+                id,
+                email,
+                role,
+                invited_by,
+                expires_at,
+            }
+        })
+        .collect();
+
+    Ok(Json(json!({ "data": invitations })))
+}
+
+#[derive(Deserialize)]
+pub struct CreateInput {
+    pub email: String,
+    pub role: String,
+}
+
+/// the token is only in this response, send it to the invited
+/// person. inviting an email again replaces its invitation
+async fn create<S>(
+    db: State<Pool<S>>,
+    admin: Extension<IClaims>,
+    input: Json<CreateInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let email = normalize_email(&input.email)?;
+    role_exists::<S>(&input.role).await?;
+    email_is_free(&db.0, &email).await?;
+
+    sqlx::query(
+        "DELETE FROM _cms_invitations WHERE email = $1;",
+    )
+    .bind(email.clone())
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    let token = random_token();
+    let (id,): (i64,) = sqlx::query_as(
+        "
+    INSERT INTO _cms_invitations
+        (token, email, role, invited_by, expires_at)
+    VALUES ($1, $2, $3, $4, $5)
+    RETURNING id;
+    ",
+    )
+    .bind(hash_token(&token))
+    .bind(email)
+    .bind(input.0.role)
+    .bind(admin.0.id)
+    .bind((Utc::now() + INVITE_TTL).timestamp())
+    .fetch_one(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    Ok(Json(json!({"data": {
+        "id": id,
+        "token": token,
+    }})))
+}
+
+#[derive(Deserialize)]
+pub struct RevokeInput {
+    pub id: i64,
+}
+
+async fn revoke<S>(
+    db: State<Pool<S>>,
+    input: Json<RevokeInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query("DELETE FROM _cms_invitations WHERE id = $1;")
+        .bind(input.id)
+        .execute(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+async fn email_is_free<S>(
+    db: &Pool<S>,
+    email: &str,
+) -> Result<(), ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    let found: Option<(i64,)> = sqlx::query_as(
+        "SELECT id FROM _super_users WHERE lower(email) = $1;",
+    )
+    .bind(email.to_string())
+    .fetch_optional(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    if found.is_some() {
+        return Err(ClientError {
+            status_code: StatusCode::CONFLICT,
+            dev_hint: format!("{email} is already a super user"),
+            user_error: None,
+        }
+        .add_user_error(
+            "email_taken",
+            "this email already has an account",
+            |_| {},
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct AcceptInput {
+    pub token: String,
+    pub user_name: String,
+    pub password: String,
+}
+
+/// creates the super user, with the role of the invitation, and
+/// logs them in like `login` does, with a TOTP challenge when
+/// one is needed
+pub async fn accept<S>(
+    db: State<Pool<S>>,
+    keys: Keys,
+    input: Json<AcceptInput>,
+) -> Result<Response<Body>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    check_password(&input.password)?;
+
+    let token = hash_token(&input.token);
+    let invitation: Option<(String, String, i64)> =
+        sqlx::query_as(
+            "
+    SELECT email, role, expires_at FROM _cms_invitations
+    WHERE token = $1;
+    ",
+        )
+        .bind(token.clone())
+        .fetch_optional(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    let Some((email, role, expires_at)) = invitation else {
+        return Err(invalid_token());
+    };
+    if expires_at < Utc::now().timestamp() {
+        return Err(invalid_token());
+    }
+    if role_exists::<S>(&role).await.is_err() {
+        return Err(ClientError {
+            status_code: StatusCode::CONFLICT,
+            dev_hint: format!(
+                "role {role} of the invitation was deleted"
+            ),
+            user_error: None,
+        }
+        .add_user_error(
+            "role_deleted",
+            "the invitation is no longer valid, ask for a new one",
+            |_| {},
+        ));
+    }
+    email_is_free(&db.0, &email).await?;
+
+    let password = input.0.password;
+    let password = tokio::task::spawn_blocking(move || {
+        password::hash(&password)
+    })
+    .await
+    .map_err(error::server_error)?;
+
+    // deleting it is what makes it single use, together with
+    // the super user so a failure doesn't use it up
+    let mut tx = db
+        .0
+        .begin()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    let deleted: Option<(i64,)> = sqlx::query_as(
+        "DELETE FROM _cms_invitations WHERE token = $1 RETURNING id;",
+    )
+    .bind(token)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+    if deleted.is_none() {
+        return Err(invalid_token());
+    }
+
+    let (id,): (i64,) = sqlx::query_as(
+        "
+    INSERT INTO _super_users (user_name, email, password)
+    VALUES ($1, $2, $3)
+    RETURNING id;
+    ",
+    )
+    .bind(input.0.user_name)
+    .bind(email)
+    .bind(password)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    store_role::<S>(&mut tx, id, &role)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    tx.commit()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+    reload(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    if let Some(challenge) = login_challenge(&db.0, id).await? {
+        return Ok(challenge);
+    }
+
+    Ok(session::start(
+        &db.0,
+        &keys,
+        Principal::SuperUser,
+        &id.to_string(),
+    )
+    .await?
+    .into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+    };
+    use http_body_util::BodyExt;
+    use jwt::ToBase64;
+    use serde_json::{json, Value};
+    use sqlx::{Pool, Sqlite};
+    use tower::ServiceExt;
+
+    use crate::{
+        auth::{
+            auth_router,
+            create_super_user_if_not_exist_and_return_init_token,
+            ijwt, session::init_sessions,
+            totp::{init_totp, settings_router},
+        },
+        permissions::{
+            init_permissions, permissions_router,
+            test::{keys, SERIAL},
+        },
+    };
+
+    use super::*;
+
+    fn request(
+        uri: &str,
+        admin: Option<&str>,
+        body: Value,
+    ) -> Request<Body> {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .header("content-type", "application/json")
+            .uri(uri);
+        if let Some(admin) = admin {
+            req = req.header("authorization", admin);
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn invitations_are_single_use_and_expire() {
+        let _serial = SERIAL.lock().await;
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        std::env::set_var("JWT_SALT", "test");
+
+        create_super_user_if_not_exist_and_return_init_token(
+            pool.clone(),
+            &keys(),
+        )
+        .await
+        .unwrap();
+        init_permissions(&pool).await.unwrap();
+        init_sessions(&pool).await.unwrap();
+        init_totp(&pool).await.unwrap();
+        init_invitations(&pool).await.unwrap();
+        crate::api_keys::init_api_keys(&pool).await.unwrap();
+
+        let app = Router::new()
+            .nest(
+                "/auth",
+                auth_router::<Sqlite>(),
+            )
+            .nest("/permissions", permissions_router::<Sqlite>())
+            .nest("/invitations", invitations_router::<Sqlite>())
+            .nest("/settings", settings_router::<Sqlite>())
+            .layer(Extension(keys()))
+            .with_state(pool.clone());
+
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                let status = res.status();
+                let token = res
+                    .headers()
+                    .get("X-Cms-Token")
+                    .map(|e| e.to_str().unwrap().to_string());
+                let body =
+                    res.into_body().collect().await.unwrap();
+                let body = serde_json::from_slice::<Value>(
+                    &body.to_bytes(),
+                )
+                .unwrap_or_default();
+                (status, body, token)
+            }
+        };
+
+        let bearer = format!(
+            "Bearer {}",
+            ijwt::sign_for(
+                &keys(),
+                "1",
+                chrono::Duration::hours(1),
+            )
+        );
+        let admin = Some(bearer.as_str());
+
+        send(request(
+            "/permissions/create_role",
+            admin,
+            json!({"name": "editor"}),
+        ))
+        .await;
+
+        let (status, _, _) = send(request(
+            "/invitations/create",
+            admin,
+            json!({"email": "new@example.com", "role": "missing"}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let invite = json!({
+            "email": " New@Example.com",
+            "role": "editor",
+        });
+        let (_, body, _) = send(request(
+            "/invitations/create",
+            admin,
+            invite.clone(),
+        ))
+        .await;
+        let replaced =
+            body["data"]["token"].as_str().unwrap().to_string();
+        let (status, body, _) =
+            send(request("/invitations/create", admin, invite))
+                .await;
+        assert_eq!(status, StatusCode::OK);
+        let token =
+            body["data"]["token"].as_str().unwrap().to_string();
+
+        let (_, listed, _) = send(
+            Request::get("/invitations")
+                .header("authorization", &bearer)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(listed["data"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            listed["data"][0]["email"],
+            "new@example.com"
+        );
+        assert_eq!(listed["data"][0]["invited_by"], "1");
+        assert!(listed["data"][0].get("token").is_none());
+
+        let accept = |token: &str, password: &str| {
+            request(
+                "/auth/sign_in_invited",
+                None,
+                json!({
+                    "token": token,
+                    "user_name": "new",
+                    "password": password,
+                }),
+            )
+        };
+
+        let (status, body, _) =
+            send(accept(&replaced, "long enough")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["error"]["user_error"]["code"],
+            "invalid_token"
+        );
+
+        // a weak password doesn't use up the invitation
+        let (status, _, _) = send(accept(&token, "short")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _, access) =
+            send(accept(&token, "long enough")).await;
+        assert_eq!(status, StatusCode::OK);
+        let claims =
+            ijwt::verify_exp(
+                &keys(),
+                &access.unwrap(),
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            Sqlite::permissions().read().await.role_for(&claims),
+            "editor"
+        );
+
+        let (status, _, _) =
+            send(accept(&token, "long enough")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let basic = json!({
+            "email": "new@example.com",
+            "password": "long enough",
+        })
+        .to_base64()
+        .unwrap()
+        .to_string();
+        let (status, _, _) = send(
+            Request::post("/auth/login")
+                .header(
+                    "authorization",
+                    format!("Basic {basic}"),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body, _) = send(request(
+            "/invitations/create",
+            admin,
+            json!({"email": "new@example.com", "role": "editor"}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(
+            body["error"]["user_error"]["code"],
+            "email_taken"
+        );
+
+        let (_, body, _) = send(request(
+            "/invitations/create",
+            admin,
+            json!({"email": "late@example.com", "role": "editor"}),
+        ))
+        .await;
+        let late =
+            body["data"]["token"].as_str().unwrap().to_string();
+        sqlx::query(
+            "UPDATE _cms_invitations SET expires_at = $1;",
+        )
+        .bind(Utc::now().timestamp() - 1)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let (status, _, _) =
+            send(accept(&late, "long enough")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // the role can't be deleted while an invitation holds
+        // it, a role created later with the same name would
+        // be handed out by it
+        send(request(
+            "/permissions/create_role",
+            admin,
+            json!({"name": "reviewer"}),
+        ))
+        .await;
+        let (_, body, _) = send(request(
+            "/invitations/create",
+            admin,
+            json!({"email": "held@example.com", "role": "reviewer"}),
+        ))
+        .await;
+        let held =
+            body["data"]["token"].as_str().unwrap().to_string();
+        let (status, body, _) = send(request(
+            "/permissions/delete_role",
+            admin,
+            json!({"name": "reviewer"}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(
+            body["error"]["user_error"]["code"],
+            "role_in_use"
+        );
+
+        let (status, _, access) =
+            send(accept(&held, "long enough")).await;
+        assert_eq!(status, StatusCode::OK);
+        let claims =
+            ijwt::verify_exp(
+                &keys(),
+                &access.unwrap(),
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            Sqlite::permissions().read().await.role_for(&claims),
+            "reviewer"
+        );
+
+        // like `login`, the tokens wait for TOTP when required
+        send(request(
+            "/settings/require_totp",
+            admin,
+            json!({"required": true}),
+        ))
+        .await;
+        let (_, body, _) = send(request(
+            "/invitations/create",
+            admin,
+            json!({"email": "totp@example.com", "role": "editor"}),
+        ))
+        .await;
+        let totp =
+            body["data"]["token"].as_str().unwrap().to_string();
+        let (status, body, access) =
+            send(accept(&totp, "long enough")).await;
+        send(request(
+            "/settings/require_totp",
+            admin,
+            json!({"required": false}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(access, None);
+        assert_eq!(body["data"]["totp"], "enroll");
+    }
+}
@@ -0,0 +1,445 @@
+//! the keys JWTs are signed and verified with.
+//!
+//! tokens are signed with one key, and verified with it or any
+//! key that was added with `JwtKeys::verify_also`, so a key can
+//! be rotated out: sign with the new key and keep verifying
+//! with the old one until the tokens it signed have expired.
+//! every key has a `kid` that is put in the header of the
+//! tokens it signs.
+//!
+//! the routers read the keys from an `Extension<Arc<JwtKeys>>`,
+//! the app adds it as a layer over all of them:
+//! `.layer(Extension(Arc::new(keys)))`. `JwtKeys::from_env`
+//! reads the `JWT_SALT` env var as an HMAC secret.
+
+use std::{fmt, fs, io, path::Path, sync::Arc};
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use ring::{
+    hmac,
+    rand::SystemRandom,
+    rsa::PublicKeyComponents,
+    signature::{
+        self, Ed25519KeyPair, KeyPair, RsaKeyPair,
+        UnparsedPublicKey,
+    },
+};
+use axum::Extension;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// how handlers and middlewares get the keys, a router without
+/// the extension answers 500
+pub type Keys = Extension<Arc<JwtKeys>>;
+
+#[derive(Debug)]
+pub enum KeyError {
+    Io(io::Error),
+    NotPem,
+    Rejected(String),
+    EnvNotSet,
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::Io(err) => {
+                write!(f, "can't read key: {err}")
+            }
+            KeyError::NotPem => write!(f, "key is not PEM"),
+            KeyError::Rejected(err) => {
+                write!(f, "key is rejected: {err}")
+            }
+            KeyError::EnvNotSet => write!(f, "JWT_SALT not set"),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+impl From<io::Error> for KeyError {
+    fn from(value: io::Error) -> Self {
+        KeyError::Io(value)
+    }
+}
+
+impl From<ring::error::KeyRejected> for KeyError {
+    fn from(value: ring::error::KeyRejected) -> Self {
+        KeyError::Rejected(value.to_string())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    HS256,
+    RS256,
+    EdDSA,
+}
+
+impl Algorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::HS256 => "HS256",
+            Algorithm::RS256 => "RS256",
+            Algorithm::EdDSA => "EdDSA",
+        }
+    }
+}
+
+enum Material {
+    Hmac(hmac::Key),
+    Rsa {
+        pair: RsaKeyPair,
+        public: PublicKeyComponents<Vec<u8>>,
+    },
+    Ed25519(Ed25519KeyPair),
+}
+
+pub struct JwtKey {
+    kid: Option<String>,
+    material: Material,
+}
+
+/// the DER inside a PEM, whatever its label
+fn pem_to_der(pem: &str) -> Result<Vec<u8>, KeyError> {
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .filter(|e| !e.is_empty() && !e.starts_with("-----"))
+        .collect();
+    STANDARD.decode(body).map_err(|_| KeyError::NotPem)
+}
+
+impl JwtKey {
+    /// HS256, the secret is shared by who signs and who
+    /// verifies, and never published in the JWKS
+    pub fn hmac(kid: &str, secret: &[u8]) -> Self {
+        JwtKey {
+            kid: Some(kid.to_string()),
+            material: Material::Hmac(hmac::Key::new(
+                hmac::HMAC_SHA256,
+                secret,
+            )),
+        }
+    }
+
+    /// EdDSA from a PKCS#8 PEM, like the ones
+    /// `openssl genpkey -algorithm ed25519` writes
+    pub fn ed25519_pem(
+        kid: &str,
+        pem: &str,
+    ) -> Result<Self, KeyError> {
+        let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(
+            &pem_to_der(pem)?,
+        )?;
+        Ok(JwtKey {
+            kid: Some(kid.to_string()),
+            material: Material::Ed25519(pair),
+        })
+    }
+
+    pub fn ed25519_pem_file(
+        kid: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, KeyError> {
+        Self::ed25519_pem(kid, &fs::read_to_string(path)?)
+    }
+
+    /// RS256 from a PKCS#8 (`BEGIN PRIVATE KEY`) or PKCS#1
+    /// (`BEGIN RSA PRIVATE KEY`) PEM, of at least 2048 bits
+    pub fn rsa_pem(
+        kid: &str,
+        pem: &str,
+    ) -> Result<Self, KeyError> {
+        let der = pem_to_der(pem)?;
+        let pair = if pem.contains("BEGIN RSA PRIVATE KEY") {
+            RsaKeyPair::from_der(&der)?
+        } else {
+            RsaKeyPair::from_pkcs8(&der)?
+        };
+        let public = PublicKeyComponents::from(pair.public());
+        Ok(JwtKey {
+            kid: Some(kid.to_string()),
+            material: Material::Rsa { pair, public },
+        })
+    }
+
+    pub fn rsa_pem_file(
+        kid: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, KeyError> {
+        Self::rsa_pem(kid, &fs::read_to_string(path)?)
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self.material {
+            Material::Hmac(_) => Algorithm::HS256,
+            Material::Rsa { .. } => Algorithm::RS256,
+            Material::Ed25519(_) => Algorithm::EdDSA,
+        }
+    }
+
+    fn sign(&self, message: &[u8]) -> Vec<u8> {
+        match &self.material {
+            Material::Hmac(key) => {
+                hmac::sign(key, message).as_ref().to_vec()
+            }
+            Material::Rsa { pair, .. } => {
+                let mut signature =
+                    vec![0; pair.public().modulus_len()];
+                pair.sign(
+                    &signature::RSA_PKCS1_SHA256,
+                    &SystemRandom::new(),
+                    message,
+                    &mut signature,
+                )
+                .expect("RSA signing failed");
+                signature
+            }
+            Material::Ed25519(pair) => {
+                pair.sign(message).as_ref().to_vec()
+            }
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match &self.material {
+            Material::Hmac(key) => {
+                hmac::verify(key, message, signature).is_ok()
+            }
+            Material::Rsa { public, .. } => public
+                .verify(
+                    &signature::RSA_PKCS1_2048_8192_SHA256,
+                    message,
+                    signature,
+                )
+                .is_ok(),
+            Material::Ed25519(pair) => UnparsedPublicKey::new(
+                &signature::ED25519,
+                pair.public_key().as_ref(),
+            )
+            .verify(message, signature)
+            .is_ok(),
+        }
+    }
+
+    /// the public JWK, HMAC keys have none
+    fn jwk(&self) -> Option<Value> {
+        let mut jwk = match &self.material {
+            Material::Hmac(_) => return None,
+            Material::Rsa { public, .. } => json!({
+                "kty": "RSA",
+                "n": URL_SAFE_NO_PAD.encode(&public.n),
+                "e": URL_SAFE_NO_PAD.encode(&public.e),
+            }),
+            Material::Ed25519(pair) => json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": URL_SAFE_NO_PAD
+                    .encode(pair.public_key().as_ref()),
+            }),
+        };
+        jwk["use"] = json!("sig");
+        jwk["alg"] = json!(self.algorithm().as_str());
+        if let Some(kid) = &self.kid {
+            jwk["kid"] = json!(kid);
+        }
+        Some(jwk)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    typ: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kid: Option<String>,
+}
+
+pub struct JwtKeys {
+    signing: JwtKey,
+    verifying: Vec<JwtKey>,
+}
+
+impl JwtKeys {
+    pub fn new(signing: JwtKey) -> Self {
+        JwtKeys {
+            signing,
+            verifying: vec![],
+        }
+    }
+
+    /// keep accepting tokens signed by a key that no longer
+    /// signs
+    pub fn verify_also(mut self, key: JwtKey) -> Self {
+        self.verifying.push(key);
+        self
+    }
+
+    /// `JWT_SALT` as an HMAC secret, tokens signed with it have
+    /// no `kid`, like the ones signed before keys could be
+    /// configured
+    pub fn from_env() -> Result<Self, KeyError> {
+        let salt = std::env::var("JWT_SALT")
+            .map_err(|_| KeyError::EnvNotSet)?;
+        let mut key = JwtKey::hmac("", salt.as_bytes());
+        key.kid = None;
+        Ok(JwtKeys::new(key))
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &JwtKey> {
+        std::iter::once(&self.signing).chain(&self.verifying)
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> String {
+        let header = Header {
+            alg: self.signing.algorithm().as_str().to_string(),
+            typ: Some("JWT".to_string()),
+            kid: self.signing.kid.clone(),
+        };
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD
+                .encode(serde_json::to_vec(&header).unwrap()),
+            URL_SAFE_NO_PAD
+                .encode(serde_json::to_vec(claims).unwrap()),
+        );
+        let signature = self.signing.sign(message.as_bytes());
+        format!(
+            "{message}.{}",
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// the claims of a token one of the keys signed, the
+    /// expiry is not checked here
+    pub fn verify<T: DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Option<T> {
+        let (message, signature) = token.rsplit_once('.')?;
+        let (header, claims) = message.split_once('.')?;
+
+        let header: Header = serde_json::from_slice(
+            &URL_SAFE_NO_PAD.decode(header).ok()?,
+        )
+        .ok()?;
+        let signature =
+            URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        // the alg has to match the key, or a public key could
+        // be used as an HMAC secret
+        let verified = self
+            .keys()
+            .filter(|e| e.algorithm().as_str() == header.alg)
+            .filter(|e| {
+                header.kid.is_none() || e.kid == header.kid
+            })
+            .any(|e| e.verify(message.as_bytes(), &signature));
+        if !verified {
+            return None;
+        }
+
+        serde_json::from_slice(
+            &URL_SAFE_NO_PAD.decode(claims).ok()?,
+        )
+        .ok()
+    }
+
+    /// the public keys, for services that verify the tokens
+    /// themselves
+    pub fn jwks(&self) -> Value {
+        let keys: Vec<Value> =
+            self.keys().filter_map(JwtKey::jwk).collect();
+        json!({ "keys": keys })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn ed25519(kid: &str) -> JwtKey {
+        JwtKey::ed25519_pem(
+            kid,
+            include_str!("testdata/ed25519.pem"),
+        )
+        .unwrap()
+    }
+
+    fn rsa(kid: &str) -> JwtKey {
+        JwtKey::rsa_pem(kid, include_str!("testdata/rsa.pem"))
+            .unwrap()
+    }
+
+    #[test]
+    fn sign_verify_and_rotate() {
+        let claims = json!({"id": "1", "exp": 0});
+
+        for key in [
+            JwtKey::hmac("h1", b"secret"),
+            ed25519("e1"),
+            rsa("r1"),
+        ] {
+            let alg = key.algorithm();
+            let keys = JwtKeys::new(key);
+            let token = keys.sign(&claims);
+            assert_eq!(
+                keys.verify::<Value>(&token),
+                Some(claims.clone()),
+                "{alg:?}"
+            );
+
+            let mut tampered = token.clone();
+            tampered.insert(token.find('.').unwrap() + 1, 'e');
+            assert_eq!(keys.verify::<Value>(&tampered), None);
+        }
+
+        let old = JwtKeys::new(ed25519("old"));
+        let token = old.sign(&claims);
+
+        let rotated =
+            JwtKeys::new(rsa("new")).verify_also(ed25519("old"));
+        assert_eq!(
+            rotated.verify::<Value>(&token),
+            Some(claims.clone()),
+            "the old key still verifies"
+        );
+        assert!(rotated
+            .verify::<Value>(&rotated.sign(&claims))
+            .is_some());
+        assert_eq!(
+            JwtKeys::new(rsa("new")).verify::<Value>(&token),
+            None,
+            "the old key was dropped"
+        );
+    }
+
+    #[test]
+    fn jwks_has_only_public_keys() {
+        let keys = JwtKeys::new(rsa("r1"))
+            .verify_also(ed25519("e1"))
+            .verify_also(JwtKey::hmac("h1", b"secret"));
+
+        let jwks = keys.jwks();
+        let keys = jwks["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0]["kid"], "r1");
+        assert_eq!(keys[0]["kty"], "RSA");
+        assert_eq!(keys[0]["alg"], "RS256");
+        assert_eq!(keys[0]["e"], "AQAB");
+        assert_eq!(keys[1]["kid"], "e1");
+        assert_eq!(keys[1]["crv"], "Ed25519");
+        assert!(keys.iter().all(|e| e.get("d").is_none()));
+    }
+}
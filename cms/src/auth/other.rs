@@ -1,12 +1,16 @@
 use std::fmt;
 
 use super::email_password::EmailPassword;
-use super::ijwt::IClaims;
+use super::ijwt::{IClaims, Principal};
+use super::keys::{JwtKeys, Keys};
+use super::password::{self, Verify};
+use super::session;
 use axum::{
     body::Body,
     extract::{Request, State},
     http::{
-        header::AUTHORIZATION, HeaderMap, HeaderValue, Response,
+        header::AUTHORIZATION, HeaderMap, Response,
+        StatusCode,
     },
     response::IntoResponse,
     Extension, Json,
@@ -15,18 +19,62 @@ use jwt::ToBase64;
 use serde::Deserialize;
 use serde_json::json;
 use sqlx::{
-    database::HasArguments, ColumnIndex, Database, Decode,
-    Executor, IntoArguments, Pool, Sqlite,
+    database::HasArguments, ColumnIndex, Database, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
 };
 
-use crate::{auth::ijwt, error::ClientError};
+use crate::{
+    auth::ijwt,
+    backend::Backend,
+    error::{self, ClientError},
+};
+
+/// `keys` sign the init token of the first super user
+pub async fn init_auth<S>(
+    db: Pool<S>,
+    keys: &JwtKeys,
+) -> Result<(), String>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments: IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    crate::permissions::init_permissions(&db)
+        .await
+        .map_err(|e| e.to_string())?;
 
-pub async fn init_auth(db: Pool<Sqlite>) -> Result<(), String> {
-    std::env::var("JWT_SALT").map_err(|_| "JWT_SALT not set")?;
+    super::users::init_users(&db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    super::session::init_sessions(&db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    crate::api_keys::init_api_keys(&db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    super::totp::init_totp(&db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    super::invitations::init_invitations(&db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    crate::audit::init_audit(&db)
+        .await
+        .map_err(|e| e.to_string())?;
 
     if let Some(init_token) =
-        create_super_user_if_not_exist_and_return_init_token(db)
-            .await
+        create_super_user_if_not_exist_and_return_init_token(
+            db, keys,
+        )
+        .await
     {
         let be = "http://localhost:3000";
         let fe = "http://localhost:5173";
@@ -48,18 +96,24 @@ pub async fn init_auth(db: Pool<Sqlite>) -> Result<(), String> {
     Ok(())
 }
 
-pub fn migration_st<S: Database>() -> &'static str {
-    r#"
+pub fn migration_st<S: Backend>() -> String {
+    format!(
+        "
     CREATE TABLE IF NOT EXISTS _super_users (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        id {},
         user_name TEXT,
         email TEXT,
         password TEXT
     );
-    "#
+    ",
+        S::id_column()
+    )
 }
 
-pub async fn cold_start_no_super_users<DB: Database, E>(exec: &E)
+pub async fn cold_start_no_super_users<DB: Database, E>(
+    exec: &E,
+    keys: &JwtKeys,
+)
 where
     DB: Database,
     i32: sqlx::Type<DB> + for<'r> Decode<'r, DB>,
@@ -92,7 +146,7 @@ where
             }
         };
 
-        let token = ijwt::sign_for(&id.0.to_string(), dur);
+        let token = ijwt::sign_for(keys, &id.0.to_string(), dur);
 
         println!("Super user token: {}", token);
     }
@@ -106,15 +160,26 @@ impl<T: fmt::Debug> From<T> for AuthError {
     }
 }
 
-pub async fn create_super_user_if_not_exist_and_return_init_token(
-    p: Pool<Sqlite>,
-) -> Option<String> {
-    sqlx::query(migration_st::<Sqlite>())
+pub async fn create_super_user_if_not_exist_and_return_init_token<
+    S,
+>(
+    p: Pool<S>,
+    keys: &JwtKeys,
+) -> Option<String>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments: IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(&migration_st::<S>())
         .execute(&p)
         .await
         .unwrap();
 
-    let count: (i32,) = sqlx::query_as(
+    let count: (i64,) = sqlx::query_as(
         "
 SELECT Count(*) FROM _super_users;
         ",
@@ -126,14 +191,14 @@ SELECT Count(*) FROM _super_users;
     if count.0 == 0 {
         sqlx::query(
             "
-INSERT INTO _super_users (user_name) VALUES (\"super_admin\");
+INSERT INTO _super_users (user_name) VALUES ('super_admin');
 ",
         )
         .execute(&p)
         .await
         .unwrap();
 
-        let id: (Option<i32>,) =
+        let id: (Option<i64>,) =
             sqlx::query_as("SELECT id FROM _super_users;")
                 .fetch_one(&p)
                 .await
@@ -141,7 +206,18 @@ INSERT INTO _super_users (user_name) VALUES (\"super_admin\");
 
         let id = id.0.unwrap();
 
+        // the first super user is the admin
+        crate::permissions::init_permissions(&p).await.unwrap();
+        crate::permissions::assign_role(
+            &p,
+            id,
+            crate::permissions::ADMIN,
+        )
+        .await
+        .unwrap();
+
         let token = ijwt::sign_and(
+            keys,
             &id.to_string(),
             chrono::Duration::minutes(30),
             vec![(
@@ -180,12 +256,29 @@ pub async fn can_init(
     Ok(next.run(req).await)
 }
 
-#[axum::debug_handler]
-pub async fn sign_in_existing(
+pub async fn sign_in_existing<S>(
     user: Extension<IClaims>, // authenticated
-    db: State<Pool<Sqlite>>,
+    db: State<Pool<S>>,
     body: Json<SetupFirstUser>,
-) -> Result<Response<Body>, ()> {
+) -> Result<Response<Body>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments: IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let id: i64 =
+        user.0.id.parse().map_err(|_| "id is not a number")?;
+
+    let password = body.0.email_password.password;
+    let password = tokio::task::spawn_blocking(move || {
+        password::hash(&password)
+    })
+    .await
+    .map_err(error::server_error)?;
+
     sqlx::query(
         "
     UPDATE _super_users SET 
@@ -197,109 +290,160 @@ pub async fn sign_in_existing(
     )
     .bind(body.0.user_name)
     .bind(body.0.email_password.email)
-    .bind(body.0.email_password.password)
-    .bind(user.0.id)
+    .bind(password)
+    .bind(id)
     .execute(&db.0)
     .await
-    .unwrap();
+    .map_err(|err| error::database(err, &[]))?;
 
     Ok(Json(json!({"data": null})).into_response())
 }
 
-#[axum::debug_handler]
-pub async fn login(
-    db: State<Pool<Sqlite>>,
-    mut may_extend: Extension<MayExtend>,
-    // safe: password was already hashed
-    basic_safe: EmailPassword,
-) -> Result<(), ()> {
-    let id: (i32,) = sqlx::query_as(
-        "
-SELECT id FROM _super_users WHERE passsword = $1 AND email = $2",
+pub async fn login<S>(
+    db: State<Pool<S>>,
+    keys: Keys,
+    basic: EmailPassword,
+) -> Result<Response<Body>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments: IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let found: Option<(i64, Option<String>)> = sqlx::query_as(
+        "SELECT id, password FROM _super_users WHERE email = $1",
     )
-    .bind(basic_safe.password)
-    .bind(basic_safe.email)
-    .fetch_one(&db.0)
+    .bind(basic.email)
+    .fetch_optional(&db.0)
     .await
-    .unwrap();
-
-    may_extend.0.extend_for(&id.0.to_string());
+    .map_err(|err| error::database(err, &[]))?;
+
+    let (id, stored) = match found {
+        Some((id, stored)) => (Some(id), stored),
+        None => (None, None),
+    };
+
+    let password = basic.password;
+    let (verified, password) =
+        tokio::task::spawn_blocking(move || {
+            let verified =
+                password::verify(&password, stored.as_deref());
+            (verified, password)
+        })
+        .await
+        .map_err(error::server_error)?;
+
+    let id = match (id, &verified) {
+        (Some(id), Verify::Valid | Verify::NeedsRehash) => id,
+        _ => {
+            return Err(ClientError {
+                status_code: StatusCode::UNAUTHORIZED,
+                dev_hint: "email or password is incorrect"
+                    .to_string(),
+                user_error: None,
+            }
+            .add_user_error(
+                "wrong_credentials",
+                "email or password is incorrect",
+                |_| {},
+            ))
+        }
+    };
+
+    if verified == Verify::NeedsRehash {
+        let rehashed = tokio::task::spawn_blocking(move || {
+            password::hash(&password)
+        })
+        .await
+        .map_err(error::server_error)?;
 
-    Ok(())
-}
+        sqlx::query(
+            "
+    UPDATE _super_users SET password = $1 WHERE id = $2;
+    ",
+        )
+        .bind(rehashed)
+        .bind(id)
+        .execute(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+    }
 
-#[derive(Clone)]
-pub struct MayExtend(Option<String>);
-impl MayExtend {
-    pub fn extend_for(&mut self, id: &str) {
-        self.0 = Some(id.to_string());
+    if let Some(challenge) =
+        super::totp::login_challenge(&db.0, id).await?
+    {
+        return Ok(challenge);
     }
+
+    let tokens = session::start(
+        &db.0,
+        &keys,
+        Principal::SuperUser,
+        &id.to_string(),
+    )
+    .await?;
+
+    Ok(tokens.into_response())
 }
 
-#[axum::debug_middleware]
-pub async fn extend_for_authinticated(
-    mut req: Request<Body>,
-    next: axum::middleware::Next,
-) -> Result<Response<Body>, String> {
-    let may_extend = MayExtend(None);
-    req.extensions_mut().insert(may_extend);
+/// claims of the Bearer token, `None` when the request has
+/// no authorization header
+pub fn bearer_claims(
+    keys: &JwtKeys,
+    headers: &HeaderMap,
+) -> Result<Option<IClaims>, ClientError> {
+    let Some(bearer) = headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    let bearer = bearer
+        .to_str()
+        .map_err(|_| "authorization is not a string")?;
 
-    let mut res = next.run(req).await;
+    let bearer = bearer
+        .strip_prefix("Bearer ")
+        .ok_or("authorization should start with Bearer")?;
 
-    let may_extend =
-        res.extensions().get::<MayExtend>().unwrap();
-    if let Some(id) = &may_extend.0 {
-        let token =
-            ijwt::sign_for(id, chrono::Duration::days(1));
+    let claims = ijwt::verify_exp(keys, bearer)?;
 
-        res.headers_mut().append(
-            "X-token",
-            HeaderValue::from_str(&token).unwrap(),
-        );
+    if session::is_revoked(&claims) {
+        Err(ijwt::TokenError::TokenRevoked)?;
     }
 
-    Ok(res)
+    Ok(Some(claims))
 }
 
-/// check if Bearer token is valid and
-/// extend it for one more day
+/// check if Bearer token is valid and issued to a super user
 #[axum::debug_middleware]
 pub async fn need_super_user(
+    keys: Keys,
     headers: HeaderMap,
     mut req: Request<Body>,
     next: axum::middleware::Next,
 ) -> Result<Response<Body>, ClientError> {
-    let bearer = headers
-        .get(AUTHORIZATION)
-        .ok_or("authorization not found")?
-        .to_str()
-        .unwrap();
-
-    let bearer = bearer
-        .strip_prefix("Bearer ")
-        .ok_or("authorization should start with Bearer")?;
-
-    let map = ijwt::verify_exp(bearer)?;
-
-    let id = map.id.clone();
+    let map = bearer_claims(&keys, &headers)?.ok_or((
+        StatusCode::UNAUTHORIZED,
+        "authorization not found",
+    ))?;
+
+    if map.principal != Principal::SuperUser {
+        return Err(ClientError {
+            status_code: StatusCode::FORBIDDEN,
+            dev_hint: "only super users can do this".to_string(),
+            user_error: None,
+        }
+        .add_user_error(
+            "forbidden",
+            "you don't have permission to do this",
+            |_| {},
+        ));
+    }
 
     req.extensions_mut().insert(map);
 
-    let mut res = next.run(req).await;
-
-    // extend for authiticated users
-    let token = ijwt::sign_for(
-        &id.to_string(),
-        chrono::Duration::days(1),
-    );
-
-    // I don't want to use cookies for potentioal misuse
-    res.headers_mut().append(
-        "X-Cms-Token",
-        HeaderValue::from_str(&token).unwrap(),
-    );
-
-    Ok(res)
+    Ok(next.run(req).await)
 }
 //
 // #[derive(Serialize, Deserialize)]
@@ -0,0 +1,165 @@
+//! super user passwords are stored as PHC strings:
+//! `$pbkdf2-sha256$i=<iterations>$<salt>$<hash>`, salt and hash
+//! are base64 without padding.
+//!
+//! iterations are stored next to the hash so they can be raised
+//! over time, a password hashed with less than `ITERATIONS` is
+//! still valid but should be rehashed (see `Verify::NeedsRehash`)
+
+use std::num::NonZeroU32;
+
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use ring::{
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+
+const ID: &str = "pbkdf2-sha256";
+static ALG: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+
+/// OWASP's recommendation for PBKDF2-HMAC-SHA256
+pub const ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = ring::digest::SHA256_OUTPUT_LEN;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verify {
+    Invalid,
+    Valid,
+    /// valid, but hashed with outdated parameters
+    NeedsRehash,
+}
+
+/// slow by design, call it from `spawn_blocking`
+pub fn hash(password: &str) -> String {
+    hash_with(password, ITERATIONS)
+}
+
+fn hash_with(password: &str, iterations: u32) -> String {
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .expect("system random is not available");
+
+    let mut hash = [0u8; HASH_LEN];
+    pbkdf2::derive(
+        ALG,
+        NonZeroU32::new(iterations).expect("iterations is zero"),
+        &salt,
+        password.as_bytes(),
+        &mut hash,
+    );
+
+    format!(
+        "${ID}$i={iterations}${}${}",
+        STANDARD_NO_PAD.encode(salt),
+        STANDARD_NO_PAD.encode(hash),
+    )
+}
+
+struct Parsed {
+    iterations: NonZeroU32,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+fn parse(stored: &str) -> Option<Parsed> {
+    let mut parts = stored.split('$');
+
+    if !parts.next()?.is_empty() || parts.next()? != ID {
+        return None;
+    }
+
+    let iterations =
+        parts.next()?.strip_prefix("i=")?.parse().ok()?;
+    let salt = STANDARD_NO_PAD.decode(parts.next()?).ok()?;
+    let hash = STANDARD_NO_PAD.decode(parts.next()?).ok()?;
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(Parsed {
+        iterations: NonZeroU32::new(iterations)?,
+        salt,
+        hash,
+    })
+}
+
+/// `stored` is `None` when there is no such user (or the user
+/// has no password yet), the same work is done in that case so
+/// response time doesn't tell whether the user exists.
+///
+/// the comparison is constant time (`ring::pbkdf2::verify`)
+///
+/// slow by design, call it from `spawn_blocking`
+pub fn verify(password: &str, stored: Option<&str>) -> Verify {
+    let Some(parsed) = stored.and_then(parse) else {
+        let _ = pbkdf2::verify(
+            ALG,
+            NonZeroU32::new(ITERATIONS).unwrap(),
+            &[0; SALT_LEN],
+            password.as_bytes(),
+            &[0; HASH_LEN],
+        );
+        return Verify::Invalid;
+    };
+
+    let res = pbkdf2::verify(
+        ALG,
+        parsed.iterations,
+        &parsed.salt,
+        password.as_bytes(),
+        &parsed.hash,
+    );
+
+    match res {
+        Err(_) => Verify::Invalid,
+        Ok(()) if parsed.iterations.get() < ITERATIONS => {
+            Verify::NeedsRehash
+        }
+        Ok(()) => Verify::Valid,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_and_verify() {
+        let stored = hash("secret");
+
+        assert!(stored.starts_with("$pbkdf2-sha256$i=600000$"));
+        assert_ne!(stored, hash("secret"), "salt is per hash");
+
+        assert_eq!(
+            verify("secret", Some(&stored)),
+            Verify::Valid
+        );
+        assert_eq!(
+            verify("Secret", Some(&stored)),
+            Verify::Invalid
+        );
+        assert_eq!(verify("secret", None), Verify::Invalid);
+        assert_eq!(
+            verify("secret", Some("secret")),
+            Verify::Invalid,
+            "plaintext is never accepted"
+        );
+    }
+
+    #[test]
+    fn outdated_iterations_need_rehash() {
+        let stored = hash_with("secret", 1_000);
+
+        assert_eq!(
+            verify("secret", Some(&stored)),
+            Verify::NeedsRehash
+        );
+        assert_eq!(
+            verify("other", Some(&stored)),
+            Verify::Invalid
+        );
+    }
+}
@@ -0,0 +1,665 @@
+//! throttles the routes that take credentials, so passwords and
+//! init tokens can't be brute forced.
+//!
+//! failed attempts (responses with a 4xx status) are counted
+//! per client ip and per account (the email of the Basic token,
+//! super users and end users are counted apart). after a few
+//! free attempts, each failure locks the ip or account out for
+//! twice as long as the one before, up to a maximum. a
+//! successful login clears the count of the account. server
+//! errors are not the client's fault and are not counted.
+//!
+//! an attempt is counted before it runs and taken back when it
+//! didn't fail, so attempts made in parallel can't all get past
+//! the lockout.
+//!
+//! the ip is taken from `ConnectInfo<SocketAddr>`, serve the
+//! app with `into_make_service_with_connect_info` to enable the
+//! per ip limit, or trust `X-Forwarded-For` behind proxies.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, LazyLock, Mutex, RwLock},
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        HeaderMap, HeaderValue, Response, StatusCode,
+    },
+    middleware::Next,
+    response::IntoResponse,
+};
+use chrono::{TimeDelta, Utc};
+use jwt::FromBase64;
+
+use super::{email_password::EmailPassword, Principal};
+use crate::error::ClientError;
+
+static LIMIT: LazyLock<RwLock<Arc<RateLimit>>> =
+    LazyLock::new(Default::default);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Attempts {
+    pub failures: u32,
+    pub last_failure: i64,
+    pub locked_until: i64,
+}
+
+/// where attempts are counted, implement it to share the
+/// counts between instances of the app. `reserve` and `release`
+/// have to be atomic, they are called concurrently for the same
+/// key
+pub trait AttemptStore: Send + Sync + 'static {
+    fn get(&self, key: &str) -> Option<Attempts>;
+    /// counts an attempt as failed before it is made, with
+    /// `Policy::fail`. when the key is locked the attempt is not
+    /// counted and `Err` has the time it is locked until
+    fn reserve(
+        &self,
+        key: &str,
+        policy: &Policy,
+        now: i64,
+    ) -> Result<(), i64>;
+    /// takes back a reserved attempt that didn't fail, with
+    /// `Policy::release`
+    fn release(&self, key: &str, policy: &Policy);
+    fn remove(&self, key: &str);
+}
+
+/// the default store, counts are lost on restart
+#[derive(Default)]
+pub struct MemoryStore {
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+impl AttemptStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<Attempts> {
+        self.attempts.lock().unwrap().get(key).copied()
+    }
+
+    fn reserve(
+        &self,
+        key: &str,
+        policy: &Policy,
+        now: i64,
+    ) -> Result<(), i64> {
+        let mut map = self.attempts.lock().unwrap();
+        if map.len() > 10_000 {
+            let day_ago =
+                (Utc::now() - TimeDelta::days(1)).timestamp();
+            map.retain(|_, e| e.last_failure > day_ago);
+        }
+        let prev = map.get(key).copied();
+        if let Some(prev) = prev {
+            if prev.locked_until > now {
+                return Err(prev.locked_until);
+            }
+        }
+        map.insert(key.to_string(), policy.fail(prev, now));
+        Ok(())
+    }
+
+    fn release(&self, key: &str, policy: &Policy) {
+        let mut map = self.attempts.lock().unwrap();
+        let Some(attempts) = map.get(key).copied() else {
+            return;
+        };
+        match policy.release(attempts) {
+            Some(attempts) => {
+                map.insert(key.to_string(), attempts);
+            }
+            None => {
+                map.remove(key);
+            }
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        self.attempts.lock().unwrap().remove(key);
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    /// failures before the first lockout
+    pub free_attempts: u32,
+    /// the first lockout, doubled on every failure after it
+    pub base_lockout: TimeDelta,
+    pub max_lockout: TimeDelta,
+    /// failures older than this are forgotten
+    pub forget_after: TimeDelta,
+}
+
+impl Policy {
+    pub const PER_ACCOUNT: Policy = Policy {
+        free_attempts: 5,
+        base_lockout: TimeDelta::seconds(1),
+        max_lockout: TimeDelta::minutes(15),
+        forget_after: TimeDelta::minutes(15),
+    };
+
+    /// many users can share an ip
+    pub const PER_IP: Policy = Policy {
+        free_attempts: 20,
+        ..Policy::PER_ACCOUNT
+    };
+
+    pub fn fail(
+        &self,
+        prev: Option<Attempts>,
+        now: i64,
+    ) -> Attempts {
+        let failures = match prev {
+            Some(prev)
+                if now - prev.last_failure
+                    < self.forget_after.num_seconds() =>
+            {
+                prev.failures + 1
+            }
+            _ => 1,
+        };
+
+        let locked_until =
+            match failures.checked_sub(self.free_attempts) {
+                Some(over) => {
+                    let lockout = self
+                        .base_lockout
+                        .num_seconds()
+                        .saturating_mul(1 << over.min(30))
+                        .min(self.max_lockout.num_seconds());
+                    now + lockout
+                }
+                None => 0,
+            };
+
+        Attempts {
+            failures,
+            last_failure: now,
+            locked_until,
+        }
+    }
+
+    /// undoes one `fail`, `None` when nothing is left to count.
+    /// the lockout is lifted once the free attempts are not all
+    /// used, a longer one from an earlier failure is kept
+    pub fn release(
+        &self,
+        attempts: Attempts,
+    ) -> Option<Attempts> {
+        let failures = attempts.failures.checked_sub(1)?;
+        if failures == 0 {
+            return None;
+        }
+        let locked_until = if failures < self.free_attempts {
+            0
+        } else {
+            attempts.locked_until
+        };
+        Some(Attempts {
+            failures,
+            locked_until,
+            ..attempts
+        })
+    }
+}
+
+pub struct RateLimit {
+    pub store: Box<dyn AttemptStore>,
+    pub per_ip: Policy,
+    pub per_account: Policy,
+    /// the proxies in front of the app that append to
+    /// `X-Forwarded-For`, the ip is the entry the outermost one
+    /// added. 0 ignores the header, only set it when every
+    /// request comes through those proxies
+    pub trusted_proxies: usize,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            store: Box::new(MemoryStore::default()),
+            per_ip: Policy::PER_IP,
+            per_account: Policy::PER_ACCOUNT,
+            trusted_proxies: 0,
+        }
+    }
+}
+
+/// replaces the in memory default
+pub fn set_rate_limit(limit: RateLimit) {
+    *LIMIT.write().unwrap() = Arc::new(limit);
+}
+
+fn client_ip(
+    req: &Request<Body>,
+    limit: &RateLimit,
+) -> Option<String> {
+    // entries on the left are whatever the client sent, only the
+    // ones appended by the trusted proxies can be relied on
+    if limit.trusted_proxies > 0 {
+        let forwarded = req
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|e| e.to_str().ok())
+            .and_then(|e| {
+                e.rsplit(',').nth(limit.trusted_proxies - 1)
+            })
+            .map(|e| e.trim().to_string())
+            .filter(|e| !e.is_empty());
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+    req.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|e| e.0.ip().to_string())
+}
+
+fn account(headers: &HeaderMap) -> Option<String> {
+    let basic = headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let basic = EmailPassword::from_base64(basic).ok()?;
+    Some(basic.email.trim().to_lowercase())
+}
+
+fn rate_limited(retry_after: i64) -> Response<Body> {
+    let mut res = ClientError {
+        status_code: StatusCode::TOO_MANY_REQUESTS,
+        dev_hint: format!(
+            "too many failed attempts, retry in {retry_after}s"
+        ),
+        user_error: None,
+    }
+    .add_user_error(
+        "rate_limited",
+        "too many attempts, try again later",
+        |u| {
+            u.structured_hint = Some(HashMap::from([(
+                "retry_after".to_string(),
+                retry_after.to_string(),
+            )]))
+        },
+    )
+    .into_response();
+    res.headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+    res
+}
+
+/// put on routes where super users give credentials
+pub async fn limit_attempts(
+    req: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    limit(Principal::SuperUser, req, next).await
+}
+
+/// put on routes where end users give credentials
+pub async fn limit_user_attempts(
+    req: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    limit(Principal::User, req, next).await
+}
+
+async fn limit(
+    principal: Principal,
+    req: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    let limit = LIMIT.read().unwrap().clone();
+
+    // the same email can be a super user and an end user
+    let principal = match principal {
+        Principal::SuperUser => "super_user",
+        Principal::User => "user",
+    };
+    let keys: Vec<(String, Policy)> = [
+        client_ip(&req, &limit)
+            .map(|e| (format!("ip:{e}"), limit.per_ip)),
+        account(req.headers()).map(|e| {
+            (
+                format!("account:{principal}:{e}"),
+                limit.per_account,
+            )
+        }),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let now = Utc::now().timestamp();
+    for (i, (key, policy)) in keys.iter().enumerate() {
+        if let Err(locked_until) =
+            limit.store.reserve(key, policy, now)
+        {
+            for (key, policy) in &keys[..i] {
+                limit.store.release(key, policy);
+            }
+            return rate_limited(locked_until - now);
+        }
+    }
+
+    let res = next.run(req).await;
+
+    if res.status().is_client_error() {
+        return res;
+    }
+    let success = res.status().is_success();
+    for (key, policy) in &keys {
+        if success && key.starts_with("account:") {
+            limit.store.remove(key);
+        } else {
+            limit.store.release(key, policy);
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+        middleware::from_fn,
+        routing::post,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use jwt::ToBase64;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn lockout_doubles_and_is_forgotten() {
+        let policy = Policy::PER_ACCOUNT;
+        let mut attempts = None;
+        let mut lockouts = vec![];
+        for _ in 0..8 {
+            let next = policy.fail(attempts, 1000);
+            lockouts.push((next.locked_until - 1000).max(0));
+            attempts = Some(next);
+        }
+        assert_eq!(lockouts, [0, 0, 0, 0, 1, 2, 4, 8]);
+
+        let forgotten = policy.fail(attempts, 1000 + 15 * 60);
+        assert_eq!(forgotten.failures, 1);
+        assert_eq!(forgotten.locked_until, 0);
+
+        let capped = Attempts {
+            failures: 40,
+            ..attempts.unwrap()
+        };
+        assert_eq!(
+            policy.fail(Some(capped), 1000).locked_until,
+            1000 + 15 * 60
+        );
+    }
+
+    fn login(
+        ip: [u8; 4],
+        email: &str,
+        password: &str,
+    ) -> Request<Body> {
+        login_at("/login", ip, email, password)
+    }
+
+    fn login_at(
+        uri: &str,
+        ip: [u8; 4],
+        email: &str,
+        password: &str,
+    ) -> Request<Body> {
+        let basic =
+            json!({"email": email, "password": password})
+                .to_base64()
+                .unwrap()
+                .to_string();
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header("authorization", format!("Basic {basic}"))
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 4000))));
+        req
+    }
+
+    #[tokio::test]
+    async fn failed_logins_are_rate_limited() {
+        let app = Router::new()
+            .route(
+                "/login",
+                post(|basic: EmailPassword| async move {
+                    if basic.password == "right" {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::UNAUTHORIZED
+                    }
+                }),
+            )
+            .layer(from_fn(limit_attempts));
+
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+
+        // per account, across ips
+        for i in 0..5 {
+            let res = send(login(
+                [10, 0, 0, i],
+                "victim@example.com",
+                "guess",
+            ))
+            .await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+        let res = send(login(
+            [10, 0, 0, 9],
+            "victim@example.com",
+            "guess",
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().contains_key(RETRY_AFTER));
+        let body = res.into_body().collect().await.unwrap();
+        let body: Value =
+            serde_json::from_slice(&body.to_bytes()).unwrap();
+        assert_eq!(
+            body["error"]["user_error"]["code"],
+            "rate_limited"
+        );
+        assert!(body["error"]["user_error"]["structured_hint"]
+            ["retry_after"]
+            .is_string());
+
+        // per ip, across accounts
+        for i in 0..20 {
+            let res = send(login(
+                [10, 0, 1, 1],
+                &format!("spray{i}@example.com"),
+                "guess",
+            ))
+            .await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+        let res = send(login(
+            [10, 0, 1, 1],
+            "ok@example.com",
+            "right",
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // a success clears the account
+        for _ in 0..3 {
+            send(login(
+                [10, 0, 2, 1],
+                "ok@example.com",
+                "wrong",
+            ))
+            .await;
+        }
+        let res = send(login(
+            [10, 0, 2, 2],
+            "ok@example.com",
+            "right",
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let limit = LIMIT.read().unwrap().clone();
+        assert_eq!(
+            limit
+                .store
+                .get("account:super_user:ok@example.com"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn parallel_attempts_are_counted_before_they_run() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+        let app = Router::new()
+            .route(
+                "/login",
+                post(|| async {
+                    RUNS.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(
+                        std::time::Duration::from_millis(50),
+                    )
+                    .await;
+                    StatusCode::UNAUTHORIZED
+                }),
+            )
+            .layer(from_fn(limit_attempts));
+
+        let sent = (0..20).map(|i| {
+            app.clone().oneshot(login(
+                [10, 0, 4, i],
+                "parallel@example.com",
+                "guess",
+            ))
+        });
+        let statuses: Vec<StatusCode> =
+            futures_util::future::join_all(sent)
+                .await
+                .into_iter()
+                .map(|e| e.unwrap().status())
+                .collect();
+
+        // only the free attempts get to the handler
+        assert_eq!(RUNS.load(Ordering::SeqCst), 5);
+        assert_eq!(
+            statuses
+                .iter()
+                .filter(|e| **e == StatusCode::TOO_MANY_REQUESTS)
+                .count(),
+            15
+        );
+
+        // and a success takes back what the ip reserved
+        let limit = LIMIT.read().unwrap().clone();
+        let app = Router::new()
+            .route("/login", post(|| async { StatusCode::OK }))
+            .layer(from_fn(limit_attempts));
+        app.oneshot(login(
+            [10, 0, 4, 100],
+            "fine@example.com",
+            "right",
+        ))
+        .await
+        .unwrap();
+        assert_eq!(limit.store.get("ip:10.0.4.100"), None);
+    }
+
+    #[test]
+    fn forwarded_for_is_read_from_the_trusted_proxies() {
+        let req = |forwarded: &str| {
+            Request::builder()
+                .header("x-forwarded-for", forwarded)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let limit = |trusted_proxies| RateLimit {
+            trusted_proxies,
+            ..Default::default()
+        };
+
+        // the client can put anything on the left
+        assert_eq!(
+            client_ip(&req("6.6.6.6, 1.2.3.4"), &limit(1)),
+            Some("1.2.3.4".to_string())
+        );
+        assert_eq!(
+            client_ip(
+                &req("6.6.6.6, 1.2.3.4, 10.0.0.1"),
+                &limit(2)
+            ),
+            Some("1.2.3.4".to_string())
+        );
+        assert_eq!(client_ip(&req("1.2.3.4"), &limit(0)), None);
+        assert_eq!(client_ip(&req("1.2.3.4"), &limit(2)), None);
+    }
+
+    #[tokio::test]
+    async fn every_client_error_counts_per_principal() {
+        let app = Router::new()
+            .route(
+                "/login",
+                post(|| async { StatusCode::BAD_REQUEST })
+                    .layer(from_fn(limit_attempts)),
+            )
+            .route(
+                "/users/login",
+                post(|| async { StatusCode::OK })
+                    .layer(from_fn(limit_user_attempts)),
+            );
+
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+
+        for i in 0..5 {
+            let res = send(login(
+                [10, 0, 3, i],
+                "both@example.com",
+                "guess",
+            ))
+            .await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+        let res = send(login(
+            [10, 0, 3, 9],
+            "both@example.com",
+            "guess",
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // the end user with the same email is not locked out
+        let res = send(login_at(
+            "/users/login",
+            [10, 0, 3, 10],
+            "both@example.com",
+            "right",
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
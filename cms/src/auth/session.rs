@@ -0,0 +1,547 @@
+//! access tokens are short lived JWTs, a login starts a session
+//! that hands out a refresh token next to them. every refresh
+//! rotates the refresh token, presenting one that was already
+//! used means it leaked, and the whole session is revoked.
+//!
+//! refresh tokens are stored hashed in `_cms_refresh_tokens`.
+//! revoked token ids (`jti`) and session ids (`sid`) are kept
+//! in `_cms_revoked_tokens` until the tokens they match have
+//! expired anyway, and in a `RevocationStore` so `bearer_claims`
+//! can check them without a query.
+//!
+//! the default store is in memory: an instance of the app only
+//! sees its own revocations and the ones in the database when
+//! it started. when running more than one instance, share them
+//! with `set_revocation_store`, or a logged out or leaked token
+//! keeps working on the other instances until it expires.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
+
+use super::{
+    ijwt::{self, IClaims, Principal},
+    keys::{JwtKeys, Keys},
+    other::bearer_claims,
+};
+use crate::{
+    backend::Backend,
+    error::{self, ClientError},
+};
+
+pub const ACCESS_TTL: chrono::TimeDelta =
+    chrono::TimeDelta::minutes(15);
+pub const REFRESH_TTL: chrono::TimeDelta =
+    chrono::TimeDelta::days(30);
+
+static REVOKED: LazyLock<RwLock<Arc<dyn RevocationStore>>> =
+    LazyLock::new(|| {
+        RwLock::new(Arc::new(MemoryRevocations::default()))
+    });
+
+/// where revoked jti and sid are looked up on every request,
+/// implement it to share revocations between instances of the
+/// app
+pub trait RevocationStore: Send + Sync + 'static {
+    fn is_revoked(&self, id: &str) -> bool;
+    /// the id can be forgotten after `expires_at`, the tokens
+    /// it matches have expired by then
+    fn revoke(&self, id: &str, expires_at: i64);
+}
+
+/// the default store, revocations made by other instances are
+/// not seen until restart
+#[derive(Default)]
+pub struct MemoryRevocations {
+    revoked: RwLock<HashMap<String, i64>>,
+}
+
+impl RevocationStore for MemoryRevocations {
+    fn is_revoked(&self, id: &str) -> bool {
+        self.revoked.read().unwrap().contains_key(id)
+    }
+
+    fn revoke(&self, id: &str, expires_at: i64) {
+        let mut map = self.revoked.write().unwrap();
+        if map.len() > 10_000 {
+            let now = Utc::now().timestamp();
+            map.retain(|_, e| *e >= now);
+        }
+        map.insert(id.to_string(), expires_at);
+    }
+}
+
+/// replaces the in memory default, set it before
+/// `init_sessions` so the revocations in the database are
+/// loaded into it
+pub fn set_revocation_store(store: impl RevocationStore) {
+    *REVOKED.write().unwrap() = Arc::new(store);
+}
+
+pub(crate) fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random is not available");
+    bytes
+}
+
+/// 32 random bytes, url safe
+pub(crate) fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(random_bytes::<32>())
+}
+
+/// what is stored instead of single use tokens
+pub(crate) fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest::digest(
+        &digest::SHA256,
+        token.as_bytes(),
+    ))
+}
+
+pub fn is_revoked(claims: &IClaims) -> bool {
+    let revoked = REVOKED.read().unwrap().clone();
+    [&claims.jti, &claims.sid]
+        .into_iter()
+        .flatten()
+        .any(|id| revoked.is_revoked(id))
+}
+
+fn principal_str(principal: Principal) -> &'static str {
+    match principal {
+        Principal::SuperUser => "super_user",
+        Principal::User => "user",
+    }
+}
+
+fn parse_principal(s: &str) -> Option<Principal> {
+    match s {
+        "super_user" => Some(Principal::SuperUser),
+        "user" => Some(Principal::User),
+        _ => None,
+    }
+}
+
+pub fn migration_st<S: Backend>() -> [String; 2] {
+    [
+        format!(
+            "
+    CREATE TABLE IF NOT EXISTS _cms_refresh_tokens (
+        token TEXT PRIMARY KEY,
+        session TEXT NOT NULL,
+        principal TEXT NOT NULL,
+        subject TEXT NOT NULL,
+        used {i64} NOT NULL DEFAULT 0,
+        expires_at {i64} NOT NULL
+    );
+    ",
+            i64 = S::i64_column(),
+        ),
+        format!(
+            "
+    CREATE TABLE IF NOT EXISTS _cms_revoked_tokens (
+        id TEXT PRIMARY KEY,
+        expires_at {i64} NOT NULL
+    );
+    ",
+            i64 = S::i64_column(),
+        ),
+    ]
+}
+
+/// create the tables, forget what expired and load what is
+/// revoked
+pub async fn init_sessions<S>(
+    db: &Pool<S>,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'d> Decode<'d, S>,
+{
+    for st in migration_st::<S>() {
+        sqlx::query(&st).execute(db).await?;
+    }
+
+    let now = Utc::now().timestamp();
+    for st in [
+        "DELETE FROM _cms_refresh_tokens WHERE expires_at < $1;",
+        "DELETE FROM _cms_revoked_tokens WHERE expires_at < $1;",
+    ] {
+        sqlx::query(st).bind(now).execute(db).await?;
+    }
+
+    let revoked: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT id, expires_at FROM _cms_revoked_tokens;",
+    )
+    .fetch_all(db)
+    .await?;
+
+    cache_revoked(revoked);
+
+    Ok(())
+}
+
+/// an access token and the refresh token of its session, the
+/// access token is sent in the `X-Cms-Token` header like
+/// before, the refresh token in the body
+pub struct Tokens {
+    pub access: String,
+    pub refresh: String,
+}
+
+impl Tokens {
+    /// `data` with the refresh token added to it
+    pub fn respond_with(
+        self,
+        mut data: serde_json::Map<String, Value>,
+    ) -> axum::response::Response {
+        data.insert(
+            "refresh_token".to_string(),
+            Value::String(self.refresh),
+        );
+        let mut res =
+            Json(json!({ "data": data })).into_response();
+        res.headers_mut().append(
+            "X-Cms-Token",
+            HeaderValue::from_str(&self.access).unwrap(),
+        );
+        res
+    }
+}
+
+impl IntoResponse for Tokens {
+    fn into_response(self) -> axum::response::Response {
+        self.respond_with(Default::default())
+    }
+}
+
+async fn issue<S>(
+    db: &Pool<S>,
+    keys: &JwtKeys,
+    session: &str,
+    principal: Principal,
+    subject: &str,
+) -> Result<Tokens, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    let refresh = random_token();
+
+    sqlx::query(
+        "
+    INSERT INTO _cms_refresh_tokens
+        (token, session, principal, subject, expires_at)
+    VALUES ($1, $2, $3, $4, $5);
+    ",
+    )
+    .bind(hash_token(&refresh))
+    .bind(session.to_string())
+    .bind(principal_str(principal).to_string())
+    .bind(subject.to_string())
+    .bind((Utc::now() + REFRESH_TTL).timestamp())
+    .execute(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    let access = ijwt::sign_in_session(
+        keys,
+        principal,
+        subject,
+        Some(session),
+        ACCESS_TTL,
+    );
+
+    Ok(Tokens { access, refresh })
+}
+
+/// called after a successful login
+pub async fn start<S>(
+    db: &Pool<S>,
+    keys: &JwtKeys,
+    principal: Principal,
+    subject: &str,
+) -> Result<Tokens, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    issue(db, keys, &random_token(), principal, subject).await
+}
+
+async fn revoke<S>(
+    db: &Pool<S>,
+    id: &str,
+    expires_at: i64,
+) -> Result<(), ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(
+        "
+    INSERT INTO _cms_revoked_tokens (id, expires_at)
+    VALUES ($1, $2)
+    ON CONFLICT (id) DO NOTHING;
+    ",
+    )
+    .bind(id.to_string())
+    .bind(expires_at)
+    .execute(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    REVOKED.read().unwrap().clone().revoke(id, expires_at);
+
+    Ok(())
+}
+
+/// no more refreshes, and the access tokens issued in it are
+/// rejected until they expire
+pub async fn revoke_session<S>(
+    db: &Pool<S>,
+    session: &str,
+) -> Result<(), ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(
+        "DELETE FROM _cms_refresh_tokens WHERE session = $1;",
+    )
+    .bind(session.to_string())
+    .execute(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    revoke(db, session, (Utc::now() + ACCESS_TTL).timestamp())
+        .await
+}
+
+/// ends every session of the subject in a transaction, for when
+/// its credentials change. the revocations are only cached by
+/// `cache_revoked` once the transaction is committed
+pub(crate) async fn revoke_sessions_of<S>(
+    conn: &mut S::Connection,
+    principal: Principal,
+    subject: &str,
+) -> Result<Vec<(String, i64)>, sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let sessions: Vec<(String,)> = sqlx::query_as(
+        "
+    SELECT DISTINCT session FROM _cms_refresh_tokens
+    WHERE principal = $1 AND subject = $2;
+    ",
+    )
+    .bind(principal_str(principal).to_string())
+    .bind(subject.to_string())
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let expires_at = (Utc::now() + ACCESS_TTL).timestamp();
+    for (session,) in &sessions {
+        sqlx::query(
+            "
+    INSERT INTO _cms_revoked_tokens (id, expires_at)
+    VALUES ($1, $2)
+    ON CONFLICT (id) DO NOTHING;
+    ",
+        )
+        .bind(session.clone())
+        .bind(expires_at)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query(
+        "
+    DELETE FROM _cms_refresh_tokens
+    WHERE principal = $1 AND subject = $2;
+    ",
+    )
+    .bind(principal_str(principal).to_string())
+    .bind(subject.to_string())
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(sessions
+        .into_iter()
+        .map(|(session,)| (session, expires_at))
+        .collect())
+}
+
+pub(crate) fn cache_revoked(revoked: Vec<(String, i64)>) {
+    let store = REVOKED.read().unwrap().clone();
+    for (id, expires_at) in revoked {
+        store.revoke(&id, expires_at);
+    }
+}
+
+fn invalid_refresh(code: &str, dev_hint: &str) -> ClientError {
+    ClientError {
+        status_code: StatusCode::UNAUTHORIZED,
+        dev_hint: dev_hint.to_string(),
+        user_error: None,
+    }
+    .add_user_error(code, "session has ended", |_| {})
+}
+
+#[derive(Deserialize)]
+pub struct RefreshInput {
+    pub refresh_token: String,
+}
+
+pub async fn refresh<S>(
+    db: State<Pool<S>>,
+    keys: Keys,
+    input: Json<RefreshInput>,
+) -> Result<Tokens, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let hashed = hash_token(&input.refresh_token);
+
+    let found: Option<(String, String, String, i64)> =
+        sqlx::query_as(
+            "
+    SELECT session, principal, subject, expires_at
+    FROM _cms_refresh_tokens WHERE token = $1;
+    ",
+        )
+        .bind(hashed.clone())
+        .fetch_optional(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    let Some((session, principal, subject, expires_at)) = found
+    else {
+        return Err(invalid_refresh(
+            "invalid_token",
+            "refresh token does not exist or its session ended",
+        ));
+    };
+
+    if expires_at < Utc::now().timestamp() {
+        return Err(invalid_refresh(
+            "token_expired",
+            "refresh token has expired",
+        ));
+    }
+
+    // marking it used is what makes the rotation, a token that
+    // was used before (or concurrently) is reused
+    let marked: Option<(String,)> = sqlx::query_as(
+        "
+    UPDATE _cms_refresh_tokens SET used = 1
+    WHERE token = $1 AND used = 0
+    RETURNING session;
+    ",
+    )
+    .bind(hashed)
+    .fetch_optional(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    if marked.is_none() {
+        tracing::warn!("refresh token reused, revoking session");
+        revoke_session(&db.0, &session).await?;
+        return Err(invalid_refresh(
+            "refresh_token_reused",
+            "refresh token was already used, the session is revoked",
+        ));
+    }
+
+    let principal = parse_principal(&principal)
+        .ok_or("refresh token has an unknown principal")?;
+
+    issue(&db.0, &keys, &session, principal, &subject).await
+}
+
+/// revokes the access token and ends its session
+pub async fn logout<S>(
+    db: State<Pool<S>>,
+    keys: Keys,
+    headers: HeaderMap,
+) -> Result<Response<Body>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let claims = bearer_claims(&keys, &headers)?.ok_or((
+        StatusCode::UNAUTHORIZED,
+        "authorization not found",
+    ))?;
+
+    if let Some(jti) = &claims.jti {
+        revoke(&db.0, jti, claims.exp).await?;
+    }
+    if let Some(sid) = &claims.sid {
+        revoke_session(&db.0, sid).await?;
+    }
+
+    Ok(Json(json!({"data": null})).into_response())
+}
@@ -0,0 +1,1248 @@
+//! optional TOTP (RFC 6238) second factor for super users.
+//!
+//! a super user with TOTP enabled doesn't get tokens from
+//! `/auth/login`, they get a challenge that is exchanged for
+//! tokens at `/auth/totp/verify` with a code from their
+//! authenticator app or one of their recovery codes. admins can
+//! require TOTP for every super user, those who haven't enrolled
+//! get a challenge to enroll with instead.
+//!
+//! secrets are in `_cms_totp`, recovery codes (hashed, single
+//! use) in `_cms_totp_recovery`, challenges (hashed, short lived)
+//! in `_cms_totp_challenges` and the setting in `_cms_settings`.
+
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, Response, StatusCode},
+    middleware::from_fn,
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{TimeDelta, Utc};
+use ring::hmac;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
+
+use super::{
+    ijwt::Principal,
+    keys::{JwtKeys, Keys},
+    other::bearer_claims,
+    rate_limit,
+    session::{self, hash_token, random_token},
+};
+use crate::{
+    backend::Backend,
+    error::{self, ClientError},
+    permissions::need_admin,
+};
+
+pub const ISSUER: &str = "cms";
+const PERIOD: i64 = 30;
+const DIGITS: u32 = 6;
+/// steps before and after the current one that are accepted,
+/// for clocks that drift
+const SKEW: i64 = 1;
+const CHALLENGE_TTL: TimeDelta = TimeDelta::minutes(5);
+/// wrong codes before a challenge is dropped, and the password
+/// has to be entered again
+const CHALLENGE_ATTEMPTS: i64 = 5;
+const RECOVERY_CODES: usize = 10;
+
+/// reads the persisted switch on every call, so an admin turning
+/// it on is seen by every instance sharing the database at once
+pub async fn is_required<S>(
+    db: &Pool<S>,
+) -> Result<bool, sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String: Type<S> + for<'d> Decode<'d, S>,
+{
+    let required: Option<(String,)> = sqlx::query_as(
+        "SELECT value FROM _cms_settings WHERE name = 'require_totp';",
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(required.is_some_and(|e| e.0 == "true"))
+}
+
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(
+                BASE32[(buffer >> bits) as usize & 31] as char,
+            );
+        }
+    }
+    if bits > 0 {
+        out.push(
+            BASE32[(buffer << (5 - bits)) as usize & 31] as char,
+        );
+    }
+    out
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.bytes().filter(|e| *e != b'=') {
+        let value = BASE32
+            .iter()
+            .position(|e| *e == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// the code of a 30 seconds step
+pub fn code_at(secret: &[u8], step: i64) -> u32 {
+    let key = hmac::Key::new(
+        hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+        secret,
+    );
+    let tag = hmac::sign(&key, &step.to_be_bytes());
+    let tag = tag.as_ref();
+    let offset = (tag[tag.len() - 1] & 0xf) as usize;
+    let binary = u32::from_be_bytes([
+        tag[offset] & 0x7f,
+        tag[offset + 1],
+        tag[offset + 2],
+        tag[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// the step `code` is valid for around `now`
+fn matching_step(
+    secret: &[u8],
+    code: &str,
+    now: i64,
+) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let current = now / PERIOD;
+    (current - SKEW..=current + SKEW)
+        .find(|step| code_at(secret, *step) == code)
+}
+
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|e| match e {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~' => (e as char).to_string(),
+            _ => format!("%{e:02X}"),
+        })
+        .collect()
+}
+
+pub fn otpauth_uri(account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD}",
+        issuer = url_encode(ISSUER),
+        account = url_encode(account),
+    )
+}
+
+pub fn migration_st<S: Backend>() -> [String; 4] {
+    [
+        format!(
+            "
+    CREATE TABLE IF NOT EXISTS _cms_totp (
+        super_user_id {i64} PRIMARY KEY,
+        secret TEXT NOT NULL,
+        enabled {i64} NOT NULL DEFAULT 0,
+        last_step {i64} NOT NULL DEFAULT 0
+    );
+    ",
+            i64 = S::i64_column(),
+        ),
+        format!(
+            "
+    CREATE TABLE IF NOT EXISTS _cms_totp_recovery (
+        code TEXT PRIMARY KEY,
+        super_user_id {i64} NOT NULL
+    );
+    ",
+            i64 = S::i64_column(),
+        ),
+        format!(
+            "
+    CREATE TABLE IF NOT EXISTS _cms_totp_challenges (
+        token TEXT PRIMARY KEY,
+        super_user_id {i64} NOT NULL,
+        attempts {i64} NOT NULL DEFAULT 0,
+        expires_at {i64} NOT NULL
+    );
+    ",
+            i64 = S::i64_column(),
+        ),
+        "
+    CREATE TABLE IF NOT EXISTS _cms_settings (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    "
+        .to_string(),
+    ]
+}
+
+pub async fn init_totp<S>(
+    db: &Pool<S>,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    for st in migration_st::<S>() {
+        sqlx::query(&st).execute(db).await?;
+    }
+
+    sqlx::query("DELETE FROM _cms_totp_challenges WHERE expires_at < $1;")
+        .bind(Utc::now().timestamp())
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+fn unauthorized(code: &str, dev_hint: &str) -> ClientError {
+    ClientError {
+        status_code: StatusCode::UNAUTHORIZED,
+        dev_hint: dev_hint.to_string(),
+        user_error: None,
+    }
+    .add_user_error(code, "the code is not valid", |_| {})
+}
+
+fn invalid_code() -> ClientError {
+    unauthorized(
+        "invalid_totp_code",
+        "code is wrong or was used",
+    )
+}
+
+fn invalid_challenge() -> ClientError {
+    unauthorized(
+        "invalid_challenge",
+        "challenge does not exist, has expired or had too many wrong codes",
+    )
+}
+
+/// `Some((secret, enabled))` when the super user has enrolled
+async fn secret_of<S>(
+    db: &Pool<S>,
+    id: i64,
+) -> Result<Option<(Vec<u8>, bool)>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let found: Option<(String, i64)> = sqlx::query_as(
+        "SELECT secret, enabled FROM _cms_totp WHERE super_user_id = $1;",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    Ok(found.map(|(secret, enabled)| {
+        (
+            base32_decode(&secret)
+                .expect("stored secret is base32"),
+            enabled != 0,
+        )
+    }))
+}
+
+/// checks a code of the super user's authenticator, a code
+/// can't be used twice
+async fn check_code<S>(
+    db: &Pool<S>,
+    id: i64,
+    secret: &[u8],
+    code: &str,
+) -> Result<bool, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let Some(step) =
+        matching_step(secret, code, Utc::now().timestamp())
+    else {
+        return Ok(false);
+    };
+
+    let updated: Option<(i64,)> = sqlx::query_as(
+        "
+    UPDATE _cms_totp SET last_step = $1
+    WHERE super_user_id = $2 AND last_step < $1
+    RETURNING super_user_id;
+    ",
+    )
+    .bind(step)
+    .bind(id)
+    .fetch_optional(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    Ok(updated.is_some())
+}
+
+async fn use_recovery_code<S>(
+    db: &Pool<S>,
+    id: i64,
+    code: &str,
+) -> Result<bool, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let deleted: Option<(String,)> = sqlx::query_as(
+        "
+    DELETE FROM _cms_totp_recovery
+    WHERE code = $1 AND super_user_id = $2
+    RETURNING code;
+    ",
+    )
+    .bind(hash_token(&code.trim().to_lowercase()))
+    .bind(id)
+    .fetch_optional(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    Ok(deleted.is_some())
+}
+
+/// called by the super user login once the password is right,
+/// `Some` is the response that replaces the tokens
+pub async fn login_challenge<S>(
+    db: &Pool<S>,
+    id: i64,
+) -> Result<Option<Response<Body>>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let required = is_required(db)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+    let next = match secret_of(db, id).await? {
+        Some((_, true)) => "verify",
+        _ if required => "enroll",
+        _ => return Ok(None),
+    };
+
+    let challenge = random_token();
+    sqlx::query(
+        "
+    INSERT INTO _cms_totp_challenges
+        (token, super_user_id, expires_at)
+    VALUES ($1, $2, $3);
+    ",
+    )
+    .bind(hash_token(&challenge))
+    .bind(id)
+    .bind((Utc::now() + CHALLENGE_TTL).timestamp())
+    .execute(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    Ok(Some(
+        Json(json!({"data": {
+            "totp": next,
+            "challenge": challenge,
+        }}))
+        .into_response(),
+    ))
+}
+
+/// the super user a challenge was issued to, every call counts
+/// as an attempt
+async fn challenged<S>(
+    db: &Pool<S>,
+    challenge: &str,
+) -> Result<i64, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let found: Option<(i64,)> = sqlx::query_as(
+        "
+    UPDATE _cms_totp_challenges SET attempts = attempts + 1
+    WHERE token = $1 AND expires_at >= $2 AND attempts < $3
+    RETURNING super_user_id;
+    ",
+    )
+    .bind(hash_token(challenge))
+    .bind(Utc::now().timestamp())
+    .bind(CHALLENGE_ATTEMPTS)
+    .fetch_optional(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    found.map(|e| e.0).ok_or_else(invalid_challenge)
+}
+
+async fn drop_challenge<S>(
+    db: &Pool<S>,
+    challenge: &str,
+) -> Result<(), ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(
+        "DELETE FROM _cms_totp_challenges WHERE token = $1;",
+    )
+    .bind(hash_token(challenge))
+    .execute(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    Ok(())
+}
+
+/// the super user of the Bearer token, or of the challenge
+/// when there is one
+async fn subject<S>(
+    db: &Pool<S>,
+    keys: &JwtKeys,
+    headers: &HeaderMap,
+    challenge: Option<&str>,
+) -> Result<i64, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    if let Some(challenge) = challenge {
+        return challenged(db, challenge).await;
+    }
+
+    let claims = bearer_claims(keys, headers)?.ok_or((
+        StatusCode::UNAUTHORIZED,
+        "authorization not found",
+    ))?;
+    if claims.principal != Principal::SuperUser {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "only super users can enroll TOTP",
+        )
+            .into());
+    }
+    Ok(claims.id.parse().map_err(|_| "id is not a number")?)
+}
+
+pub fn totp_router<S>() -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    Router::new()
+        .route("/confirm", post(confirm::<S>))
+        .route("/verify", post(verify::<S>))
+        .route("/disable", post(disable::<S>))
+        // the routes that take a code
+        .route_layer(from_fn(rate_limit::limit_attempts))
+        .route("/enroll", post(enroll::<S>))
+}
+
+#[derive(Deserialize)]
+pub struct EnrollInput {
+    /// an enrollment challenge, or `null` with a Bearer token
+    #[serde(default)]
+    pub challenge: Option<String>,
+}
+
+/// a new secret, it is used once `/confirm` gets a code of it
+async fn enroll<S>(
+    db: State<Pool<S>>,
+    keys: Keys,
+    headers: HeaderMap,
+    input: Json<EnrollInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let id = subject(
+        &db.0,
+        &keys,
+        &headers,
+        input.challenge.as_deref(),
+    )
+    .await?;
+
+    if let Some((_, true)) = secret_of(&db.0, id).await? {
+        return Err(ClientError {
+            status_code: StatusCode::CONFLICT,
+            dev_hint:
+                "TOTP is already enabled, disable it first"
+                    .to_string(),
+            user_error: None,
+        }
+        .add_user_error(
+            "totp_already_enabled",
+            "two factor authentication is already enabled",
+            |_| {},
+        ));
+    }
+
+    // 20 random bytes, like the RFC's examples
+    let secret = base32_encode(&session::random_bytes::<20>());
+
+    sqlx::query(
+        "
+    INSERT INTO _cms_totp (super_user_id, secret)
+    VALUES ($1, $2)
+    ON CONFLICT (super_user_id) DO UPDATE
+    SET secret = excluded.secret, enabled = 0, last_step = 0;
+    ",
+    )
+    .bind(id)
+    .bind(secret.clone())
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    let email: Option<(Option<String>,)> = sqlx::query_as(
+        "SELECT email FROM _super_users WHERE id = $1;",
+    )
+    .bind(id)
+    .fetch_optional(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+    let account = email
+        .and_then(|e| e.0)
+        .unwrap_or_else(|| id.to_string());
+
+    Ok(Json(json!({"data": {
+        "secret": secret,
+        "otpauth_uri": otpauth_uri(&account, &secret),
+    }})))
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmInput {
+    pub code: String,
+    pub challenge: Option<String>,
+}
+
+/// enables TOTP and returns new recovery codes, confirming with
+/// an enrollment challenge also starts a session
+async fn confirm<S>(
+    db: State<Pool<S>>,
+    keys: Keys,
+    headers: HeaderMap,
+    input: Json<ConfirmInput>,
+) -> Result<Response<Body>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let id = subject(
+        &db.0,
+        &keys,
+        &headers,
+        input.challenge.as_deref(),
+    )
+    .await?;
+
+    let secret = match secret_of(&db.0, id).await? {
+        Some((secret, false)) => secret,
+        _ => {
+            return Err(ClientError {
+                status_code: StatusCode::CONFLICT,
+                dev_hint: "nothing to confirm, enroll first"
+                    .to_string(),
+                user_error: None,
+            }
+            .add_user_error(
+                "totp_not_enrolled",
+                "start the enrollment again",
+                |_| {},
+            ))
+        }
+    };
+
+    if !check_code(&db.0, id, &secret, &input.code).await? {
+        return Err(invalid_code());
+    }
+
+    sqlx::query(
+        "UPDATE _cms_totp SET enabled = 1 WHERE super_user_id = $1;",
+    )
+    .bind(id)
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    sqlx::query(
+        "DELETE FROM _cms_totp_recovery WHERE super_user_id = $1;",
+    )
+    .bind(id)
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    let mut codes = vec![];
+    for _ in 0..RECOVERY_CODES {
+        let code: String = random_token()
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|e| e.to_ascii_lowercase())
+            .take(10)
+            .collect();
+        sqlx::query(
+            "
+    INSERT INTO _cms_totp_recovery (code, super_user_id)
+    VALUES ($1, $2);
+    ",
+        )
+        .bind(hash_token(&code))
+        .bind(id)
+        .execute(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+        codes.push(code);
+    }
+
+    let mut data = serde_json::Map::new();
+    data.insert("recovery_codes".to_string(), json!(codes));
+
+    match &input.challenge {
+        Some(challenge) => {
+            drop_challenge(&db.0, challenge).await?;
+            let tokens = session::start(
+                &db.0,
+                &keys,
+                Principal::SuperUser,
+                &id.to_string(),
+            )
+            .await?;
+            Ok(tokens.respond_with(data))
+        }
+        None => {
+            Ok(Json(json!({ "data": data })).into_response())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VerifyInput {
+    pub challenge: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+/// the second step of the login
+async fn verify<S>(
+    db: State<Pool<S>>,
+    keys: Keys,
+    input: Json<VerifyInput>,
+) -> Result<session::Tokens, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let id = challenged(&db.0, &input.challenge).await?;
+
+    let Some((secret, true)) = secret_of(&db.0, id).await? else {
+        return Err(invalid_challenge());
+    };
+
+    let verified = match (&input.code, &input.recovery_code) {
+        (Some(code), _) => {
+            check_code(&db.0, id, &secret, code).await?
+        }
+        (None, Some(code)) => {
+            use_recovery_code(&db.0, id, code).await?
+        }
+        (None, None) => false,
+    };
+    if !verified {
+        return Err(invalid_code());
+    }
+
+    drop_challenge(&db.0, &input.challenge).await?;
+
+    session::start(
+        &db.0,
+        &keys,
+        Principal::SuperUser,
+        &id.to_string(),
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct DisableInput {
+    pub code: String,
+}
+
+async fn disable<S>(
+    db: State<Pool<S>>,
+    keys: Keys,
+    headers: HeaderMap,
+    input: Json<DisableInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let id = subject(&db.0, &keys, &headers, None).await?;
+
+    if is_required(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?
+    {
+        return Err(ClientError {
+            status_code: StatusCode::FORBIDDEN,
+            dev_hint: "an admin requires TOTP for super users"
+                .to_string(),
+            user_error: None,
+        }
+        .add_user_error(
+            "totp_required",
+            "two factor authentication is required",
+            |_| {},
+        ));
+    }
+
+    let Some((secret, true)) = secret_of(&db.0, id).await? else {
+        return Ok(Json(json!({"data": null})));
+    };
+    if !check_code(&db.0, id, &secret, &input.code).await? {
+        return Err(invalid_code());
+    }
+
+    for st in [
+        "DELETE FROM _cms_totp WHERE super_user_id = $1;",
+        "DELETE FROM _cms_totp_recovery WHERE super_user_id = $1;",
+    ] {
+        sqlx::query(st)
+            .bind(id)
+            .execute(&db.0)
+            .await
+            .map_err(|err| error::database(err, &[]))?;
+    }
+
+    Ok(Json(json!({"data": null})))
+}
+
+/// nested in `admin_router`
+pub fn settings_router<S>() -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    Router::new()
+        .route("/", get(settings::<S>))
+        .route("/require_totp", post(require_totp::<S>))
+        .route_layer(from_fn(need_admin::<S>))
+}
+
+async fn settings<S>(
+    db: State<Pool<S>>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String: Type<S> + for<'d> Decode<'d, S>,
+{
+    let required = is_required(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    Ok(Json(json!({"data": {
+        "require_totp": required,
+    }})))
+}
+
+#[derive(Deserialize)]
+pub struct RequireInput {
+    pub required: bool,
+}
+
+async fn require_totp<S>(
+    db: State<Pool<S>>,
+    input: Json<RequireInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(
+        "
+    INSERT INTO _cms_settings (name, value)
+    VALUES ('require_totp', $1)
+    ON CONFLICT (name) DO UPDATE SET value = excluded.value;
+    ",
+    )
+    .bind(input.required.to_string())
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+        Extension, Router,
+    };
+    use http_body_util::BodyExt;
+    use jwt::ToBase64;
+    use serde_json::{json, Value};
+    use sqlx::{Pool, Sqlite};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        auth::{
+            auth_router,
+            create_super_user_if_not_exist_and_return_init_token,
+            ijwt, password,
+        },
+        permissions::{
+            init_permissions,
+            test::{keys, SERIAL},
+        },
+    };
+
+    #[test]
+    fn rfc_6238_codes() {
+        let secret = b"12345678901234567890";
+        assert_eq!(code_at(secret, 59 / 30), 287082);
+        assert_eq!(code_at(secret, 1111111109 / 30), 81804);
+        assert_eq!(code_at(secret, 2000000000 / 30), 279037);
+
+        let encoded = base32_encode(secret);
+        assert_eq!(encoded, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32_decode(&encoded).unwrap(), secret);
+        assert_eq!(
+            otpauth_uri("a@b.c", "ABC"),
+            "otpauth://totp/cms:a%40b.c?secret=ABC&issuer=cms&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    fn request(
+        uri: &str,
+        token: Option<&str>,
+        body: Value,
+    ) -> Request<Body> {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .header("content-type", "application/json")
+            .uri(uri);
+        if let Some(token) = token {
+            req = req.header(
+                "authorization",
+                format!("Bearer {token}"),
+            );
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
+
+    fn login(email: &str) -> Request<Body> {
+        let basic = json!({"email": email, "password": "test"})
+            .to_base64()
+            .unwrap()
+            .to_string();
+        Request::builder()
+            .method(Method::POST)
+            .uri("/auth/login")
+            .header("authorization", format!("Basic {basic}"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// the access token and the body
+    async fn read(
+        res: Response<Body>,
+    ) -> (StatusCode, Option<String>, Value) {
+        let status = res.status();
+        let access = res
+            .headers()
+            .get("X-Cms-Token")
+            .map(|e| e.to_str().unwrap().to_string());
+        let body = res.into_body().collect().await.unwrap();
+        let body =
+            serde_json::from_slice(&body.to_bytes()).unwrap();
+        (status, access, body)
+    }
+
+    fn code(secret: &str, step: i64) -> String {
+        let secret = base32_decode(secret).unwrap();
+        format!("{:06}", code_at(&secret, step))
+    }
+
+    #[tokio::test]
+    async fn enroll_verify_and_require() {
+        let _serial = SERIAL.lock().await;
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        std::env::set_var("JWT_SALT", "test");
+        // codes are taken around one step, so the test doesn't
+        // depend on when a new one starts
+        let step = Utc::now().timestamp() / PERIOD;
+
+        create_super_user_if_not_exist_and_return_init_token(
+            pool.clone(),
+            &keys(),
+        )
+        .await
+        .unwrap();
+        init_permissions(&pool).await.unwrap();
+        session::init_sessions(&pool).await.unwrap();
+        init_totp(&pool).await.unwrap();
+        sqlx::query(
+            "UPDATE _super_users SET email = $1, password = $2;",
+        )
+        .bind("totp@example.com")
+        .bind(password::hash("test"))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let app = Router::new()
+            .nest(
+                "/auth",
+                auth_router::<Sqlite>(),
+            )
+            .nest("/admin/settings", settings_router::<Sqlite>())
+            .layer(Extension(keys()))
+            .with_state(pool.clone());
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move { read(app.oneshot(req).await.unwrap()).await }
+        };
+
+        let (_, access, _) =
+            send(login("totp@example.com")).await;
+        let access = access.expect("no TOTP yet");
+
+        let (_, _, body) = send(request(
+            "/auth/totp/enroll",
+            Some(&access),
+            json!({}),
+        ))
+        .await;
+        let secret =
+            body["data"]["secret"].as_str().unwrap().to_string();
+        assert!(body["data"]["otpauth_uri"]
+            .as_str()
+            .unwrap()
+            .starts_with(
+                "otpauth://totp/cms:totp%40example.com?"
+            ));
+
+        let (status, _, body) = send(request(
+            "/auth/totp/confirm",
+            Some(&access),
+            json!({"code": "000000x"}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body["error"]["user_error"]["code"],
+            "invalid_totp_code"
+        );
+
+        let (status, _, body) = send(request(
+            "/auth/totp/confirm",
+            Some(&access),
+            json!({"code": code(&secret, step)}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let recovery: Vec<String> = serde_json::from_value(
+            body["data"]["recovery_codes"].clone(),
+        )
+        .unwrap();
+        assert_eq!(recovery.len(), RECOVERY_CODES);
+
+        // the password is not enough anymore
+        let (status, access, body) =
+            send(login("totp@example.com")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(access, None);
+        assert_eq!(body["data"]["totp"], "verify");
+        let challenge =
+            body["data"]["challenge"].as_str().unwrap();
+
+        let (status, _, _) = send(request(
+            "/auth/totp/verify",
+            None,
+            json!({"challenge": challenge, "code": code(&secret, step)}),
+        ))
+        .await;
+        assert_eq!(
+            status,
+            StatusCode::UNAUTHORIZED,
+            "codes are single use"
+        );
+
+        let (status, access, _) = send(request(
+            "/auth/totp/verify",
+            None,
+            json!({"challenge": challenge, "code": code(&secret, step + 1)}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(access.is_some());
+
+        // recovery codes, single use too
+        for expected in
+            [StatusCode::OK, StatusCode::UNAUTHORIZED]
+        {
+            let (_, _, body) =
+                send(login("totp@example.com")).await;
+            let challenge =
+                body["data"]["challenge"].as_str().unwrap();
+            let (status, _, _) = send(request(
+                "/auth/totp/verify",
+                None,
+                json!({
+                    "challenge": challenge,
+                    "recovery_code": recovery[0],
+                }),
+            ))
+            .await;
+            assert_eq!(status, expected);
+        }
+
+        // a challenge takes a few wrong codes
+        let (_, _, body) = send(login("totp@example.com")).await;
+        let challenge =
+            body["data"]["challenge"].as_str().unwrap();
+        for _ in 0..CHALLENGE_ATTEMPTS {
+            send(request(
+                "/auth/totp/verify",
+                None,
+                json!({"challenge": challenge, "code": "000000"}),
+            ))
+            .await;
+        }
+        let (status, _, body) = send(request(
+            "/auth/totp/verify",
+            None,
+            json!({"challenge": challenge, "code": code(&secret, step - 1)}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body["error"]["user_error"]["code"],
+            "invalid_challenge"
+        );
+
+        // admins require it for everyone, the others enroll on
+        // their next login
+        let admin = ijwt::sign_for(
+            &keys(),
+            "1",
+            TimeDelta::hours(1),
+        );
+        let (status, _, _) = send(request(
+            "/admin/settings/require_totp",
+            Some(&admin),
+            json!({"required": true}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        sqlx::query(
+            "INSERT INTO _super_users (email, password) VALUES ($1, $2);",
+        )
+        .bind("other@example.com")
+        .bind(password::hash("test"))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let (_, access, body) =
+            send(login("other@example.com")).await;
+        assert_eq!(access, None);
+        assert_eq!(body["data"]["totp"], "enroll");
+        let challenge =
+            body["data"]["challenge"].as_str().unwrap();
+
+        let (_, _, body) = send(request(
+            "/auth/totp/enroll",
+            None,
+            json!({"challenge": challenge}),
+        ))
+        .await;
+        let secret = body["data"]["secret"].as_str().unwrap();
+
+        let (status, access, body) = send(request(
+            "/auth/totp/confirm",
+            None,
+            json!({"challenge": challenge, "code": code(secret, step)}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(access.is_some());
+        assert!(body["data"]["refresh_token"].is_string());
+        assert!(body["data"]["recovery_codes"].is_array());
+
+        send(request(
+            "/admin/settings/require_totp",
+            Some(&admin),
+            json!({"required": false}),
+        ))
+        .await;
+
+        // the switch is read from the database, so another
+        // instance turning it on applies here without a restart
+        sqlx::query(
+            "UPDATE _cms_settings SET value = 'true' WHERE name = 'require_totp';",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO _super_users (email, password) VALUES ($1, $2);",
+        )
+        .bind("third@example.com")
+        .bind(password::hash("test"))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let (_, access, body) =
+            send(login("third@example.com")).await;
+        assert_eq!(access, None);
+        assert_eq!(body["data"]["totp"], "enroll");
+
+        send(request(
+            "/admin/settings/require_totp",
+            Some(&admin),
+            json!({"required": false}),
+        ))
+        .await;
+    }
+}
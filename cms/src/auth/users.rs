@@ -0,0 +1,1282 @@
+//! end users of the app, separate from `_super_users`: they
+//! sign up themselves, log in with email and password, and
+//! have a profile. the profile is a row of a collection the app
+//! picks (usually a `#[standard_collection]`), inserted as
+//! owned by the user.
+//!
+//! tokens issued to end users have the `user` principal, the
+//! permission system runs them as `permissions::USER` and the
+//! super user routes reject them.
+//!
+//! email verification and password reset tokens are random and
+//! single use, only their sha256 is stored in
+//! `_cms_user_tokens`. they are handed to a `UserMailer`.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{HeaderMap, Response, StatusCode},
+    middleware::{from_fn, Next},
+    response::IntoResponse,
+    routing::post,
+    Extension, Json, Router,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
+
+use super::{
+    email_password::EmailPassword,
+    ijwt::{IClaims, Principal},
+    keys::Keys,
+    other::bearer_claims,
+    password::{self, Verify},
+    rate_limit,
+    session::{self, hash_token, random_token},
+};
+use crate::{
+    backend::Backend,
+    error::ClientError,
+    operations::{
+        insert_one::{insert_one_dynamic_inner, InputInsertOne},
+        select_one::{get_one_dynamic, InputGetOne},
+        update_one::{update_one_dynmaic, InputUpdatetOne},
+    },
+    error,
+    permissions::{
+        as_owner, check_writable, hide_fields, Access, USER,
+    },
+    traits::Collection,
+};
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl Purpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Purpose::VerifyEmail => "verify_email",
+            Purpose::ResetPassword => "reset_password",
+        }
+    }
+
+    fn valid_for(&self) -> chrono::TimeDelta {
+        match self {
+            Purpose::VerifyEmail => chrono::Duration::days(1),
+            Purpose::ResetPassword => chrono::Duration::hours(1),
+        }
+    }
+}
+
+/// delivers verification and reset tokens, usually as a link to
+/// the frontend. called from the request, implementations that
+/// do io should spawn
+pub trait UserMailer: Send + Sync + 'static {
+    fn send(&self, email: &str, purpose: Purpose, token: &str);
+}
+
+/// prints tokens to stdout, for development
+pub struct PrintMailer;
+
+impl UserMailer for PrintMailer {
+    fn send(&self, email: &str, purpose: Purpose, token: &str) {
+        println!(
+            "{} token for {email}: {token}",
+            purpose.as_str()
+        );
+    }
+}
+
+type Mailer = Extension<Arc<dyn UserMailer>>;
+
+pub fn migration_st<S: Backend>() -> [String; 2] {
+    [
+        format!(
+            "
+    CREATE TABLE IF NOT EXISTS _cms_users (
+        id {},
+        email TEXT NOT NULL UNIQUE,
+        password TEXT NOT NULL,
+        email_verified {i64} NOT NULL DEFAULT 0,
+        profile_id {i64}
+    );
+    ",
+            S::id_column(),
+            i64 = S::i64_column(),
+        ),
+        format!(
+            "
+    CREATE TABLE IF NOT EXISTS _cms_user_tokens (
+        token TEXT PRIMARY KEY,
+        user_id {i64} NOT NULL,
+        purpose TEXT NOT NULL,
+        expires_at {i64} NOT NULL
+    );
+    ",
+            i64 = S::i64_column(),
+        ),
+    ]
+}
+
+pub async fn init_users<S>(
+    db: &Pool<S>,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+{
+    for st in migration_st::<S>() {
+        sqlx::query(&st).execute(db).await?;
+    }
+    Ok(())
+}
+
+/// `P` is the collection of profiles, `POST /sign_up` takes its
+/// insert input at `profile`
+pub fn users_router<S, P>(
+    mailer: impl UserMailer,
+) -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    P: Collection<S> + 'static,
+{
+    let mailer: Arc<dyn UserMailer> = Arc::new(mailer);
+
+    Router::new()
+        .route("/me", post(me::<S, P>))
+        .route("/update_me", post(update_me::<S, P>))
+        .route_layer(from_fn(need_user))
+        .route("/sign_up", post(sign_up::<S, P>))
+        .route(
+            "/login",
+            post(login::<S>).route_layer(from_fn(
+                rate_limit::limit_user_attempts,
+            )),
+        )
+        .route("/verify_email", post(verify_email::<S>))
+        .route(
+            "/request_verification",
+            post(request_verification::<S>),
+        )
+        .route(
+            "/request_password_reset",
+            post(request_password_reset::<S>),
+        )
+        .route("/reset_password", post(reset_password::<S>))
+        .layer(Extension(mailer))
+}
+
+fn invalid_input(
+    field: &str,
+    reason: &str,
+    hint: &str,
+) -> ClientError {
+    ClientError {
+        status_code: StatusCode::BAD_REQUEST,
+        dev_hint: format!("{field} is {reason}"),
+        user_error: None,
+    }
+    .add_user_error("invalid_input", hint, |u| {
+        u.structured_hint = Some(
+            [(field.to_string(), reason.to_string())].into(),
+        )
+    })
+}
+
+pub(super) fn invalid_token() -> ClientError {
+    ClientError {
+        status_code: StatusCode::BAD_REQUEST,
+        dev_hint: "token is invalid, used or expired"
+            .to_string(),
+        user_error: None,
+    }
+    .add_user_error(
+        "invalid_token",
+        "the link is invalid or has expired",
+        |_| {},
+    )
+}
+
+pub(super) fn normalize_email(
+    email: &str,
+) -> Result<String, ClientError> {
+    let email = email.trim().to_lowercase();
+    if !email.contains('@') {
+        return Err(invalid_input(
+            "email",
+            "invalid",
+            "email is not valid",
+        ));
+    }
+    Ok(email)
+}
+
+pub(super) fn check_password(
+    password: &str,
+) -> Result<(), ClientError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(invalid_input(
+            "password",
+            "too_short",
+            "password is too short",
+        ));
+    }
+    Ok(())
+}
+
+/// replaces the previous token of the same purpose
+async fn send_token<S>(
+    db: &Pool<S>,
+    mailer: &dyn UserMailer,
+    user_id: i64,
+    email: &str,
+    purpose: Purpose,
+) -> Result<(), ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    let token = random_token();
+
+    sqlx::query(
+        "DELETE FROM _cms_user_tokens WHERE user_id = $1 AND purpose = $2;",
+    )
+    .bind(user_id)
+    .bind(purpose.as_str().to_string())
+    .execute(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    sqlx::query(
+        "
+    INSERT INTO _cms_user_tokens (token, user_id, purpose, expires_at)
+    VALUES ($1, $2, $3, $4);
+    ",
+    )
+    .bind(hash_token(&token))
+    .bind(user_id)
+    .bind(purpose.as_str().to_string())
+    .bind((Utc::now() + purpose.valid_for()).timestamp())
+    .execute(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    mailer.send(email, purpose, &token);
+
+    Ok(())
+}
+
+/// the user the token was issued to, the token is deleted
+/// whether it is still valid or not
+async fn take_token<S>(
+    db: &Pool<S>,
+    token: &str,
+    purpose: Purpose,
+) -> Result<i64, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    let found: Option<(i64, i64)> = sqlx::query_as(
+        "
+    DELETE FROM _cms_user_tokens WHERE token = $1 AND purpose = $2
+    RETURNING user_id, expires_at;
+    ",
+    )
+    .bind(hash_token(token))
+    .bind(purpose.as_str().to_string())
+    .fetch_optional(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    match found {
+        Some((user_id, expires_at))
+            if Utc::now().timestamp() <= expires_at =>
+        {
+            Ok(user_id)
+        }
+        _ => Err(invalid_token()),
+    }
+}
+
+/// only tokens issued to end users
+pub async fn need_user(
+    keys: Keys,
+    headers: HeaderMap,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, ClientError> {
+    let claims = bearer_claims(&keys, &headers)?.ok_or((
+        StatusCode::UNAUTHORIZED,
+        "authorization not found",
+    ))?;
+
+    if claims.principal != Principal::User {
+        return Err(ClientError {
+            status_code: StatusCode::FORBIDDEN,
+            dev_hint: "only end users can do this".to_string(),
+            user_error: None,
+        });
+    }
+
+    req.extensions_mut().insert(claims);
+
+    Ok(next.run(req).await)
+}
+
+#[derive(Deserialize)]
+pub struct SignUp {
+    #[serde(flatten)]
+    pub email_password: EmailPassword,
+    /// insert input of the profile collection
+    pub profile: Value,
+}
+
+async fn sign_up<S, P>(
+    db: State<Pool<S>>,
+    mailer: Mailer,
+    body: Json<SignUp>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    P: Collection<S>,
+{
+    let SignUp {
+        email_password,
+        profile,
+    } = body.0;
+
+    let email = normalize_email(&email_password.email)?;
+    check_password(&email_password.password)?;
+    check_writable(
+        USER,
+        P::table_name(),
+        &profile,
+        &*S::permissions().read().await,
+    )?;
+
+    let password = email_password.password;
+    let password = tokio::task::spawn_blocking(move || {
+        password::hash(&password)
+    })
+    .await
+    .map_err(error::server_error)?;
+
+    let inserted: Result<(i64,), _> = sqlx::query_as(
+        "
+    INSERT INTO _cms_users (email, password) VALUES ($1, $2)
+    RETURNING id;
+    ",
+    )
+    .bind(email.clone())
+    .bind(password)
+    .fetch_one(&db.0)
+    .await;
+
+    let id = match inserted {
+        Ok((id,)) => id,
+        Err(err)
+            if err
+                .as_database_error()
+                .is_some_and(|e| e.is_unique_violation()) =>
+        {
+            return Err(ClientError {
+                status_code: StatusCode::CONFLICT,
+                dev_hint: "email is taken".to_string(),
+                user_error: None,
+            }
+            .add_user_error(
+                "email_taken",
+                "an account with this email already exists",
+                |_| {},
+            ));
+        }
+        Err(err) => {
+            return Err(error::database(err, &["email", "password"]))
+        }
+    };
+
+    let owner = Principal::User.owner(&id.to_string());
+    let profile = as_owner(
+        owner,
+        insert_one_dynamic_inner(
+            db.0.clone(),
+            P::table_name(),
+            InputInsertOne {
+                input: profile,
+                relation: Map::new(),
+            },
+        ),
+    )
+    .await;
+
+    let profile = match profile {
+        Ok(profile) => profile,
+        Err(err) => {
+            sqlx::query("DELETE FROM _cms_users WHERE id = $1;")
+                .bind(id)
+                .execute(&db.0)
+                .await
+                .map_err(|err| error::database(err, &[]))?;
+            return Err(err.into());
+        }
+    };
+
+    sqlx::query(
+        "UPDATE _cms_users SET profile_id = $1 WHERE id = $2;",
+    )
+    .bind(profile.id)
+    .bind(id)
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    send_token(
+        &db.0,
+        &**mailer,
+        id,
+        &email,
+        Purpose::VerifyEmail,
+    )
+    .await?;
+
+    Ok(Json(json!({"data": {"id": id}})))
+}
+
+/// same as super users' login, the email has to be verified
+async fn login<S>(
+    db: State<Pool<S>>,
+    keys: Keys,
+    basic: EmailPassword,
+) -> Result<Response<Body>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let email = basic.email.trim().to_lowercase();
+    let found: Option<(i64, String, i64)> = sqlx::query_as(
+        "SELECT id, password, email_verified FROM _cms_users WHERE email = $1;",
+    )
+    .bind(email)
+    .fetch_optional(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    let (user, stored) = match found {
+        Some((id, stored, verified)) => {
+            (Some((id, verified != 0)), Some(stored))
+        }
+        None => (None, None),
+    };
+
+    let password = basic.password;
+    let (verified, password) =
+        tokio::task::spawn_blocking(move || {
+            let verified =
+                password::verify(&password, stored.as_deref());
+            (verified, password)
+        })
+        .await
+        .map_err(error::server_error)?;
+
+    let (id, email_verified) = match (user, &verified) {
+        (Some(user), Verify::Valid | Verify::NeedsRehash) => {
+            user
+        }
+        _ => {
+            return Err(ClientError {
+                status_code: StatusCode::UNAUTHORIZED,
+                dev_hint: "email or password is incorrect"
+                    .to_string(),
+                user_error: None,
+            }
+            .add_user_error(
+                "wrong_credentials",
+                "email or password is incorrect",
+                |_| {},
+            ))
+        }
+    };
+
+    if !email_verified {
+        return Err(ClientError {
+            status_code: StatusCode::FORBIDDEN,
+            dev_hint: "email is not verified".to_string(),
+            user_error: None,
+        }
+        .add_user_error(
+            "email_not_verified",
+            "verify your email before logging in",
+            |_| {},
+        ));
+    }
+
+    if verified == Verify::NeedsRehash {
+        let rehashed = tokio::task::spawn_blocking(move || {
+            password::hash(&password)
+        })
+        .await
+        .map_err(error::server_error)?;
+
+        sqlx::query(
+            "UPDATE _cms_users SET password = $1 WHERE id = $2;",
+        )
+        .bind(rehashed)
+        .bind(id)
+        .execute(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+    }
+
+    Ok(session::start(
+        &db.0,
+        &keys,
+        Principal::User,
+        &id.to_string(),
+    )
+    .await?
+    .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct TokenInput {
+    pub token: String,
+}
+
+async fn verify_email<S>(
+    db: State<Pool<S>>,
+    body: Json<TokenInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let id =
+        take_token(&db.0, &body.token, Purpose::VerifyEmail)
+            .await?;
+
+    sqlx::query(
+        "UPDATE _cms_users SET email_verified = 1 WHERE id = $1;",
+    )
+    .bind(id)
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+#[derive(Deserialize)]
+pub struct EmailInput {
+    pub email: String,
+}
+
+/// responds the same whether the email exists or not
+async fn request_verification<S>(
+    db: State<Pool<S>>,
+    mailer: Mailer,
+    body: Json<EmailInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let email = normalize_email(&body.email)?;
+    let found: Option<(i64,)> = sqlx::query_as(
+        "SELECT id FROM _cms_users WHERE email = $1 AND email_verified = 0;",
+    )
+    .bind(email.clone())
+    .fetch_optional(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    if let Some((id,)) = found {
+        send_token(
+            &db.0,
+            &**mailer,
+            id,
+            &email,
+            Purpose::VerifyEmail,
+        )
+        .await?;
+    }
+
+    Ok(Json(json!({"data": null})))
+}
+
+/// responds the same whether the email exists or not
+async fn request_password_reset<S>(
+    db: State<Pool<S>>,
+    mailer: Mailer,
+    body: Json<EmailInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let email = normalize_email(&body.email)?;
+    let found: Option<(i64,)> = sqlx::query_as(
+        "SELECT id FROM _cms_users WHERE email = $1;",
+    )
+    .bind(email.clone())
+    .fetch_optional(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    if let Some((id,)) = found {
+        send_token(
+            &db.0,
+            &**mailer,
+            id,
+            &email,
+            Purpose::ResetPassword,
+        )
+        .await?;
+    }
+
+    Ok(Json(json!({"data": null})))
+}
+
+#[derive(Deserialize)]
+pub struct ResetPassword {
+    pub token: String,
+    pub password: String,
+}
+
+/// the token was sent to the user's email, so the email is
+/// verified as well. every session of the user is revoked
+async fn reset_password<S>(
+    db: State<Pool<S>>,
+    body: Json<ResetPassword>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let ResetPassword { token, password } = body.0;
+    check_password(&password)?;
+
+    let id = take_token(&db.0, &token, Purpose::ResetPassword)
+        .await?;
+
+    let password = tokio::task::spawn_blocking(move || {
+        password::hash(&password)
+    })
+    .await
+    .map_err(error::server_error)?;
+
+    // a reset is how a compromised account is taken back, the
+    // sessions started with the old password end with it
+    let mut tx = db
+        .0
+        .begin()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    sqlx::query(
+        "
+    UPDATE _cms_users SET password = $1, email_verified = 1
+    WHERE id = $2;
+    ",
+    )
+    .bind(password)
+    .bind(id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    let revoked = session::revoke_sessions_of::<S>(
+        &mut tx,
+        Principal::User,
+        &id.to_string(),
+    )
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    tx.commit()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+    session::cache_revoked(revoked);
+
+    Ok(Json(json!({"data": null})))
+}
+
+async fn profile_of<S>(
+    db: &Pool<S>,
+    claims: &IClaims,
+) -> Result<(i64, String, bool, Option<i64>), ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'d> Decode<'d, S>,
+{
+    let id: i64 =
+        claims.id.parse().map_err(|_| "user id is not valid")?;
+
+    let found: Option<(String, i64, Option<i64>)> = sqlx::query_as(
+        "SELECT email, email_verified, profile_id FROM _cms_users WHERE id = $1;",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    let (email, verified, profile_id) = found.ok_or((
+        StatusCode::NOT_FOUND,
+        "user does not exist anymore",
+    ))?;
+
+    Ok((id, email, verified != 0, profile_id))
+}
+
+/// the profile goes through the field permissions of `user`,
+/// like the collection routes would
+async fn hidden_of<S, P>() -> Vec<String>
+where
+    S: Backend,
+    P: Collection<S>,
+{
+    S::permissions().read().await.denied_fields(
+        USER,
+        P::table_name(),
+        Access::Read,
+    )
+}
+
+async fn me<S, P>(
+    db: State<Pool<S>>,
+    claims: Extension<IClaims>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    P: Collection<S>,
+{
+    let (id, email, email_verified, profile_id) =
+        profile_of(&db.0, &claims.0).await?;
+
+    let mut profile = match profile_id {
+        Some(profile_id) => {
+            get_one_dynamic(
+                State(db.0.clone()),
+                Path(P::table_name().to_string()),
+                Json(InputGetOne {
+                    filters: Map::new(),
+                    relations: Map::new(),
+                    id: profile_id,
+                }),
+            )
+            .await?
+            .0
+            .attr
+        }
+        None => Value::Null,
+    };
+    hide_fields(&mut profile, &hidden_of::<S, P>().await);
+
+    Ok(Json(json!({"data": {
+        "id": id,
+        "email": email,
+        "email_verified": email_verified,
+        "profile": profile,
+    }})))
+}
+
+#[derive(Deserialize)]
+pub struct UpdateMe {
+    /// update input of the profile collection
+    pub partial: Value,
+}
+
+async fn update_me<S, P>(
+    db: State<Pool<S>>,
+    claims: Extension<IClaims>,
+    body: Json<UpdateMe>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    P: Collection<S>,
+{
+    let (_, _, _, profile_id) =
+        profile_of(&db.0, &claims.0).await?;
+    let profile_id = profile_id
+        .ok_or((StatusCode::NOT_FOUND, "user has no profile"))?;
+    check_writable(
+        USER,
+        P::table_name(),
+        &body.partial,
+        &*S::permissions().read().await,
+    )?;
+
+    let updated = update_one_dynmaic(
+        State(db.0.clone()),
+        Path(P::table_name().to_string()),
+        Json(InputUpdatetOne {
+            id: profile_id,
+            partial: body.0.partial,
+            relations: Map::new(),
+        }),
+    )
+    .await?;
+
+    let mut profile = serde_json::to_value(updated.0)
+        .map_err(error::server_error)?
+        .get("attr")
+        .cloned()
+        .unwrap_or_default();
+    hide_fields(&mut profile, &hidden_of::<S, P>().await);
+
+    Ok(Json(json!({"data": {"profile": profile}})))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use axum::http::Method;
+    use http_body_util::BodyExt;
+    use jwt::ToBase64;
+    use queries_for_sqlx::ident_safety::define_schema;
+    use sqlx::Sqlite;
+    use tower::ServiceExt;
+
+    use crate::{
+        auth::ijwt,
+        client_example::Category,
+        permissions::{
+            assign_role, init_permissions, permissions_router,
+            test::{keys, SERIAL},
+            ADMIN, OWNER_COLUMN,
+        },
+    };
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Outbox(Arc<Mutex<Vec<(String, Purpose, String)>>>);
+
+    impl UserMailer for Outbox {
+        fn send(
+            &self,
+            email: &str,
+            purpose: Purpose,
+            token: &str,
+        ) {
+            self.0.lock().unwrap().push((
+                email.to_string(),
+                purpose,
+                token.to_string(),
+            ));
+        }
+    }
+
+    impl Outbox {
+        fn last(&self, purpose: Purpose) -> String {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .rev()
+                .find(|e| e.1 == purpose)
+                .map(|e| e.2.clone())
+                .expect("no token was sent")
+        }
+    }
+
+    fn request(
+        uri: &str,
+        token: Option<&str>,
+        body: Value,
+    ) -> Request<Body> {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .header("content-type", "application/json")
+            .uri(uri);
+        if let Some(token) = token {
+            req = req.header(
+                "authorization",
+                format!("Bearer {}", token),
+            );
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
+
+    fn login_request(
+        email: &str,
+        password: &str,
+    ) -> Request<Body> {
+        let basic =
+            json!({"email": email, "password": password})
+                .to_base64()
+                .unwrap()
+                .to_string();
+
+        Request::builder()
+            .method(Method::POST)
+            .header("authorization", format!("Basic {}", basic))
+            .uri("/users/login")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn body(res: Response<Body>) -> Value {
+        let bytes = res.into_body().collect().await.unwrap();
+        serde_json::from_slice(&bytes.to_bytes()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn users_sign_up_verify_and_reset() {
+        let _serial = SERIAL.lock().await;
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        std::env::set_var("JWT_SALT", "test");
+
+        // `Category` of `client_example` is the profile, it is
+        // owned
+        sqlx::query(
+            "
+        CREATE TABLE Category (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            cat_title TEXT NOT NULL,
+            _owner TEXT
+        );
+        ",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        define_schema(&[(
+            "Category",
+            &["id", "cat_title", OWNER_COLUMN],
+        )]);
+        init_permissions(&pool).await.unwrap();
+        assign_role(&pool, 1, ADMIN).await.unwrap();
+        init_users(&pool).await.unwrap();
+        session::init_sessions(&pool).await.unwrap();
+
+        let outbox = Outbox::default();
+        let app = Router::new()
+            .nest(
+                "/users",
+                users_router::<Sqlite, Category>(outbox.clone()),
+            )
+            .nest("/permissions", permissions_router::<Sqlite>())
+            .layer(Extension(keys()))
+            .with_state(pool.clone());
+
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+
+        let sign_up = |email: &str, password: &str| {
+            request(
+                "/users/sign_up",
+                None,
+                json!({
+                    "email": email,
+                    "password": password,
+                    "profile": {"cat_title": "ada"},
+                }),
+            )
+        };
+
+        let res =
+            send(sign_up("ada@example.com", "short")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body(res).await["error"]["user_error"]
+                ["structured_hint"],
+            json!({"password": "too_short"})
+        );
+
+        let res =
+            send(sign_up(" Ada@Example.com", "correct horse"))
+                .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body(res).await["data"]["id"], json!(1));
+
+        let res =
+            send(sign_up("ada@example.com", "correct horse"))
+                .await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            body(res).await["error"]["user_error"]["code"],
+            json!("email_taken")
+        );
+
+        let (owner,): (Option<String>,) =
+            sqlx::query_as("SELECT _owner FROM Category;")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(owner.as_deref(), Some("user:1"));
+
+        let res = send(login_request(
+            "ada@example.com",
+            "correct horse",
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            body(res).await["error"]["user_error"]["code"],
+            json!("email_not_verified")
+        );
+
+        let verify = outbox.last(Purpose::VerifyEmail);
+        for status in [StatusCode::OK, StatusCode::BAD_REQUEST] {
+            let res = send(request(
+                "/users/verify_email",
+                None,
+                json!({"token": verify}),
+            ))
+            .await;
+            assert_eq!(
+                res.status(),
+                status,
+                "tokens are single use"
+            );
+        }
+
+        let res = send(login_request(
+            "ada@example.com",
+            "correct horse",
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let token = res.headers()["X-Cms-Token"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let claims = ijwt::verify_exp(
+            &keys(),
+            &token,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(claims.principal, Principal::User);
+
+        let res =
+            send(request("/users/me", Some(&token), json!({})))
+                .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            body(res).await["data"],
+            json!({
+                "id": 1,
+                "email": "ada@example.com",
+                "email_verified": true,
+                "profile": {"cat_title": "ada"},
+            })
+        );
+
+        let res = send(request(
+            "/users/update_me",
+            Some(&token),
+            json!({"partial": {"cat_title": ["set", "lovelace"]}}),
+        ))
+        .await;
+        assert_eq!(
+            body(res).await["data"]["profile"],
+            json!({"cat_title": "lovelace"})
+        );
+
+        // end users are not super users
+        let res = send(request(
+            "/permissions/create_role",
+            Some(&token),
+            json!({"name": "hacker"}),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let admin =
+            ijwt::sign_for(
+                &keys(),
+                "1",
+                chrono::Duration::hours(1),
+            );
+        let res =
+            send(request("/users/me", Some(&admin), json!({})))
+                .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // the profile follows the field permissions of `user`
+        let deny = |access: &str| {
+            request(
+                "/permissions/deny_field",
+                Some(&admin),
+                json!({
+                    "role": "user",
+                    "collection": "Category",
+                    "field": "cat_title",
+                    "access": access,
+                }),
+            )
+        };
+        let res = send(deny("write")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = send(request(
+            "/users/update_me",
+            Some(&token),
+            json!({"partial": {"cat_title": ["set", "hacked"]}}),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            body(res).await["error"]["user_error"]["code"],
+            json!("forbidden_fields")
+        );
+        let res =
+            send(sign_up("eve@example.com", "correct horse"))
+                .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let (users,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM _cms_users;")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(users, 1);
+
+        let res = send(deny("read")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res =
+            send(request("/users/me", Some(&token), json!({})))
+                .await;
+        assert_eq!(body(res).await["data"]["profile"], json!({}));
+
+        // unknown emails look the same
+        for email in ["nobody@example.com", "ada@example.com"] {
+            let res = send(request(
+                "/users/request_password_reset",
+                None,
+                json!({"email": email}),
+            ))
+            .await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        assert_eq!(outbox.0.lock().unwrap().len(), 2);
+
+        let reset = outbox.last(Purpose::ResetPassword);
+        let res = send(request(
+            "/users/reset_password",
+            None,
+            json!({"token": reset, "password": "battery staple"}),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // the sessions of the old password are over
+        let res =
+            send(request("/users/me", Some(&token), json!({})))
+                .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let (refresh_tokens,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM _cms_refresh_tokens;",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(refresh_tokens, 0);
+
+        let res = send(login_request(
+            "ada@example.com",
+            "correct horse",
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = send(login_request(
+            "ada@example.com",
+            "battery staple",
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -1,31 +1,34 @@
 use axum::middleware::from_fn;
 use axum::{routing::post, Router};
-use sqlx::{Pool, Sqlite};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode, Executor,
+    IntoArguments, Pool, Type,
+};
 
-use crate::auth::need_super_user;
+use crate::backend::Backend;
 use crate::operations::delete_one::delete_one_dynmaic as delete_one;
 use crate::operations::insert_one::insert_one_dynamic as insert_one;
 use crate::operations::select_many::get_all_dynamic as get_many;
 use crate::operations::select_one::get_one_dynamic as get_one;
 use crate::operations::update_one::update_one_dynmaic as update_one;
+use crate::permissions::authorize;
 
-pub fn collections_router() -> Router<Pool<Sqlite>> {
+pub fn collections_router<S>() -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    i32: Type<S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
     Router::new()
-        .route("/{collection}/get_one", post(get_one))
-        .route("/{collection}/get_many", post(get_many))
-        .route(
-            "/{collection}/insert_one",
-            post(insert_one)
-                .route_layer(from_fn(need_super_user)),
-        )
-        .route(
-            "/{collection}/update_one",
-            post(update_one)
-                .route_layer(from_fn(need_super_user)),
-        )
-        .route(
-            "/{collection}/delete_one",
-            post(delete_one)
-                .route_layer(from_fn(need_super_user)),
-        )
+        .route("/{collection}/get_one", post(get_one::<S>))
+        .route("/{collection}/get_many", post(get_many::<S>))
+        .route("/{collection}/insert_one", post(insert_one::<S>))
+        .route("/{collection}/update_one", post(update_one::<S>))
+        .route("/{collection}/delete_one", post(delete_one::<S>))
+        .route_layer(from_fn(authorize::<S>))
 }
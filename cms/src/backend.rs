@@ -0,0 +1,654 @@
+use std::{collections::HashMap, sync::Arc, sync::LazyLock};
+
+use inventory::Collect;
+use queries_for_sqlx::{SupportNamedBind, SupportReturning};
+use sqlx::{Database, Postgres, Sqlite};
+use tokio::sync::RwLock;
+
+use crate::{
+    api_keys::ApiKeys,
+    dynamic_schema::{
+        CompleteRelationForServer, DynCollection,
+        SubmitDynCollection, SubmitDynRelation,
+    },
+    filters::{self, Compare, FilterValue},
+    migration2::{DynMigration, SubmitDynMigrate},
+    permissions::Permissions,
+    queries_bridge::SelectSt,
+    queries_for_sqlx_extention::{
+        primary_key, ColumnKind, SqlxQuery,
+    },
+};
+
+pub type Collections<S> =
+    RwLock<HashMap<String, Box<dyn DynCollection<S>>>>;
+
+pub type Relations<S> = RwLock<
+    HashMap<String, Vec<Arc<dyn CompleteRelationForServer<S>>>>,
+>;
+
+/// a database the cms can run on, the backend is picked at
+/// startup by the pool that is given to the routers and to
+/// `run_migration`.
+///
+/// sqlx bounds that are not about `Self` (like
+/// `i64: Type<S>`) can't be implied by this trait, generic
+/// code still has to spell them out.
+pub trait Backend:
+    Database
+    + SupportNamedBind
+    + SupportReturning
+    + SqlxQuery
+    + Sized
+{
+    /// every collection submitted for this backend, by table
+    /// name
+    fn collections() -> &'static Collections<Self>;
+
+    /// every relation submitted for this backend, by the
+    /// table it is listed under
+    fn relations() -> &'static Relations<Self>;
+
+    /// roles and grants loaded by `init_permissions`
+    fn permissions() -> &'static RwLock<Permissions>;
+
+    /// api keys loaded by `init_api_keys`
+    fn api_keys() -> &'static RwLock<ApiKeys>;
+
+    /// every migration submitted for this backend
+    fn migrations() -> Vec<Box<dyn DynMigration<Self>>>;
+
+    /// statements to turn foreign key enforcement on or off for
+    /// the current connection, used around table rebuilds
+    fn foreign_keys(on: bool) -> Option<&'static str> {
+        let _ = on;
+        None
+    }
+
+    /// columns can be added and dropped with `ALTER TABLE`, a
+    /// migration only rebuilds the table when a column or a
+    /// constraint changes
+    fn alters_columns() -> bool {
+        false
+    }
+
+    /// column definition of the id of tables the cms owns
+    /// (migration history, seeds, super users)
+    fn id_column() -> String {
+        use queries_for_sqlx::SchemaColumn;
+        SchemaColumn::<Self>::display(&primary_key::<Self>())
+    }
+
+    /// column type of `i64` values in tables the cms owns
+    fn i64_column() -> &'static str;
+
+    /// lists the name of every table of the database
+    fn tables_st() -> &'static str;
+
+    /// the value at `path` inside the JSON `column`, for filters
+    /// like `{"settings.theme": {"$eq": "dark"}}`. segments are
+    /// checked to be plain words before they get here
+    fn json_path(column: &str, path: &[&str]) -> String;
+
+    /// the column (or `json_path`) of a filter and how its
+    /// placeholder is written, for columns that don't compare as
+    /// they are stored
+    fn filter_operands(
+        kind: ColumnKind,
+        lhs: String,
+    ) -> (String, fn(String) -> String) {
+        let _ = kind;
+        (lhs, |e| e)
+    }
+
+    /// adds a filter to the where clause, implemented with
+    /// `filters::bind_filter` where the sqlx types of the backend
+    /// are known
+    fn bind_filter(
+        st: &mut SelectSt<Self>,
+        compare: Compare<FilterValue>,
+    );
+}
+
+fn collect_collections<S>() -> Collections<S>
+where
+    S: Backend,
+    SubmitDynCollection<S>: Collect,
+{
+    let mut map = HashMap::new();
+
+    for collection in inventory::iter::<SubmitDynCollection<S>> {
+        let obj = (collection.obj)();
+        map.insert(obj.table_name().to_owned(), obj);
+    }
+
+    RwLock::new(map)
+}
+
+fn collect_relations<S>() -> Relations<S>
+where
+    S: Backend,
+    SubmitDynRelation<S>: Collect,
+{
+    let mut map = HashMap::<_, Vec<_>>::new();
+
+    for relation in inventory::iter::<SubmitDynRelation<S>> {
+        let obj = (relation.obj)();
+        let key = obj.list_iteself_under();
+        map.entry(key).or_default().push(obj);
+    }
+
+    tracing::debug!("{:?}", map);
+
+    RwLock::new(map)
+}
+
+fn collect_migrations<S>() -> Vec<Box<dyn DynMigration<S>>>
+where
+    S: Backend,
+    SubmitDynMigrate<S>: Collect,
+{
+    inventory::iter::<SubmitDynMigrate<S>>
+        .into_iter()
+        .map(|e| (e.obj)())
+        .collect()
+}
+
+impl Backend for Sqlite {
+    fn collections() -> &'static Collections<Self> {
+        static COLLECTIONS: LazyLock<Collections<Sqlite>> =
+            LazyLock::new(collect_collections);
+        &COLLECTIONS
+    }
+    fn relations() -> &'static Relations<Self> {
+        static RELATIONS: LazyLock<Relations<Sqlite>> =
+            LazyLock::new(collect_relations);
+        &RELATIONS
+    }
+    fn permissions() -> &'static RwLock<Permissions> {
+        static PERMISSIONS: LazyLock<RwLock<Permissions>> =
+            LazyLock::new(Default::default);
+        &PERMISSIONS
+    }
+    fn api_keys() -> &'static RwLock<ApiKeys> {
+        static API_KEYS: LazyLock<RwLock<ApiKeys>> =
+            LazyLock::new(Default::default);
+        &API_KEYS
+    }
+    fn migrations() -> Vec<Box<dyn DynMigration<Self>>> {
+        collect_migrations()
+    }
+    fn i64_column() -> &'static str {
+        "INTEGER"
+    }
+    fn tables_st() -> &'static str {
+        "SELECT name FROM sqlite_master WHERE type = 'table';"
+    }
+    fn json_path(column: &str, path: &[&str]) -> String {
+        let path = path
+            .iter()
+            .map(|e| match e.parse::<usize>() {
+                Ok(index) => format!("[{index}]"),
+                Err(_) => format!(".{e}"),
+            })
+            .collect::<String>();
+        format!("json_extract({column}, '${path}')")
+    }
+    /// decimals are TEXT, they are compared as numbers
+    fn filter_operands(
+        kind: ColumnKind,
+        lhs: String,
+    ) -> (String, fn(String) -> String) {
+        match kind {
+            ColumnKind::Decimal => {
+                (format!("CAST({lhs} AS REAL)"), |e| {
+                    format!("CAST({e} AS REAL)")
+                })
+            }
+            _ => (lhs, |e| e),
+        }
+    }
+    fn bind_filter(
+        st: &mut SelectSt<Self>,
+        compare: Compare<FilterValue>,
+    ) {
+        filters::bind_filter(st, compare)
+    }
+    /// table rebuilds drop tables that other tables may refer
+    /// to, with foreign keys on, sqlite would cascade the
+    /// implicit delete. the pragma is a no-op inside a
+    /// transaction, so it has to be set before `BEGIN`
+    fn foreign_keys(on: bool) -> Option<&'static str> {
+        Some(if on {
+            "PRAGMA foreign_keys = ON;"
+        } else {
+            "PRAGMA foreign_keys = OFF;"
+        })
+    }
+}
+
+impl Backend for Postgres {
+    /// a rebuild restarts the identity of the table and has to
+    /// drop the foreign keys pointing at it
+    fn alters_columns() -> bool {
+        true
+    }
+
+    fn collections() -> &'static Collections<Self> {
+        static COLLECTIONS: LazyLock<Collections<Postgres>> =
+            LazyLock::new(collect_collections);
+        &COLLECTIONS
+    }
+    fn relations() -> &'static Relations<Self> {
+        static RELATIONS: LazyLock<Relations<Postgres>> =
+            LazyLock::new(collect_relations);
+        &RELATIONS
+    }
+    fn permissions() -> &'static RwLock<Permissions> {
+        static PERMISSIONS: LazyLock<RwLock<Permissions>> =
+            LazyLock::new(Default::default);
+        &PERMISSIONS
+    }
+    fn api_keys() -> &'static RwLock<ApiKeys> {
+        static API_KEYS: LazyLock<RwLock<ApiKeys>> =
+            LazyLock::new(Default::default);
+        &API_KEYS
+    }
+    fn migrations() -> Vec<Box<dyn DynMigration<Self>>> {
+        collect_migrations()
+    }
+    fn i64_column() -> &'static str {
+        "BIGINT"
+    }
+    fn tables_st() -> &'static str {
+        "SELECT table_name::TEXT FROM information_schema.tables
+        WHERE table_schema = current_schema();"
+    }
+    /// `#>` keeps the jsonb, so numbers and strings compare by
+    /// their JSON type
+    fn json_path(column: &str, path: &[&str]) -> String {
+        format!("{column} #> '{{{}}}'", path.join(","))
+    }
+    fn filter_operands(
+        kind: ColumnKind,
+        lhs: String,
+    ) -> (String, fn(String) -> String) {
+        match kind {
+            ColumnKind::Json => {
+                (lhs, |e| format!("to_jsonb({e})"))
+            }
+            _ => (lhs, |e| e),
+        }
+    }
+    fn bind_filter(
+        st: &mut SelectSt<Self>,
+        compare: Compare<FilterValue>,
+    ) {
+        filters::bind_filter(st, compare)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        extract::{Path, State},
+        http::{Method, Request},
+        Extension, Json,
+    };
+    use jwt::ToBase64;
+    use queries_for_sqlx::ident_safety::define_schema;
+    use serde_json::{from_value, json};
+    use sqlx::{Pool, Postgres};
+    use tower::ServiceExt;
+
+    use crate::{
+        auth::{
+            auth_router,
+            create_super_user_if_not_exist_and_return_init_token,
+        },
+        migration2::{
+            diff::diff,
+            history::{self, current_version},
+            rollback_migration, run_migration,
+        },
+        permissions::test::keys,
+        operations::{
+            delete_one::delete_one_dynmaic,
+            insert_one::insert_one_dynamic,
+            select_one::get_one_dynamic,
+            update_one::update_one_dynmaic,
+        },
+    };
+
+    /// runs only when `CMS_TEST_POSTGRES_URL` is set, the
+    /// database is wiped on every run
+    #[tokio::test]
+    async fn postgres_end_to_end() {
+        let Ok(url) = std::env::var("CMS_TEST_POSTGRES_URL")
+        else {
+            return;
+        };
+        // the super user logs in, and TOTP can be required
+        let _serial =
+            crate::permissions::test::SERIAL.lock().await;
+
+        let pool =
+            Pool::<Postgres>::connect(&url).await.unwrap();
+
+        sqlx::raw_sql(
+            "DROP SCHEMA public CASCADE; CREATE SCHEMA public;",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        run_migration(pool.clone()).await.unwrap();
+        let version = current_version(&pool).await.unwrap();
+        run_migration(pool.clone()).await.unwrap();
+        assert_eq!(
+            current_version(&pool).await.unwrap(),
+            version
+        );
+
+        // the relation columns and `TodoTag` come from the
+        // migration, the rest of the rows from the
+        // `test_fixtures` seed, which has tags 1 and 2
+        sqlx::raw_sql(
+            "INSERT INTO Tag (tag_title) VALUES ('tag_1'), ('tag_2');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        define_schema(&[
+            ("Todo", &["id", "title", "done", "description"]),
+            ("Tag", &["id", "tag_title"]),
+            ("Category", &["id", "cat_title"]),
+            ("TodoTag", &["todo_id", "tag_id"]),
+        ]);
+
+        let res = insert_one_dynamic(
+            State(pool.clone()),
+            Path("todo".to_string()),
+            Json(
+                from_value(json!({
+                    "input": {
+                        "title": "todo_1",
+                        "done": false,
+                        "description": "description"
+                    },
+                    "relation": {
+                        "category": {
+                            "set_id_to_and_populate": 1
+                        },
+                        "tag": {
+                            "set_id_to_and_populate": [3, 4]
+                        },
+                    }
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        pretty_assertions::assert_eq!(
+            serde_json::to_value(res.0).unwrap(),
+            json!({
+                "id": 2,
+                "attr": {
+                    "title": "todo_1",
+                    "done": false,
+                    "description": "description"
+                },
+                "relations": {
+                    "category": {
+                        "id": 1,
+                        "attr": { "cat_title": "work" }
+                    },
+                    "tag": [
+                        { "id": 3, "attr": { "tag_title": "tag_1" } },
+                        { "id": 4, "attr": { "tag_title": "tag_2" } },
+                    ]
+                }
+            })
+        );
+
+        let _ = update_one_dynmaic(
+            State(pool.clone()),
+            Path("todo".to_string()),
+            Json(
+                from_value(json!({
+                    "id": 2,
+                    "partial": { "done": ["set", true] },
+                    "relations": {
+                        "category": { "set": null },
+                        "tag": [{ "remove_link": 3 }],
+                    }
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let res = get_one_dynamic(
+            State(pool.clone()),
+            Path("todo".to_string()),
+            Json(
+                from_value(json!({
+                    "id": 2,
+                    "filters": {},
+                    "relations": { "category": {}, "tag": {} }
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        pretty_assertions::assert_eq!(
+            serde_json::to_value(res.0).unwrap(),
+            json!({
+                "id": 2,
+                "attr": {
+                    "title": "todo_1",
+                    "done": true,
+                    "description": "description"
+                },
+                "relations": {
+                    "category": null,
+                    "tag": [
+                        { "id": 4, "attr": { "tag_title": "tag_2" } },
+                    ]
+                }
+            })
+        );
+
+        let _ = delete_one_dynmaic(
+            State(pool.clone()),
+            Path("todo".to_string()),
+            Json(
+                from_value(json!({
+                    "id": 2,
+                    "return_attr": false,
+                    "return_residual": []
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let left: (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM TodoTag WHERE todo_id = 2",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(left.0, 0);
+
+        // a migration that rebuilds `Category`, which `Todo`
+        // points at, then its rollback. the identity and the key
+        // of `Todo` survive both
+        let before = current_version(&pool).await.unwrap();
+        let prev = history::latest_snapshot::<Postgres>(
+            &mut pool.acquire().await.unwrap(),
+        )
+        .await
+        .unwrap();
+        let mut next = prev.clone();
+        next.get_mut("Category")
+            .unwrap()
+            .columns
+            .iter_mut()
+            .find(|e| e.0 == "cat_title")
+            .unwrap()
+            .1
+            .push_str(" DEFAULT 'untitled'");
+        let steps = diff::<Postgres>(
+            &prev,
+            &next,
+            &Default::default(),
+            &Default::default(),
+        )
+        .unwrap();
+        history::apply(&pool, steps, &next).await.unwrap();
+
+        let insert = |title: &'static str, category: i64| {
+            let pool = pool.clone();
+            async move {
+                insert_one_dynamic(
+                    State(pool),
+                    Path("todo".to_string()),
+                    Json(
+                        from_value(json!({
+                            "input": {
+                                "title": title,
+                                "done": false,
+                                "description": null
+                            },
+                            "relation": { "category": {
+                                "set_id_to_and_populate": category
+                            }}
+                        }))
+                        .unwrap(),
+                    ),
+                )
+                .await
+            }
+        };
+        let new_category = || async {
+            sqlx::query_as::<_, (i64,)>(
+                "INSERT INTO Category (cat_title) VALUES ('new')
+                RETURNING id;",
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .0
+        };
+
+        let linked = |res: Json<_>| {
+            serde_json::to_value(res.0).unwrap()["relations"]
+                ["category"]["id"]
+                .clone()
+        };
+
+        assert_eq!(new_category().await, 2);
+        let res = insert("after_rebuild", 2).await.unwrap();
+        assert_eq!(linked(res), json!(2));
+
+        rollback_migration(&pool, before).await.unwrap();
+        assert_eq!(new_category().await, 3);
+        let res = insert("after_rollback", 3).await.unwrap();
+        assert_eq!(linked(res), json!(3));
+
+        let err = insert("dangling", 99).await.unwrap_err();
+        assert_eq!(
+            err.user_error.unwrap().code,
+            "foreign_key_violation"
+        );
+
+        std::env::set_var("JWT_SALT", "test");
+
+        let token =
+            create_super_user_if_not_exist_and_return_init_token(
+                pool.clone(),
+                &keys(),
+            )
+            .await
+            .expect("new db therfore token should be generated");
+        crate::auth::session::init_sessions(&pool)
+            .await
+            .unwrap();
+        crate::auth::totp::init_totp(&pool).await.unwrap();
+
+        let res = auth_router::<Postgres>()
+        .layer(Extension(keys()))
+        .with_state(pool.clone())
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .header("content-type", "application/json")
+                .header(
+                    "authorization",
+                    format!("Bearer {}", token),
+                )
+                .uri("/init/sign_in_first")
+                .body(Body::from(
+                    json!({
+                        "email": "test@example.com",
+                        "user_name": "test",
+                        "password": "test",
+                    })
+                    .to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert!(res.status().is_success());
+
+        let user: (String, String) = sqlx::query_as(
+            "SELECT user_name, email FROM _super_users",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            user,
+            ("test".to_string(), "test@example.com".to_string())
+        );
+
+        let basic = json!({
+            "email": "test@example.com",
+            "password": "test",
+        })
+        .to_base64()
+        .unwrap()
+        .to_string();
+
+        let res = auth_router::<Postgres>()
+        .layer(Extension(keys()))
+        .with_state(pool.clone())
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .header(
+                    "authorization",
+                    format!("Basic {}", basic),
+                )
+                .uri("/login")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert!(res.status().is_success());
+        assert!(res.headers().get("X-Cms-Token").is_some());
+    }
+}
@@ -7,9 +7,11 @@ use std::{
 use crate::{
     dynamic_schema::{SubmitDynCollection, SubmitDynRelation},
     migration2::SubmitDynMigrate,
-    queries_bridge::{CreatTableSt, SelectSt, UpdateSt},
+    queries_bridge::{
+        CreatTableSt, CreateIndexSt, SelectSt, UpdateSt,
+    },
     queries_for_sqlx_extention::{
-        col_type_check_if_null, primary_key,
+        col_type_check_if_null, primary_key, SqlxQuery,
     },
     relations::{
         many_to_many::{ManyToMany, ManyToManyDynamic},
@@ -19,7 +21,9 @@ use crate::{
         optional_to_many_inverse::OptionalToManyInverse,
         LinkSpec, Linked,
     },
-    traits::{DynValidate, Collection, Update},
+    traits::{
+        Collection, DynValidate, FieldErrors, Update, Validate,
+    },
 };
 use inventory::submit;
 use queries_for_sqlx::{
@@ -29,10 +33,14 @@ use queries_for_sqlx::{
     ident_safety::PanicOnUnsafe,
     prelude::*,
     quick_query::QuickQuery,
+    SupportNamedBind,
 };
 use serde::{de::Visitor, Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::{Row, Sqlite};
+use sqlx::{
+    ColumnIndex, Database, Decode, Encode, Postgres, Row, Sqlite,
+    Type,
+};
 
 impl Linked<Category> for Todo {
     type Spec = OptionalToMany;
@@ -44,15 +52,40 @@ impl Linked<Category> for Todo {
 }
 
 submit! {
-    SubmitDynRelation {
+    SubmitDynRelation::<Sqlite> {
         obj: || {
             Arc::new(
-                OptionalToManyDynamic::<Todo, Category>::new()
+                OptionalToManyDynamic::<Todo, Category, Sqlite>::new()
             )
         }
     }
 }
 
+submit! {
+    SubmitDynRelation::<Postgres> {
+        obj: || {
+            Arc::new(
+                OptionalToManyDynamic::<Todo, Category, Postgres>::new()
+            )
+        }
+    }
+}
+
+submit!(SubmitDynMigrate::<Sqlite> {
+    obj: || {
+        Box::new(
+            OptionalToManyDynamic::<Todo, Category, Sqlite>::new(),
+        )
+    }
+});
+submit!(SubmitDynMigrate::<Postgres> {
+    obj: || {
+        Box::new(
+            OptionalToManyDynamic::<Todo, Category, Postgres>::new(),
+        )
+    }
+});
+
 impl Linked<Todo> for Category {
     type Spec = OptionalToManyInverse;
     fn spec() -> Self::Spec {
@@ -66,37 +99,68 @@ impl Linked<Tag> for Todo {
         ManyToMany {
             conjuction_table: format!(
                 "{}{}",
-                Todo::table_name(),
-                Tag::table_name(),
+                <Todo as Collection<Sqlite>>::table_name(),
+                <Tag as Collection<Sqlite>>::table_name(),
             ),
             // this should be the inverse
             base_id: format!(
                 "{}_id",
-                Todo::table_name().to_lowercase()
+                <Todo as Collection<Sqlite>>::table_name().to_lowercase()
             ),
             destination_id: format!(
                 "{}_id",
-                Tag::table_name().to_lowercase()
+                <Tag as Collection<Sqlite>>::table_name().to_lowercase()
             ),
         }
     }
 }
 
 submit! {
-    SubmitDynRelation {
+    SubmitDynRelation::<Sqlite> {
+        obj: || {
+            Arc::new(
+                ManyToManyDynamic::<Todo, Tag, Sqlite>::new()
+            )
+        }
+    }
+}
+
+submit! {
+    SubmitDynRelation::<Postgres> {
+        obj: || {
+            Arc::new(
+                ManyToManyDynamic::<Todo, Tag, Postgres>::new()
+            )
+        }
+    }
+}
+
+submit!(SubmitDynMigrate::<Sqlite> {
+    obj: || {
+        Box::new(ManyToManyDynamic::<Todo, Tag, Sqlite>::new())
+    }
+});
+submit!(SubmitDynMigrate::<Postgres> {
+    obj: || {
+        Box::new(ManyToManyDynamic::<Todo, Tag, Postgres>::new())
+    }
+});
+
+submit! {
+    SubmitDynRelation::<Sqlite> {
         obj: || {
             Arc::new(
-                ManyToManyDynamic::<Todo, Tag>::new()
+                ManyToManyDynamic::<Tag, Todo, Sqlite>::new()
             )
         }
     }
 }
 
 submit! {
-    SubmitDynRelation {
+    SubmitDynRelation::<Postgres> {
         obj: || {
             Arc::new(
-                ManyToManyDynamic::<Tag, Todo>::new()
+                ManyToManyDynamic::<Tag, Todo, Postgres>::new()
             )
         }
     }
@@ -108,17 +172,17 @@ impl Linked<Todo> for Tag {
         ManyToMany {
             conjuction_table: format!(
                 "{}{}",
-                Todo::table_name(),
-                Tag::table_name(),
+                <Todo as Collection<Sqlite>>::table_name(),
+                <Tag as Collection<Sqlite>>::table_name(),
             ),
             // this should be the inverse
             base_id: format!(
                 "{}_id",
-                Tag::table_name().to_lowercase()
+                <Tag as Collection<Sqlite>>::table_name().to_lowercase()
             ),
             destination_id: format!(
                 "{}_id",
-                Todo::table_name().to_lowercase()
+                <Todo as Collection<Sqlite>>::table_name().to_lowercase()
             ),
         }
     }
@@ -141,10 +205,16 @@ pub struct Todo {
     pub description: Option<String>,
 }
 
-submit! {SubmitDynCollection {
+submit! {SubmitDynCollection::<Sqlite> {
     obj: || Box::new(PhantomData::<Todo>)
 }}
-submit!(SubmitDynMigrate {
+submit!(SubmitDynMigrate::<Sqlite> {
+    obj: || Box::new(PhantomData::<Todo>)
+});
+submit! {SubmitDynCollection::<Postgres> {
+    obj: || Box::new(PhantomData::<Todo>)
+}}
+submit!(SubmitDynMigrate::<Postgres> {
     obj: || Box::new(PhantomData::<Todo>)
 });
 
@@ -164,9 +234,38 @@ pub struct Partial {
     pub description: Update<Option<String>>,
 }
 
-impl Collection<Sqlite> for Todo {
-    fn on_migrate(stmt: &mut CreatTableSt<Sqlite>) {
-        stmt.column("id", primary_key::<Sqlite>());
+impl Validate for Todo {
+    type Partial = Partial;
+    fn validate_on_insert(&mut self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::default();
+        if self.title.trim().is_empty() {
+            errors.add("title", "title can't be empty");
+        }
+        errors.into_result()
+    }
+    fn validate_on_update(
+        partial: &mut Partial,
+    ) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::default();
+        if let Update::set(title) = &partial.title {
+            if title.trim().is_empty() {
+                errors.add("title", "title can't be empty");
+            }
+        }
+        errors.into_result()
+    }
+}
+
+impl<S> Collection<S> for Todo
+where
+    S: Database + SupportNamedBind + SqlxQuery,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    String: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    bool: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    Option<String>: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    fn on_migrate(stmt: &mut CreatTableSt<S>) {
+        stmt.column("id", primary_key::<S>());
         stmt.column("title", col_type_check_if_null::<String>());
         stmt.column("done", col_type_check_if_null::<bool>());
         stmt.column(
@@ -174,15 +273,29 @@ impl Collection<Sqlite> for Todo {
             col_type_check_if_null::<String>(),
         );
     }
+    fn on_index(indexes: &mut Vec<CreateIndexSt<S>>) {
+        let mut index =
+            CreateIndexSt::init(("todo_done_idx", "Todo"));
+        index.column("done");
+        indexes.push(index);
+    }
     fn members() -> &'static [&'static str] {
         &["title", "done", "description"]
     }
+    fn on_validate_insert(&mut self) -> Result<(), FieldErrors> {
+        <Self as Validate>::validate_on_insert(self)
+    }
+    fn on_validate_update(
+        this: &mut Self::PartailCollection,
+    ) -> Result<(), FieldErrors> {
+        <Self as Validate>::validate_on_update(this)
+    }
     fn members_scoped() -> &'static [&'static str] {
         &["todo_title", "todo_done", "todo_description"]
     }
     type PartailCollection = Partial;
     fn on_update(
-        stmt: &mut UpdateSt<Sqlite>,
+        stmt: &mut UpdateSt<S>,
         this: Self::PartailCollection,
     ) -> Result<(), std::string::String> {
         if let Update::set(val) = this.title {
@@ -283,14 +396,14 @@ impl Collection<Sqlite> for Todo {
     fn table_name() -> &'static str {
         "Todo"
     }
-    fn on_select(stmt: &mut SelectSt<Sqlite>) {
+    fn on_select(stmt: &mut SelectSt<S>) {
         stmt.select("title");
         stmt.select("done");
         stmt.select("description");
     }
     fn on_insert(
         self,
-        stmt: &mut stmt::InsertStOne<'_, sqlx::Sqlite>,
+        stmt: &mut stmt::InsertStOne<'_, S>,
     ) -> Result<(), String> {
         stmt.insert("title".to_owned(), {
             // run mods
@@ -310,7 +423,7 @@ impl Collection<Sqlite> for Todo {
     // }
     // fn on_insert_ref_mod(
     //     this: Value,
-    //     stmt: &mut stmt::InsertStOne<'_, sqlx::Sqlite>,
+    //     stmt: &mut stmt::InsertStOne<'_, S>,
     //     mods: &HashMap<
     //         String,
     //         Vec<Box<dyn DynValidate + Send + Sync>>,
@@ -353,7 +466,7 @@ impl Collection<Sqlite> for Todo {
     //             .clone(),
     //     );
     // }
-    fn from_row_scoped(row: &sqlx::sqlite::SqliteRow) -> Self {
+    fn from_row_scoped(row: &S::Row) -> Self {
         Self {
             // run all mods
             title: {
@@ -367,7 +480,7 @@ impl Collection<Sqlite> for Todo {
             description: row.get("description"),
         }
     }
-    fn from_row_noscope(row: &sqlx::sqlite::SqliteRow) -> Self {
+    fn from_row_noscope(row: &S::Row) -> Self {
         Self {
             // run all mods
             title: {
@@ -383,7 +496,7 @@ impl Collection<Sqlite> for Todo {
     }
 
     // fn on_get_no_mods(
-    //     row: &mut sqlx::sqlite::SqliteRow,
+    //     row: &mut S::Row,
     // ) -> Self {
     //     Self {
     //         title: row.get("title"),
@@ -407,10 +520,16 @@ pub struct Category {
     pub cat_title: String,
 }
 
-submit! {SubmitDynCollection {
+submit! {SubmitDynCollection::<Sqlite> {
+    obj: || Box::new(PhantomData::<Category>)
+}}
+submit!(SubmitDynMigrate::<Sqlite> {
+    obj: || Box::new(PhantomData::<Category>)
+});
+submit! {SubmitDynCollection::<Postgres> {
     obj: || Box::new(PhantomData::<Category>)
 }}
-submit!(SubmitDynMigrate {
+submit!(SubmitDynMigrate::<Postgres> {
     obj: || Box::new(PhantomData::<Category>)
 });
 
@@ -428,10 +547,15 @@ pub struct PartialCategory {
     pub cat_title: Update<String>,
 }
 
-impl Collection<Sqlite> for Category {
+impl<S> Collection<S> for Category
+where
+    S: Database + SupportNamedBind + SqlxQuery,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    String: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
     type PartailCollection = PartialCategory;
-    fn on_migrate(stmt: &mut CreatTableSt<Sqlite>) {
-        stmt.column("id", primary_key::<Sqlite>());
+    fn on_migrate(stmt: &mut CreatTableSt<S>) {
+        stmt.column("id", primary_key::<S>());
         stmt.column(
             "cat_title",
             col_type_check_if_null::<String>(),
@@ -444,7 +568,7 @@ impl Collection<Sqlite> for Category {
         &["cat_cat_title"]
     }
     fn on_update(
-        stmt: &mut UpdateSt<Sqlite>,
+        stmt: &mut UpdateSt<S>,
         this: Self::PartailCollection,
     ) -> Result<(), String> {
         if let Update::set(val) = this.cat_title {
@@ -489,7 +613,10 @@ impl Collection<Sqlite> for Category {
     fn table_name() -> &'static str {
         "Category"
     }
-    fn on_select(stmt: &mut SelectSt<Sqlite>) {
+    fn owned() -> bool {
+        true
+    }
+    fn on_select(stmt: &mut SelectSt<S>) {
         stmt.select_aliased(
             "Category",
             "cat_title",
@@ -498,7 +625,7 @@ impl Collection<Sqlite> for Category {
     }
     fn on_insert(
         self,
-        stmt: &mut stmt::InsertStOne<'_, sqlx::Sqlite>,
+        stmt: &mut stmt::InsertStOne<'_, S>,
     ) -> Result<(), String> {
         stmt.insert("cat_title".to_owned(), self.cat_title);
         Ok(())
@@ -508,7 +635,7 @@ impl Collection<Sqlite> for Category {
     // }
     // fn on_insert_ref_mod(
     //     this: Value,
-    //     stmt: &mut stmt::InsertStOne<'_, sqlx::Sqlite>,
+    //     stmt: &mut stmt::InsertStOne<'_, S>,
     //     mods: &HashMap<
     //         String,
     //         Vec<Box<dyn DynValidate + Send + Sync>>,
@@ -533,18 +660,18 @@ impl Collection<Sqlite> for Category {
     //         val
     //     });
     // }
-    fn from_row_scoped(row: &sqlx::sqlite::SqliteRow) -> Self {
+    fn from_row_scoped(row: &S::Row) -> Self {
         Self {
             cat_title: row.try_get("cat_cat_title").unwrap(),
         }
     }
-    fn from_row_noscope(row: &sqlx::sqlite::SqliteRow) -> Self {
+    fn from_row_noscope(row: &S::Row) -> Self {
         Self {
             cat_title: row.try_get("cat_title").unwrap(),
         }
     }
     // fn on_get_no_mods(
-    //     row: &mut sqlx::sqlite::SqliteRow,
+    //     row: &mut S::Row,
     // ) -> Self {
     //     Self {
     //         cat_title: row.try_get("cat_cat_title").unwrap(),
@@ -567,10 +694,16 @@ pub struct Tag {
     pub tag_title: String,
 }
 
-submit! {SubmitDynCollection {
+submit! {SubmitDynCollection::<Sqlite> {
+    obj: || Box::new(PhantomData::<Tag>)
+}}
+submit!(SubmitDynMigrate::<Sqlite> {
+    obj: || Box::new(PhantomData::<Tag>)
+});
+submit! {SubmitDynCollection::<Postgres> {
     obj: || Box::new(PhantomData::<Tag>)
 }}
-submit!(SubmitDynMigrate {
+submit!(SubmitDynMigrate::<Postgres> {
     obj: || Box::new(PhantomData::<Tag>)
 });
 
@@ -588,10 +721,15 @@ pub struct PartialTag {
     pub tag_title: Update<String>,
 }
 
-impl Collection<Sqlite> for Tag {
+impl<S> Collection<S> for Tag
+where
+    S: Database + SupportNamedBind + SqlxQuery,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    String: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
     type PartailCollection = PartialTag;
-    fn on_migrate(stmt: &mut CreatTableSt<Sqlite>) {
-        stmt.column("id", primary_key::<Sqlite>());
+    fn on_migrate(stmt: &mut CreatTableSt<S>) {
+        stmt.column("id", primary_key::<S>());
         stmt.column(
             "tag_title",
             col_type_check_if_null::<String>(),
@@ -606,7 +744,7 @@ impl Collection<Sqlite> for Tag {
     }
 
     fn on_update(
-        stmt: &mut UpdateSt<Sqlite>,
+        stmt: &mut UpdateSt<S>,
         this: Self::PartailCollection,
     ) -> Result<(), String> {
         if let Update::set(val) = this.tag_title {
@@ -653,14 +791,14 @@ impl Collection<Sqlite> for Tag {
     fn table_name() -> &'static str {
         "Tag"
     }
-    fn on_select(stmt: &mut SelectSt<Sqlite>) {
+    fn on_select(stmt: &mut SelectSt<S>) {
         stmt.select_aliased("Tag", "tag_title", "tag_tag_title");
     }
     fn on_insert(
         self,
-        stmt: &mut stmt::InsertStOne<'_, sqlx::Sqlite>,
+        stmt: &mut stmt::InsertStOne<'_, S>,
     ) -> Result<(), String> {
-        stmt.insert("title".to_owned(), self.tag_title);
+        stmt.insert("tag_title".to_owned(), self.tag_title);
         Ok(())
     }
     // fn on_insert_returning() -> Vec<&'static str> {
@@ -668,7 +806,7 @@ impl Collection<Sqlite> for Tag {
     // }
     // fn on_insert_ref_mod(
     //     this: Value,
-    //     stmt: &mut stmt::InsertStOne<'_, sqlx::Sqlite>,
+    //     stmt: &mut stmt::InsertStOne<'_, S>,
     //     mods: &HashMap<
     //         String,
     //         Vec<Box<dyn DynValidate + Send + Sync>>,
@@ -693,18 +831,18 @@ impl Collection<Sqlite> for Tag {
     //         val
     //     });
     // }
-    fn from_row_scoped(row: &sqlx::sqlite::SqliteRow) -> Self {
+    fn from_row_scoped(row: &S::Row) -> Self {
         Self {
             tag_title: row.try_get("tag_tag_title").unwrap(),
         }
     }
-    fn from_row_noscope(row: &sqlx::sqlite::SqliteRow) -> Self {
+    fn from_row_noscope(row: &S::Row) -> Self {
         Self {
             tag_title: row.try_get("tag_title").unwrap(),
         }
     }
     // fn on_get_no_mods(
-    //     row: &mut sqlx::sqlite::SqliteRow,
+    //     row: &mut S::Row,
     // ) -> Self {
     //     Self {
     //         tag_title: row.try_get("tag_title").unwrap(),
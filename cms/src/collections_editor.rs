@@ -9,21 +9,45 @@ use axum::{
 };
 use serde::Serialize;
 use serde_json::json;
-use sqlx::{Pool, Sqlite};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
 use std::{
     collections::HashMap, convert::Infallible, future::Future,
     marker::PhantomData, ops::Not, sync::RwLock,
 };
 use tower::Service;
 
+use crate::api_keys::api_keys_router;
+use crate::audit::audit_router;
+use crate::auth::invitations::invitations_router;
 use crate::auth::need_super_user;
+use crate::auth::totp::settings_router;
+use crate::backend::Backend;
+use crate::permissions::permissions_router;
 
-pub fn admin_router() -> Router<Pool<Sqlite>> {
+pub fn admin_router<S>() -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
     let app = Router::new();
 
     let mut app = app
         .route("/inspect_schema", get(inspect_schema))
-        .route_layer(from_fn(need_super_user));
+        .route_layer(from_fn(need_super_user))
+        .nest("/permissions", permissions_router::<S>())
+        .nest("/api_keys", api_keys_router::<S>())
+        .nest("/settings", settings_router::<S>())
+        .nest("/invitations", invitations_router::<S>())
+        .nest("/audit", audit_router::<S>());
 
     app
 }
@@ -1,95 +1,38 @@
 use core::fmt;
 use std::{
-    collections::HashMap, future::Future, marker::PhantomData,
-    mem, pin::Pin, sync::Arc,
+    future::Future, marker::PhantomData, mem, pin::Pin, sync::Arc,
 };
 
 use inventory::collect;
-use queries_for_sqlx::prelude::*;
+use queries_for_sqlx::{prelude::*, SupportNamedBind};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::{from_value, Value};
-use sqlx::{sqlite::SqliteRow, Pool, Sqlite};
-use tokio::sync::RwLock;
+use sqlx::{Database, Encode, Pool, Postgres, Sqlite, Type};
 
-use crate::traits::Collection;
+use crate::{
+    permissions::OWNER_COLUMN,
+    queries_for_sqlx_extention::ColumnKind,
+    traits::{Collection, FieldErrors},
+    validation::{self, Rule},
+};
 
 use super::{
-    error::{self, insert::InsertError, GlobalError},
+    error::{self, insert::InsertError, ClientError, GlobalError},
     operations::{
         delete_one::DynDeleteWorker,
         insert_one::InsertOneWorker, select_many::GetAllWorker,
         update_one::UpdateOneWorker,
     },
-    queries_bridge::{InsertSt, SelectSt, UpdateSt},
+    queries_bridge::{DeleteSt, InsertSt, SelectSt, UpdateSt},
     relations::prelude::GetOneWorker,
 };
 
-use std::sync::LazyLock;
-
-pub static COLLECTIONS: LazyLock<
-    RwLock<HashMap<String, Box<dyn DynCollection>>>,
-> = LazyLock::new(|| {
-    let mut map = HashMap::new();
-
-    for collection in inventory::iter::<SubmitDynCollection> {
-        let obj = (collection.obj)();
-        map.insert(obj.table_name().to_owned(), obj);
-    }
-
-    return RwLock::new(map);
-});
-
-pub static RELATIONS: LazyLock<
-    RwLock<
-        HashMap<String, Vec<Arc<dyn CompleteRelationForServer>>>,
-    >,
-> = LazyLock::new(|| {
-    let mut map = HashMap::default();
-
-    for relation in inventory::iter::<SubmitDynRelation> {
-        let obj = (relation.obj)();
-        let key = obj.list_iteself_under();
-        let ve: &mut Vec<_> = map.entry(key).or_default();
-        ve.push(obj.clone())
-    }
-
-    tracing::debug!("{:?}", map);
-
-    return RwLock::new(map);
-});
-
-// lazy_static::lazy_static! {
-//     pub static ref COLLECTIONS: RwLock<HashMap<String, Box<dyn DynCollection>>> = {
-//         let mut map = HashMap::default();
-//
-//         for collection in inventory::iter::<SubmitDynCollection> {
-//             let obj = (collection.obj)();
-//             map.insert(obj.table_name().to_owned(), obj);
-//         }
-//
-//         return RwLock::new(map);
-//     };
-//     pub static ref RELATIONS: RwLock<HashMap<String, Vec<Arc<dyn CompleteRelationForServer>>>> = {
-//         let mut map = HashMap::default();
-//
-//         for relation in inventory::iter::<SubmitDynRelation> {
-//             let obj = (relation.obj)();
-//             let key = obj.list_iteself_under();
-//             let ve: &mut Vec<_> = map.entry(key).or_default();
-//             ve.push(obj.clone())
-//         }
-//
-//         tracing::debug!("{:?}", map);
-//
-//         return RwLock::new(map);
-//     };
-// }
-
-pub struct SubmitDynRelation {
-    pub obj: fn() -> Arc<dyn CompleteRelationForServer>,
+pub struct SubmitDynRelation<S: Database + SupportNamedBind> {
+    pub obj: fn() -> Arc<dyn CompleteRelationForServer<S>>,
 }
 
-collect!(SubmitDynRelation);
+collect!(SubmitDynRelation<Sqlite>);
+collect!(SubmitDynRelation<Postgres>);
 
 pub enum DynamicRelationResult<T> {
     Ok(T),
@@ -97,62 +40,66 @@ pub enum DynamicRelationResult<T> {
     NotFound,
 }
 
-pub trait CompleteRelationForServer:
+pub trait CompleteRelationForServer<S: Database + SupportNamedBind>:
     Send + Sync + 'static
 {
     // CamelCase
     fn list_iteself_under(&self) -> String;
     // snake_case
     fn key(&self) -> String;
+    // CamelCase, the collection the relation loads
+    fn to(&self) -> String;
     fn init_on_update(
         self: Arc<Self>,
         to: &str,
         input: Value,
-    ) -> DynamicRelationResult<Box<dyn DynUpdateOneWorker>>;
+    ) -> DynamicRelationResult<Box<dyn DynUpdateOneWorker<S>>>;
     fn init_on_insert(
         self: Arc<Self>,
         to: &str,
         input: Value,
-    ) -> DynamicRelationResult<Box<dyn DynInsertOneWorker>>;
+    ) -> DynamicRelationResult<Box<dyn DynInsertOneWorker<S>>>;
     fn init_on_get_all(
         self: Arc<Self>,
         to: &str,
         input: Value,
-    ) -> DynamicRelationResult<Box<dyn DynGetManyWorker>>;
+    ) -> DynamicRelationResult<Box<dyn DynGetManyWorker<S>>>;
     fn init_on_delete(
         self: Arc<Self>,
         to: &str,
-    ) -> DynamicRelationResult<Box<dyn DynDeleteWorker>> {
+    ) -> DynamicRelationResult<Box<dyn DynDeleteWorker<S>>> {
         DynamicRelationResult::NotFound
     }
     fn init_on_get(
         self: Arc<Self>,
         to: &str,
         input: Value,
-    ) -> DynamicRelationResult<Box<dyn DynGetOneWorker>>;
+    ) -> DynamicRelationResult<Box<dyn DynGetOneWorker<S>>>;
 }
 
-pub trait DynUpdateOneWorker: Send + Sync {
-    fn on_update(&mut self, st: &mut UpdateSt<Sqlite>);
-    fn from_row(&mut self, row: &SqliteRow);
+pub trait DynUpdateOneWorker<S: Database + SupportNamedBind>: Send + Sync {
+    fn on_update(&mut self, st: &mut UpdateSt<S>);
+    fn from_row(&mut self, row: &S::Row);
     fn sub_op1<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>>;
+        conn: &'this mut S::Connection,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>>;
     fn sub_op2<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>>;
+        conn: &'this mut S::Connection,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>>;
     fn take(&mut self) -> Value;
 }
 
-impl<RW> DynUpdateOneWorker for DynamicWorker<RW::Inner, RW>
+impl<S, RW> DynUpdateOneWorker<S>
+    for DynamicWorker<RW::Inner, RW, S>
 where
+    S: Database + SupportNamedBind,
     RW: Send + Sync,
-    RW: UpdateOneWorker,
+    RW: UpdateOneWorker<S>,
     RW::Output: Serialize,
 {
-    fn on_update(&mut self, st: &mut UpdateSt<Sqlite>) {
+    fn on_update(&mut self, st: &mut UpdateSt<S>) {
         RW::on_update(
             self.rw.as_ref().expect("should not be taken"),
             &mut self.inner,
@@ -160,7 +107,7 @@ where
         )
     }
 
-    fn from_row(&mut self, row: &SqliteRow) {
+    fn from_row(&mut self, row: &S::Row) {
         RW::from_row(
             self.rw.as_ref().expect("should not be taken"),
             &mut self.inner,
@@ -170,13 +117,13 @@ where
 
     fn sub_op1<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>> {
+        conn: &'this mut S::Connection,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>> {
         Box::pin(async {
             RW::sub_op1(
                 self.rw.as_ref().expect("should not be taken"),
                 &mut self.inner,
-                pool,
+                conn,
             )
             .await
         })
@@ -184,13 +131,13 @@ where
 
     fn sub_op2<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>> {
+        conn: &'this mut S::Connection,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>> {
         Box::pin(async {
             RW::sub_op2(
                 self.rw.as_ref().expect("should not be taken"),
                 &mut self.inner,
-                pool,
+                conn,
             )
             .await
         })
@@ -205,27 +152,29 @@ where
     }
 }
 
-pub trait DynInsertOneWorker: Send + Sync {
-    fn on_insert(&mut self, st: &mut InsertSt<Sqlite>);
-    fn from_row(&mut self, row: &SqliteRow);
+pub trait DynInsertOneWorker<S: Database + SupportNamedBind>: Send + Sync {
+    fn on_insert(&mut self, st: &mut InsertSt<S>);
+    fn from_row(&mut self, row: &S::Row);
     fn sub_op1<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>>;
+        conn: &'this mut S::Connection,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>>;
     fn sub_op2<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>>;
+        conn: &'this mut S::Connection,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>>;
     fn take(&mut self) -> Value;
 }
 
-impl<RW> DynInsertOneWorker for DynamicWorker<RW::Inner, RW>
+impl<S, RW> DynInsertOneWorker<S>
+    for DynamicWorker<RW::Inner, RW, S>
 where
+    S: Database + SupportNamedBind,
     RW: Send + Sync,
-    RW: InsertOneWorker,
+    RW: InsertOneWorker<S>,
     RW::Output: Serialize,
 {
-    fn on_insert(&mut self, st: &mut InsertSt<Sqlite>) {
+    fn on_insert(&mut self, st: &mut InsertSt<S>) {
         RW::on_insert(
             self.rw.as_ref().expect("should not be taken"),
             &mut self.inner,
@@ -233,7 +182,7 @@ where
         )
     }
 
-    fn from_row(&mut self, row: &SqliteRow) {
+    fn from_row(&mut self, row: &S::Row) {
         RW::from_row(
             self.rw.as_ref().expect("should not be taken"),
             &mut self.inner,
@@ -243,13 +192,13 @@ where
 
     fn sub_op1<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>> {
+        conn: &'this mut S::Connection,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>> {
         Box::pin(async {
             RW::sub_op1(
                 self.rw.as_ref().expect("should not be taken"),
                 &mut self.inner,
-                pool,
+                conn,
             )
             .await
         })
@@ -257,13 +206,13 @@ where
 
     fn sub_op2<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>> {
+        conn: &'this mut S::Connection,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>> {
         Box::pin(async {
             RW::sub_op2(
                 self.rw.as_ref().expect("should not be taken"),
                 &mut self.inner,
-                pool,
+                conn,
             )
             .await
         })
@@ -278,7 +227,9 @@ where
     }
 }
 
-impl fmt::Debug for dyn CompleteRelationForServer {
+impl<S: Database + SupportNamedBind> fmt::Debug
+    for dyn CompleteRelationForServer<S>
+{
     fn fmt(
         &self,
         f: &mut std::fmt::Formatter<'_>,
@@ -293,36 +244,36 @@ impl fmt::Debug for dyn CompleteRelationForServer {
     }
 }
 
-pub trait DynGetManyWorker: Send + Sync {
-    fn on_select(&mut self, st: &mut SelectSt<Sqlite>);
-    fn from_row(&mut self, row: &SqliteRow);
+pub trait DynGetManyWorker<S: Database + SupportNamedBind>: Send + Sync {
+    fn on_select(&mut self, st: &mut SelectSt<S>);
+    fn from_row(&mut self, row: &S::Row);
     fn sub_op<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>>;
+        pool: Pool<S>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>>;
     fn take(&mut self, current_id: i64) -> Value;
 }
-pub trait DynGetOneWorker: Send + Sync {
-    fn on_select(&mut self, st: &mut SelectSt<Sqlite>);
-    fn from_row(&mut self, row: &SqliteRow);
+pub trait DynGetOneWorker<S: Database + SupportNamedBind>: Send + Sync {
+    fn on_select(&mut self, st: &mut SelectSt<S>);
+    fn from_row(&mut self, row: &S::Row);
     fn sub_op<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>>;
+        pool: Pool<S>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>>;
     fn take(&mut self) -> Value;
 }
 
-pub struct DynamicWorker<Inner, RW> {
-    pub(crate) arc: Arc<dyn CompleteRelationForServer>,
+pub struct DynamicWorker<Inner, RW, S: Database + SupportNamedBind = Sqlite> {
+    pub(crate) arc: Arc<dyn CompleteRelationForServer<S>>,
     pub(crate) inner: Inner,
     pub(crate) rw: Option<RW>,
 }
 
-impl<I: Default, RW> DynamicWorker<I, RW> {
+impl<I: Default, RW, S: Database + SupportNamedBind> DynamicWorker<I, RW, S> {
     pub fn new(
-        arc: Arc<dyn CompleteRelationForServer>,
+        arc: Arc<dyn CompleteRelationForServer<S>>,
         worker: RW,
-    ) -> Box<DynamicWorker<I, RW>> {
+    ) -> Box<DynamicWorker<I, RW, S>> {
         Box::new(DynamicWorker {
             arc,
             inner: I::default(),
@@ -331,13 +282,15 @@ impl<I: Default, RW> DynamicWorker<I, RW> {
     }
 }
 
-impl<RW> DynGetOneWorker for DynamicWorker<RW::Inner, RW>
+impl<S, RW> DynGetOneWorker<S>
+    for DynamicWorker<RW::Inner, RW, S>
 where
+    S: Database + SupportNamedBind,
     RW: Send + Sync,
-    RW: GetOneWorker,
+    RW: GetOneWorker<S>,
     RW::Output: Serialize,
 {
-    fn on_select(&mut self, st: &mut SelectSt<Sqlite>) {
+    fn on_select(&mut self, st: &mut SelectSt<S>) {
         RW::on_select(
             self.rw.as_ref().expect("should not be taken"),
             &mut self.inner,
@@ -345,7 +298,7 @@ where
         );
     }
 
-    fn from_row(&mut self, row: &SqliteRow) {
+    fn from_row(&mut self, row: &S::Row) {
         RW::from_row(
             self.rw.as_ref().expect("should not be taken"),
             &mut self.inner,
@@ -355,8 +308,8 @@ where
 
     fn sub_op<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>> {
+        pool: Pool<S>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>> {
         Box::pin(async move {
             RW::sub_op(
                 self.rw.as_ref().expect("should not be taken"),
@@ -376,13 +329,15 @@ where
     }
 }
 
-impl<RW> DynGetManyWorker for DynamicWorker<RW::Inner, RW>
+impl<S, RW> DynGetManyWorker<S>
+    for DynamicWorker<RW::Inner, RW, S>
 where
+    S: Database + SupportNamedBind,
     RW: Send + Sync,
-    RW: GetAllWorker,
+    RW: GetAllWorker<S>,
     RW::Output: Serialize,
 {
-    fn on_select(&mut self, st: &mut SelectSt<Sqlite>) {
+    fn on_select(&mut self, st: &mut SelectSt<S>) {
         RW::on_select(
             self.rw.as_ref().expect("should not be taken"),
             &mut self.inner,
@@ -390,7 +345,7 @@ where
         );
     }
 
-    fn from_row(&mut self, row: &SqliteRow) {
+    fn from_row(&mut self, row: &S::Row) {
         RW::from_row(
             self.rw.as_ref().expect("should not be taken"),
             &mut self.inner,
@@ -400,8 +355,8 @@ where
 
     fn sub_op<'this>(
         &'this mut self,
-        pool: Pool<Sqlite>,
-    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'this>> {
+        pool: Pool<S>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), sqlx::Error>> + Send + 'this>> {
         Box::pin(async move {
             RW::sub_op(
                 self.rw.as_ref().expect("should not be taken"),
@@ -422,34 +377,66 @@ where
     }
 }
 
-pub trait DynCollection: Send + Sync + 'static {
+pub trait DynCollection<S: Database + SupportNamedBind>:
+    Send + Sync + 'static
+{
     fn members_no_scope(&self) -> &'static [&'static str];
+    fn field_rules(
+        &self,
+    ) -> &'static [(&'static str, &'static [Rule])];
+    fn field_variants(
+        &self,
+    ) -> &'static [(&'static str, Option<&'static [&'static str]>)];
+    fn column_kind(&self, member: &str) -> ColumnKind;
     fn table_name(&self) -> &str;
     // all scoped
-    fn on_select(&self, stmt: &mut SelectSt<Sqlite>);
+    fn on_select(&self, stmt: &mut SelectSt<S>);
     // all scoped, no modification
     fn from_row_scoped(
         &self,
-        row: &sqlx::sqlite::SqliteRow,
+        row: &S::Row,
     ) -> Value;
     fn from_row_noscope(
         &self,
-        row: &sqlx::sqlite::SqliteRow,
+        row: &S::Row,
     ) -> Value;
 
     fn on_insert(
         &self,
         input: Value,
-        stmt: &mut InsertSt<Sqlite>,
+        stmt: &mut InsertSt<S>,
     ) -> Result<(), ValidatedAndTyped>;
     fn on_update(
         &self,
         input: Value,
-        stmt: &mut UpdateSt<Sqlite>,
+        stmt: &mut UpdateSt<S>,
     ) -> Result<(), ValidatedAndTyped>;
+
+    fn owned(&self) -> bool;
+    // the rest are no-ops when the collection is not owned
+    fn insert_owner(
+        &self,
+        owner: String,
+        stmt: &mut InsertSt<S>,
+    );
+    fn select_owned_by(
+        &self,
+        owner: String,
+        stmt: &mut SelectSt<S>,
+    );
+    fn update_owned_by(
+        &self,
+        owner: String,
+        stmt: &mut UpdateSt<S>,
+    );
+    fn delete_owned_by(
+        &self,
+        owner: String,
+        stmt: &mut DeleteSt<S>,
+    );
 }
 
-impl fmt::Debug for dyn DynCollection {
+impl<S: Database + SupportNamedBind> fmt::Debug for dyn DynCollection<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Dyn_Collection")
             .field("table_name", &self.table_name())
@@ -461,6 +448,14 @@ impl fmt::Debug for dyn DynCollection {
 pub enum ValidatedAndTyped {
     TypeError(serde_json::Error),
     ValidationError(String),
+    /// from `Validate`, reported per field
+    FieldErrors(FieldErrors),
+}
+
+impl From<FieldErrors> for ValidatedAndTyped {
+    fn from(value: FieldErrors) -> Self {
+        ValidatedAndTyped::FieldErrors(value)
+    }
 }
 
 impl From<ValidatedAndTyped> for GlobalError {
@@ -478,34 +473,154 @@ impl From<ValidatedAndTyped> for GlobalError {
                     err
                 ))
             }
+            ValidatedAndTyped::FieldErrors(err) => {
+                error::unprocessable(&err)
+            }
         }
     }
 }
 
 impl From<ValidatedAndTyped> for InsertError {
     fn from(value: ValidatedAndTyped) -> Self {
-        <GlobalError as From<ValidatedAndTyped>>::from(value)
-            .into()
+        match value {
+            ValidatedAndTyped::FieldErrors(err) => err.into(),
+            value => {
+                <GlobalError as From<ValidatedAndTyped>>::from(
+                    value,
+                )
+                .into()
+            }
+        }
+    }
+}
+
+impl From<ValidatedAndTyped> for ClientError {
+    fn from(value: ValidatedAndTyped) -> Self {
+        match value {
+            ValidatedAndTyped::FieldErrors(err) => err.into(),
+            value => {
+                <GlobalError as From<ValidatedAndTyped>>::from(
+                    value,
+                )
+                .into()
+            }
+        }
+    }
+}
+
+/// enum members are checked before deserializing, so unknown
+/// variants are reported on their field and not as a type error.
+/// members of an update are `["set", value]`
+fn check_variants(
+    fields: &[(&str, Option<&[&str]>)],
+    input: &Value,
+    update: bool,
+) -> Result<(), FieldErrors> {
+    let mut errors = FieldErrors::default();
+    for (name, variants) in fields {
+        let Some(variants) = variants else {
+            continue;
+        };
+        let value = match (input.get(name), update) {
+            (Some(Value::Array(update)), true) => {
+                match update.as_slice() {
+                    [tag, value] if tag == "set" => value,
+                    _ => continue,
+                }
+            }
+            (Some(value), false) => value,
+            _ => continue,
+        };
+        if let Value::String(text) = value {
+            if let Err(message) =
+                validation::one_of(text.as_str(), variants)
+            {
+                errors.add(name, message);
+            }
+        }
     }
+    errors.into_result()
 }
 
-impl<T> DynCollection for PhantomData<T>
+impl<S, T> DynCollection<S> for PhantomData<T>
 where
-    T: Collection<Sqlite> + Serialize + 'static,
+    S: Database + SupportNamedBind,
+    T: Collection<S> + Serialize + 'static,
     T: DeserializeOwned,
     T::PartailCollection: DeserializeOwned,
+    String: Type<S> + for<'e> Encode<'e, S>,
 {
     fn members_no_scope(&self) -> &'static [&'static str] {
         T::members()
     }
+    fn field_rules(
+        &self,
+    ) -> &'static [(&'static str, &'static [Rule])] {
+        T::field_rules()
+    }
+    fn field_variants(
+        &self,
+    ) -> &'static [(&'static str, Option<&'static [&'static str]>)]
+    {
+        T::field_variants()
+    }
+    fn column_kind(&self, member: &str) -> ColumnKind {
+        T::column_kind(member)
+    }
+    fn owned(&self) -> bool {
+        T::owned()
+    }
+    fn insert_owner(
+        &self,
+        owner: String,
+        stmt: &mut InsertSt<S>,
+    ) {
+        if T::owned() {
+            stmt.insert(OWNER_COLUMN.to_string(), owner);
+        }
+    }
+    fn select_owned_by(
+        &self,
+        owner: String,
+        stmt: &mut SelectSt<S>,
+    ) {
+        if T::owned() {
+            // scoped, joined tables may be owned too
+            stmt.where_(
+                scoped(T::table_name(), OWNER_COLUMN).eq(owner),
+            );
+        }
+    }
+    fn update_owned_by(
+        &self,
+        owner: String,
+        stmt: &mut UpdateSt<S>,
+    ) {
+        if T::owned() {
+            stmt.where_(col(OWNER_COLUMN).eq(owner));
+        }
+    }
+    fn delete_owned_by(
+        &self,
+        owner: String,
+        stmt: &mut DeleteSt<S>,
+    ) {
+        if T::owned() {
+            stmt.where_(col(OWNER_COLUMN).eq(owner));
+        }
+    }
     fn on_update(
         &self,
         input: Value,
-        stmt: &mut UpdateSt<Sqlite>,
+        stmt: &mut UpdateSt<S>,
     ) -> Result<(), ValidatedAndTyped> {
-        let v = from_value::<T::PartailCollection>(input)
+        check_variants(T::field_variants(), &input, true)?;
+
+        let mut v = from_value::<T::PartailCollection>(input)
             .map_err(|e| ValidatedAndTyped::TypeError(e))?;
 
+        T::on_validate_update(&mut v)?;
+
         T::on_update(stmt, v).map_err(|e| {
             ValidatedAndTyped::ValidationError(e)
         })?;
@@ -516,39 +631,35 @@ where
     fn on_insert(
         &self,
         input: Value,
-        stmt: &mut InsertSt<Sqlite>,
+        stmt: &mut InsertSt<S>,
     ) -> Result<(), ValidatedAndTyped> {
-        let v = from_value::<T>(input)
-            .map_err(|e| ValidatedAndTyped::TypeError(e))?;
+        check_variants(T::field_variants(), &input, false)?;
 
-        T::on_insert(v, stmt).map_err(|e| {
-            ValidatedAndTyped::ValidationError(e)
-        })?;
-
-        Ok(())
+        T::on_insert_json(input, stmt)
     }
     fn table_name(&self) -> &str {
         T::table_name()
     }
 
-    fn on_select(&self, stmt: &mut SelectSt<Sqlite>) {
+    fn on_select(&self, stmt: &mut SelectSt<S>) {
         T::on_select(stmt)
     }
-    fn from_row_scoped(&self, row: &SqliteRow) -> Value {
+    fn from_row_scoped(&self, row: &S::Row) -> Value {
         let t = T::from_row_scoped(row);
         serde_json::to_value(t).unwrap()
     }
     fn from_row_noscope(
         &self,
-        row: &sqlx::sqlite::SqliteRow,
+        row: &S::Row,
     ) -> Value {
         let t = T::from_row_noscope(row);
         serde_json::to_value(t).unwrap()
     }
 }
 
-pub struct SubmitDynCollection {
-    pub obj: fn() -> Box<dyn DynCollection>,
+pub struct SubmitDynCollection<S: Database + SupportNamedBind> {
+    pub obj: fn() -> Box<dyn DynCollection<S>>,
 }
 
-collect!(SubmitDynCollection);
+collect!(SubmitDynCollection<Sqlite>);
+collect!(SubmitDynCollection<Postgres>);
@@ -4,8 +4,11 @@ use axum::{
 };
 use serde::Serialize;
 use serde_json::json;
+use sqlx::{error::ErrorKind, postgres::PgDatabaseError};
 use std::collections::HashMap;
 
+use crate::traits::FieldErrors;
+
 pub struct PanicError;
 impl IntoResponse for PanicError {
     fn into_response(self) -> axum::response::Response {
@@ -33,8 +36,8 @@ pub struct UserError {
 /// https://developer.mozilla.org/en-US/docs/Web/HTTP/Status
 ///
 /// some errors are the fault of the server (like bugs), these are 5xx
-/// please consider panicing on them, and they will be handled
-/// by axum
+/// the `dev_hint` of those is logged and not sent, see
+/// `server_error`
 ///
 /// some errors are the user's fault, and the frontend dev
 /// is responsible to create the appropriate UI for them
@@ -111,10 +114,44 @@ impl From<String> for ClientError {
     }
 }
 
+impl From<FieldErrors> for ClientError {
+    fn from(value: FieldErrors) -> Self {
+        ClientError {
+            status_code: StatusCode::UNPROCESSABLE_ENTITY,
+            dev_hint: invalid_fields(&value),
+            user_error: None,
+        }
+        .add_user_error(
+            "invalid_input",
+            "some fields are invalid",
+            |user_error| {
+                user_error.structured_hint =
+                    Some(value.0.into_iter().collect());
+            },
+        )
+    }
+}
+
+fn invalid_fields(errors: &FieldErrors) -> String {
+    let fields =
+        errors.0.keys().cloned().collect::<Vec<_>>().join(", ");
+    format!("input failed validation: {fields}")
+}
+
 impl IntoResponse for ClientError {
     fn into_response(self) -> axum::response::Response {
         if self.status_code.is_server_error() {
-            panic!("Server errors are not meant to be handled by 'ClientError': {:?}", self);
+            tracing::error!("{}", self.dev_hint);
+            return (
+                self.status_code,
+                Json(json!({
+                    "error": {
+                        "dev_hint": self.status_code.canonical_reason(),
+                        "user_error": null,
+                    },
+                })),
+            )
+                .into_response();
         }
 
         let dev_hint = self.dev_hint;
@@ -146,7 +183,7 @@ pub mod insert {
     use axum::{http::StatusCode, response::IntoResponse, Json};
     use serde_json::json;
 
-    use crate::error::ErrorInternal;
+    use crate::{error::ErrorInternal, traits::FieldErrors};
 
     #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
     pub struct InsertError(StatusCode, InsertErrorInternal);
@@ -169,19 +206,58 @@ pub mod insert {
                 "error": self.0.canonical_reason().unwrap_or_default(),
             });
 
-            if let InsertErrorInternal::Other(o) = self.1 {
-                body.as_object_mut()
-                    .unwrap()
-                    .insert("for_dev".to_string(), o.into());
+            match self.1 {
+                InsertErrorInternal::Other(o) => {
+                    body.as_object_mut()
+                        .unwrap()
+                        .insert("for_dev".to_string(), o.into());
+                }
+                InsertErrorInternal::Validation(fields) => {
+                    body.as_object_mut().unwrap().insert(
+                        "fields".to_string(),
+                        json!(fields),
+                    );
+                }
+                _ => {}
             }
 
             (self.0, Json(body)).into_response()
         }
     }
 
+    impl From<FieldErrors> for InsertError {
+        fn from(value: FieldErrors) -> Self {
+            InsertError(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                InsertErrorInternal::Validation(value),
+            )
+        }
+    }
+
+    impl From<InsertError> for super::ClientError {
+        fn from(value: InsertError) -> Self {
+            let dev_hint = match value.1 {
+                InsertErrorInternal::Validation(fields) => {
+                    return fields.into();
+                }
+                InsertErrorInternal::Other(o) => o,
+                InsertErrorInternal::ForiegnKeyViolation => {
+                    "foreign key violation".to_string()
+                }
+                InsertErrorInternal::Unkown => String::new(),
+            };
+            super::ClientError {
+                status_code: value.0,
+                dev_hint,
+                user_error: None,
+            }
+        }
+    }
+
     #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
     pub enum InsertErrorInternal {
         ForiegnKeyViolation,
+        Validation(FieldErrors),
         Other(String),
         Unkown,
     }
@@ -243,6 +319,23 @@ impl IntoResponse for GlobalError {
     }
 }
 
+impl From<GlobalError> for ClientError {
+    fn from(value: GlobalError) -> Self {
+        let dev_hint = match value.1 {
+            ErrorInternal::Unkown => String::new(),
+            ErrorInternal::EntryNotFound(entry) => {
+                format!("{entry} not found")
+            }
+            ErrorInternal::MoreInfo(info) => info,
+        };
+        ClientError {
+            status_code: value.0,
+            dev_hint,
+            user_error: None,
+        }
+    }
+}
+
 pub fn entry_not_found(entry: &str) -> GlobalError {
     GlobalError(
         StatusCode::NOT_FOUND,
@@ -257,6 +350,203 @@ pub fn to_refactor(info: &str) -> GlobalError {
     )
 }
 
+/// filters of `get_many` that can't be run, on unknown fields,
+/// with unknown operators or values
+pub fn invalid_filter(key: &str, hint: &str) -> ClientError {
+    ClientError {
+        status_code: StatusCode::BAD_REQUEST,
+        dev_hint: format!("filter `{key}` {hint}"),
+        user_error: None,
+    }
+    .add_user_error(
+        "invalid_filter",
+        "some filters are invalid",
+        |user_error| {
+            user_error.structured_hint =
+                Some(HashMap::from([(
+                    key.to_string(),
+                    hint.to_string(),
+                )]));
+        },
+    )
+}
+
+/// for error types that can't carry the fields, `ClientError`
+/// should be preferred
+pub fn unprocessable(errors: &FieldErrors) -> GlobalError {
+    GlobalError(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        ErrorInternal::MoreInfo(invalid_fields(errors)),
+    )
+}
+
+/// errors that are the server's fault, the client only sees a
+/// 500
+pub fn server_error(err: impl std::fmt::Display) -> ClientError {
+    ClientError {
+        status_code: StatusCode::INTERNAL_SERVER_ERROR,
+        dev_hint: err.to_string(),
+        user_error: None,
+    }
+}
+
+/// constraint violations are the client's fault: unique
+/// violations are 409, foreign key, not null and check
+/// violations are 422, other errors are `server_error`s.
+///
+/// `members` are the columns of the collection, the ones that
+/// show up in the database's message are reported as the fields.
+pub fn database(
+    err: sqlx::Error,
+    members: &[&str],
+) -> ClientError {
+    let db_err = match err {
+        sqlx::Error::Database(db_err) => db_err,
+        err => return server_error(err),
+    };
+
+    let (status_code, code, user_hint, field_hint) = match db_err
+        .kind()
+    {
+        ErrorKind::UniqueViolation => (
+            StatusCode::CONFLICT,
+            "unique_violation",
+            "a record with the same value already exists",
+            "is already taken",
+        ),
+        ErrorKind::ForeignKeyViolation => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "foreign_key_violation",
+            "a related record does not exist or is still in use",
+            "refers to a missing record",
+        ),
+        ErrorKind::NotNullViolation => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "not_null_violation",
+            "a required field is missing",
+            "is required",
+        ),
+        ErrorKind::CheckViolation => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "check_violation",
+            "some fields are invalid",
+            "is invalid",
+        ),
+        _ => return server_error(db_err),
+    };
+
+    let fields = violated_fields(&*db_err, members);
+
+    let dev_hint = match fields.is_empty() {
+        true => db_err.message().to_string(),
+        false => format!(
+            "{} ({})",
+            db_err.message(),
+            fields.join(", ")
+        ),
+    };
+
+    ClientError {
+        status_code,
+        dev_hint,
+        user_error: None,
+    }
+    .add_user_error(code, user_hint, |user_error| {
+        if !fields.is_empty() {
+            user_error.structured_hint = Some(
+                fields
+                    .into_iter()
+                    .map(|field| (field, field_hint.to_string()))
+                    .collect(),
+            );
+        }
+    })
+}
+
+fn violated_fields(
+    err: &dyn sqlx::error::DatabaseError,
+    members: &[&str],
+) -> Vec<String> {
+    let pg = err.try_downcast_ref::<PgDatabaseError>();
+    fields_of_violation(
+        err.message(),
+        pg.and_then(|pg| pg.detail()),
+        pg.and_then(|pg| pg.column()),
+        err.constraint(),
+        members,
+    )
+}
+
+// backends name the columns differently: sqlite lists them in
+// the message "UNIQUE constraint failed: Todo.title", postgres
+// puts them in the detail "Key (title)=(..) already exists" or
+// the column, mysql names the key "for key 'Todo.title'". when
+// none of them do, the generated constraint name
+// "Todo_title_check" is all there is.
+fn fields_of_violation(
+    message: &str,
+    detail: Option<&str>,
+    column: Option<&str>,
+    constraint: Option<&str>,
+    members: &[&str],
+) -> Vec<String> {
+    // "Todo.title" and "status IN (..)" both name one column
+    let column_of = |item: &str| {
+        let item = item.trim().trim_matches('"');
+        let item = item.rsplit('.').next().unwrap_or(item);
+        item.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let columns: Vec<String> = if let Some(column) = column {
+        vec![column.to_string()]
+    } else if let Some(list) = detail
+        .and_then(|e| e.strip_prefix("Key ("))
+        .and_then(|e| e.split_once(")=").map(|e| e.0))
+    {
+        list.split(", ").map(column_of).collect()
+    } else if let Some((_, list)) =
+        message.split_once("constraint failed: ")
+    {
+        list.split(", ").map(column_of).collect()
+    } else if let Some((_, key)) = message.split_once("for key '")
+    {
+        vec![column_of(key.trim_end_matches('\''))]
+    } else {
+        vec![]
+    };
+
+    let fields = members
+        .iter()
+        .filter(|member| columns.iter().any(|e| e == *member))
+        .map(|member| member.to_string())
+        .collect::<Vec<_>>();
+    if !fields.is_empty() {
+        return fields;
+    }
+
+    // the longest member wins, `Todo_category_id_fkey` is about
+    // `category_id` and not `id`
+    let Some(constraint) = constraint else {
+        return vec![];
+    };
+    members
+        .iter()
+        .filter(|member| {
+            ["check", "fkey", "key", "not_null"].iter().any(
+                |suffix| {
+                    constraint
+                        .ends_with(&format!("_{member}_{suffix}"))
+                },
+            )
+        })
+        .max_by_key(|member| member.len())
+        .map(|member| vec![member.to_string()])
+        .unwrap_or_default()
+}
+
 pub fn not_found(id: i32) -> GlobalError {
     GlobalError(StatusCode::NOT_FOUND, ErrorInternal::default())
 }
@@ -267,3 +557,64 @@ pub fn missing_id_in_query() -> GlobalError {
         ErrorInternal::default(),
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::fields_of_violation;
+
+    #[test]
+    fn violations_name_exact_columns() {
+        let members = ["id", "title", "category_id"];
+
+        assert_eq!(
+            fields_of_violation(
+                "UNIQUE constraint failed: Todo.title",
+                None,
+                None,
+                None,
+                &members,
+            ),
+            vec!["title"]
+        );
+        assert_eq!(
+            fields_of_violation(
+                "insert or update on table \"Todo\" violates foreign key constraint \"Todo_category_id_fkey\"",
+                Some("Key (category_id)=(5) is not present in table \"Category\"."),
+                None,
+                Some("Todo_category_id_fkey"),
+                &members,
+            ),
+            vec!["category_id"]
+        );
+        assert_eq!(
+            fields_of_violation(
+                "duplicate key value violates unique constraint \"Todo_title_key\"",
+                Some("Key (title)=(id) already exists."),
+                None,
+                Some("Todo_title_key"),
+                &members,
+            ),
+            vec!["title"]
+        );
+        assert_eq!(
+            fields_of_violation(
+                "new row for relation \"Todo\" violates check constraint \"Todo_category_id_check\"",
+                None,
+                None,
+                Some("Todo_category_id_check"),
+                &members,
+            ),
+            vec!["category_id"]
+        );
+        assert_eq!(
+            fields_of_violation(
+                "FOREIGN KEY constraint failed",
+                None,
+                None,
+                None,
+                &members,
+            ),
+            Vec::<String>::new()
+        );
+    }
+}
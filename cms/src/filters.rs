@@ -1,15 +1,32 @@
-use queries_for_sqlx::prelude::*;
-use sqlx::Sqlite;
+use queries_for_sqlx::{
+    prelude::*, Accept, BindItem, Query, SupportNamedBind,
+};
+use serde_json::{Map, Value};
+use sqlx::{
+    types::{
+        chrono::{DateTime, NaiveDate, NaiveTime, Utc},
+        Decimal, Uuid,
+    },
+    Database, Encode, Sqlite, Type,
+};
+use std::str::FromStr;
 
-use crate::traits::Collection;
+use crate::{
+    backend::Backend,
+    dynamic_schema::DynCollection,
+    error::{self, ClientError},
+    permissions::hidden_fields,
+    queries_for_sqlx_extention::{ColumnKind, DecimalColumn},
+    traits::Collection,
+};
 
 use super::{
     operations::select_many::Pagination,
     queries_bridge::SelectSt,
 };
 
-pub trait AgnosticFilter: Sync + Send {
-    fn on_select(self, st: &mut SelectSt<Sqlite>);
+pub trait AgnosticFilter<S: Database + SupportNamedBind = Sqlite>: Sync + Send {
+    fn on_select(self, st: &mut SelectSt<S>);
 
     #[inline]
     fn into_filter(self) -> ImplFilter<Self>
@@ -22,17 +39,18 @@ pub trait AgnosticFilter: Sync + Send {
 
 pub struct ImplFilter<T>(pub T);
 
-impl<C, T> Filters<C> for ImplFilter<T>
+impl<C, T, S> Filters<C, S> for ImplFilter<T>
 where
-    T: AgnosticFilter,
+    S: Database + SupportNamedBind,
+    T: AgnosticFilter<S>,
 {
-    fn on_select(self, st: &mut SelectSt<Sqlite>) {
+    fn on_select(self, st: &mut SelectSt<S>) {
         self.0.on_select(st);
     }
 }
 
-pub trait Filters<C>: Sync + Send {
-    fn on_select(self, st: &mut SelectSt<Sqlite>);
+pub trait Filters<C, S: Database + SupportNamedBind = Sqlite>: Sync + Send {
+    fn on_select(self, st: &mut SelectSt<S>);
 }
 
 pub struct ById(pub i64);
@@ -40,14 +58,23 @@ pub fn by_id(id: i64) -> ById {
     ById(id)
 }
 
-impl<C: Collection<Sqlite>> Filters<C> for ById {
-    fn on_select(self, st: &mut SelectSt<Sqlite>) {
+impl<C, S> Filters<C, S> for ById
+where
+    C: Collection<S>,
+    S: Database + SupportNamedBind,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+{
+    fn on_select(self, st: &mut SelectSt<S>) {
         st.where_(scoped(C::table_name(), "id").eq(self.0));
     }
 }
 
-impl AgnosticFilter for Pagination {
-    fn on_select(self, st: &mut SelectSt<Sqlite>) {
+impl<S> AgnosticFilter<S> for Pagination
+where
+    S: Database + SupportNamedBind,
+    i32: Type<S> + for<'e> Encode<'e, S>,
+{
+    fn on_select(self, st: &mut SelectSt<S>) {
         let offset = ((self.page - 1) * self.page_size);
         let limit = self.page_size;
 
@@ -56,8 +83,395 @@ impl AgnosticFilter for Pagination {
     }
 }
 
+/// `lhs op value`, `lhs` is only built out of known members and
+/// checked paths
+pub struct Compare<T> {
+    lhs: String,
+    op: &'static str,
+    /// how the placeholder is written, see
+    /// `Backend::filter_operands`
+    rhs: fn(String) -> String,
+    value: T,
+}
+
+impl<S, Q, I, T> BindItem<S, Q, I> for Compare<T>
+where
+    Q: Query + Accept<T, S>,
+{
+    fn bind_item(
+        self,
+        ctx: &mut Q::Context1,
+    ) -> impl FnOnce(&mut Q::Context2) -> String + 'static {
+        let value = Q::accept(self.value, ctx);
+        move |ctx| {
+            format!(
+                "{} {} {}",
+                self.lhs,
+                self.op,
+                (self.rhs)(value(ctx))
+            )
+        }
+    }
+}
+
+/// `{"$eq": null}` and `{"$ne": null}`
+pub struct CompareNull {
+    lhs: String,
+    not: bool,
+}
+
+impl<S, Q: Query, I> BindItem<S, Q, I> for CompareNull {
+    fn bind_item(
+        self,
+        _ctx: &mut Q::Context1,
+    ) -> impl FnOnce(&mut Q::Context2) -> String + 'static {
+        move |_| match self.not {
+            true => format!("{} IS NOT NULL", self.lhs),
+            false => format!("{} IS NULL", self.lhs),
+        }
+    }
+}
+
+fn operator(op: &str) -> Option<&'static str> {
+    Some(match op {
+        "$eq" => "=",
+        "$ne" => "<>",
+        "$gt" => ">",
+        "$gte" => ">=",
+        "$lt" => "<",
+        "$lte" => "<=",
+        _ => return None,
+    })
+}
+
+/// a filter value, parsed by the kind of the column so it is
+/// bound the way the column's values are stored
+#[derive(Debug, PartialEq)]
+pub enum FilterValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    DateTime(DateTime<Utc>),
+    Date(NaiveDate),
+    Uuid(Uuid),
+    Decimal(DecimalColumn),
+}
+
+/// dates are `2024-01-31`, date times are RFC 3339, a date alone
+/// is its midnight in UTC. decimals can be strings or numbers
+fn filter_value(
+    kind: ColumnKind,
+    value: &Value,
+) -> Result<FilterValue, &'static str> {
+    let text = value.as_str();
+    let date = |text: &str| {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()
+    };
+    Ok(match (kind, value) {
+        (ColumnKind::DateTime, _) => {
+            let text = text.ok_or("is not a date")?;
+            let value = match DateTime::parse_from_rfc3339(text) {
+                Ok(value) => value.to_utc(),
+                Err(_) => date(text)
+                    .ok_or("is not a date")?
+                    .and_time(NaiveTime::MIN)
+                    .and_utc(),
+            };
+            FilterValue::DateTime(value)
+        }
+        (ColumnKind::Date, _) => FilterValue::Date(
+            text.and_then(date).ok_or("is not a date")?,
+        ),
+        (ColumnKind::Uuid, _) => FilterValue::Uuid(
+            text.and_then(|e| Uuid::parse_str(e).ok())
+                .ok_or("is not a uuid")?,
+        ),
+        (ColumnKind::Decimal, Value::Number(number)) => {
+            FilterValue::Decimal(DecimalColumn(
+                Decimal::from_str(&number.to_string())
+                    .or_else(|_| {
+                        Decimal::from_scientific(&number.to_string())
+                    })
+                    .map_err(|_| "is not a decimal")?,
+            ))
+        }
+        (ColumnKind::Decimal, _) => {
+            FilterValue::Decimal(DecimalColumn(
+                text.and_then(|e| Decimal::from_str(e).ok())
+                    .ok_or("is not a decimal")?,
+            ))
+        }
+        (_, Value::Bool(value)) => FilterValue::Bool(*value),
+        (_, Value::Number(number)) => match number.as_i64() {
+            Some(value) => FilterValue::Int(value),
+            None => FilterValue::Float(
+                number.as_f64().unwrap_or_default(),
+            ),
+        },
+        (_, Value::String(value)) => {
+            FilterValue::Text(value.clone())
+        }
+        _ => {
+            return Err("can only be compared to a string, a number, a boolean or null")
+        }
+    })
+}
+
+/// binds the value with its own type, `Backend::bind_filter`
+/// calls this where the backend is known, so generic code doesn't
+/// have to spell out a bound for every type
+pub(crate) fn bind_filter<S>(
+    st: &mut SelectSt<S>,
+    compare: Compare<FilterValue>,
+) where
+    S: Database + SupportNamedBind,
+    bool: Type<S> + for<'e> Encode<'e, S>,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+    f64: Type<S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+    DateTime<Utc>: Type<S> + for<'e> Encode<'e, S>,
+    NaiveDate: Type<S> + for<'e> Encode<'e, S>,
+    Uuid: Type<S> + for<'e> Encode<'e, S>,
+    DecimalColumn: Type<S> + for<'e> Encode<'e, S>,
+{
+    let Compare {
+        lhs,
+        op,
+        rhs,
+        value,
+    } = compare;
+    macro_rules! bind {
+        ($value:expr) => {
+            st.where_(Compare {
+                lhs,
+                op,
+                rhs,
+                value: $value,
+            })
+        };
+    }
+    match value {
+        FilterValue::Bool(value) => bind!(value),
+        FilterValue::Int(value) => bind!(value),
+        FilterValue::Float(value) => bind!(value),
+        FilterValue::Text(value) => bind!(value),
+        FilterValue::DateTime(value) => bind!(value),
+        FilterValue::Date(value) => bind!(value),
+        FilterValue::Uuid(value) => bind!(value),
+        FilterValue::Decimal(value) => bind!(value),
+    }
+}
+
+/// the filter language of `get_many`, every condition has to
+/// hold:
+///
+/// `{"done": true, "title": {"$ne": "draft"}}`
+///
+/// operators are `$eq`, `$ne`, `$gt`, `$gte`, `$lt` and `$lte`,
+/// a plain value is short for `$eq`. dates, uuids and decimals
+/// are given as strings, so date ranges are
+/// `{"due": {"$gte": "2024-01-01", "$lt": "2024-02-01"}}`.
+/// members stored as JSON can be reached into with a path:
+/// `{"settings.theme": "dark"}`. fields the role can't read
+/// (see `permissions::hidden_fields`) can't be filtered on
+pub fn where_filters<S: Backend>(
+    collection: &dyn DynCollection<S>,
+    filters: &Map<String, Value>,
+    st: &mut SelectSt<S>,
+) -> Result<(), ClientError> {
+    let hidden = hidden_fields();
+    for (key, condition) in filters {
+        let mut path = key.split('.');
+        // `split` yields at least one item
+        let member = path.next().unwrap_or_default();
+        let path = path.collect::<Vec<_>>();
+
+        // fields the role can't read look like they don't
+        // exist, filtering on them would reveal their values
+        if !collection.members_no_scope().contains(&member)
+            || hidden.iter().any(|e| e == member)
+        {
+            return Err(error::invalid_filter(
+                key,
+                "is not a field",
+            ));
+        }
+
+        let column =
+            format!("{}.{}", collection.table_name(), member);
+        let kind = collection.column_kind(member);
+        let (lhs, rhs) = if path.is_empty() {
+            S::filter_operands(kind, column)
+        } else if kind != ColumnKind::Json {
+            return Err(error::invalid_filter(
+                key,
+                "only JSON fields have paths",
+            ));
+        } else if path.iter().any(|e| {
+            e.is_empty()
+                || !e.chars().all(|c| {
+                    c.is_ascii_alphanumeric() || c == '_'
+                })
+        }) {
+            return Err(error::invalid_filter(
+                key,
+                "path can only have letters, digits and `_`",
+            ));
+        } else {
+            S::filter_operands(
+                kind,
+                S::json_path(&column, &path),
+            )
+        };
+
+        let conditions = match condition {
+            Value::Object(ops) => ops
+                .iter()
+                .map(|(op, v)| (op.as_str(), v))
+                .collect(),
+            value => vec![("$eq", value)],
+        };
+
+        for (op, value) in conditions {
+            let Some(op) = operator(op) else {
+                return Err(error::invalid_filter(
+                    key,
+                    &format!("has an unknown operator `{op}`"),
+                ));
+            };
+            let lhs = lhs.clone();
+            if value.is_null() && (op == "=" || op == "<>") {
+                st.where_(CompareNull {
+                    lhs,
+                    not: op == "<>",
+                });
+                continue;
+            }
+            let value =
+                filter_value(kind, value).map_err(|hint| {
+                    error::invalid_filter(key, hint)
+                })?;
+            S::bind_filter(
+                st,
+                Compare {
+                    lhs,
+                    op,
+                    rhs,
+                    value,
+                },
+            );
+        }
+    }
+
+    Ok(())
+}
+
 pub struct FilterLike<T> {
     _phantom: std::marker::PhantomData<T>,
 }
 
 pub struct Eq {}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use sqlx::{
+        types::{chrono::NaiveDate, Decimal},
+        Pool, Postgres, Sqlite,
+    };
+
+    use crate::{
+        backend::Backend,
+        queries_for_sqlx_extention::{
+            ColumnKind, DecimalColumn,
+        },
+    };
+
+    use super::{filter_value, FilterValue};
+
+    #[test]
+    fn values_are_parsed_by_kind() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        assert_eq!(
+            filter_value(ColumnKind::Date, &json!("2024-01-31")),
+            Ok(FilterValue::Date(date))
+        );
+        assert_eq!(
+            filter_value(
+                ColumnKind::DateTime,
+                &json!("2024-01-31")
+            ),
+            Ok(FilterValue::DateTime(
+                date.and_hms_opt(0, 0, 0).unwrap().and_utc()
+            ))
+        );
+        assert_eq!(
+            filter_value(
+                ColumnKind::DateTime,
+                &json!("2024-01-31T02:00:00+02:00")
+            ),
+            Ok(FilterValue::DateTime(
+                date.and_hms_opt(0, 0, 0).unwrap().and_utc()
+            ))
+        );
+        assert_eq!(
+            filter_value(ColumnKind::Date, &json!(20240131)),
+            Err("is not a date")
+        );
+        assert_eq!(
+            filter_value(ColumnKind::Uuid, &json!("not-a-uuid")),
+            Err("is not a uuid")
+        );
+        assert_eq!(
+            filter_value(ColumnKind::Decimal, &json!(1.5)),
+            filter_value(ColumnKind::Decimal, &json!("1.5"))
+        );
+        assert_eq!(
+            filter_value(ColumnKind::Decimal, &json!("1.50")),
+            Ok(FilterValue::Decimal(DecimalColumn(
+                Decimal::new(150, 2)
+            )))
+        );
+        assert_eq!(
+            filter_value(ColumnKind::Plain, &json!([1])),
+            Err("can only be compared to a string, a number, a boolean or null")
+        );
+    }
+
+    #[test]
+    fn json_paths() {
+        assert_eq!(
+            Postgres::json_path(
+                "Todo.settings",
+                &["theme", "0"]
+            ),
+            "Todo.settings #> '{theme,0}'"
+        );
+        let (_, rhs) = Postgres::filter_operands(
+            ColumnKind::Json,
+            String::new(),
+        );
+        assert_eq!(rhs("$1".to_string()), "to_jsonb($1)");
+    }
+
+    #[tokio::test]
+    async fn sqlite_json_paths() {
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        let path = Sqlite::json_path("settings", &["tabs", "1"]);
+        assert_eq!(path, "json_extract(settings, '$.tabs[1]')");
+
+        let found: (String,) = sqlx::query_as(&format!(
+            "SELECT {path} FROM (SELECT ? AS settings) WHERE {path} = ?"
+        ))
+        .bind(r#"{"tabs": ["home", "todos"]}"#)
+        .bind("todos")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(found.0, "todos");
+    }
+}
@@ -1,6 +1,9 @@
 pub use cms_macros;
+pub mod api_keys;
+pub mod audit;
 pub mod auth;
 pub mod axum_router;
+pub mod backend;
 pub mod build_tuple;
 pub mod uniform_response_layer;
 #[cfg(test)]
@@ -13,6 +16,7 @@ pub mod filters;
 pub mod macro_prelude;
 pub mod migration2;
 pub mod operations;
+pub mod permissions;
 pub mod queries_bridge;
 pub mod queries_for_sqlx_extention;
 pub mod relations;
@@ -22,3 +26,4 @@ pub mod traits;
 pub mod tuple_impls;
 pub mod tuple_index;
 pub mod utils;
+pub mod validation;
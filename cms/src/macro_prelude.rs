@@ -2,6 +2,8 @@ pub mod derive_collection {
     pub use crate::queries_bridge::*;
     pub use crate::traits::Collection;
     pub use crate::traits::Update;
+    pub use crate::traits::{FieldErrors, Validate};
+    pub use crate::validation::{self, Rule};
     pub use queries_for_sqlx::SupportNamedBind;
     pub use sqlx::{
         ColumnIndex, Database, Decode, Encode, Row, Type,
@@ -15,8 +17,28 @@ pub mod derive_collection {
     pub use std::marker::PhantomData;
     pub use crate::queries_for_sqlx_extention::SqlxQuery;
     pub use crate::queries_for_sqlx_extention::col_type_check_if_null;
+    pub use crate::queries_for_sqlx_extention::{
+        col_type_of, ColumnKind, IsNull, StoredAs,
+    };
     pub use crate::queries_for_sqlx_extention::primary_key;
     pub use queries_for_sqlx::expressions_2::schema_items_for_tupe::all;
+    pub use queries_for_sqlx::expressions_2::schema_items::unique;
+    pub use queries_for_sqlx::expressions_2::schema_items::{
+        check, default_value,
+    };
+    pub use crate::dynamic_schema::ValidatedAndTyped;
+    pub use sqlx::{Postgres, Sqlite};
+    pub use serde_json::{from_value, Value};
+    pub use sqlx::types::Json;
+}
+
+pub mod text_enum {
+    pub use crate::queries_for_sqlx_extention::{IsNull, TextEnum};
+    pub use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+    pub use sqlx::{
+        database::{HasArguments, HasValueRef},
+        encode, error::BoxDynError, Database, Decode, Encode, Type,
+    };
 }
 
 pub mod relation_macro {
@@ -30,11 +52,17 @@ pub mod relation_macro {
     pub use crate::dynamic_schema::SubmitDynRelation;
     pub use crate::relations::Linked;
 
-    pub use sqlx::Sqlite;
+    pub use sqlx::{Postgres, Sqlite};
     pub use inventory::submit;
     pub use std::sync::Arc;
 }
 
+pub mod seed {
+    pub use crate::migration2::seed::{SeedRecord, SubmitSeed};
+    pub use inventory::submit;
+    pub use serde_json::{json, Value};
+}
+
 pub mod serde {
     pub use serde::Deserialize;
     pub use serde::Serialize;
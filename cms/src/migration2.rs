@@ -5,86 +5,160 @@ use std::{
 use inventory::collect;
 use queries_for_sqlx::{
     create_table_st::{CreateTableHeader, CreateTableSt},
-    prelude::ExecuteNoCache,
+    SupportNamedBind,
 };
-use sqlx::{Pool, Sqlite};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Database, Decode,
+    Encode, Executor, IntoArguments, Pool, Postgres, Sqlite,
+    Type,
+};
+
+use crate::{
+    backend::Backend,
+    permissions::OWNER_COLUMN,
+    queries_bridge::{CreatTableSt, CreateIndexSt},
+    queries_for_sqlx_extention::col_type_check_if_null,
+    traits::Collection,
+};
+
+pub mod diff;
+pub mod history;
+pub mod seed;
 
-use crate::{queries_bridge::CreatTableSt, traits::Collection};
+pub use history::rollback_migration;
+pub use seed::run_seeds;
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum Events {
     TableCreated(&'static str),
+    /// a relation column waiting for the table it is added to
+    RelationDeferred(&'static str, String),
 }
 
-pub(crate) struct Store(
-    pub(crate) HashMap<String, CreatTableSt<Sqlite>>,
+pub(crate) struct Store<S: Database + SupportNamedBind>(
+    pub(crate) HashMap<String, CreatTableSt<S>>,
 );
 
-pub struct MigrationCtx<'l> {
+pub struct MigrationCtx<
+    'l,
+    S: Database + SupportNamedBind = Sqlite,
+> {
     pub(crate) events: &'l mut Vec<Events>,
-    pub(crate) executables: &'l mut Vec<Box<dyn DynMigration>>,
-    pub(crate) store: &'l mut Store,
+    pub(crate) executables:
+        &'l mut Vec<Box<dyn DynMigration<S>>>,
+    pub(crate) store: &'l mut Store<S>,
+    pub(crate) renames: &'l mut diff::Renames,
+    pub(crate) indexes: &'l mut Vec<CreateIndexSt<S>>,
 }
 
-pub trait DynMigration {
+pub trait DynMigration<S: Database + SupportNamedBind = Sqlite> {
     fn migrate(
         &self,
-        ctx: &mut MigrationCtx,
+        ctx: &mut MigrationCtx<S>,
     ) -> Result<(), String>;
 
     fn panic_on_unsafe_schema(&self);
 }
 
-impl<T> DynMigration for PhantomData<T>
+impl<S, T> DynMigration<S> for PhantomData<T>
 where
-    T: Collection<Sqlite>,
+    S: Backend,
+    T: Collection<S>,
+    String: Type<S>,
 {
     fn panic_on_unsafe_schema(&self) {
-        queries_for_sqlx::ident_safety::append_schema(
-            T::table_name(),
-            T::members(),
-        )
+        use queries_for_sqlx::ident_safety::*;
+
+        if let Some(old) = T::renamed_from() {
+            rename_table(old, T::table_name());
+        }
+        for (old, new) in T::members_renamed_from() {
+            rename_column(T::table_name(), old, new);
+        }
+        if T::owned() {
+            let mut members = T::members().to_vec();
+            members.push(OWNER_COLUMN);
+            append_schema(T::table_name(), &members)
+        } else {
+            append_schema(T::table_name(), T::members())
+        }
     }
     fn migrate(
         &self,
-        ctx: &mut MigrationCtx,
+        ctx: &mut MigrationCtx<S>,
     ) -> Result<(), String> {
         let table_name = T::table_name();
-        if ctx.events.contains(&Events::TableCreated(table_name))
-        {
-            let found = ctx.store.0.get_mut(table_name).unwrap();
-            T::on_migrate(found)
-        } else {
-            let mut new = CreateTableSt::init((
+
+        if let Some(old) = T::renamed_from() {
+            ctx.renames
+                .tables
+                .insert(table_name.to_string(), old.to_string());
+        }
+        if !T::members_renamed_from().is_empty() {
+            ctx.renames
+                .columns
+                .entry(table_name.to_string())
+                .or_default()
+                .extend(T::members_renamed_from().iter().map(
+                    |(old, new)| {
+                        (old.to_string(), new.to_string())
+                    },
+                ));
+        }
+
+        let created = Events::TableCreated(table_name);
+        if !ctx.events.contains(&created) {
+            let new = CreateTableSt::init((
                 CreateTableHeader::IfNotExists,
                 table_name,
             ));
-            T::on_migrate(&mut new);
             ctx.store.0.insert(table_name.to_string(), new);
             ctx.events.push(Events::TableCreated(table_name));
         }
 
+        let table = ctx.store.0.get_mut(table_name).unwrap();
+        T::on_migrate(table);
+        if T::owned() {
+            table.column(
+                OWNER_COLUMN,
+                col_type_check_if_null::<Option<String>>(),
+            );
+        }
+
+        T::on_index(ctx.indexes);
+
         Ok(())
     }
 }
 
-pub struct SubmitDynMigrate {
-    pub obj: fn() -> Box<dyn DynMigration>,
+pub struct SubmitDynMigrate<S: Database + SupportNamedBind> {
+    pub obj: fn() -> Box<dyn DynMigration<S>>,
 }
 
-collect!(SubmitDynMigrate);
+collect!(SubmitDynMigrate<Sqlite>);
+collect!(SubmitDynMigrate<Postgres>);
 
-pub async fn run_migration(
-    db: Pool<Sqlite>,
-) -> Result<(), Box<dyn Error>> {
+pub async fn run_migration<S>(
+    db: Pool<S>,
+) -> Result<(), Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
     let mut store = Store(Default::default());
 
-    let mut execs = inventory::iter::<SubmitDynMigrate>
-        .into_iter()
-        .map(|e| ((e.obj)()))
-        .collect::<Vec<_>>();
+    let mut execs = S::migrations();
 
     let mut events: Vec<Events> = vec![];
+    let mut renames = diff::Renames::default();
+    let mut indexes = vec![];
 
     while let Some(item) = execs.pop() {
         item.panic_on_unsafe_schema();
@@ -92,14 +166,587 @@ pub async fn run_migration(
             events: &mut events,
             executables: &mut execs,
             store: &mut store,
+            renames: &mut renames,
+            indexes: &mut indexes,
         })?
     }
 
-    for (name, table) in store.0 {
-        table.execute(&db).await.map_err(|e| {
-            format!("failed to run migration on {}: {}", name, e)
-        })?;
+    let mut next: diff::SchemaSnapshot = store
+        .0
+        .iter()
+        .map(|(name, table)| (name.clone(), table.into()))
+        .collect();
+
+    for index in indexes {
+        let Some(table) = next.get_mut(index.table_name())
+        else {
+            return Err(format!(
+                "index {} is on unknown table {}",
+                index.name(),
+                index.table_name()
+            )
+            .into());
+        };
+        table.indexes.push(index.into());
     }
 
+    let prev =
+        history::latest_snapshot::<S>(&mut *db.acquire().await?)
+            .await?;
+    let existing = history::existing_tables(&db).await?;
+
+    let steps =
+        diff::diff::<S>(&prev, &next, &renames, &existing)?;
+
+    if !steps.is_empty() {
+        history::apply(&db, steps, &next).await?;
+    }
+
+    run_seeds(&db).await?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use sqlx::{Pool, Postgres, Sqlite};
+
+    use super::{
+        diff::{
+            diff, IndexSnapshot, Renames, SchemaSnapshot,
+            TableSnapshot,
+        },
+        history::{self, current_version},
+        rollback_migration, run_migration,
+    };
+    use crate::backend::Backend;
+
+    #[test]
+    fn added_column_rolls_back_by_rebuild() {
+        let prev: SchemaSnapshot = [(
+            "Todo".to_string(),
+            TableSnapshot {
+                columns: vec![(
+                    "id".to_string(),
+                    "INTEGER PRIMARY KEY AUTOINCREMENT"
+                        .to_string(),
+                )],
+                constraints: vec![],
+                indexes: vec![],
+            },
+        )]
+        .into();
+
+        let mut next = prev.clone();
+        next.get_mut("Todo")
+            .unwrap()
+            .columns
+            .push(("title".to_string(), "TEXT".to_string()));
+
+        let steps = diff::<Sqlite>(
+            &prev,
+            &next,
+            &Default::default(),
+            &Default::default(),
+        )
+        .unwrap();
+
+        pretty_assertions::assert_eq!(
+            steps[0].up,
+            vec!["ALTER TABLE Todo ADD COLUMN title TEXT;"]
+        );
+        pretty_assertions::assert_eq!(
+            steps[0].down,
+            vec![
+                "CREATE TABLE IF NOT EXISTS _cms_rebuild_Todo (id INTEGER PRIMARY KEY AUTOINCREMENT);",
+                "INSERT INTO _cms_rebuild_Todo (id) SELECT id FROM Todo;",
+                "DROP TABLE Todo;",
+                "ALTER TABLE _cms_rebuild_Todo RENAME TO Todo;",
+            ]
+        );
+    }
+
+    /// `Category` with a `Todo` pointing at it, ids as
+    /// postgres makes them
+    fn postgres_schema() -> SchemaSnapshot {
+        let table = |columns: &[(&str, &str)]| TableSnapshot {
+            columns: columns
+                .iter()
+                .map(|(col, def)| {
+                    (col.to_string(), def.to_string())
+                })
+                .collect(),
+            constraints: vec![],
+            indexes: vec![],
+        };
+        let id = Postgres::id_column();
+
+        [
+            (
+                "Category".to_string(),
+                table(&[("id", &id), ("cat_title", "TEXT")]),
+            ),
+            (
+                "Todo".to_string(),
+                table(&[
+                    ("id", &id),
+                    (
+                        "category_id",
+                        "BIGINT REFERENCES Category (id) ON DELETE CASCADE",
+                    ),
+                ]),
+            ),
+        ]
+        .into()
+    }
+
+    #[test]
+    fn postgres_alters_columns_in_place() {
+        let prev = postgres_schema();
+
+        let mut next = prev.clone();
+        next.get_mut("Category").unwrap().columns.push((
+            "note".to_string(),
+            "TEXT NOT NULL DEFAULT ''".to_string(),
+        ));
+
+        let steps = diff::<Postgres>(
+            &prev,
+            &next,
+            &Default::default(),
+            &Default::default(),
+        )
+        .unwrap();
+        pretty_assertions::assert_eq!(
+            steps[0].up,
+            vec!["ALTER TABLE Category ADD COLUMN note TEXT NOT NULL DEFAULT '';"]
+        );
+        pretty_assertions::assert_eq!(
+            steps[0].down,
+            vec!["ALTER TABLE Category DROP COLUMN note;"]
+        );
+
+        // a changed column still rebuilds, the identity and the
+        // keys pointing at the table are restored
+        next.get_mut("Category").unwrap().columns[1].1 =
+            "TEXT NOT NULL DEFAULT ''".to_string();
+        next.get_mut("Category").unwrap().columns.pop();
+
+        let steps = diff::<Postgres>(
+            &prev,
+            &next,
+            &Default::default(),
+            &Default::default(),
+        )
+        .unwrap();
+        let up = &steps[0].up;
+        assert!(up
+            .contains(&"DROP TABLE Category CASCADE;".to_string()));
+        assert_eq!(
+            up[up.len() - 2..],
+            [
+                "SELECT setval(pg_get_serial_sequence('Category', 'id'), \
+                COALESCE(MAX(id), 0) + 1, false) FROM Category;"
+                    .to_string(),
+                "ALTER TABLE Todo ADD FOREIGN KEY (category_id) \
+                REFERENCES Category (id) ON DELETE CASCADE;"
+                    .to_string(),
+            ]
+        );
+    }
+
+    /// runs only when `CMS_TEST_POSTGRES_URL` is set, the
+    /// database is wiped on every run
+    #[tokio::test]
+    async fn postgres_rollback_then_insert() {
+        let Ok(url) = std::env::var("CMS_TEST_POSTGRES_URL")
+        else {
+            return;
+        };
+        // `postgres_end_to_end` wipes the same database
+        let _serial =
+            crate::permissions::test::SERIAL.lock().await;
+
+        let pool =
+            Pool::<Postgres>::connect(&url).await.unwrap();
+        sqlx::raw_sql(
+            "DROP SCHEMA public CASCADE; CREATE SCHEMA public;",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let apply = |prev: SchemaSnapshot, next: SchemaSnapshot| {
+            let pool = pool.clone();
+            async move {
+                let steps = diff::<Postgres>(
+                    &prev,
+                    &next,
+                    &Default::default(),
+                    &Default::default(),
+                )
+                .unwrap();
+                history::apply(&pool, steps, &next).await.unwrap()
+            }
+        };
+
+        let first = postgres_schema();
+        let created =
+            apply(Default::default(), first.clone()).await;
+        sqlx::raw_sql(
+            "INSERT INTO Category (cat_title) VALUES ('a'), ('b');
+            INSERT INTO Todo (category_id) VALUES (2);",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // an added column, then a changed one on the table
+        // `Todo` points at
+        let mut added = first.clone();
+        added.get_mut("Category").unwrap().columns.push((
+            "note".to_string(),
+            "TEXT".to_string(),
+        ));
+        let with_note = apply(first.clone(), added.clone()).await;
+
+        let mut changed = added.clone();
+        changed.get_mut("Category").unwrap().columns[1].1 =
+            "TEXT NOT NULL DEFAULT ''".to_string();
+        apply(added, changed).await;
+
+        let insert = || async {
+            sqlx::query_as::<_, (i64,)>(
+                "INSERT INTO Category (cat_title) VALUES ('new')
+                RETURNING id;",
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .0
+        };
+        assert_eq!(insert().await, 3);
+
+        rollback_migration(&pool, with_note).await.unwrap();
+        assert_eq!(insert().await, 4);
+
+        rollback_migration(&pool, created).await.unwrap();
+        assert_eq!(insert().await, 5);
+
+        // the key of `Todo` survived both rollbacks
+        let err = sqlx::query(
+            "INSERT INTO Todo (category_id) VALUES (99);",
+        )
+        .execute(&pool)
+        .await
+        .unwrap_err();
+        assert!(
+            err.as_database_error()
+                .is_some_and(|e| e.is_foreign_key_violation()),
+            "{err}"
+        );
+        sqlx::query("DELETE FROM Category WHERE id = 2;")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let todos: (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM Todo;")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(todos.0, 0);
+    }
+
+    #[test]
+    fn added_index_is_dropped_on_rollback() {
+        let prev: SchemaSnapshot = [(
+            "Todo".to_string(),
+            TableSnapshot {
+                columns: vec![(
+                    "done".to_string(),
+                    "BOOLEAN".to_string(),
+                )],
+                constraints: vec![],
+                indexes: vec![],
+            },
+        )]
+        .into();
+
+        let mut next = prev.clone();
+        next.get_mut("Todo").unwrap().indexes.push(
+            IndexSnapshot {
+                name: "todo_done_idx".to_string(),
+                sql: "CREATE INDEX IF NOT EXISTS todo_done_idx ON Todo (done);"
+                    .to_string(),
+            },
+        );
+
+        let steps = diff::<Sqlite>(
+            &prev,
+            &next,
+            &Default::default(),
+            &Default::default(),
+        )
+        .unwrap();
+
+        pretty_assertions::assert_eq!(
+            steps[0].up,
+            vec!["CREATE INDEX IF NOT EXISTS todo_done_idx ON Todo (done);"]
+        );
+        pretty_assertions::assert_eq!(
+            steps[0].down,
+            vec!["DROP INDEX IF EXISTS todo_done_idx;"]
+        );
+
+        // and the other way around
+        let steps = diff::<Sqlite>(
+            &next,
+            &prev,
+            &Default::default(),
+            &Default::default(),
+        )
+        .unwrap();
+        pretty_assertions::assert_eq!(
+            steps[0].up,
+            vec!["DROP INDEX IF EXISTS todo_done_idx;"]
+        );
+    }
+
+    #[test]
+    fn rename_hints_keep_data() {
+        let prev: SchemaSnapshot = [(
+            "Task".to_string(),
+            TableSnapshot {
+                columns: vec![
+                    ("id".to_string(), "INTEGER".to_string()),
+                    ("name".to_string(), "TEXT".to_string()),
+                ],
+                constraints: vec![],
+                indexes: vec![],
+            },
+        )]
+        .into();
+
+        let next: SchemaSnapshot = [(
+            "Todo".to_string(),
+            TableSnapshot {
+                columns: vec![
+                    ("id".to_string(), "INTEGER".to_string()),
+                    ("title".to_string(), "TEXT".to_string()),
+                ],
+                constraints: vec![],
+                indexes: vec![],
+            },
+        )]
+        .into();
+
+        let renames = Renames {
+            tables: [("Todo".to_string(), "Task".to_string())]
+                .into(),
+            columns: [(
+                "Todo".to_string(),
+                vec![("name".to_string(), "title".to_string())],
+            )]
+            .into(),
+        };
+
+        let steps = diff::<Sqlite>(
+            &prev,
+            &next,
+            &renames,
+            &Default::default(),
+        )
+        .unwrap();
+
+        pretty_assertions::assert_eq!(
+            steps.iter().map(|e| e.up.clone()).collect::<Vec<_>>(),
+            vec![
+                vec!["ALTER TABLE Task RENAME TO Todo;"],
+                vec!["ALTER TABLE Todo RENAME COLUMN name TO title;"],
+            ]
+        );
+
+        // hints that already ran are no-op
+        assert!(diff::<Sqlite>(
+            &next,
+            &next,
+            &renames,
+            &Default::default()
+        )
+        .unwrap()
+        .is_empty());
+    }
+
+    #[test]
+    fn not_null_column_needs_a_default() {
+        let prev: SchemaSnapshot = [(
+            "Todo".to_string(),
+            TableSnapshot {
+                columns: vec![(
+                    "id".to_string(),
+                    "INTEGER PRIMARY KEY AUTOINCREMENT"
+                        .to_string(),
+                )],
+                constraints: vec![],
+                indexes: vec![],
+            },
+        )]
+        .into();
+
+        let with = |def: &str| {
+            let mut next = prev.clone();
+            next.get_mut("Todo")
+                .unwrap()
+                .columns
+                .push(("title".to_string(), def.to_string()));
+            diff::<Sqlite>(
+                &prev,
+                &next,
+                &Default::default(),
+                &Default::default(),
+            )
+        };
+
+        let err = with("TEXT NOT NULL").unwrap_err();
+        assert!(err.contains("title"), "{err}");
+        assert!(with("TEXT NOT NULL DEFAULT ''").is_ok());
+    }
+
+    #[test]
+    fn existing_table_is_not_dropped_on_rollback() {
+        let next: SchemaSnapshot = [(
+            "Todo".to_string(),
+            TableSnapshot::default(),
+        )]
+        .into();
+
+        let steps = diff::<Sqlite>(
+            &Default::default(),
+            &next,
+            &Default::default(),
+            &["todo".to_string()].into(),
+        )
+        .unwrap();
+        assert_eq!(steps.len(), 1);
+        assert!(steps[0].down.is_empty());
+    }
+
+    crate::seed! { "test_fixtures" =>
+        Category { "cat_title": "work" },
+        Tag { "tag_title": "urgent" },
+        Tag { "tag_title": "later" },
+        Todo { "title": "first", "done": false, "description": "" }
+            relation {
+                "category": { "set_id_to_and_populate": 1 },
+                "tag": { "set_id_to_and_populate": [2] },
+            },
+    }
+
+    /// the category and tags of the seeded todo
+    async fn seeded_links(
+        pool: &Pool<Sqlite>,
+    ) -> (String, Vec<String>) {
+        let category: (String,) = sqlx::query_as(
+            "SELECT cat_title FROM Todo
+            JOIN Category ON Category.id = Todo.category_id;",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        let tags: Vec<(String,)> = sqlx::query_as(
+            "SELECT tag_title FROM TodoTag
+            JOIN Tag ON Tag.id = TodoTag.tag_id;",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        (category.0, tags.into_iter().map(|e| e.0).collect())
+    }
+
+    async fn count(pool: &Pool<Sqlite>, table: &str) -> i64 {
+        let found: (i64,) = sqlx::query_as(&format!(
+            "SELECT COUNT(*) FROM {};",
+            table
+        ))
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        found.0
+    }
+
+    #[tokio::test]
+    async fn migrate_and_rollback() {
+        // seeds are audited while the audit test runs
+        let _serial =
+            crate::permissions::test::SERIAL.lock().await;
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        run_migration(pool.clone()).await.unwrap();
+        // schema then seeds
+        assert_eq!(current_version(&pool).await.unwrap(), 2);
+        assert_eq!(count(&pool, "Todo").await, 1);
+
+        let index: Vec<(String,)> = sqlx::query_as(
+            "SELECT name FROM sqlite_master WHERE type = 'index' AND name = 'todo_done_idx';",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(index.len(), 1);
+
+        // relations add their key columns and link tables
+        sqlx::query("SELECT category_id FROM Todo;")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            seeded_links(&pool).await,
+            ("work".to_string(), vec!["later".to_string()])
+        );
+
+        // same schema and seeds, nothing to record
+        run_migration(pool.clone()).await.unwrap();
+        assert_eq!(current_version(&pool).await.unwrap(), 2);
+        assert_eq!(count(&pool, "Todo").await, 1);
+
+        // rolling back the seed deletes its records, the next
+        // run inserts them again
+        rollback_migration(&pool, 1).await.unwrap();
+        assert_eq!(count(&pool, "Todo").await, 0);
+        assert_eq!(count(&pool, "Tag").await, 0);
+        assert_eq!(count(&pool, "TodoTag").await, 0);
+        assert_eq!(count(&pool, "_cms_seeds").await, 0);
+
+        // relations point at the seeded records, not at the
+        // rows that happen to have their ids
+        sqlx::query(
+            "INSERT INTO Category (cat_title) VALUES ('manual');
+            INSERT INTO Tag (tag_title) VALUES ('manual');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        run_migration(pool.clone()).await.unwrap();
+        assert_eq!(count(&pool, "Category").await, 2);
+        assert_eq!(count(&pool, "Todo").await, 1);
+        assert_eq!(
+            seeded_links(&pool).await,
+            ("work".to_string(), vec!["later".to_string()])
+        );
+
+        rollback_migration(&pool, 0).await.unwrap();
+        assert_eq!(current_version(&pool).await.unwrap(), 0);
+
+        let tables: Vec<(String,)> = sqlx::query_as(
+            "SELECT name FROM sqlite_master WHERE name = 'Todo';",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+        assert!(tables.is_empty());
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use queries_for_sqlx::Statement;
+
+use crate::{
+    backend::Backend,
+    queries_bridge::{CreatTableSt, CreateIndexSt},
+};
+
+/// what a table looked like after a migration was applied,
+/// stored with each entry of the migration history so the next
+/// run can diff against it, and the down path can rebuild it
+#[derive(
+    Debug, Clone, Default, PartialEq, Serialize, Deserialize,
+)]
+pub struct TableSnapshot {
+    pub columns: Vec<(String, String)>,
+    pub constraints: Vec<String>,
+    /// snapshots recorded before indexes were tracked don't
+    /// have this field
+    #[serde(default)]
+    pub indexes: Vec<IndexSnapshot>,
+}
+
+#[derive(
+    Debug, Clone, Default, PartialEq, Serialize, Deserialize,
+)]
+pub struct IndexSnapshot {
+    pub name: String,
+    /// the full `CREATE INDEX` statement
+    pub sql: String,
+}
+
+pub type SchemaSnapshot = BTreeMap<String, TableSnapshot>;
+
+impl<S> From<&CreatTableSt<S>> for TableSnapshot
+where
+    S: sqlx::Database + queries_for_sqlx::SupportNamedBind,
+{
+    fn from(value: &CreatTableSt<S>) -> Self {
+        TableSnapshot {
+            columns: value.columns().to_vec(),
+            constraints: value
+                .constraints()
+                .iter()
+                .chain(value.verbatims())
+                .cloned()
+                .collect(),
+            indexes: vec![],
+        }
+    }
+}
+
+impl<S> From<CreateIndexSt<S>> for IndexSnapshot
+where
+    S: sqlx::Database + queries_for_sqlx::SupportNamedBind,
+{
+    fn from(value: CreateIndexSt<S>) -> Self {
+        IndexSnapshot {
+            name: value.name().to_string(),
+            sql: value._build().0,
+        }
+    }
+}
+
+impl IndexSnapshot {
+    fn drop_sql(&self) -> String {
+        format!("DROP INDEX IF EXISTS {};", self.name)
+    }
+}
+
+impl TableSnapshot {
+    fn has_column(&self, name: &str) -> bool {
+        self.columns.iter().any(|e| e.0 == name)
+    }
+}
+
+/// hints collected from `#[cms(renamed_from = "..")]`, without
+/// them a rename looks like a drop plus an add
+#[derive(Debug, Clone, Default)]
+pub struct Renames {
+    /// new table name -> old table name
+    pub tables: BTreeMap<String, String>,
+    /// table (new name) -> `(old, new)` column names
+    pub columns: BTreeMap<String, Vec<(String, String)>>,
+}
+
+/// one unit of change, `down` undoes exactly what `up` did
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStep {
+    pub up: Vec<String>,
+    pub down: Vec<String>,
+}
+
+pub fn create_table_sql(
+    name: &str,
+    table: &TableSnapshot,
+) -> String {
+    let clauses = table
+        .columns
+        .iter()
+        .map(|(col, def)| format!("{} {}", col, def))
+        .chain(table.constraints.iter().cloned())
+        .collect::<Vec<_>>();
+
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} ({});",
+        name,
+        clauses.join(", ")
+    )
+}
+
+fn create_table_with_indexes(
+    name: &str,
+    table: &TableSnapshot,
+) -> Vec<String> {
+    let mut stmts = vec![create_table_sql(name, table)];
+    stmts.extend(table.indexes.iter().map(|e| e.sql.clone()));
+    stmts
+}
+
+/// the rows copied by `rebuild_table` would have no value for
+/// a new `NOT NULL` column, reject it before anything runs
+fn check_added_columns(
+    name: &str,
+    from: &TableSnapshot,
+    to: &TableSnapshot,
+) -> Result<(), String> {
+    let missing = to.columns.iter().find(|(col, def)| {
+        !from.has_column(col)
+            && def.contains("NOT NULL")
+            && !def.contains("DEFAULT")
+            && !def.contains("PRIMARY KEY")
+    });
+
+    match missing {
+        Some((col, _)) => Err(format!(
+            "can't add column {col} to {name}: it is NOT NULL \
+            without a DEFAULT, so existing rows would have no \
+            value for it. give it a default or make it optional"
+        )),
+        None => Ok(()),
+    }
+}
+
+/// sqlite can't drop or alter columns in place, the only
+/// reliable way is to create the new shape, copy the common
+/// columns over and swap the two tables. `schema` is every
+/// table as it is once the step ran, `stable` the ones that
+/// are there before it too
+pub fn rebuild_table<S: Backend>(
+    name: &str,
+    from: &TableSnapshot,
+    to: &TableSnapshot,
+    stable: &SchemaSnapshot,
+) -> Vec<String> {
+    let tmp = format!("_cms_rebuild_{}", name);
+
+    let common = to
+        .columns
+        .iter()
+        .filter(|(col, _)| from.has_column(col))
+        .map(|(col, _)| col.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut stmts = vec![create_table_sql(&tmp, to)];
+
+    if !common.is_empty() {
+        stmts.push(format!(
+            "INSERT INTO {tmp} ({common}) SELECT {common} FROM {name};"
+        ));
+    }
+
+    if S::alters_columns() {
+        // postgres refuses to drop a table others refer to, the
+        // foreign keys go with it and are added back below
+        stmts.push(format!("DROP TABLE {} CASCADE;", name));
+    } else {
+        stmts.push(format!("DROP TABLE {};", name));
+    }
+    stmts.push(format!("ALTER TABLE {tmp} RENAME TO {name};"));
+
+    // dropping the table took its indexes with it
+    stmts.extend(to.indexes.iter().map(|e| e.sql.clone()));
+
+    if S::alters_columns() {
+        stmts.extend(restore_after_rebuild(name, to, stable));
+    }
+
+    stmts
+}
+
+/// postgres: the identity of the new table starts at 1, move
+/// it past the copied ids, and add back the foreign keys that
+/// `DROP TABLE .. CASCADE` removed
+fn restore_after_rebuild(
+    name: &str,
+    to: &TableSnapshot,
+    stable: &SchemaSnapshot,
+) -> Vec<String> {
+    let mut stmts = vec![];
+
+    let identity = to.columns.iter().any(|(col, def)| {
+        col == "id" && def.contains("IDENTITY")
+    });
+    if identity {
+        stmts.push(format!(
+            "SELECT setval(pg_get_serial_sequence('{name}', 'id'), \
+            COALESCE(MAX(id), 0) + 1, false) FROM {name};"
+        ));
+    }
+
+    let references = format!("REFERENCES {} (id)", name);
+    let referring = stable
+        .iter()
+        .filter(|(other, _)| other.as_str() != name)
+        .map(|(other, table)| (other.as_str(), table))
+        .chain([(name, to)]);
+    for (other, table) in referring {
+        for (col, def) in table.columns.iter() {
+            if let Some(at) = def.find(&references) {
+                stmts.push(format!(
+                    "ALTER TABLE {other} ADD FOREIGN KEY ({col}) {};",
+                    &def[at..]
+                ));
+            }
+        }
+    }
+
+    stmts
+}
+
+/// the columns that are in both schemas with the same
+/// definition, the foreign keys they hold are there before and
+/// after a step
+fn stable_tables(
+    prev: &SchemaSnapshot,
+    next: &SchemaSnapshot,
+) -> SchemaSnapshot {
+    prev.iter()
+        .filter_map(|(name, table)| {
+            let next = next.get(name)?;
+            let columns = table
+                .columns
+                .iter()
+                .filter(|e| next.columns.contains(e))
+                .cloned()
+                .collect();
+            Some((
+                name.clone(),
+                TableSnapshot {
+                    columns,
+                    ..Default::default()
+                },
+            ))
+        })
+        .collect()
+}
+
+/// apply the rename hints that are still relevant to `prev`,
+/// hints that already ran in an older version are ignored
+fn renames(
+    prev: &mut SchemaSnapshot,
+    next: &SchemaSnapshot,
+    renames: &Renames,
+) -> Vec<MigrationStep> {
+    let mut steps = vec![];
+
+    for (new, old) in renames.tables.iter() {
+        if prev.contains_key(new) || !next.contains_key(new) {
+            continue;
+        }
+        let Some(table) = prev.remove(old) else {
+            continue;
+        };
+
+        steps.push(MigrationStep {
+            up: vec![format!(
+                "ALTER TABLE {old} RENAME TO {new};"
+            )],
+            down: vec![format!(
+                "ALTER TABLE {new} RENAME TO {old};"
+            )],
+        });
+        prev.insert(new.clone(), table);
+    }
+
+    for (table, columns) in renames.columns.iter() {
+        let (Some(snapshot), Some(next_table)) =
+            (prev.get_mut(table), next.get(table))
+        else {
+            continue;
+        };
+
+        for (old, new) in columns.iter() {
+            if snapshot.has_column(new)
+                || !snapshot.has_column(old)
+                || !next_table.has_column(new)
+            {
+                continue;
+            }
+
+            for col in snapshot.columns.iter_mut() {
+                if &col.0 == old {
+                    col.0 = new.clone();
+                }
+            }
+
+            steps.push(MigrationStep {
+                up: vec![format!(
+                    "ALTER TABLE {table} RENAME COLUMN {old} TO {new};"
+                )],
+                down: vec![format!(
+                    "ALTER TABLE {table} RENAME COLUMN {new} TO {old};"
+                )],
+            });
+        }
+    }
+
+    steps
+}
+
+/// drop what is gone first, an index that changed shape under
+/// the same name is dropped then created again
+fn diff_indexes(
+    from: &TableSnapshot,
+    to: &TableSnapshot,
+) -> MigrationStep {
+    let removed = from
+        .indexes
+        .iter()
+        .filter(|e| !to.indexes.contains(e))
+        .collect::<Vec<_>>();
+    let added = to
+        .indexes
+        .iter()
+        .filter(|e| !from.indexes.contains(e))
+        .collect::<Vec<_>>();
+
+    MigrationStep {
+        up: removed
+            .iter()
+            .map(|e| e.drop_sql())
+            .chain(added.iter().map(|e| e.sql.clone()))
+            .collect(),
+        down: added
+            .iter()
+            .map(|e| e.drop_sql())
+            .chain(removed.iter().map(|e| e.sql.clone()))
+            .collect(),
+    }
+}
+
+/// postgres: added and dropped columns change in place, the
+/// rows keep their ids and the keys pointing at them. indexes
+/// on new columns are created once the columns are there
+fn alter_columns(
+    name: &str,
+    from: &TableSnapshot,
+    to: &TableSnapshot,
+) -> MigrationStep {
+    let add = |table: &TableSnapshot, other: &TableSnapshot| {
+        table
+            .columns
+            .iter()
+            .filter(|(col, _)| !other.has_column(col))
+            .map(|(col, def)| {
+                format!("ALTER TABLE {name} ADD COLUMN {col} {def};")
+            })
+            .collect::<Vec<_>>()
+    };
+    let drop = |table: &TableSnapshot, other: &TableSnapshot| {
+        table
+            .columns
+            .iter()
+            .filter(|(col, _)| !other.has_column(col))
+            .map(|(col, _)| {
+                format!("ALTER TABLE {name} DROP COLUMN {col};")
+            })
+            .collect::<Vec<_>>()
+    };
+    let indexes = diff_indexes(from, to);
+
+    MigrationStep {
+        up: drop(from, to)
+            .into_iter()
+            .chain(add(to, from))
+            .chain(indexes.up)
+            .collect(),
+        down: drop(to, from)
+            .into_iter()
+            .chain(add(from, to))
+            .chain(indexes.down)
+            .collect(),
+    }
+}
+
+/// `existing` is the lowercased names of the tables in the
+/// database, see `history::existing_tables`
+pub fn diff<S: Backend>(
+    prev: &SchemaSnapshot,
+    next: &SchemaSnapshot,
+    renames_hint: &Renames,
+    existing: &BTreeSet<String>,
+) -> Result<Vec<MigrationStep>, String> {
+    let mut prev = prev.clone();
+    let mut steps = renames(&mut prev, next, renames_hint);
+
+    for (name, table) in next.iter() {
+        let old = match prev.get(name) {
+            Some(old) => old,
+            // made before the cms recorded it, creating it is a
+            // no-op and rolling back must not drop its data
+            None if existing.contains(&name.to_lowercase()) => {
+                steps.push(MigrationStep {
+                    up: create_table_with_indexes(name, table),
+                    down: vec![],
+                });
+                continue;
+            }
+            None => {
+                steps.push(MigrationStep {
+                    up: create_table_with_indexes(name, table),
+                    down: vec![format!(
+                        "DROP TABLE IF EXISTS {};",
+                        name
+                    )],
+                });
+                continue;
+            }
+        };
+
+        if old == table {
+            continue;
+        }
+
+        if old.columns == table.columns
+            && old.constraints == table.constraints
+        {
+            steps.push(diff_indexes(old, table));
+            continue;
+        }
+
+        let only_adds_and_drops = old.constraints
+            == table.constraints
+            && old.columns.iter().all(|e| {
+                !table.has_column(&e.0)
+                    || table.columns.contains(e)
+            });
+
+        if S::alters_columns() && only_adds_and_drops {
+            check_added_columns(name, old, table)?;
+            steps.push(alter_columns(name, old, table));
+            continue;
+        }
+
+        let stable = stable_tables(&prev, next);
+
+        let only_nullable_adds = old.constraints
+            == table.constraints
+            && old
+                .columns
+                .iter()
+                .all(|e| table.columns.contains(e))
+            && table
+                .columns
+                .iter()
+                .filter(|e| !old.columns.contains(e))
+                .all(|(_, def)| {
+                    !def.contains("NOT NULL")
+                        && !def.contains("PRIMARY KEY")
+                });
+
+        if !only_nullable_adds {
+            check_added_columns(name, old, table)?;
+            steps.push(MigrationStep {
+                up: rebuild_table::<S>(
+                    name, old, table, &stable,
+                ),
+                down: rebuild_table::<S>(
+                    name, table, old, &stable,
+                ),
+            });
+            continue;
+        }
+
+        let indexes = diff_indexes(old, table);
+
+        steps.push(MigrationStep {
+            up: table
+                .columns
+                .iter()
+                .filter(|e| !old.columns.contains(e))
+                .map(|(col, def)| {
+                    format!(
+                        "ALTER TABLE {} ADD COLUMN {} {};",
+                        name, col, def
+                    )
+                })
+                .chain(indexes.up)
+                .collect(),
+            down: rebuild_table::<S>(
+                name, table, old, &stable,
+            ),
+        });
+    }
+
+    for (name, table) in prev.iter() {
+        if next.contains_key(name) {
+            continue;
+        }
+        steps.push(MigrationStep {
+            up: vec![format!("DROP TABLE IF EXISTS {};", name)],
+            down: create_table_with_indexes(name, table),
+        });
+    }
+
+    Ok(steps)
+}
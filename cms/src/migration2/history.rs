@@ -0,0 +1,319 @@
+use std::{collections::BTreeSet, error::Error};
+
+use sqlx::{
+    database::HasArguments, pool::PoolConnection, ColumnIndex,
+    Connection, Decode, Encode, Executor, IntoArguments, Pool,
+    Type,
+};
+
+use super::diff::{MigrationStep, SchemaSnapshot};
+use crate::backend::Backend;
+
+pub fn migration_st<S: Backend>() -> String {
+    format!(
+        "
+    CREATE TABLE IF NOT EXISTS _cms_migrations (
+        version {},
+        up TEXT NOT NULL,
+        down TEXT NOT NULL,
+        snapshot TEXT NOT NULL,
+        applied_at {} NOT NULL
+    );
+    ",
+        S::id_column(),
+        S::i64_column(),
+    )
+}
+
+pub async fn latest_snapshot<S>(
+    conn: &mut S::Connection,
+) -> Result<SchemaSnapshot, Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(&migration_st::<S>())
+        .execute(&mut *conn)
+        .await?;
+
+    let found: Option<(String,)> = sqlx::query_as(
+        "SELECT snapshot FROM _cms_migrations
+        ORDER BY version DESC LIMIT 1;",
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match found {
+        Some((snapshot,)) => {
+            Ok(serde_json::from_str(&snapshot)?)
+        }
+        None => Ok(Default::default()),
+    }
+}
+
+/// lowercased, postgres folds unquoted names to lowercase
+pub async fn existing_tables<S>(
+    db: &Pool<S>,
+) -> Result<BTreeSet<String>, Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let found: Vec<(String,)> =
+        sqlx::query_as(S::tables_st()).fetch_all(db).await?;
+
+    Ok(found.into_iter().map(|e| e.0.to_lowercase()).collect())
+}
+
+pub async fn current_version<S>(
+    db: &Pool<S>,
+) -> Result<i64, Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(&migration_st::<S>()).execute(db).await?;
+
+    let found: (Option<i64>,) = sqlx::query_as(
+        "SELECT MAX(version) FROM _cms_migrations;",
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(found.0.unwrap_or_default())
+}
+
+/// see `Backend::foreign_keys`
+async fn foreign_keys<S>(
+    conn: &mut PoolConnection<S>,
+    on: bool,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+{
+    if let Some(st) = S::foreign_keys(on) {
+        sqlx::raw_sql(st).execute(&mut **conn).await?;
+    }
+    Ok(())
+}
+
+async fn run_all<S>(
+    conn: &mut S::Connection,
+    stmts: &[String],
+) -> Result<(), Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+{
+    for each in stmts.iter() {
+        sqlx::raw_sql(each).execute(&mut *conn).await.map_err(
+            |e| format!("failed to run `{}`: {}", each, e),
+        )?;
+    }
+    Ok(())
+}
+
+async fn apply_inner<S>(
+    conn: &mut PoolConnection<S>,
+    up: &[String],
+    down: &[String],
+    snapshot: &SchemaSnapshot,
+) -> Result<(), Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let mut tx = conn.begin().await?;
+
+    run_all::<S>(&mut tx, up).await?;
+
+    sqlx::query(
+        "INSERT INTO _cms_migrations
+        (up, down, snapshot, applied_at) VALUES ($1, $2, $3, $4);",
+    )
+    .bind(serde_json::to_string(up)?)
+    .bind(serde_json::to_string(down)?)
+    .bind(serde_json::to_string(snapshot)?)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// run all steps in one transaction and record them as a
+/// new version, returns that version
+pub async fn apply<S>(
+    db: &Pool<S>,
+    steps: Vec<MigrationStep>,
+    snapshot: &SchemaSnapshot,
+) -> Result<i64, Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let up = steps
+        .iter()
+        .flat_map(|e| e.up.iter().cloned())
+        .collect::<Vec<_>>();
+    let down = steps
+        .iter()
+        .rev()
+        .flat_map(|e| e.down.iter().cloned())
+        .collect::<Vec<_>>();
+
+    let mut conn = db.acquire().await?;
+
+    foreign_keys(&mut conn, false).await?;
+    let res = apply_inner(&mut conn, &up, &down, snapshot).await;
+    foreign_keys(&mut conn, true).await?;
+    res?;
+
+    current_version(db).await
+}
+
+/// record a version whose changes were already made outside
+/// of `apply`, only its down path will ever run. `conn` is the
+/// transaction that made them
+pub(crate) async fn record<S>(
+    conn: &mut S::Connection,
+    up: &[String],
+    down: &[String],
+) -> Result<i64, Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let snapshot = latest_snapshot::<S>(&mut *conn).await?;
+
+    let (version,): (i64,) = sqlx::query_as(
+        "INSERT INTO _cms_migrations
+        (up, down, snapshot, applied_at) VALUES ($1, $2, $3, $4)
+        RETURNING version;",
+    )
+    .bind(serde_json::to_string(up)?)
+    .bind(serde_json::to_string(down)?)
+    .bind(serde_json::to_string(&snapshot)?)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(version)
+}
+
+async fn rollback_inner<S>(
+    conn: &mut PoolConnection<S>,
+    versions: Vec<(i64, String)>,
+) -> Result<(), Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let mut tx = conn.begin().await?;
+
+    for (version, down) in versions {
+        let down: Vec<String> = serde_json::from_str(&down)?;
+
+        run_all::<S>(&mut tx, &down).await.map_err(|e| {
+            format!(
+                "failed to roll back version {}: {}",
+                version, e
+            )
+        })?;
+
+        sqlx::query(
+            "DELETE FROM _cms_migrations WHERE version = $1;",
+        )
+        .bind(version)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// run the down path of every migration applied after
+/// `to_version`, newest first. `to_version = 0` reverts
+/// everything the cms has ever migrated
+pub async fn rollback_migration<S>(
+    db: &Pool<S>,
+    to_version: i64,
+) -> Result<(), Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(&migration_st::<S>()).execute(db).await?;
+
+    let versions: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT version, down FROM _cms_migrations
+        WHERE version > $1 ORDER BY version DESC;",
+    )
+    .bind(to_version)
+    .fetch_all(db)
+    .await?;
+
+    if versions.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = db.acquire().await?;
+
+    foreign_keys(&mut conn, false).await?;
+    let res = rollback_inner(&mut conn, versions).await;
+    foreign_keys(&mut conn, true).await?;
+
+    res
+}
@@ -0,0 +1,324 @@
+use std::{collections::HashMap, error::Error};
+
+use case::CaseExt;
+use serde_json::{Map, Value};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
+
+use super::{diff::SchemaSnapshot, history};
+use crate::{
+    backend::Backend,
+    operations::insert_one::{insert_one_in, InputInsertOne},
+};
+
+/// one record to insert, `input` and `relation` have the same
+/// shape as the body of `POST /collection/{name}`
+pub struct SeedRecord {
+    pub collection: &'static str,
+    pub input: Value,
+    pub relation: Map<String, Value>,
+}
+
+pub struct SubmitSeed {
+    pub name: &'static str,
+    pub records: fn() -> Vec<SeedRecord>,
+}
+
+inventory::collect!(SubmitSeed);
+
+/// declare records that `run_migration` inserts once.
+///
+/// records are inserted in order, in one transaction. ids in
+/// relations refer to records of the same seed: `1` is the
+/// first record of the collection the relation loads, whatever
+/// id the database gives it.
+///
+/// ```ignore
+/// seed! { "demo" =>
+///     Category { "title": "work" },
+///     Todo { "title": "first", "done": false, "description": null }
+///         relation { "category": { "set_id_to_and_populate": 1 } },
+/// }
+/// ```
+#[macro_export]
+macro_rules! seed {
+    ($name:literal => $(
+        $collection:ident $input:tt $(relation $relation:tt)?
+    ),* $(,)?) => {
+        $crate::macro_prelude::seed::submit!(
+            $crate::macro_prelude::seed::SubmitSeed {
+                name: $name,
+                records: || vec![$(
+                    $crate::macro_prelude::seed::SeedRecord {
+                        collection: stringify!($collection),
+                        input: $crate::macro_prelude::seed::json!($input),
+                        relation: {
+                            #[allow(unused_mut)]
+                            let mut relation = Default::default();
+                            $(
+                            if let $crate::macro_prelude::seed::Value::Object(map) =
+                                $crate::macro_prelude::seed::json!($relation)
+                            {
+                                relation = map;
+                            }
+                            )?
+                            relation
+                        },
+                    },
+                )*],
+            }
+        );
+    };
+}
+
+fn seeds_st<S: Backend>() -> String {
+    format!(
+        "
+    CREATE TABLE IF NOT EXISTS _cms_seeds (
+        name TEXT PRIMARY KEY,
+        version {i64} NOT NULL,
+        applied_at {i64} NOT NULL
+    );
+    ",
+        i64 = S::i64_column(),
+    )
+}
+
+async fn is_applied<S>(
+    db: &Pool<S>,
+    name: &str,
+) -> Result<bool, sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let found: Option<(String,)> = sqlx::query_as(
+        "SELECT name FROM _cms_seeds WHERE name = $1;",
+    )
+    .bind(name.to_string())
+    .fetch_optional(db)
+    .await?;
+
+    Ok(found.is_some())
+}
+
+/// newest first, so rows that other seeded rows point to are
+/// deleted last. rollbacks run with foreign keys off on sqlite,
+/// what `ON DELETE` would do to the rows pointing at a seeded
+/// row (links, optional keys) is spelled out before it
+fn down_path(
+    name: &str,
+    inserted: &[(String, i64)],
+    schema: &SchemaSnapshot,
+) -> Vec<String> {
+    inserted
+        .iter()
+        .rev()
+        .flat_map(|(table, id)| {
+            let references = format!("REFERENCES {} (id)", table);
+            let cascade =
+                format!("{} ON DELETE CASCADE", references);
+            let set_null =
+                format!("{} ON DELETE SET NULL", references);
+
+            let mut down = vec![];
+            for (other, snapshot) in schema {
+                for (column, def) in snapshot.columns.iter() {
+                    if def.ends_with(&cascade) {
+                        down.push(format!(
+                            "DELETE FROM {} WHERE {} = {};",
+                            other, column, id
+                        ));
+                    } else if def.ends_with(&set_null) {
+                        down.push(format!(
+                            "UPDATE {0} SET {1} = NULL WHERE {1} = {2};",
+                            other, column, id
+                        ));
+                    }
+                }
+            }
+            down.push(format!(
+                "DELETE FROM {} WHERE id = {};",
+                table, id
+            ));
+            down
+        })
+        .chain([format!(
+            "DELETE FROM _cms_seeds WHERE name = '{}';",
+            name.replace('\'', "''")
+        )])
+        .collect()
+}
+
+/// replaces the ids in a relation input, the `n`th record of
+/// `seeded` for `n`. `Err` is the first id with no such record
+fn resolve_ids(
+    value: &mut Value,
+    seeded: &[i64],
+) -> Result<(), i64> {
+    match value {
+        Value::Number(n) => {
+            let Some(n) = n.as_i64() else {
+                return Ok(());
+            };
+            let id = usize::try_from(n - 1)
+                .ok()
+                .and_then(|i| seeded.get(i))
+                .ok_or(n)?;
+            *value = (*id).into();
+            Ok(())
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .try_for_each(|e| resolve_ids(e, seeded)),
+        Value::Object(map) => map
+            .values_mut()
+            .try_for_each(|e| resolve_ids(e, seeded)),
+        _ => Ok(()),
+    }
+}
+
+async fn apply<S>(
+    db: &Pool<S>,
+    seed: &SubmitSeed,
+) -> Result<(), Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    // dropping it on an error leaves the database as it was
+    // before this seed
+    let mut tx = db.begin().await?;
+    let mut inserted: Vec<(String, i64)> = vec![];
+
+    for mut record in (seed.records)() {
+        let (table, targets) = {
+            let collections = S::collections().read().await;
+            let collection = collections
+                .get(&record.collection.to_camel())
+                .ok_or(format!(
+                    "seed {} inserts into unknown collection {}",
+                    seed.name, record.collection
+                ))?;
+            let table = collection.table_name().to_string();
+
+            // relation key -> the table it loads
+            let targets = S::relations()
+                .read()
+                .await
+                .get(&table)
+                .into_iter()
+                .flatten()
+                .map(|e| (e.key(), e.to()))
+                .collect::<HashMap<_, _>>();
+            (table, targets)
+        };
+
+        for (key, value) in record.relation.iter_mut() {
+            let Some(target) = targets.get(key) else {
+                continue;
+            };
+            let seeded = inserted
+                .iter()
+                .filter(|e| &e.0 == target)
+                .map(|e| e.1)
+                .collect::<Vec<_>>();
+            resolve_ids(value, &seeded).map_err(|n| {
+                format!(
+                    "seed {} has no record {} of {} for {}",
+                    seed.name, n, target, record.collection
+                )
+            })?;
+        }
+
+        let res = insert_one_in(
+            &mut tx,
+            record.collection,
+            InputInsertOne {
+                input: record.input,
+                relation: record.relation,
+            },
+        )
+        .await
+        .map_err(|err| {
+            format!(
+                "seed {} failed to insert into {}: {:?}",
+                seed.name, record.collection, err
+            )
+        })?;
+
+        inserted.push((table, res.id));
+    }
+
+    let schema = history::latest_snapshot::<S>(&mut tx).await?;
+    let version = history::record::<S>(
+        &mut tx,
+        &[format!("-- seed {}", seed.name)],
+        &down_path(seed.name, &inserted, &schema),
+    )
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO _cms_seeds (name, version, applied_at)
+        VALUES ($1, $2, $3);",
+    )
+    .bind(seed.name.to_string())
+    .bind(version)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// insert every seed that was not applied to this database yet,
+/// in name order. each seed is one version in the migration
+/// history, rolling it back deletes what it inserted
+pub async fn run_seeds<S>(
+    db: &Pool<S>,
+) -> Result<(), Box<dyn Error>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(&seeds_st::<S>()).execute(db).await?;
+
+    let mut seeds = inventory::iter::<SubmitSeed>
+        .into_iter()
+        .collect::<Vec<_>>();
+    seeds.sort_by_key(|e| e.name);
+
+    for seed in seeds {
+        if is_applied(db, seed.name).await? {
+            continue;
+        }
+        apply(db, seed).await?;
+    }
+
+    Ok(())
+}
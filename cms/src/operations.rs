@@ -40,10 +40,14 @@ impl<C: Collection<Sqlite>> TupleElementKey for IdOutput<C> {
 
 #[cfg(test)]
 mod test {
-    use std::{marker::PhantomData, panic::catch_unwind};
+    use std::{
+        collections::HashMap, marker::PhantomData,
+        panic::catch_unwind,
+    };
 
     use axum::{
         extract::{Path, State},
+        http::StatusCode,
         Json,
     };
     use queries_for_sqlx::ident_safety::define_schema;
@@ -52,7 +56,6 @@ mod test {
 
     use crate::{
         client_example::{Category, Partial, Tag, Todo},
-        dynamic_schema::COLLECTIONS,
         operations::{
             insert_one::insert_one_dynamic,
             select_many::get_all_dynamic,
@@ -139,6 +142,9 @@ mod test {
 
     #[tokio::test]
     async fn test_1() {
+        // writes are audited while the audit test runs
+        let _serial =
+            crate::permissions::test::SERIAL.lock().await;
         let db = init().await;
 
         tracing_subscriber::fmt()
@@ -149,8 +155,14 @@ mod test {
 
         test_get_all(db.clone()).await;
 
+        test_filters(db.clone()).await;
+
         test_insert_one(db.clone()).await;
 
+        test_invalid_input(db.clone()).await;
+
+        test_constraint_violation(db.clone()).await;
+
         test_update_one(db.clone()).await;
 
         test_delete_one(db.clone()).await;
@@ -487,6 +499,259 @@ mod test {
         );
     }
 
+    async fn test_invalid_input(db: Pool<Sqlite>) {
+        let err = insert_one_dynamic(
+            State(db.clone()),
+            Path("todo".to_string()),
+            Json(
+                from_value(json!({
+                    "input": {
+                        "title": "  ",
+                        "done": true,
+                        "description": null
+                    },
+                    "relation": {}
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.status_code,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        let user_error = err.user_error.unwrap();
+        assert_eq!(user_error.code, "invalid_input");
+        assert_eq!(
+            user_error.structured_hint,
+            Some(HashMap::from([(
+                "title".to_string(),
+                "title can't be empty".to_string()
+            )]))
+        );
+
+        let err = update_one_dynmaic(
+            State(db.clone()),
+            Path("todo".to_string()),
+            Json(
+                from_value(json!({
+                    "id": 1,
+                    "partial": { "title": ["set", ""] },
+                    "relations": {}
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.status_code,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM Todo WHERE title = ''",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    async fn test_filters(db: Pool<Sqlite>) {
+        let get_many = |filters| {
+            get_all_dynamic(
+                State(db.clone()),
+                Path("todo".to_string()),
+                Json(
+                    from_value(json!({
+                        "pagination": { "page": 1, "page_size": 10 },
+                        "filters": filters,
+                        "relations": {},
+                    }))
+                    .unwrap(),
+                ),
+            )
+        };
+
+        let res = get_many(json!({
+            "done": true,
+            "title": { "$ne": "todo_3" },
+        }))
+        .await
+        .unwrap();
+
+        let ids = serde_json::to_value(res.0).unwrap()["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["id"].as_i64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 5]);
+
+        let err = get_many(json!({ "title.length": 6 }))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.status_code, StatusCode::BAD_REQUEST);
+        let user_error = err.user_error.unwrap();
+        assert_eq!(user_error.code, "invalid_filter");
+        assert_eq!(
+            user_error.structured_hint,
+            Some(HashMap::from([(
+                "title.length".to_string(),
+                "only JSON fields have paths".to_string()
+            )]))
+        );
+
+        let err = get_many(json!({ "done": { "$like": true } }))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.status_code, StatusCode::BAD_REQUEST);
+    }
+
+    async fn test_constraint_violation(db: Pool<Sqlite>) {
+        let err = insert_one_dynamic(
+            State(db.clone()),
+            Path("todo".to_string()),
+            Json(
+                from_value(json!({
+                    "input": {
+                        "title": "orphan",
+                        "done": false,
+                        "description": null
+                    },
+                    "relation": {
+                        "category": {
+                            "set_id_to_and_populate": 99
+                        }
+                    }
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.status_code,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            err.user_error.unwrap().code,
+            "foreign_key_violation"
+        );
+
+        // a link that fails takes the row it links down with it
+        let err = insert_one_dynamic(
+            State(db.clone()),
+            Path("todo".to_string()),
+            Json(
+                from_value(json!({
+                    "input": {
+                        "title": "badly_linked",
+                        "done": false,
+                        "description": null
+                    },
+                    "relation": {
+                        "tag": {
+                            "set_id_to_and_populate": [1, 99]
+                        }
+                    }
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.user_error.unwrap().code,
+            "foreign_key_violation"
+        );
+
+        let rows: (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM Todo WHERE title = 'badly_linked'",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(rows.0, 0);
+
+        let err = update_one_dynmaic(
+            State(db.clone()),
+            Path("todo".to_string()),
+            Json(
+                from_value(json!({
+                    "id": 2,
+                    "partial": {
+                        "title": ["set", "badly_linked"]
+                    },
+                    "relations": {
+                        "tag": [{ "set_link": 99 }]
+                    }
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.user_error.unwrap().code,
+            "foreign_key_violation"
+        );
+
+        let title: (String,) = sqlx::query_as(
+            "SELECT title FROM Todo WHERE id = 2",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(title.0, "todo_2");
+
+        sqlx::query(
+            "CREATE UNIQUE INDEX tag_title ON Tag (tag_title)",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let err = insert_one_dynamic(
+            State(db.clone()),
+            Path("tag".to_string()),
+            Json(
+                from_value(json!({
+                    "input": { "tag_title": "tag_1" },
+                    "relation": {}
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(err.status_code, StatusCode::CONFLICT);
+        let user_error = err.user_error.unwrap();
+        assert_eq!(user_error.code, "unique_violation");
+        assert_eq!(
+            user_error.structured_hint,
+            Some(HashMap::from([(
+                "tag_title".to_string(),
+                "is already taken".to_string()
+            )]))
+        );
+
+        sqlx::query("DROP INDEX tag_title")
+            .execute(&db)
+            .await
+            .unwrap();
+    }
+
     async fn test_insert_one(db: Pool<Sqlite>) {
         let res = insert_one_dynamic(
             State(db.clone()),
@@ -5,25 +5,29 @@ use axum::{
     Json,
 };
 use case::CaseExt;
-use queries_for_sqlx::prelude::*;
+use queries_for_sqlx::{prelude::*, SupportNamedBind};
 use serde_json::{Map, Value};
-use sqlx::{sqlite::SqliteRow, Row};
-use sqlx::{Pool, Sqlite};
+use sqlx::Row;
+use sqlx::{
+    database::HasArguments, ColumnIndex, Database, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
 
 use crate::{
-    dynamic_schema::{
-        DynamicRelationResult, COLLECTIONS, RELATIONS,
-    },
-    error::{self, GlobalError},
+    audit::{self, Change},
+    backend::Backend,
+    dynamic_schema::DynamicRelationResult,
+    error::{self, ClientError},
+    permissions::owner_filter,
     queries_bridge::DeleteSt,
 };
 
-pub trait DynDeleteWorker: Send + Sync {
+pub trait DynDeleteWorker<S: Database + SupportNamedBind>: Send + Sync {
     fn sub_op(
         &mut self,
-        db: State<Pool<Sqlite>>,
-    ) -> Pin<Box<dyn std::future::Future<Output = ()> + Send>>;
-    fn from_row(&mut self, r: &SqliteRow) -> Value;
+        db: State<Pool<S>>,
+    ) -> Pin<Box<dyn std::future::Future<Output = Result<(), sqlx::Error>> + Send>>;
+    fn from_row(&mut self, r: &S::Row) -> Value;
 }
 
 #[derive(serde::Deserialize)]
@@ -40,43 +44,58 @@ pub struct DeleteOutput {
     pub relations: Map<String, Value>,
 }
 
-#[axum::debug_handler]
-pub async fn delete_one_dynmaic(
-    db: State<Pool<Sqlite>>,
+pub async fn delete_one_dynmaic<S>(
+    db: State<Pool<S>>,
     collection_name: Path<String>,
     input: Json<DeleteInput>,
-) -> Result<Json<Option<DeleteOutput>>, GlobalError> {
-    let collection_gaurd = COLLECTIONS.read().await;
+) -> Result<Json<Option<DeleteOutput>>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    let collection_gaurd = S::collections().read().await;
 
     let collection = collection_gaurd
         .get(&collection_name.0.to_camel())
         .ok_or(error::entry_not_found(&collection_name.0))?;
+    let members = collection.members_no_scope();
 
     let mut st =
         DeleteSt::init(collection.table_name().to_string());
 
     st.where_(col("id").eq(input.id));
 
+    if let Some(owner) = owner_filter() {
+        collection.delete_owned_by(owner, &mut st);
+    }
+
     let retrun_any =
         input.return_attr || !input.return_residual.is_empty();
 
     let ret = if input.return_attr {
         st.returning(vec!["*"])
     } else {
-        st.returning(vec![])
+        // an empty `RETURNING` is not valid sql
+        st.returning(vec!["id"])
     };
 
     let mut rels = if input.return_residual.is_empty() {
         None
     } else {
-        let relation_gaurd = RELATIONS.read().await;
+        let relation_gaurd = S::relations().read().await;
         let mut rels = Vec::new();
         let mut tra = Vec::new();
 
         'found: for key in input.0.return_residual.iter() {
             for r in relation_gaurd
                 .get(collection.table_name())
-                .unwrap()
+                .into_iter()
+                .flatten()
             {
                 match r
                     .clone()
@@ -106,14 +125,29 @@ pub async fn delete_one_dynmaic(
         }
 
         for each in rels.iter_mut() {
-            each.sub_op(db.clone()).await;
+            each.sub_op(db.clone())
+                .await
+                .map_err(|err| error::database(err, members))?;
         }
 
         Some((relation_gaurd, rels, tra))
     };
 
+    // the row before, the delete and its audit entry commit
+    // together
+    let mut tx = db
+        .0
+        .begin()
+        .await
+        .map_err(|err| error::database(err, members))?;
+
+    let before =
+        audit::snapshot(&mut *tx, collection.as_ref(), input.id)
+            .await
+            .map_err(|err| error::database(err, members))?;
+
     let res = ret
-        .fetch_one(&db.0, |r| {
+        .fetch_optional(&mut *tx, |r| {
             if retrun_any {
                 let mut relations = Map::default();
                 let id = r.get("id");
@@ -134,7 +168,24 @@ pub async fn delete_one_dynmaic(
             return Ok(None);
         })
         .await
-        .unwrap();
+        .map_err(|err| error::database(err, members))?
+        .ok_or(error::to_refactor("entry with id not found"))?;
+
+    audit::record(
+        &mut *tx,
+        Change::Delete,
+        collection.table_name(),
+        input.id,
+        before,
+        None,
+        Map::default(),
+    )
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    tx.commit()
+        .await
+        .map_err(|err| error::database(err, members))?;
 
     Ok(Json(res))
 }
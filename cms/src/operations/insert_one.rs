@@ -6,18 +6,21 @@ use axum::{
     Json,
 };
 use case::CaseExt;
-use queries_for_sqlx::prelude::*;
+use queries_for_sqlx::{prelude::*, SupportNamedBind};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
-use sqlx::{sqlite::SqliteRow, Pool, Sqlite};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Database, Decode, Encode,
+    Executor, IntoArguments, Pool, Sqlite, Type,
+};
 
 use crate::{
+    audit::{self, Change},
+    backend::Backend,
     build_tuple::BuildTuple,
-    dynamic_schema::{
-        DynamicRelationResult, ValidatedAndTyped, COLLECTIONS,
-        RELATIONS,
-    },
-    error::{self, insert::InsertError},
+    dynamic_schema::{DynamicRelationResult, ValidatedAndTyped},
+    error::{self, insert::InsertError, ClientError},
+    permissions::{check_relation, current_owner},
     queries_bridge::InsertSt,
     relations::{LinkData, LinkId, LinkSpecCanInsert, Linked},
     traits::Collection,
@@ -25,30 +28,30 @@ use crate::{
 };
 
 #[allow(unused)]
-pub trait InsertOneWorker: Sync + Send {
+pub trait InsertOneWorker<S: Database + SupportNamedBind = Sqlite>: Sync + Send {
     type Inner: Default + Send + Sync;
     type Output;
     fn on_insert(
         &self,
         data: &mut Self::Inner,
-        st: &mut InsertSt<Sqlite>,
+        st: &mut InsertSt<S>,
     ) {
     }
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
     }
     fn sub_op2<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
-        async {}
+        conn: &'this mut S::Connection,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
+        async { Ok(()) }
     }
     fn sub_op1<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
-        async {}
+        conn: &'this mut S::Connection,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
+        async { Ok(()) }
     }
     fn take(self, data: Self::Inner) -> Self::Output;
 }
@@ -161,33 +164,98 @@ pub struct InputInsertOne {
 
 #[derive(Debug, Serialize)]
 pub struct OuputDynamic {
-    id: i64,
+    pub(crate) id: i64,
     attr: Value,
     relations: Map<String, Value>,
 }
 
-#[axum::debug_handler]
-pub async fn insert_one_dynamic(
-    db: State<Pool<Sqlite>>,
+pub async fn insert_one_dynamic<S>(
+    db: State<Pool<S>>,
     collection_name: Path<String>,
     input: Json<InputInsertOne>,
-) -> Result<Json<OuputDynamic>, InsertError> {
-    let collection_gaurd = COLLECTIONS.read().await;
-    let relation_gaurd = RELATIONS.read().await;
+) -> Result<Json<OuputDynamic>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    insert_one_dynamic_inner(db.0, &collection_name.0, input.0)
+        .await
+        .map(Json)
+}
+
+/// same as the handler, usable outside of a request (sign up)
+pub(crate) async fn insert_one_dynamic_inner<S>(
+    db: Pool<S>,
+    collection_name: &str,
+    input: InputInsertOne,
+) -> Result<OuputDynamic, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    // the insert, its relations and its audit entry commit
+    // together
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    let res =
+        insert_one_in(&mut tx, collection_name, input).await?;
+
+    tx.commit()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    Ok(res)
+}
+
+/// inserts on a connection the caller commits, so it can be
+/// part of a larger transaction (seeding)
+pub(crate) async fn insert_one_in<S>(
+    tx: &mut S::Connection,
+    collection_name: &str,
+    input: InputInsertOne,
+) -> Result<OuputDynamic, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    let collection_gaurd = S::collections().read().await;
+    let relation_gaurd = S::relations().read().await;
 
     let collection = collection_gaurd
-        .get(&collection_name.0.to_camel())
-        .ok_or(error::entry_not_found(&collection_name.0))?;
+        .get(&collection_name.to_camel())
+        .ok_or(error::entry_not_found(collection_name))?;
+    let members = collection.members_no_scope();
 
     let (mut rels, tra) = {
         let mut rels = vec![];
         let mut trans = vec![];
 
         // key: snake_case
-        'found: for (key, value) in input.0.relation.iter() {
+        'found: for (key, value) in input.relation.iter() {
+            check_relation(key)?;
+
             for r in relation_gaurd
                 .get(collection.table_name())
-                .unwrap()
+                .into_iter()
+                .flatten()
             {
                 match r
                     .clone()
@@ -222,7 +290,7 @@ pub async fn insert_one_dynamic(
         collection.table_name().to_string(),
     );
 
-    match collection.on_insert(input.0.input, &mut st) {
+    match collection.on_insert(input.input, &mut st) {
         Ok(()) => {}
         Err(ValidatedAndTyped::ValidationError(err)) => {
             return Err(error::to_refactor(&format!(
@@ -238,10 +306,19 @@ pub async fn insert_one_dynamic(
             ))
             .into())
         }
+        Err(ValidatedAndTyped::FieldErrors(err)) => {
+            return Err(err.into())
+        }
+    }
+
+    if let Some(owner) = current_owner() {
+        collection.insert_owner(owner, &mut st);
     }
 
     for rel in rels.iter_mut() {
-        rel.sub_op1(db.0.clone()).await;
+        rel.sub_op1(&mut *tx)
+            .await
+            .map_err(|err| error::database(err, members))?;
     }
 
     for rel in rels.iter_mut() {
@@ -250,7 +327,7 @@ pub async fn insert_one_dynamic(
 
     let mut res = st
         .returning(vec!["*"])
-        .fetch_one(&db.0, |r| {
+        .fetch_one(&mut *tx, |r| {
             let attr = collection.from_row_noscope(&r);
             let id: i64 = r.get("id");
 
@@ -265,10 +342,24 @@ pub async fn insert_one_dynamic(
             })
         })
         .await
-        .unwrap();
+        .map_err(|err| error::database(err, members))?;
+
+    audit::record(
+        &mut *tx,
+        Change::Insert,
+        collection.table_name(),
+        res.id,
+        None,
+        Some(res.attr.clone()),
+        input.relation,
+    )
+    .await
+    .map_err(|err| error::database(err, &[]))?;
 
     for rel in rels.iter_mut() {
-        rel.sub_op2(db.0.clone()).await;
+        rel.sub_op2(&mut *tx)
+            .await
+            .map_err(|err| error::database(err, members))?;
     }
 
     for (mut rel, tra) in rels.into_iter().zip(tra.into_iter()) {
@@ -276,5 +367,5 @@ pub async fn insert_one_dynamic(
         res.relations.insert(tra, value);
     }
 
-    Ok(Json(res))
+    Ok(res)
 }
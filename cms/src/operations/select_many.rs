@@ -5,40 +5,43 @@ use axum::{
     Json,
 };
 use case::CaseExt;
-use queries_for_sqlx::prelude::*;
+use queries_for_sqlx::{prelude::*, SupportNamedBind};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
-use sqlx::{sqlite::SqliteRow, Row};
-use sqlx::{Pool, Sqlite};
+use sqlx::Row;
+use sqlx::{
+    ColumnIndex, Database, Decode, Encode, Executor, Pool, Sqlite,
+    Type,
+};
 
 use crate::{
-    dynamic_schema::{
-        DynamicRelationResult, COLLECTIONS, RELATIONS,
-    },
-    error::{self, GlobalError},
-    filters::{AgnosticFilter, Filters},
+    backend::Backend,
+    dynamic_schema::DynamicRelationResult,
+    error::{self, ClientError},
+    filters::{where_filters, AgnosticFilter, Filters},
+    permissions::{check_relation, owner_filter},
     queries_bridge::SelectSt,
 };
 
 use super::select_one::{GetOneOuputDynamic, GetOneWorker};
 
-pub trait GetAllWorker: Sync + Send {
+pub trait GetAllWorker<S: Database + SupportNamedBind = Sqlite>: Sync + Send {
     type Inner: Default + Send + Sync;
     type Output;
     fn on_select(
         &self,
         data: &mut Self::Inner,
-        st: &mut SelectSt<Sqlite>,
+        st: &mut SelectSt<S>,
     ) {
     }
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
     }
     fn sub_op<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
-        async {}
+        pool: Pool<S>,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
+        async { Ok(()) }
     }
     fn take(
         &mut self,
@@ -66,18 +69,25 @@ pub struct GetManyOutputDynamic {
     page_count: (),
 }
 
-#[axum::debug_handler]
-pub async fn get_all_dynamic(
-    db: State<Pool<Sqlite>>,
+pub async fn get_all_dynamic<S>(
+    db: State<Pool<S>>,
     collection_name: Path<String>,
     input: Json<InputGetMany>,
-) -> Result<Json<GetManyOutputDynamic>, GlobalError> {
-    let collection_gaurd = COLLECTIONS.read().await;
-    let relation_gaurd = RELATIONS.read().await;
+) -> Result<Json<GetManyOutputDynamic>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    i32: Type<S> + for<'e> Encode<'e, S>,
+{
+    let collection_gaurd = S::collections().read().await;
+    let relation_gaurd = S::relations().read().await;
 
     let collection = collection_gaurd
         .get(&collection_name.0.to_camel())
         .ok_or(error::entry_not_found(&collection_name.0))?;
+    let members = collection.members_no_scope();
 
     let (mut rels, tra) = {
         let mut rels = vec![];
@@ -85,9 +95,12 @@ pub async fn get_all_dynamic(
 
         // key: snake_case
         'found: for (key, value) in input.0.relations.iter() {
+            check_relation(key)?;
+
             for r in relation_gaurd
                 .get(collection.table_name())
-                .unwrap()
+                .into_iter()
+                .flatten()
             {
                 match r
                     .clone()
@@ -127,6 +140,16 @@ pub async fn get_all_dynamic(
 
     collection.on_select(&mut st);
 
+    if let Some(owner) = owner_filter() {
+        collection.select_owned_by(owner, &mut st);
+    }
+
+    where_filters(
+        collection.as_ref(),
+        &input.0.filters,
+        &mut st,
+    )?;
+
     input.0.pagination.on_select(&mut st);
 
     st.select_aliased(
@@ -152,10 +175,12 @@ pub async fn get_all_dynamic(
             });
         })
         .await
-        .unwrap();
+        .map_err(|err| error::database(err, members))?;
 
     for mut rel in rels.iter_mut() {
-        rel.sub_op(db.0.clone()).await;
+        rel.sub_op(db.0.clone())
+            .await
+            .map_err(|err| error::database(err, members))?;
     }
 
     for (mut rel, t) in rels.into_iter().zip(tra) {
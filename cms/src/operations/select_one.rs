@@ -14,40 +14,43 @@ use queries_for_sqlx::{
     ident_safety::{define_schema, PanicOnUnsafe},
     prelude::*,
     quick_query::QuickQuery,
+    SupportNamedBind,
+};
+use sqlx::{
+    ColumnIndex, Database, Decode, Encode, Executor, Pool, Sqlite,
+    Type,
 };
-use sqlx::{Database, Pool, Sqlite};
 
 use crate::{
+    backend::Backend,
     build_tuple::BuildTuple,
-    dynamic_schema::{
-        DynGetOneWorker, DynamicRelationResult, COLLECTIONS,
-        RELATIONS,
-    },
-    error::{self, GlobalError},
+    dynamic_schema::{DynGetOneWorker, DynamicRelationResult},
+    error::{self, ClientError},
     filters::{ById, Filters},
+    permissions::{check_relation, owner_filter},
     queries_bridge::SelectSt,
     relations::{relation, LinkData, Relation},
     traits::Collection,
     tuple_index::{tuple_as_map::TupleElementKey, TupleAsMap},
 };
 
-pub trait GetOneWorker: Sync + Send {
+pub trait GetOneWorker<S: Database + SupportNamedBind = Sqlite>: Sync + Send {
     type Inner: Default + Send + Sync;
     type Output;
     fn on_select(
         &self,
         data: &mut Self::Inner,
-        st: &mut SelectSt<Sqlite>,
+        st: &mut SelectSt<S>,
     ) {
     }
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
     }
     fn sub_op<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
-        async {}
+        pool: Pool<S>,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
+        async { Ok(()) }
     }
     fn take(self, data: Self::Inner) -> Self::Output;
 }
@@ -193,7 +196,7 @@ where
             .await
             .unwrap()?;
 
-        self.links.sub_op(&mut worker_data, db).await;
+        self.links.sub_op(&mut worker_data, db).await.unwrap();
         let data = self.links.take(worker_data);
 
         return Some(GetOneOutput {
@@ -218,18 +221,24 @@ pub struct GetOneOuputDynamic {
     pub relations: Map<String, Value>,
 }
 
-#[axum::debug_handler]
-pub async fn get_one_dynamic(
-    db: State<Pool<Sqlite>>,
+pub async fn get_one_dynamic<S>(
+    db: State<Pool<S>>,
     collection_name: Path<String>,
     input: Json<InputGetOne>,
-) -> Result<Json<GetOneOuputDynamic>, GlobalError> {
-    let collection_gaurd = COLLECTIONS.read().await;
-    let relation_gaurd = RELATIONS.read().await;
+) -> Result<Json<GetOneOuputDynamic>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let collection_gaurd = S::collections().read().await;
+    let relation_gaurd = S::relations().read().await;
 
     let collection = collection_gaurd
         .get(&collection_name.0.to_camel())
         .ok_or(error::entry_not_found(&collection_name.0))?;
+    let members = collection.members_no_scope();
 
     let (mut rels, tra) = {
         let mut rels = vec![];
@@ -237,9 +246,12 @@ pub async fn get_one_dynamic(
 
         // key: snake_case
         'found: for (key, value) in input.0.relations.iter() {
+            check_relation(key)?;
+
             for r in relation_gaurd
                 .get(collection.table_name())
-                .unwrap()
+                .into_iter()
+                .flatten()
             {
                 match r
                     .clone()
@@ -288,7 +300,14 @@ pub async fn get_one_dynamic(
 
     let id = input.0.id;
 
-    st.where_(col("local_id").eq(id));
+    // postgres can't refer to select aliases in `WHERE`
+    st.where_(
+        scoped(collection.table_name().to_string(), "id").eq(id),
+    );
+
+    if let Some(owner) = owner_filter() {
+        collection.select_owned_by(owner, &mut st);
+    }
 
     let mut res = st
         .fetch_optional(&db.0, |r| {
@@ -306,13 +325,15 @@ pub async fn get_one_dynamic(
             });
         })
         .await
-        .unwrap()
+        .map_err(|err| error::database(err, members))?
         .ok_or(error::to_refactor("entry with id not found"))?;
 
     // todo: concurent awaits
 
     for mut rel in rels.iter_mut() {
-        rel.sub_op(db.0.clone()).await;
+        rel.sub_op(db.0.clone())
+            .await
+            .map_err(|err| error::database(err, members))?;
     }
     for (mut rel, t) in rels.into_iter().zip(tra) {
         let taken = rel.take();
@@ -6,47 +6,51 @@ use axum::{
     Json,
 };
 use case::CaseExt;
-use queries_for_sqlx::prelude::*;
+use queries_for_sqlx::{prelude::*, SupportNamedBind};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
-use sqlx::{sqlite::SqliteRow, Pool, Sqlite};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Database, Decode, Encode,
+    Executor, IntoArguments, Pool, Sqlite, Type,
+};
 
 use crate::{
+    audit::{self, Change},
+    backend::Backend,
     build_tuple::BuildTuple,
-    dynamic_schema::{
-        DynamicRelationResult, COLLECTIONS, RELATIONS,
-    },
-    error::{self, insert::InsertError, GlobalError},
+    dynamic_schema::DynamicRelationResult,
+    error::{self, insert::InsertError, ClientError},
+    permissions::{check_relation, owner_filter},
     queries_bridge::UpdateSt,
     relations::{LinkData, LinkSpecCanInsert, Linked, UpdateId},
     traits::Collection,
     tuple_index::TupleAsMap,
 };
 
-pub trait UpdateOneWorker: Sync + Send {
+pub trait UpdateOneWorker<S: Database + SupportNamedBind = Sqlite>: Sync + Send {
     type Inner: Default + Send + Sync;
     type Output;
     fn on_update(
         &self,
         data: &mut Self::Inner,
-        st: &mut UpdateSt<Sqlite>,
+        st: &mut UpdateSt<S>,
     ) {
     }
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
     }
     fn sub_op2<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
-        async {}
+        conn: &'this mut S::Connection,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
+        async { Ok(()) }
     }
     fn sub_op1<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
-        async {}
+        conn: &'this mut S::Connection,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
+        async { Ok(()) }
     }
     fn take(self, data: Self::Inner) -> Self::Output;
 }
@@ -142,18 +146,27 @@ pub struct OuputDynamic {
     relations: Map<String, Value>,
 }
 
-#[axum::debug_handler]
-pub async fn update_one_dynmaic(
-    db: State<Pool<Sqlite>>,
+pub async fn update_one_dynmaic<S>(
+    db: State<Pool<S>>,
     collection_name: Path<String>,
     input: Json<InputUpdatetOne>,
-) -> Result<Json<OuputDynamic>, GlobalError> {
-    let collection_gaurd = COLLECTIONS.read().await;
-    let relation_gaurd = RELATIONS.read().await;
+) -> Result<Json<OuputDynamic>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    let collection_gaurd = S::collections().read().await;
+    let relation_gaurd = S::relations().read().await;
 
     let collection = collection_gaurd
         .get(&collection_name.0.to_camel())
         .ok_or(error::entry_not_found(&collection_name.0))?;
+    let members = collection.members_no_scope();
 
     let (mut rels, tra) = {
         let mut rels = vec![];
@@ -161,9 +174,12 @@ pub async fn update_one_dynmaic(
 
         // key: snake_case
         'found: for (key, value) in input.0.relations.iter() {
+            check_relation(key)?;
+
             for r in relation_gaurd
                 .get(collection.table_name())
-                .unwrap()
+                .into_iter()
+                .flatten()
             {
                 match r
                     .clone()
@@ -201,10 +217,40 @@ pub async fn update_one_dynmaic(
 
     st.where_(col("id").eq(id));
 
+    // the ownership check, the row before, the update, its
+    // relations and its audit entry commit together
+    let mut tx = db
+        .0
+        .begin()
+        .await
+        .map_err(|err| error::database(err, members))?;
+
+    if let Some(owner) = owner_filter() {
+        // relations are written before the row is, rows the role
+        // doesn't own have to be turned away before that
+        let mut owned =
+            stmt::SelectSt::init(collection.table_name());
+        owned.select(col("id"));
+        owned.where_(
+            scoped(collection.table_name().to_string(), "id")
+                .eq(id),
+        );
+        collection.select_owned_by(owner.clone(), &mut owned);
+        owned
+            .fetch_optional(&mut *tx, |_| Ok(()))
+            .await
+            .map_err(|err| error::database(err, members))?
+            .ok_or(error::to_refactor("entry with id not found"))?;
+
+        collection.update_owned_by(owner, &mut st);
+    }
+
     collection.on_update(input.0.partial, &mut st)?;
 
     for rel in rels.iter_mut() {
-        rel.sub_op1(db.0.clone()).await;
+        rel.sub_op1(&mut *tx)
+            .await
+            .map_err(|err| error::database(err, members))?;
     }
 
     for rel in rels.iter_mut() {
@@ -215,9 +261,14 @@ pub async fn update_one_dynmaic(
         Err(String::from("no-op"))?;
     }
 
+    let before =
+        audit::snapshot(&mut *tx, collection.as_ref(), id)
+            .await
+            .map_err(|err| error::database(err, members))?;
+
     let mut res = st
         .returning_(vec!["*"])
-        .fetch_one(&db.0, |r| {
+        .fetch_optional(&mut *tx, |r| {
             let c = collection.from_row_noscope(&r);
             for rel in rels.iter_mut() {
                 rel.from_row(&r);
@@ -229,12 +280,31 @@ pub async fn update_one_dynmaic(
             })
         })
         .await
-        .unwrap();
+        .map_err(|err| error::database(err, members))?
+        .ok_or(error::to_refactor("entry with id not found"))?;
+
+    audit::record(
+        &mut *tx,
+        Change::Update,
+        collection.table_name(),
+        res.id,
+        before,
+        Some(res.attr.clone()),
+        input.0.relations,
+    )
+    .await
+    .map_err(|err| error::database(err, &[]))?;
 
     for rel in rels.iter_mut() {
-        rel.sub_op2(db.0.clone()).await;
+        rel.sub_op2(&mut *tx)
+            .await
+            .map_err(|err| error::database(err, members))?;
     }
 
+    tx.commit()
+        .await
+        .map_err(|err| error::database(err, members))?;
+
     for (mut rel, tra) in rels.into_iter().zip(tra.into_iter()) {
         let value = rel.take();
         res.relations.insert(tra, value);
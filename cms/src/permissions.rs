@@ -0,0 +1,2493 @@
+//! role based access control for the collection routes.
+//!
+//! a request without a token runs as `public`, an authenticated
+//! super user runs as the role assigned to them (`public` when
+//! nothing is assigned), and every end user (see
+//! `auth::users`) runs as `user`. requests with an api key (see
+//! `api_keys`) run as the role of the key. a role can run an
+//! operation on a collection when it is granted for that
+//! collection or for `*`, `admin` can run everything.
+//!
+//! on top of that, a role can be denied reading or writing
+//! single fields of a collection: hidden fields are stripped
+//! from the `attr` of responses, and inputs that set unwritable
+//! fields are rejected.
+//!
+//! collections that are `Collection::owned` remember who
+//! inserted each row, a role can be restricted to the rows it
+//! owns for an operation. the restriction is a `WHERE` on the
+//! statements the dynamic operations build, so it can't be
+//! worked around with filters. relations to a collection the
+//! role is restricted on for reading (`get_many` when listing,
+//! `get_one` otherwise) are refused, they would load or link
+//! rows whoever owns them.
+//!
+//! the matrix is stored in `_cms_roles`, `_cms_permissions`,
+//! `_cms_field_permissions`, `_cms_owner_policies` and
+//! `_cms_user_roles`, and cached in `Backend::permissions`
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    future::Future,
+};
+
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_LENGTH},
+        HeaderMap, Response, StatusCode,
+    },
+    middleware::{from_fn, Next},
+    routing::{get, post},
+    Json, Router,
+};
+use case::CaseExt;
+use http_body_util::BodyExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode,
+    Executor, IntoArguments, Pool, Type,
+};
+
+use crate::{
+    api_keys::{authenticate, API_KEY_HEADER},
+    auth::{bearer_claims, keys::Keys, IClaims, Principal},
+    backend::Backend,
+    error::{self, ClientError},
+};
+
+pub const ADMIN: &str = "admin";
+pub const PUBLIC: &str = "public";
+/// the role of every authenticated end user
+pub const USER: &str = "user";
+pub const ANY_COLLECTION: &str = "*";
+
+/// column of owned collections, `IClaims::owner` of whoever
+/// inserted the row
+pub const OWNER_COLUMN: &str = "_owner";
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    GetOne,
+    GetMany,
+    InsertOne,
+    UpdateOne,
+    DeleteOne,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 5] = [
+        Operation::GetOne,
+        Operation::GetMany,
+        Operation::InsertOne,
+        Operation::UpdateOne,
+        Operation::DeleteOne,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::GetOne => "get_one",
+            Operation::GetMany => "get_many",
+            Operation::InsertOne => "insert_one",
+            Operation::UpdateOne => "update_one",
+            Operation::DeleteOne => "delete_one",
+        }
+    }
+
+    /// the operation of a collection route is its last segment
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.as_str() == s)
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+pub struct Grant {
+    pub role: String,
+    /// table name of the collection, or `*`
+    pub collection: String,
+    pub operation: Operation,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [Access::Read, Access::Write]
+            .into_iter()
+            .find(|e| e.as_str() == s)
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+pub struct FieldDenial {
+    pub role: String,
+    /// table name of the collection
+    pub collection: String,
+    /// one of `Collection::members()`
+    pub field: String,
+    pub access: Access,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Permissions {
+    pub roles: BTreeSet<String>,
+    pub grants: BTreeSet<Grant>,
+    pub denied_fields: BTreeSet<FieldDenial>,
+    /// the role can run the operation only on rows it owns
+    pub owner_only: BTreeSet<Grant>,
+    /// super user id to role
+    pub assigned: BTreeMap<String, String>,
+}
+
+/// before `init_permissions` runs, and for new databases:
+/// reads are public and writes are for editors and admins
+impl Default for Permissions {
+    fn default() -> Self {
+        let grant = |role: &str, operation| Grant {
+            role: role.to_string(),
+            collection: ANY_COLLECTION.to_string(),
+            operation,
+        };
+
+        let reads = [Operation::GetOne, Operation::GetMany];
+        let mut grants = BTreeSet::new();
+
+        for role in ["editor", "viewer", USER, PUBLIC] {
+            for op in reads {
+                grants.insert(grant(role, op));
+            }
+        }
+        for op in [Operation::InsertOne, Operation::UpdateOne] {
+            grants.insert(grant("editor", op));
+        }
+
+        Permissions {
+            roles: [ADMIN, "editor", "viewer", USER, PUBLIC]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            grants,
+            denied_fields: Default::default(),
+            owner_only: Default::default(),
+            assigned: Default::default(),
+        }
+    }
+}
+
+impl Permissions {
+    /// super users without a role get the least privileged one,
+    /// the first super user is assigned `admin` when created
+    pub fn role_of(&self, user_id: &str) -> &str {
+        self.assigned
+            .get(user_id)
+            .map(|e| e.as_str())
+            .unwrap_or(PUBLIC)
+    }
+
+    /// roles are assigned to super users only, end users are
+    /// always `user`
+    pub fn role_for(&self, claims: &IClaims) -> &str {
+        match claims.principal {
+            Principal::SuperUser => self.role_of(&claims.id),
+            Principal::User => USER,
+        }
+    }
+
+    pub fn allows(
+        &self,
+        role: &str,
+        collection: &str,
+        operation: Operation,
+    ) -> bool {
+        if role == ADMIN {
+            return true;
+        }
+
+        [collection, ANY_COLLECTION].into_iter().any(
+            |collection| {
+                self.grants.contains(&Grant {
+                    role: role.to_string(),
+                    collection: collection.to_string(),
+                    operation,
+                })
+            },
+        )
+    }
+
+    /// fields of `collection` that `role` can't read or write
+    pub fn denied_fields(
+        &self,
+        role: &str,
+        collection: &str,
+        access: Access,
+    ) -> Vec<String> {
+        if role == ADMIN {
+            return vec![];
+        }
+
+        self.denied_fields
+            .iter()
+            .filter(|e| {
+                e.role == role
+                    && e.collection == collection
+                    && e.access == access
+            })
+            .map(|e| e.field.clone())
+            .collect()
+    }
+
+    pub fn owner_only(
+        &self,
+        role: &str,
+        collection: &str,
+        operation: Operation,
+    ) -> bool {
+        if role == ADMIN {
+            return false;
+        }
+
+        [collection, ANY_COLLECTION].into_iter().any(
+            |collection| {
+                self.owner_only.contains(&Grant {
+                    role: role.to_string(),
+                    collection: collection.to_string(),
+                    operation,
+                })
+            },
+        )
+    }
+}
+
+/// what the dynamic operations need to know about the request
+/// they run in, set by `authorize`
+#[derive(Debug, Clone)]
+pub struct RowPolicy {
+    /// `IClaims::owner` of the authenticated principal
+    pub owner: Option<String>,
+    pub owner_only: bool,
+    /// fields the role can't read, they can't be filtered on
+    /// either
+    pub hidden: Vec<String>,
+    /// keys of the relations that reach an owned collection the
+    /// role is restricted to its own rows on
+    pub owner_only_relations: Vec<String>,
+}
+
+tokio::task_local! {
+    static ROW_POLICY: RowPolicy;
+}
+
+/// the owner of rows inserted by the current request, `None`
+/// outside of `authorize` (seeds, direct calls)
+pub fn current_owner() -> Option<String> {
+    ROW_POLICY.try_with(|e| e.owner.clone()).ok().flatten()
+}
+
+/// the owner rows have to match for the current request, when
+/// it is restricted to owned rows. unauthenticated requests
+/// match no row
+pub fn owner_filter() -> Option<String> {
+    ROW_POLICY
+        .try_with(|e| {
+            e.owner_only
+                .then(|| e.owner.clone().unwrap_or_default())
+        })
+        .ok()
+        .flatten()
+}
+
+/// fields of the collection the current request can't read,
+/// empty outside of `authorize`
+pub fn hidden_fields() -> Vec<String> {
+    ROW_POLICY
+        .try_with(|e| e.hidden.clone())
+        .unwrap_or_default()
+}
+
+/// refuses the relation `key` (snake_case) when it reaches rows
+/// the current request may only see when it owns them
+pub fn check_relation(key: &str) -> Result<(), ClientError> {
+    let refused = ROW_POLICY
+        .try_with(|e| {
+            e.owner_only_relations.iter().any(|e| e == key)
+        })
+        .unwrap_or_default();
+
+    if !refused {
+        return Ok(());
+    }
+
+    Err(ClientError {
+        status_code: StatusCode::FORBIDDEN,
+        dev_hint: format!(
+            "relation {key} reaches rows the role only sees when \
+             it owns them"
+        ),
+        user_error: None,
+    }
+    .add_user_error(
+        "relation_owner_only",
+        "you don't have permission to use this relation",
+        |_| {},
+    ))
+}
+
+/// run `fut` as if `owner` made the request, rows it inserts
+/// into owned collections are theirs
+pub(crate) async fn as_owner<F: Future>(
+    owner: String,
+    fut: F,
+) -> F::Output {
+    let policy = RowPolicy {
+        owner: Some(owner),
+        owner_only: false,
+        hidden: vec![],
+        owner_only_relations: vec![],
+    };
+    ROW_POLICY.scope(policy, fut).await
+}
+
+pub fn migration_st() -> [&'static str; 5] {
+    [
+        "
+    CREATE TABLE IF NOT EXISTS _cms_roles (
+        name TEXT PRIMARY KEY
+    );
+    ",
+        "
+    CREATE TABLE IF NOT EXISTS _cms_permissions (
+        role TEXT NOT NULL,
+        collection TEXT NOT NULL,
+        operation TEXT NOT NULL,
+        PRIMARY KEY (role, collection, operation)
+    );
+    ",
+        "
+    CREATE TABLE IF NOT EXISTS _cms_field_permissions (
+        role TEXT NOT NULL,
+        collection TEXT NOT NULL,
+        field TEXT NOT NULL,
+        access TEXT NOT NULL,
+        PRIMARY KEY (role, collection, field, access)
+    );
+    ",
+        "
+    CREATE TABLE IF NOT EXISTS _cms_owner_policies (
+        role TEXT NOT NULL,
+        collection TEXT NOT NULL,
+        operation TEXT NOT NULL,
+        PRIMARY KEY (role, collection, operation)
+    );
+    ",
+        "
+    CREATE TABLE IF NOT EXISTS _cms_user_roles (
+        user_id TEXT PRIMARY KEY,
+        role TEXT NOT NULL
+    );
+    ",
+    ]
+}
+
+/// create the tables, seed them with `Permissions::default`
+/// when there are no roles, and load them into
+/// `Backend::permissions`
+pub async fn init_permissions<S>(
+    db: &Pool<S>,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    for st in migration_st() {
+        sqlx::query(st).execute(db).await?;
+    }
+
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM _cms_roles;")
+            .fetch_one(db)
+            .await?;
+
+    if count == 0 {
+        let defaults = Permissions::default();
+        for role in defaults.roles {
+            sqlx::query(
+                "INSERT INTO _cms_roles (name) VALUES ($1);",
+            )
+            .bind(role)
+            .execute(db)
+            .await?;
+        }
+        for grant in defaults.grants {
+            insert_grant(db, grant).await?;
+        }
+
+        // super users created before roles existed were all
+        // admins, keep them so
+        sqlx::query(&crate::auth::migration_st::<S>())
+            .execute(db)
+            .await?;
+        sqlx::query(
+            "
+    INSERT INTO _cms_user_roles (user_id, role)
+    SELECT CAST(id AS TEXT), $1 FROM _super_users;
+    ",
+        )
+        .bind(ADMIN.to_string())
+        .execute(db)
+        .await?;
+    }
+
+    // databases seeded before end users existed
+    let (users,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM _cms_roles WHERE name = $1;",
+    )
+    .bind(USER.to_string())
+    .fetch_one(db)
+    .await?;
+    if users == 0 {
+        sqlx::query(
+            "INSERT INTO _cms_roles (name) VALUES ($1);",
+        )
+        .bind(USER.to_string())
+        .execute(db)
+        .await?;
+    }
+
+    reload(db).await
+}
+
+async fn insert_grant<S>(
+    db: &Pool<S>,
+    grant: Grant,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(
+        "
+    INSERT INTO _cms_permissions (role, collection, operation)
+    VALUES ($1, $2, $3);
+    ",
+    )
+    .bind(grant.role)
+    .bind(grant.collection)
+    .bind(grant.operation.as_str().to_string())
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn reload<S>(
+    db: &Pool<S>,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String: Type<S> + for<'d> Decode<'d, S>,
+{
+    let roles: Vec<(String,)> =
+        sqlx::query_as("SELECT name FROM _cms_roles;")
+            .fetch_all(db)
+            .await?;
+
+    let grants: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT role, collection, operation FROM _cms_permissions;",
+    )
+    .fetch_all(db)
+    .await?;
+
+    let denied_fields: Vec<(String, String, String, String)> =
+        sqlx::query_as(
+            "
+    SELECT role, collection, field, access
+    FROM _cms_field_permissions;
+    ",
+        )
+        .fetch_all(db)
+        .await?;
+
+    let owner_only: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT role, collection, operation FROM _cms_owner_policies;",
+    )
+    .fetch_all(db)
+    .await?;
+
+    let assigned: Vec<(String, String)> = sqlx::query_as(
+        "SELECT user_id, role FROM _cms_user_roles;",
+    )
+    .fetch_all(db)
+    .await?;
+
+    let to_grants = |rows: Vec<(String, String, String)>| {
+        rows.into_iter()
+            .filter_map(|(role, collection, operation)| {
+                let Some(operation) =
+                    Operation::parse(&operation)
+                else {
+                    tracing::warn!(
+                        "unknown operation {operation}"
+                    );
+                    return None;
+                };
+                Some(Grant {
+                    role,
+                    collection,
+                    operation,
+                })
+            })
+            .collect()
+    };
+
+    let denied_fields = denied_fields
+        .into_iter()
+        .filter_map(|(role, collection, field, access)| {
+            let Some(access) = Access::parse(&access) else {
+                tracing::warn!("unknown access {access}");
+                return None;
+            };
+            Some(FieldDenial {
+                role,
+                collection,
+                field,
+                access,
+            })
+        })
+        .collect();
+
+    *S::permissions().write().await = Permissions {
+        roles: roles.into_iter().map(|e| e.0).collect(),
+        grants: to_grants(grants),
+        denied_fields,
+        owner_only: to_grants(owner_only),
+        assigned: assigned.into_iter().collect(),
+    };
+
+    Ok(())
+}
+
+fn forbidden(
+    role: &str,
+    operation: &str,
+    on: &str,
+) -> ClientError {
+    ClientError {
+        status_code: StatusCode::FORBIDDEN,
+        dev_hint: format!(
+            "role {role} can't {operation} on {on}"
+        ),
+        user_error: None,
+    }
+    .add_user_error(
+        "forbidden",
+        "you don't have permission to do this",
+        |_| {},
+    )
+}
+
+/// replaces `need_super_user` on the collection routes, the
+/// operation is taken from the last segment of the route
+pub async fn authorize<S: Backend>(
+    collection: Path<String>,
+    keys: Keys,
+    headers: HeaderMap,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, ClientError> {
+    let operation = req
+        .uri()
+        .path()
+        .rsplit('/')
+        .next()
+        .and_then(Operation::parse)
+        .ok_or(ClientError::endpoint_not_found())?;
+
+    let api_key = match headers.get(API_KEY_HEADER) {
+        Some(_) if headers.contains_key(AUTHORIZATION) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "send either an api key or a bearer token",
+            )
+                .into());
+        }
+        Some(key) => Some(
+            authenticate::<S>(key.to_str().unwrap_or_default())
+                .await?,
+        ),
+        None => None,
+    };
+    let claims = bearer_claims(&keys, &headers)?;
+    let collection = collection.0.to_camel();
+
+    let (
+        role,
+        hidden,
+        hidden_in_relations,
+        unwritable,
+        owner_only,
+        owner_only_relations,
+    ) = {
+        let permissions = S::permissions().read().await;
+        let role = match (&api_key, &claims) {
+            (Some(key), _) => key.role.as_str(),
+            (None, Some(claims)) => permissions.role_for(claims),
+            (None, None) => PUBLIC,
+        };
+
+        let in_scope = api_key
+            .as_ref()
+            .is_none_or(|e| e.allows_collection(&collection));
+
+        if !in_scope
+            || !permissions.allows(role, &collection, operation)
+        {
+            if claims.is_none() && api_key.is_none() {
+                return Err((
+                    StatusCode::UNAUTHORIZED,
+                    "authorization not found",
+                )
+                    .into());
+            }
+            return Err(forbidden(
+                role,
+                operation.as_str(),
+                &collection,
+            ));
+        }
+
+        let unwritable = match operation {
+            Operation::InsertOne | Operation::UpdateOne => {
+                permissions.denied_fields(
+                    role,
+                    &collection,
+                    Access::Write,
+                )
+            }
+            _ => vec![],
+        };
+
+        // relations are listed by their key, and hide the fields
+        // the role can't read on the collection they load
+        let hidden_in_relations = S::relations()
+            .read()
+            .await
+            .get(&collection)
+            .into_iter()
+            .flatten()
+            .map(|e| {
+                let hidden = permissions.denied_fields(
+                    role,
+                    &e.to(),
+                    Access::Read,
+                );
+                (e.key(), hidden)
+            })
+            .filter(|(_, hidden)| !hidden.is_empty())
+            .collect::<HashMap<_, _>>();
+
+        // relations don't filter by owner, the ones that reach
+        // an owned collection the role is restricted on are
+        // refused
+        let read = match operation {
+            Operation::GetMany => Operation::GetMany,
+            _ => Operation::GetOne,
+        };
+        let collections = S::collections().read().await;
+        let owner_only_relations = S::relations()
+            .read()
+            .await
+            .get(&collection)
+            .into_iter()
+            .flatten()
+            .filter(|e| {
+                let owned = collections
+                    .get(&e.to())
+                    .is_some_and(|c| c.owned());
+                owned && permissions.owner_only(role, &e.to(), read)
+            })
+            .map(|e| e.key())
+            .collect::<Vec<_>>();
+
+        (
+            role.to_string(),
+            permissions.denied_fields(
+                role,
+                &collection,
+                Access::Read,
+            ),
+            hidden_in_relations,
+            unwritable,
+            permissions.owner_only(role, &collection, operation),
+            owner_only_relations,
+        )
+    };
+
+    if !unwritable.is_empty() {
+        let input_key = match operation {
+            Operation::InsertOne => "input",
+            _ => "partial",
+        };
+        req = reject_unwritable(req, input_key, &unwritable)
+            .await
+            .map_err(|e| forbidden_fields(&role, &collection, e))?;
+    }
+
+    let owner = match &api_key {
+        Some(key) => Some(key.owner()),
+        None => claims.as_ref().map(IClaims::owner),
+    };
+    let policy = RowPolicy {
+        owner,
+        owner_only,
+        hidden: hidden.clone(),
+        owner_only_relations,
+    };
+    if let Some(claims) = claims {
+        req.extensions_mut().insert(claims);
+    }
+    if let Some(key) = api_key {
+        req.extensions_mut().insert(key);
+    }
+
+    let mut res = ROW_POLICY.scope(policy, next.run(req)).await;
+
+    if !hidden.is_empty() || !hidden_in_relations.is_empty() {
+        res = strip_hidden(res, &hidden, &hidden_in_relations)
+            .await;
+    }
+
+    Ok(res)
+}
+
+fn forbidden_fields(
+    role: &str,
+    collection: &str,
+    fields: HashMap<String, String>,
+) -> ClientError {
+    let dev_hint = format!(
+        "role {role} can't write {} on {collection}",
+        fields.keys().cloned().collect::<Vec<_>>().join(", ")
+    );
+    ClientError {
+        status_code: StatusCode::FORBIDDEN,
+        dev_hint,
+        user_error: None,
+    }
+    .add_user_error(
+        "forbidden_fields",
+        "you don't have permission to set some fields",
+        |u| u.structured_hint = Some(fields),
+    )
+}
+
+fn unwritable_in(
+    input: &Value,
+    unwritable: &[String],
+) -> HashMap<String, String> {
+    let Some(input) = input.as_object() else {
+        return HashMap::new();
+    };
+    unwritable
+        .iter()
+        .filter(|e| input.contains_key(e.as_str()))
+        .map(|e| (e.clone(), "forbidden".to_string()))
+        .collect()
+}
+
+/// what `authorize` does for the inputs of the collection
+/// routes, for routes that write to a collection on their own
+pub(crate) fn check_writable(
+    role: &str,
+    collection: &str,
+    input: &Value,
+    permissions: &Permissions,
+) -> Result<(), ClientError> {
+    let unwritable =
+        permissions.denied_fields(role, collection, Access::Write);
+    let forbidden = unwritable_in(input, &unwritable);
+    if !forbidden.is_empty() {
+        return Err(forbidden_fields(role, collection, forbidden));
+    }
+    Ok(())
+}
+
+/// what `authorize` does for the responses of the collection
+/// routes, `attr` is the attributes of one row
+pub(crate) fn hide_fields(attr: &mut Value, hidden: &[String]) {
+    if let Some(attr) = attr.as_object_mut() {
+        for field in hidden {
+            attr.remove(field);
+        }
+    }
+}
+
+/// the input of insert_one is at `input`, and update_one's is at
+/// `partial`. bodies that are not json are left to the handler
+/// to reject
+async fn reject_unwritable(
+    req: Request<Body>,
+    input_key: &str,
+    unwritable: &[String],
+) -> Result<Request<Body>, HashMap<String, String>> {
+    let (parts, body) = req.into_parts();
+    let bytes = match body.collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(_) => {
+            return Ok(Request::from_parts(parts, Body::empty()))
+        }
+    };
+
+    let value = serde_json::from_slice::<Value>(&bytes).ok();
+    if let Some(input) =
+        value.as_ref().and_then(|e| e.get(input_key))
+    {
+        let forbidden = unwritable_in(input, unwritable);
+        if !forbidden.is_empty() {
+            return Err(forbidden);
+        }
+    }
+
+    Ok(Request::from_parts(parts, Body::from(bytes)))
+}
+
+/// remove hidden fields from the `attr` of get_one, insert_one,
+/// update_one and delete_one, and from each entry of get_many.
+/// the entries under `relations` are stripped by the hidden
+/// fields of their own collection
+async fn strip_hidden(
+    res: Response<Body>,
+    hidden: &[String],
+    hidden_in_relations: &HashMap<String, Vec<String>>,
+) -> Response<Body> {
+    if !res.status().is_success() {
+        return res;
+    }
+
+    let (mut parts, body) = res.into_parts();
+    let bytes = match body.collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(_) => {
+            return Response::from_parts(parts, Body::empty())
+        }
+    };
+
+    let Ok(mut value) = serde_json::from_slice::<Value>(&bytes)
+    else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    fn strip_attr(entry: &mut Value, hidden: &[String]) {
+        if let Some(attr) = entry.get_mut("attr") {
+            hide_fields(attr, hidden);
+        }
+    }
+
+    let strip = |entry: &mut Value| {
+        strip_attr(entry, hidden);
+        let Some(relations) = entry
+            .get_mut("relations")
+            .and_then(Value::as_object_mut)
+        else {
+            return;
+        };
+        for (key, hidden) in hidden_in_relations {
+            match relations.get_mut(key) {
+                // to many relations are lists of entries
+                Some(Value::Array(linked)) => linked
+                    .iter_mut()
+                    .for_each(|e| strip_attr(e, hidden)),
+                Some(linked) => strip_attr(linked, hidden),
+                None => {}
+            }
+        }
+    };
+
+    match value.get_mut("data").and_then(Value::as_array_mut) {
+        Some(data) => data.iter_mut().for_each(strip),
+        None => strip(&mut value),
+    }
+
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(value.to_string()))
+}
+
+/// only super users with the `admin` role can manage the
+/// permissions
+pub async fn need_admin<S: Backend>(
+    keys: Keys,
+    headers: HeaderMap,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>, ClientError> {
+    let claims = bearer_claims(&keys, &headers)?.ok_or((
+        StatusCode::UNAUTHORIZED,
+        "authorization not found",
+    ))?;
+
+    {
+        let permissions = S::permissions().read().await;
+        let role = permissions.role_for(&claims);
+        if role != ADMIN {
+            return Err(forbidden(
+                role,
+                "manage",
+                "permissions",
+            ));
+        }
+    }
+
+    req.extensions_mut().insert(claims);
+
+    Ok(next.run(req).await)
+}
+
+pub fn permissions_router<S>() -> Router<Pool<S>>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    Router::new()
+        .route("/", get(get_permissions::<S>))
+        .route("/create_role", post(create_role::<S>))
+        .route("/delete_role", post(delete_role::<S>))
+        .route("/grant", post(grant::<S>))
+        .route("/revoke", post(revoke::<S>))
+        .route("/assign", post(assign::<S>))
+        .route("/deny_field", post(deny_field::<S>))
+        .route("/allow_field", post(allow_field::<S>))
+        .route("/owner_only", post(owner_only::<S>))
+        .route(
+            "/remove_owner_only",
+            post(remove_owner_only::<S>),
+        )
+        .route_layer(from_fn(need_admin::<S>))
+}
+
+async fn get_permissions<S: Backend>() -> Json<Permissions> {
+    Json(S::permissions().read().await.clone())
+}
+
+#[derive(Deserialize)]
+pub struct RoleInput {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct AssignInput {
+    pub user_id: i64,
+    pub role: String,
+}
+
+pub(crate) async fn role_exists<S: Backend>(
+    role: &str,
+) -> Result<(), ClientError> {
+    if !S::permissions().read().await.roles.contains(role) {
+        return Err(ClientError {
+            status_code: StatusCode::NOT_FOUND,
+            dev_hint: format!("role {role} does not exist"),
+            user_error: None,
+        });
+    }
+    Ok(())
+}
+
+async fn create_role<S>(
+    db: State<Pool<S>>,
+    input: Json<RoleInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    if role_exists::<S>(&input.name).await.is_ok() {
+        return Err(
+            format!("role {} exists", input.0.name).into()
+        );
+    }
+
+    sqlx::query("INSERT INTO _cms_roles (name) VALUES ($1);")
+        .bind(input.0.name)
+        .execute(&db.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+async fn delete_role<S>(
+    db: State<Pool<S>>,
+    input: Json<RoleInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    if [ADMIN, PUBLIC, USER].contains(&input.name.as_str()) {
+        return Err(format!(
+            "role {} is built in",
+            input.0.name
+        )
+        .into());
+    }
+    role_exists::<S>(&input.name).await?;
+
+    let mut tx = db
+        .0
+        .begin()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    // expired invitations can't be accepted, they are not
+    // listed either
+    sqlx::query(
+        "DELETE FROM _cms_invitations WHERE role = $1 AND expires_at < $2;",
+    )
+    .bind(input.name.clone())
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&mut *tx)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    // holders left behind would get the permissions of a role
+    // created later with the same name
+    for (st, holders) in [
+        (
+            "SELECT COUNT(*) FROM _cms_user_roles WHERE role = $1;",
+            "super users, assign them another role",
+        ),
+        (
+            "SELECT COUNT(*) FROM _cms_api_keys WHERE role = $1;",
+            "api keys, revoke them",
+        ),
+        (
+            "SELECT COUNT(*) FROM _cms_invitations WHERE role = $1;",
+            "invitations, revoke them",
+        ),
+    ] {
+        let (count,): (i64,) = sqlx::query_as(st)
+            .bind(input.name.clone())
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|err| error::database(err, &[]))?;
+        if count > 0 {
+            return Err(ClientError {
+                status_code: StatusCode::CONFLICT,
+                dev_hint: format!(
+                    "role {} is held by {count} {holders} first",
+                    input.name
+                ),
+                user_error: None,
+            }
+            .add_user_error(
+                "role_in_use",
+                "the role is still held by some users, api keys or invitations",
+                |_| {},
+            ));
+        }
+    }
+
+    for st in [
+        "DELETE FROM _cms_permissions WHERE role = $1;",
+        "DELETE FROM _cms_field_permissions WHERE role = $1;",
+        "DELETE FROM _cms_owner_policies WHERE role = $1;",
+        "DELETE FROM _cms_roles WHERE name = $1;",
+    ] {
+        sqlx::query(st)
+            .bind(input.name.clone())
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| error::database(err, &[]))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+async fn grant<S>(
+    db: State<Pool<S>>,
+    input: Json<Grant>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    role_exists::<S>(&input.role).await?;
+
+    if S::permissions().read().await.grants.contains(&input.0) {
+        return Ok(Json(json!({"data": null})));
+    }
+
+    insert_grant(&db.0, input.0)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+async fn revoke<S>(
+    db: State<Pool<S>>,
+    input: Json<Grant>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(
+        "
+    DELETE FROM _cms_permissions
+    WHERE role = $1 AND collection = $2 AND operation = $3;
+    ",
+    )
+    .bind(input.0.role)
+    .bind(input.0.collection)
+    .bind(input.0.operation.as_str().to_string())
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+async fn assign<S>(
+    db: State<Pool<S>>,
+    input: Json<AssignInput>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    role_exists::<S>(&input.role).await?;
+
+    assign_role(&db.0, input.user_id, &input.role)
+        .await
+        .map_err(|err| error::database(err, &[]))?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+/// replaces the role of a super user, the role has to exist
+pub(crate) async fn assign_role<S>(
+    db: &Pool<S>,
+    user_id: i64,
+    role: &str,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    let mut conn = db.acquire().await?;
+    store_role(&mut conn, user_id, role).await?;
+
+    reload(db).await
+}
+
+/// `assign_role` without reloading the cache, for transactions
+pub(crate) async fn store_role<S>(
+    conn: &mut S::Connection,
+    user_id: i64,
+    role: &str,
+) -> Result<(), sqlx::Error>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    String: Type<S> + for<'e> Encode<'e, S>,
+{
+    let user_id = user_id.to_string();
+
+    sqlx::query(
+        "DELETE FROM _cms_user_roles WHERE user_id = $1;",
+    )
+    .bind(user_id.clone())
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO _cms_user_roles (user_id, role) VALUES ($1, $2);",
+    )
+    .bind(user_id)
+    .bind(role.to_string())
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+async fn field_exists<S: Backend>(
+    collection: &str,
+    field: &str,
+) -> Result<(), ClientError> {
+    let collections = S::collections().read().await;
+    let found = collections
+        .get(collection)
+        .is_some_and(|e| e.members_no_scope().contains(&field));
+
+    if !found {
+        return Err(ClientError {
+            status_code: StatusCode::NOT_FOUND,
+            dev_hint: format!(
+                "field {field} does not exist on {collection}"
+            ),
+            user_error: None,
+        });
+    }
+    Ok(())
+}
+
+async fn deny_field<S>(
+    db: State<Pool<S>>,
+    input: Json<FieldDenial>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    role_exists::<S>(&input.role).await?;
+    field_exists::<S>(&input.collection, &input.field).await?;
+
+    if S::permissions()
+        .read()
+        .await
+        .denied_fields
+        .contains(&input.0)
+    {
+        return Ok(Json(json!({"data": null})));
+    }
+
+    sqlx::query(
+        "
+    INSERT INTO _cms_field_permissions
+        (role, collection, field, access)
+    VALUES ($1, $2, $3, $4);
+    ",
+    )
+    .bind(input.0.role)
+    .bind(input.0.collection)
+    .bind(input.0.field)
+    .bind(input.0.access.as_str().to_string())
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+async fn allow_field<S>(
+    db: State<Pool<S>>,
+    input: Json<FieldDenial>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(
+        "
+    DELETE FROM _cms_field_permissions
+    WHERE role = $1 AND collection = $2 AND field = $3
+        AND access = $4;
+    ",
+    )
+    .bind(input.0.role)
+    .bind(input.0.collection)
+    .bind(input.0.field)
+    .bind(input.0.access.as_str().to_string())
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+async fn owner_only<S>(
+    db: State<Pool<S>>,
+    input: Json<Grant>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    role_exists::<S>(&input.role).await?;
+
+    if input.operation == Operation::InsertOne {
+        return Err("insert_one has no rows to restrict".into());
+    }
+
+    if input.collection != ANY_COLLECTION {
+        let collections = S::collections().read().await;
+        let Some(collection) =
+            collections.get(&input.collection)
+        else {
+            return Err(ClientError {
+                status_code: StatusCode::NOT_FOUND,
+                dev_hint: format!(
+                    "collection {} does not exist",
+                    input.collection
+                ),
+                user_error: None,
+            });
+        };
+        if !collection.owned() {
+            return Err(format!(
+                "collection {} is not owned",
+                input.collection
+            )
+            .into());
+        }
+    }
+
+    if S::permissions()
+        .read()
+        .await
+        .owner_only
+        .contains(&input.0)
+    {
+        return Ok(Json(json!({"data": null})));
+    }
+
+    sqlx::query(
+        "
+    INSERT INTO _cms_owner_policies (role, collection, operation)
+    VALUES ($1, $2, $3);
+    ",
+    )
+    .bind(input.0.role)
+    .bind(input.0.collection)
+    .bind(input.0.operation.as_str().to_string())
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+async fn remove_owner_only<S>(
+    db: State<Pool<S>>,
+    input: Json<Grant>,
+) -> Result<Json<Value>, ClientError>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    usize: ColumnIndex<S::Row>,
+    String:
+        Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
+    sqlx::query(
+        "
+    DELETE FROM _cms_owner_policies
+    WHERE role = $1 AND collection = $2 AND operation = $3;
+    ",
+    )
+    .bind(input.0.role)
+    .bind(input.0.collection)
+    .bind(input.0.operation.as_str().to_string())
+    .execute(&db.0)
+    .await
+    .map_err(|err| error::database(err, &[]))?;
+
+    reload(&db.0).await.map_err(error::server_error)?;
+
+    Ok(Json(json!({"data": null})))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+        middleware::from_fn,
+        routing::post,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use sqlx::{Pool, Sqlite};
+    use tower::ServiceExt;
+
+    use std::sync::Arc;
+
+    use axum::Extension;
+
+    use crate::auth::{
+        ijwt,
+        keys::{JwtKey, JwtKeys},
+    };
+
+    use super::*;
+
+    /// the permissions of a backend are a static, tests that
+    /// change them can't run at the same time
+    pub(crate) static SERIAL: tokio::sync::Mutex<()> =
+        tokio::sync::Mutex::const_new(());
+
+    /// what the tests sign and verify tokens with
+    pub(crate) fn keys() -> Arc<JwtKeys> {
+        Arc::new(JwtKeys::new(JwtKey::hmac("test", b"test")))
+    }
+
+    fn request(
+        uri: &str,
+        token: Option<&str>,
+        body: Value,
+    ) -> Request<Body> {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .header("content-type", "application/json")
+            .uri(uri);
+        if let Some(token) = token {
+            req = req.header(
+                "authorization",
+                format!("Bearer {}", token),
+            );
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn roles_are_enforced_and_managed() {
+        let _serial = SERIAL.lock().await;
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        std::env::set_var("JWT_SALT", "test");
+
+        init_permissions(&pool).await.unwrap();
+        crate::api_keys::init_api_keys(&pool).await.unwrap();
+        crate::auth::invitations::init_invitations(&pool)
+            .await
+            .unwrap();
+        assign_role(&pool, 1, ADMIN).await.unwrap();
+
+        let collections = Router::new()
+            .route(
+                "/{collection}/get_many",
+                post(|| async { "ok" }),
+            )
+            .route(
+                "/{collection}/insert_one",
+                post(|| async { "ok" }),
+            )
+            .route(
+                "/{collection}/delete_one",
+                post(|| async { "ok" }),
+            )
+            .route_layer(from_fn(authorize::<Sqlite>));
+
+        let app = Router::new()
+            .nest("/collection", collections)
+            .nest("/permissions", permissions_router::<Sqlite>())
+            .layer(Extension(keys()))
+            .with_state(pool.clone());
+
+        let status = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+
+        let admin =
+            ijwt::sign_for(
+                &keys(),
+                "1",
+                chrono::Duration::hours(1),
+            );
+        let editor =
+            ijwt::sign_for(
+                &keys(),
+                "2",
+                chrono::Duration::hours(1),
+            );
+
+        // public can read but not write
+        assert_eq!(
+            status(request(
+                "/collection/todo/get_many",
+                None,
+                json!({})
+            ))
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(request(
+                "/collection/todo/insert_one",
+                None,
+                json!({})
+            ))
+            .await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        assert_eq!(
+            status(request(
+                "/permissions/assign",
+                Some(&admin),
+                json!({"user_id": 2, "role": "editor"}),
+            ))
+            .await,
+            StatusCode::OK
+        );
+
+        assert_eq!(
+            status(request(
+                "/collection/todo/insert_one",
+                Some(&editor),
+                json!({}),
+            ))
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(request(
+                "/collection/todo/delete_one",
+                Some(&editor),
+                json!({}),
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+
+        let grant = json!({
+            "role": "editor",
+            "collection": "Todo",
+            "operation": "delete_one",
+        });
+
+        assert_eq!(
+            status(request(
+                "/permissions/grant",
+                Some(&editor),
+                grant.clone(),
+            ))
+            .await,
+            StatusCode::FORBIDDEN,
+            "only admins manage permissions"
+        );
+
+        assert_eq!(
+            status(request(
+                "/permissions/grant",
+                Some(&admin),
+                grant.clone(),
+            ))
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(request(
+                "/collection/todo/delete_one",
+                Some(&editor),
+                json!({}),
+            ))
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(request(
+                "/collection/category/delete_one",
+                Some(&editor),
+                json!({}),
+            ))
+            .await,
+            StatusCode::FORBIDDEN,
+            "the grant is for Todo only"
+        );
+
+        let res = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/permissions")
+                    .header(
+                        "authorization",
+                        format!("Bearer {}", admin),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = res.into_body().collect().await.unwrap();
+        let body: Value =
+            serde_json::from_slice(&body.to_bytes()).unwrap();
+        assert_eq!(
+            body["assigned"],
+            json!({"1": "admin", "2": "editor"})
+        );
+        assert!(body["grants"]
+            .as_array()
+            .unwrap()
+            .contains(&grant));
+
+        assert_eq!(
+            status(request(
+                "/permissions/revoke",
+                Some(&admin),
+                grant
+            ))
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(request(
+                "/collection/todo/delete_one",
+                Some(&editor),
+                json!({}),
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+
+        // super users without a role are not admins
+        let unassigned =
+            ijwt::sign_for(
+                &keys(),
+                "3",
+                chrono::Duration::hours(1),
+            );
+        assert_eq!(
+            status(request(
+                "/collection/todo/insert_one",
+                Some(&unassigned),
+                json!({}),
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+
+        // a role can't be deleted while it's held, so its
+        // holders never fall back to another role
+        let delete_editor = || {
+            request(
+                "/permissions/delete_role",
+                Some(&admin),
+                json!({"name": "editor"}),
+            )
+        };
+        assert_eq!(
+            status(delete_editor()).await,
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            status(request(
+                "/permissions/grant",
+                Some(&editor),
+                json!({
+                    "role": "editor",
+                    "collection": "*",
+                    "operation": "delete_one",
+                }),
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+
+        status(request(
+            "/permissions/assign",
+            Some(&admin),
+            json!({"user_id": 2, "role": "viewer"}),
+        ))
+        .await;
+
+        // api keys and pending invitations hold it too, expired
+        // invitations don't
+        let now = chrono::Utc::now().timestamp();
+        sqlx::query(
+            "
+        INSERT INTO _cms_api_keys
+            (name, prefix, hash, role, created_at)
+        VALUES ('ci', 'ci', 'ci', 'editor', 0);
+        INSERT INTO _cms_invitations
+            (token, email, role, invited_by, expires_at)
+        VALUES
+            ('a', 'a@example.com', 'editor', '1', $1),
+            ('b', 'b@example.com', 'editor', '1', $2);
+        ",
+        )
+        .bind(now + 60)
+        .bind(now - 60)
+        .execute(&pool)
+        .await
+        .unwrap();
+        for st in [
+            "DELETE FROM _cms_api_keys;",
+            "DELETE FROM _cms_invitations WHERE token = 'a';",
+        ] {
+            assert_eq!(
+                status(delete_editor()).await,
+                StatusCode::CONFLICT
+            );
+            sqlx::query(st).execute(&pool).await.unwrap();
+        }
+        assert_eq!(status(delete_editor()).await, StatusCode::OK);
+        let (invitations,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM _cms_invitations;",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(invitations, 0);
+        assert_eq!(
+            status(request(
+                "/permissions/create_role",
+                Some(&editor),
+                json!({"name": "editor"}),
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    async fn body(res: Response<Body>) -> Value {
+        let body = res.into_body().collect().await.unwrap();
+        serde_json::from_slice(&body.to_bytes()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn fields_are_hidden_and_read_only() {
+        let _serial = SERIAL.lock().await;
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        std::env::set_var("JWT_SALT", "test");
+
+        init_permissions(&pool).await.unwrap();
+        assign_role(&pool, 1, ADMIN).await.unwrap();
+
+        let collections = Router::new()
+            .route(
+                "/{collection}/get_many",
+                post(|| async {
+                    Json(json!({
+                        "data": [{
+                            "id": 1,
+                            "attr": {
+                                "title": "todo_1",
+                                "description": "internal",
+                            },
+                            "relations": {
+                                "category": {
+                                    "id": 1,
+                                    "attr": {
+                                        "cat_title": "internal",
+                                    },
+                                },
+                                "tag": [{
+                                    "id": 1,
+                                    "attr": {
+                                        "tag_title": "internal",
+                                    },
+                                }],
+                            },
+                        }],
+                        "page_count": null,
+                    }))
+                }),
+            )
+            .route(
+                "/{collection}/update_one",
+                post(|body: String| async move {
+                    let body: Value =
+                        serde_json::from_str(&body).unwrap();
+                    Json(json!({
+                        "id": 1,
+                        "attr": body["partial"],
+                        "relations": {},
+                    }))
+                }),
+            )
+            .route_layer(from_fn(authorize::<Sqlite>));
+
+        let app = Router::new()
+            .nest("/collection", collections)
+            .nest("/permissions", permissions_router::<Sqlite>())
+            .layer(Extension(keys()))
+            .with_state(pool.clone());
+
+        let admin =
+            ijwt::sign_for(
+                &keys(),
+                "1",
+                chrono::Duration::hours(1),
+            );
+        let editor =
+            ijwt::sign_for(
+                &keys(),
+                "2",
+                chrono::Duration::hours(1),
+            );
+
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+
+        send(request(
+            "/permissions/assign",
+            Some(&admin),
+            json!({"user_id": 2, "role": "editor"}),
+        ))
+        .await;
+
+        for (role, access) in
+            [("public", "read"), ("editor", "write")]
+        {
+            let res = send(request(
+                "/permissions/deny_field",
+                Some(&admin),
+                json!({
+                    "role": role,
+                    "collection": "Todo",
+                    "field": "description",
+                    "access": access,
+                }),
+            ))
+            .await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let res = send(request(
+            "/permissions/deny_field",
+            Some(&admin),
+            json!({
+                "role": "public",
+                "collection": "Todo",
+                "field": "not_a_field",
+                "access": "read",
+            }),
+        ))
+        .await;
+        assert_eq!(
+            res.status(),
+            StatusCode::NOT_FOUND,
+            "only members of the collection can be denied"
+        );
+
+        // public can't see the description
+        let res = send(request(
+            "/collection/todo/get_many",
+            None,
+            json!({}),
+        ))
+        .await;
+        assert_eq!(
+            body(res).await["data"][0]["attr"],
+            json!({"title": "todo_1"})
+        );
+
+        // and neither the linked rows' hidden fields
+        for (collection, field) in
+            [("Category", "cat_title"), ("Tag", "tag_title")]
+        {
+            send(request(
+                "/permissions/deny_field",
+                Some(&admin),
+                json!({
+                    "role": "public",
+                    "collection": collection,
+                    "field": field,
+                    "access": "read",
+                }),
+            ))
+            .await;
+        }
+        let res = send(request(
+            "/collection/todo/get_many",
+            None,
+            json!({}),
+        ))
+        .await;
+        assert_eq!(
+            body(res).await["data"][0]["relations"],
+            json!({
+                "category": {"id": 1, "attr": {}},
+                "tag": [{"id": 1, "attr": {}}],
+            })
+        );
+
+        // editors can see it, but not set it
+        let res = send(request(
+            "/collection/todo/get_many",
+            Some(&editor),
+            json!({}),
+        ))
+        .await;
+        assert_eq!(
+            body(res).await["data"][0]["attr"]["description"],
+            json!("internal")
+        );
+
+        let res = send(request(
+            "/collection/todo/update_one",
+            Some(&editor),
+            json!({
+                "id": 1,
+                "partial": {"title": "new", "description": "new"},
+                "relations": {},
+            }),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            body(res).await["error"]["user_error"],
+            json!({
+                "code": "forbidden_fields",
+                "user_hint": "you don't have permission to set some fields",
+                "structured_hint": {"description": "forbidden"},
+                "server_suggest": null,
+            })
+        );
+
+        let res = send(request(
+            "/collection/todo/update_one",
+            Some(&editor),
+            json!({
+                "id": 1,
+                "partial": {"title": "new"},
+                "relations": {},
+            }),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            body(res).await["attr"],
+            json!({"title": "new"})
+        );
+
+        // admins are never denied
+        let res = send(request(
+            "/collection/todo/update_one",
+            Some(&admin),
+            json!({
+                "id": 1,
+                "partial": {"description": "new"},
+                "relations": {},
+            }),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        send(request(
+            "/permissions/allow_field",
+            Some(&admin),
+            json!({
+                "role": "public",
+                "collection": "Todo",
+                "field": "description",
+                "access": "read",
+            }),
+        ))
+        .await;
+
+        let res = send(request(
+            "/collection/todo/get_many",
+            None,
+            json!({}),
+        ))
+        .await;
+        assert_eq!(
+            body(res).await["data"][0]["attr"]["description"],
+            json!("internal")
+        );
+    }
+
+    #[tokio::test]
+    async fn rows_are_restricted_to_their_owner() {
+        use queries_for_sqlx::ident_safety::define_schema;
+
+        use crate::axum_router::collections_router;
+
+        let _serial = SERIAL.lock().await;
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        std::env::set_var("JWT_SALT", "test");
+
+        // `Category` of `client_example` is owned
+        sqlx::query(
+            "
+        CREATE TABLE Category (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            cat_title TEXT NOT NULL,
+            _owner TEXT
+        );
+        CREATE TABLE Todo (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            title TEXT NOT NULL,
+            done BOOLEAN NOT NULL,
+            description TEXT,
+            category_id INTEGER REFERENCES Category (id)
+        );
+        ",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        define_schema(&[
+            ("Category", &["id", "cat_title", OWNER_COLUMN]),
+            (
+                "Todo",
+                &[
+                    "id",
+                    "title",
+                    "done",
+                    "description",
+                    "category_id",
+                ],
+            ),
+        ]);
+
+        init_permissions(&pool).await.unwrap();
+        assign_role(&pool, 1, ADMIN).await.unwrap();
+
+        let app = Router::new()
+            .nest("/collection", collections_router::<Sqlite>())
+            .nest("/permissions", permissions_router::<Sqlite>())
+            .layer(Extension(keys()))
+            .with_state(pool.clone());
+
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+
+        let hour = chrono::Duration::hours(1);
+        let admin = ijwt::sign_for(&keys(), "1", hour);
+        let two = ijwt::sign_for(&keys(), "2", hour);
+        let three = ijwt::sign_for(&keys(), "3", hour);
+
+        for user_id in [2, 3] {
+            send(request(
+                "/permissions/assign",
+                Some(&admin),
+                json!({"user_id": user_id, "role": "editor"}),
+            ))
+            .await;
+        }
+        send(request(
+            "/permissions/grant",
+            Some(&admin),
+            json!({
+                "role": "editor",
+                "collection": "Category",
+                "operation": "delete_one",
+            }),
+        ))
+        .await;
+
+        for operation in ["get_many", "update_one", "delete_one"]
+        {
+            let res = send(request(
+                "/permissions/owner_only",
+                Some(&admin),
+                json!({
+                    "role": "editor",
+                    "collection": "Category",
+                    "operation": operation,
+                }),
+            ))
+            .await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let res = send(request(
+            "/permissions/owner_only",
+            Some(&admin),
+            json!({
+                "role": "editor",
+                "collection": "Todo",
+                "operation": "get_many",
+            }),
+        ))
+        .await;
+        assert_eq!(
+            res.status(),
+            StatusCode::BAD_REQUEST,
+            "Todo is not owned"
+        );
+
+        for (token, title) in [
+            (&admin, "admin's"),
+            (&two, "two's"),
+            (&three, "three's"),
+        ] {
+            let res = send(request(
+                "/collection/category/insert_one",
+                Some(token),
+                json!({
+                    "input": {"cat_title": title},
+                    "relation": {},
+                }),
+            ))
+            .await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let owners: Vec<(String, Option<String>)> = sqlx::query_as(
+            "SELECT cat_title, _owner FROM Category ORDER BY id;",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            owners,
+            vec![
+                ("admin's".to_string(), Some("1".to_string())),
+                ("two's".to_string(), Some("2".to_string())),
+                ("three's".to_string(), Some("3".to_string())),
+            ]
+        );
+
+        let titles = |token: String| {
+            let send = send.clone();
+            async move {
+                let res = send(request(
+                    "/collection/category/get_many",
+                    Some(&token),
+                    json!({
+                        "filters": {},
+                        "relations": {},
+                        "pagination": {"page": 1, "page_size": 10},
+                    }),
+                ))
+                .await;
+                body(res).await["data"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|e| {
+                        e["attr"]["cat_title"]
+                            .as_str()
+                            .unwrap()
+                            .to_string()
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(titles(two.clone()).await, vec!["two's"]);
+        assert_eq!(
+            titles(admin.clone()).await,
+            vec!["admin's", "two's", "three's"]
+        );
+
+        // get_one is not restricted
+        let res = send(request(
+            "/collection/category/get_one",
+            Some(&two),
+            json!({"id": 3, "filters": {}, "relations": {}}),
+        ))
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // relations reaching rows of other owners are refused
+        for operation in ["get_many", "get_one", "insert_one"] {
+            send(request(
+                "/permissions/grant",
+                Some(&admin),
+                json!({
+                    "role": "editor",
+                    "collection": "Todo",
+                    "operation": operation,
+                }),
+            ))
+            .await;
+        }
+        let todos = |token: String, relations: Value| {
+            async move {
+                send(request(
+                    "/collection/todo/get_many",
+                    Some(&token),
+                    json!({
+                        "filters": {},
+                        "relations": relations,
+                        "pagination": {"page": 1, "page_size": 10},
+                    }),
+                ))
+                .await
+            }
+        };
+        let res =
+            todos(two.clone(), json!({"category": {}})).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            body(res).await["error"]["user_error"]["code"],
+            "relation_owner_only"
+        );
+        for (token, relations) in [
+            (&two, json!({})),
+            (&admin, json!({"category": {}})),
+        ] {
+            let res = todos(token.clone(), relations).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let insert = |category: i64| {
+            let two = two.clone();
+            async move {
+                send(request(
+                    "/collection/todo/insert_one",
+                    Some(&two),
+                    json!({
+                        "input": {
+                            "title": "linked",
+                            "done": false,
+                            "description": null,
+                        },
+                        "relation": {
+                            "category": {
+                                "set_id_to_and_populate": category
+                            }
+                        },
+                    }),
+                ))
+                .await
+            }
+        };
+        // get_one isn't restricted, linking is fine until it is
+        assert_eq!(insert(3).await.status(), StatusCode::OK);
+        send(request(
+            "/permissions/owner_only",
+            Some(&admin),
+            json!({
+                "role": "editor",
+                "collection": "Category",
+                "operation": "get_one",
+            }),
+        ))
+        .await;
+        assert_eq!(
+            insert(3).await.status(),
+            StatusCode::FORBIDDEN
+        );
+        send(request(
+            "/permissions/remove_owner_only",
+            Some(&admin),
+            json!({
+                "role": "editor",
+                "collection": "Category",
+                "operation": "get_one",
+            }),
+        ))
+        .await;
+
+        for (id, expected) in
+            [(3, StatusCode::BAD_REQUEST), (2, StatusCode::OK)]
+        {
+            let res = send(request(
+                "/collection/category/update_one",
+                Some(&two),
+                json!({
+                    "id": id,
+                    "partial": {"cat_title": ["set", "updated"]},
+                    "relations": {},
+                }),
+            ))
+            .await;
+            assert_eq!(res.status(), expected);
+        }
+
+        for (id, expected) in
+            [(1, StatusCode::BAD_REQUEST), (2, StatusCode::OK)]
+        {
+            let res = send(request(
+                "/collection/category/delete_one",
+                Some(&two),
+                json!({
+                    "id": id,
+                    "return_attr": false,
+                    "return_residual": [],
+                }),
+            ))
+            .await;
+            assert_eq!(res.status(), expected);
+        }
+
+        assert_eq!(
+            titles(admin.clone()).await,
+            vec!["admin's", "three's"],
+            "only the owned row is updated and deleted"
+        );
+
+        send(request(
+            "/permissions/remove_owner_only",
+            Some(&admin),
+            json!({
+                "role": "editor",
+                "collection": "Category",
+                "operation": "get_many",
+            }),
+        ))
+        .await;
+        assert_eq!(
+            titles(two.clone()).await,
+            vec!["admin's", "three's"]
+        );
+
+        // hidden fields can't be filtered on
+        send(request(
+            "/permissions/deny_field",
+            Some(&admin),
+            json!({
+                "role": "editor",
+                "collection": "Category",
+                "field": "cat_title",
+                "access": "read",
+            }),
+        ))
+        .await;
+        for (token, expected) in [
+            (&two, StatusCode::BAD_REQUEST),
+            (&admin, StatusCode::OK),
+        ] {
+            let res = send(request(
+                "/collection/category/get_many",
+                Some(token),
+                json!({
+                    "filters": {"cat_title": "three's"},
+                    "relations": {},
+                    "pagination": {"page": 1, "page_size": 10},
+                }),
+            ))
+            .await;
+            assert_eq!(res.status(), expected);
+        }
+    }
+}
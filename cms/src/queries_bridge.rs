@@ -20,3 +20,6 @@ pub type CreatTableSt<S> = st::CreateTableSt<
     ClonablQuery<'static, S>,
     PanicOnUnsafe,
 >;
+
+pub type CreateIndexSt<S> =
+    st::CreateIndexSt<S, QuickQuery<S>, PanicOnUnsafe>;
@@ -4,19 +4,81 @@ use queries_for_sqlx::{
     impl_into_mut_arguments_prelude::Type, BindItem, Constraint,
     Query, SchemaColumn,
 };
-use sqlx::Database;
+use serde::Serialize;
+use sqlx::{types::Decimal, Database};
+
+/// what a column holds beyond its sql type, filters parse and
+/// compare their values by it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnKind {
+    Plain,
+    /// stored as JSON, TEXT in sqlite and JSONB in postgres,
+    /// filters can reach inside it by path
+    Json,
+    DateTime,
+    Date,
+    Uuid,
+    Decimal,
+}
 
 pub trait IsNull {
     fn is_null() -> bool;
+    /// the values a column of this type is restricted to, only
+    /// set by `#[derive(TextEnum)]`
+    const VARIANTS: Option<&'static [&'static str]> = None;
+    const KIND: ColumnKind = ColumnKind::Plain;
+}
+
+/// unit enums stored as TEXT, derived with `#[derive(TextEnum)]`
+pub trait TextEnum: Sized {
+    const VARIANTS: &'static [&'static str];
+    fn as_str(&self) -> &'static str;
+    fn from_text(text: &str) -> Option<Self>;
 }
 
 mod impl_is_null_no_spectialization {
-    use super::IsNull;
+    use sqlx::types::{
+        chrono::{DateTime, NaiveDate, Utc},
+        Json, Uuid,
+    };
 
-    impl<T> IsNull for Option<T> {
+    use super::{ColumnKind, DecimalColumn, IsNull};
+
+    impl<T: IsNull> IsNull for Option<T> {
         fn is_null() -> bool {
             true
         }
+        const VARIANTS: Option<&'static [&'static str]> =
+            T::VARIANTS;
+        const KIND: ColumnKind = T::KIND;
+    }
+
+    macro_rules! impl_kind {
+        ($($ty:ty => $kind:ident,)*) => {
+            $(impl IsNull for $ty {
+                fn is_null() -> bool {
+                    false
+                }
+                const KIND: ColumnKind = ColumnKind::$kind;
+            })*
+        };
+    }
+
+    impl_kind!(
+        serde_json::Value => Json,
+        DateTime<Utc> => DateTime,
+        NaiveDate => Date,
+        Uuid => Uuid,
+        DecimalColumn => Decimal,
+    );
+
+    /// members marked `#[cms(json)]` are stored in this
+    impl<T> IsNull for Json<T> {
+        fn is_null() -> bool {
+            false
+        }
+        const KIND: ColumnKind = ColumnKind::Json;
     }
 
     macro_rules! impl_no_gens {
@@ -32,6 +94,105 @@ mod impl_is_null_no_spectialization {
     impl_no_gens!(i32 i64 bool char String);
 }
 
+/// members the derive can't store as they are, their column
+/// type is `Column`
+pub trait StoredAs {
+    type Column;
+    fn into_column(self) -> Self::Column;
+    fn from_column(column: Self::Column) -> Self;
+}
+
+/// sqlx has no `Decimal` for sqlite, there it is kept as TEXT to
+/// not lose precision. postgres uses NUMERIC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecimalColumn(pub Decimal);
+
+impl StoredAs for Decimal {
+    type Column = DecimalColumn;
+    fn into_column(self) -> DecimalColumn {
+        DecimalColumn(self)
+    }
+    fn from_column(column: DecimalColumn) -> Self {
+        column.0
+    }
+}
+
+impl StoredAs for Option<Decimal> {
+    type Column = Option<DecimalColumn>;
+    fn into_column(self) -> Option<DecimalColumn> {
+        self.map(DecimalColumn)
+    }
+    fn from_column(column: Option<DecimalColumn>) -> Self {
+        column.map(|e| e.0)
+    }
+}
+
+mod impl_decimal_column {
+    use std::str::FromStr;
+
+    use sqlx::{
+        encode::IsNull,
+        error::BoxDynError,
+        postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef},
+        sqlite::{
+            SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef,
+        },
+        types::Decimal,
+        Decode, Encode, Postgres, Sqlite, Type,
+    };
+
+    use super::DecimalColumn;
+
+    impl Type<Sqlite> for DecimalColumn {
+        fn type_info() -> SqliteTypeInfo {
+            <String as Type<Sqlite>>::type_info()
+        }
+    }
+
+    impl<'q> Encode<'q, Sqlite> for DecimalColumn {
+        fn encode_by_ref(
+            &self,
+            buf: &mut Vec<SqliteArgumentValue<'q>>,
+        ) -> IsNull {
+            Encode::<Sqlite>::encode(self.0.to_string(), buf)
+        }
+    }
+
+    impl<'r> Decode<'r, Sqlite> for DecimalColumn {
+        fn decode(
+            value: SqliteValueRef<'r>,
+        ) -> Result<Self, BoxDynError> {
+            let text = <&str as Decode<Sqlite>>::decode(value)?;
+            Ok(DecimalColumn(Decimal::from_str(text)?))
+        }
+    }
+
+    impl Type<Postgres> for DecimalColumn {
+        fn type_info() -> PgTypeInfo {
+            <Decimal as Type<Postgres>>::type_info()
+        }
+    }
+
+    impl<'q> Encode<'q, Postgres> for DecimalColumn {
+        fn encode_by_ref(
+            &self,
+            buf: &mut PgArgumentBuffer,
+        ) -> IsNull {
+            Encode::<Postgres>::encode_by_ref(&self.0, buf)
+        }
+    }
+
+    impl<'r> Decode<'r, Postgres> for DecimalColumn {
+        fn decode(
+            value: PgValueRef<'r>,
+        ) -> Result<Self, BoxDynError> {
+            Ok(DecimalColumn(
+                <Decimal as Decode<Postgres>>::decode(value)?,
+            ))
+        }
+    }
+}
+
 pub trait SqlxQuery: Database {
     type KeyType: Type<Self> + IsNull + Send + Sync + 'static;
     fn default_primary_key() -> &'static str;
@@ -47,11 +208,15 @@ impl SqlxQuery for sqlx::Sqlite {
 impl SqlxQuery for sqlx::Postgres {
     type KeyType = i64;
     fn default_primary_key() -> &'static str {
-        "PRIMARY KEY"
+        "GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY"
     }
 }
 
-pub struct ColumnTypeCheckIfNull<T>(PhantomData<T>);
+/// the column's name is only needed for the `CHECK` of enums
+pub struct ColumnTypeCheckIfNull<T>(
+    PhantomData<T>,
+    Option<String>,
+);
 
 impl<S, T> SchemaColumn<S> for ColumnTypeCheckIfNull<T>
 where
@@ -63,10 +228,24 @@ where
         let ty = T::type_info();
         let ty = ty.name().to_string();
 
+        let variants = match (&self.1, T::VARIANTS) {
+            (Some(column), Some(variants)) => {
+                let variants = variants
+                    .iter()
+                    .map(|e| e.replace('\'', "''"))
+                    .map(|e| format!("'{e}'"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(" CHECK ({column} IN ({variants}))")
+            }
+            _ => String::new(),
+        };
+
         format!(
-            "{}{}",
+            "{}{}{}",
             ty,
-            if T::is_null() { "" } else { " NOT NULL" }
+            if T::is_null() { "" } else { " NOT NULL" },
+            variants
         )
     }
 }
@@ -87,7 +266,13 @@ where
 }
 
 pub fn col_type_check_if_null<T>() -> ColumnTypeCheckIfNull<T> {
-    ColumnTypeCheckIfNull(PhantomData)
+    ColumnTypeCheckIfNull(PhantomData, None)
+}
+
+/// same as `col_type_check_if_null`, enum columns are also kept
+/// to their variants with a `CHECK`
+pub fn col_type_of<T>(column: &str) -> ColumnTypeCheckIfNull<T> {
+    ColumnTypeCheckIfNull(PhantomData, Some(column.to_string()))
 }
 
 pub struct DefaultPrimaryKey;
@@ -144,3 +329,170 @@ where
         move |_| <Self as SchemaColumn<S>>::display(&self)
     }
 }
+
+/// a key column pointing at the `id` of another table, the
+/// reference is written inline so a nullable one can be added
+/// with `ALTER TABLE .. ADD COLUMN`
+pub struct ForeignKey<S> {
+    table: String,
+    not_null: bool,
+    _pd: PhantomData<S>,
+}
+
+/// set to `NULL` when the row it points at is deleted
+pub fn optional_foreign_key<S>(table: &str) -> ForeignKey<S> {
+    ForeignKey {
+        table: table.to_string(),
+        not_null: false,
+        _pd: PhantomData,
+    }
+}
+
+/// deleted along with the row it points at
+pub fn foreign_key<S>(table: &str) -> ForeignKey<S> {
+    ForeignKey {
+        table: table.to_string(),
+        not_null: true,
+        _pd: PhantomData,
+    }
+}
+
+impl<S> SchemaColumn<S> for ForeignKey<S>
+where
+    S: SqlxQuery,
+    S: Database,
+{
+    fn display(&self) -> String {
+        let ty = S::KeyType::type_info().to_string();
+        if self.not_null {
+            format!(
+                "{} NOT NULL REFERENCES {} (id) ON DELETE CASCADE",
+                ty, self.table
+            )
+        } else {
+            format!(
+                "{} REFERENCES {} (id) ON DELETE SET NULL",
+                ty, self.table
+            )
+        }
+    }
+}
+
+impl<S, Q, I> BindItem<S, Q, I> for ForeignKey<S>
+where
+    S: Database,
+    S: SqlxQuery,
+    Q: Query,
+{
+    fn bind_item(
+        self,
+        _: &mut <Q as Query>::Context1,
+    ) -> impl FnOnce(&mut <Q as Query>::Context2) -> String + 'static
+    {
+        move |_| <Self as SchemaColumn<S>>::display(&self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use queries_for_sqlx::SchemaColumn;
+    use sqlx::Sqlite;
+
+    use super::{
+        col_type_check_if_null, col_type_of, DecimalColumn,
+        IsNull,
+    };
+    use sqlx::{
+        types::{
+            chrono::{DateTime, NaiveDate, Utc},
+            Decimal, Uuid,
+        },
+        Pool, Postgres,
+    };
+
+    struct Quoted;
+
+    impl IsNull for Quoted {
+        fn is_null() -> bool {
+            false
+        }
+        const VARIANTS: Option<&'static [&'static str]> =
+            Some(&["plain", "it's"]);
+    }
+
+    impl sqlx::Type<Sqlite> for Quoted {
+        fn type_info() -> sqlx::sqlite::SqliteTypeInfo {
+            <String as sqlx::Type<Sqlite>>::type_info()
+        }
+    }
+
+    #[test]
+    fn enums_are_checked() {
+        assert_eq!(
+            SchemaColumn::<Sqlite>::display(&col_type_of::<
+                Option<Quoted>,
+            >("kind")),
+            "TEXT CHECK (kind IN ('plain', 'it''s'))"
+        );
+        assert_eq!(
+            SchemaColumn::<Sqlite>::display(
+                &col_type_of::<String>("title")
+            ),
+            "TEXT NOT NULL"
+        );
+        assert_eq!(
+            SchemaColumn::<Sqlite>::display(
+                &col_type_check_if_null::<Quoted>()
+            ),
+            "TEXT NOT NULL"
+        );
+    }
+
+    #[test]
+    fn typed_columns() {
+        let sqlite = |e: &dyn SchemaColumn<Sqlite>| e.display();
+        let postgres =
+            |e: &dyn SchemaColumn<Postgres>| e.display();
+
+        assert_eq!(
+            sqlite(&col_type_of::<DateTime<Utc>>("due")),
+            "DATETIME NOT NULL"
+        );
+        assert_eq!(
+            postgres(&col_type_of::<DateTime<Utc>>("due")),
+            "TIMESTAMPTZ NOT NULL"
+        );
+        assert_eq!(
+            postgres(&col_type_of::<Option<NaiveDate>>("due")),
+            "DATE"
+        );
+        assert_eq!(
+            postgres(&col_type_of::<Uuid>("reference")),
+            "UUID NOT NULL"
+        );
+        assert_eq!(
+            sqlite(&col_type_of::<DecimalColumn>("amount")),
+            "TEXT NOT NULL"
+        );
+        assert_eq!(
+            postgres(&col_type_of::<DecimalColumn>("amount")),
+            "NUMERIC NOT NULL"
+        );
+    }
+
+    #[tokio::test]
+    async fn decimals_keep_their_scale_in_sqlite() {
+        let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        let amount = DecimalColumn(Decimal::new(1050, 2));
+        let (back,): (DecimalColumn,) =
+            sqlx::query_as("SELECT ?")
+                .bind(amount)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(back.0.to_string(), "10.50");
+    }
+}
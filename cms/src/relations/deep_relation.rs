@@ -141,7 +141,7 @@ where
         &'this self,
         data: &'this mut Self::Inner,
         pool: Pool<Sqlite>,
-    ) -> impl std::future::Future<Output = ()> + Send + 'this
+    ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
     {
         async move {
             let todo = FromTodo::table_name();
@@ -165,8 +165,7 @@ WHERE {category}.id = {}",
                     deep.0.clone()
                 ))
                 .fetch_all(&pool.clone())
-                .await
-                .unwrap();
+                .await?;
 
                 for one in ret {
                     data.1.push(SimpleOutput {
@@ -175,6 +174,7 @@ WHERE {category}.id = {}",
                     })
                 }
             }
+            Ok(())
         }
     }
 
@@ -2,24 +2,28 @@ use std::collections::HashMap;
 
 use case::CaseExt;
 use queries_for_sqlx::{
-    create_table_st::CreateTableHeader,
+    create_table_st::{CreateTableHeader, CreateTableSt},
     insert_many_st::insert_many,
 };
 use serde::de::DeserializeOwned;
 use serde_json::from_value;
 use stmt::InsertMany;
 
+use sqlx::{ColumnIndex, Decode, Encode, Executor, Type};
+
 use crate::{
+    backend::Backend,
     dynamic_schema::{
         DynInsertOneWorker, DynUpdateOneWorker,
         DynamicRelationResult,
     },
-    migration2::DynMigration,
+    migration2::{DynMigration, MigrationCtx},
     operations::{
         insert_one::InsertOneWorker, select_many::GetAllWorker,
         update_one::UpdateOneWorker, SimpleOutput,
     },
     queries_bridge::{DeleteSt, SelectSt},
+    queries_for_sqlx_extention::foreign_key,
     relations::ManyWorker,
 };
 
@@ -35,38 +39,6 @@ pub struct ManyToMany {
     pub destination_id: String,
 }
 
-impl DynMigration for ManyToMany {
-    fn panic_on_unsafe_schema(&self) {
-        queries_for_sqlx::ident_safety::append_schema(
-            &self.conjuction_table,
-            &[&self.base_id, &self.destination_id],
-        )
-    }
-    fn migrate(
-        &self,
-        ctx: &mut crate::migration2::MigrationCtx,
-    ) -> Result<(), String> {
-        let name = self.conjuction_table.clone();
-        let new = stmt::CreateTableSt::init((
-            CreateTableHeader::IfNotExists,
-            &name,
-        ));
-
-        match ctx.store.0.insert(name.to_string(), new) {
-            None => {}
-            Some(_) => {
-                panic!("should not contain old table")
-            }
-        };
-
-        let table = ctx.store.0.get_mut(&name);
-
-        todo!();
-
-        Ok(())
-    }
-}
-
 impl LinkSpec for ManyToMany {}
 impl LinkSpecCanInsert for ManyToMany {
     type Input = Vec<i64>;
@@ -75,17 +47,18 @@ impl LinkSpecCanUpdate for ManyToMany {
     type Input = Vec<UpdateIdInput>;
 }
 
-pub struct ManyToManyDynamic<From, To> {
+pub struct ManyToManyDynamic<From, To, S> {
     pub(crate) list_itself_under: String,
     pub(crate) key: String,
     pub(crate) rel_spec: ManyToMany,
     pub(crate) _pd: PhantomData<(From, To)>,
+    pub(crate) _db: PhantomData<fn() -> S>,
 }
 
-impl<From, To> ManyToManyDynamic<From, To>
+impl<From, To, S> ManyToManyDynamic<From, To, S>
 where
-    From: Collection<Sqlite>,
-    To: Collection<Sqlite>,
+    From: Collection<S>,
+    To: Collection<S>,
     From: Linked<To, Spec = ManyToMany>,
 {
     pub fn new() -> Self {
@@ -95,14 +68,71 @@ where
             rel_spec: From::spec(),
 
             _pd: PhantomData,
+            _db: PhantomData,
         }
     }
 }
 
-impl<F, T> CompleteRelationForServer for ManyToManyDynamic<F, T>
+/// both sides share the link table, submit it from one of them
+/// so the order of its columns does not change between builds
+impl<F, T, S> DynMigration<S> for ManyToManyDynamic<F, T, S>
 where
-    F: Collection<Sqlite> + 'static,
-    T: Collection<Sqlite> + 'static + Serialize + DeserializeOwned,
+    S: Backend,
+    F: Collection<S> + 'static,
+    T: Collection<S> + 'static,
+{
+    fn panic_on_unsafe_schema(&self) {
+        queries_for_sqlx::ident_safety::append_schema(
+            &self.rel_spec.conjuction_table,
+            &[
+                &self.rel_spec.base_id,
+                &self.rel_spec.destination_id,
+            ],
+        )
+    }
+    fn migrate(
+        &self,
+        ctx: &mut MigrationCtx<S>,
+    ) -> Result<(), String> {
+        let ManyToMany {
+            conjuction_table,
+            base_id,
+            destination_id,
+        } = &self.rel_spec;
+
+        if ctx.store.0.contains_key(conjuction_table) {
+            return Ok(());
+        }
+
+        let mut table = CreateTableSt::init((
+            CreateTableHeader::IfNotExists,
+            conjuction_table,
+        ));
+        table.column(base_id, foreign_key::<S>(F::table_name()));
+        table.column(
+            destination_id,
+            foreign_key::<S>(T::table_name()),
+        );
+        table.verbatim(&format!(
+            "PRIMARY KEY ({}, {})",
+            base_id, destination_id
+        ));
+
+        ctx.store.0.insert(conjuction_table.clone(), table);
+
+        Ok(())
+    }
+}
+
+impl<F, T, S> CompleteRelationForServer<S>
+    for ManyToManyDynamic<F, T, S>
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    F: Collection<S> + 'static,
+    T: Collection<S> + 'static + Serialize + DeserializeOwned,
 {
     fn list_iteself_under(&self) -> String {
         self.list_itself_under.clone()
@@ -112,11 +142,15 @@ where
         self.key.clone()
     }
 
+    fn to(&self) -> String {
+        T::table_name().to_string()
+    }
+
     fn init_on_insert(
         self: Arc<Self>,
         to: &str,
         input: Value,
-    ) -> DynamicRelationResult<Box<dyn DynInsertOneWorker>> {
+    ) -> DynamicRelationResult<Box<dyn DynInsertOneWorker<S>>> {
         if to != self.key {
             return DynamicRelationResult::NotFound;
         }
@@ -138,7 +172,7 @@ where
             }
         };
 
-        let ret: Box<dyn DynInsertOneWorker> = match input {
+        let ret: Box<dyn DynInsertOneWorker<S>> = match input {
             ValidInput::set_id_to_and_populate(vec) => {
                 DynamicWorker::new(
                     self.clone(),
@@ -161,7 +195,7 @@ where
         self: Arc<Self>,
         to: &str,
         input: Value,
-    ) -> DynamicRelationResult<Box<dyn DynGetOneWorker>> {
+    ) -> DynamicRelationResult<Box<dyn DynGetOneWorker<S>>> {
         if to != self.key {
             return DynamicRelationResult::NotFound;
         }
@@ -181,7 +215,7 @@ where
         DynamicRelationResult::Ok(Box::new(DynamicWorker {
             rw: Some(RelationWorker {
                 rel_spec: self.rel_spec.clone(),
-                _pd: self._pd,
+                _pd: PhantomData::<(F, T)>,
             }),
             arc: self.clone(),
             inner: Default::default(),
@@ -193,7 +227,7 @@ where
         to: &str,
         input: Value,
     ) -> DynamicRelationResult<
-        Box<dyn crate::dynamic_schema::DynGetManyWorker>,
+        Box<dyn crate::dynamic_schema::DynGetManyWorker<S>>,
     > {
         if to != self.key {
             return DynamicRelationResult::NotFound;
@@ -226,7 +260,7 @@ where
         self: Arc<Self>,
         to: &str,
         input: Value,
-    ) -> DynamicRelationResult<Box<dyn DynUpdateOneWorker>> {
+    ) -> DynamicRelationResult<Box<dyn DynUpdateOneWorker<S>>> {
         if to != self.key {
             return DynamicRelationResult::NotFound;
         }
@@ -252,24 +286,28 @@ where
     }
 }
 
-impl<Base, Destination> GetOneWorker
+impl<Base, Destination, S> GetOneWorker<S>
     for RelationWorker<ManyToMany, Base, Destination>
 where
-    Base: Collection<Sqlite>,
-    Destination: Collection<Sqlite>,
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    Base: Collection<S>,
+    Destination: Collection<S>,
 {
     type Inner = (Option<i64>, Vec<(i64, Destination)>);
     type Output = Vec<SimpleOutput<Destination>>;
 
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
         *&mut data.0 = Some(row.get("local_id"))
     }
 
     fn sub_op<'t>(
         &'t self,
         data: &'t mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 't {
+        pool: Pool<S>,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 't {
         async move {
             let id = data.0.unwrap();
 
@@ -301,10 +339,10 @@ where
                     let val = Destination::from_row_scoped(&row);
                     Ok((row.get::<'_, i64, _>("dest_id"), val))
                 })
-                .await
-                .unwrap();
+                .await?;
 
             *&mut data.1 = vals;
+            Ok(())
         }
     }
 
@@ -316,25 +354,29 @@ where
     }
 }
 
-impl<B, T> InsertOneWorker
+impl<B, T, S> InsertOneWorker<S>
     for LinkIdWorker<B, T, ManyToMany, Vec<i64>>
 where
-    B: Collection<Sqlite>,
-    T: Collection<Sqlite>,
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    B: Collection<S>,
+    T: Collection<S>,
 {
     type Inner = (Option<i64>, Vec<T>);
 
     type Output = Vec<SimpleOutput<T>>;
 
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
         *&mut data.0 = Some(row.get("id"));
     }
 
     fn sub_op2<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
+        conn: &'this mut S::Connection,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
         async move {
             let local_id = data.0.unwrap();
             let mut st = insert_many(
@@ -350,7 +392,7 @@ where
                     .map(|id| (local_id, *id))
                     .collect(),
             );
-            st.execute(&pool).await.unwrap();
+            st.execute(&mut *conn).await?;
 
             let mut st =
                 SelectSt::init(T::table_name().to_string());
@@ -365,11 +407,13 @@ where
             T::on_select(&mut st);
 
             let res = st
-                .fetch_all(&pool, |r| Ok(T::from_row_scoped(&r)))
-                .await
-                .unwrap();
+                .fetch_all(&mut *conn, |r| {
+                    Ok(T::from_row_scoped(&r))
+                })
+                .await?;
 
             *&mut data.1 = res;
+            Ok(())
         }
     }
 
@@ -383,25 +427,29 @@ where
     }
 }
 
-impl<B, T> UpdateOneWorker
+impl<B, T, S> UpdateOneWorker<S>
     for UpdateIdWorker<B, T, ManyToMany, Vec<UpdateIdInput>>
 where
-    B: Collection<Sqlite>,
-    T: Collection<Sqlite>,
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    B: Collection<S>,
+    T: Collection<S>,
 {
     type Inner = (Option<i64>, Vec<i64>);
 
     type Output = Vec<i64>;
 
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
         *&mut data.0 = Some(row.get("id"));
     }
 
     fn sub_op2<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
+        conn: &'this mut S::Connection,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
         async move {
             let mut new = vec![];
             let mut remove = vec![];
@@ -419,35 +467,38 @@ where
                 }
             }
 
-            // exec all set_link
-            let mut new_st: InsertMany<Sqlite, _> = insert_many(
-                self.spec.conjuction_table.to_string(),
-            );
-
-            new_st
-                .columns(vec![
-                    self.spec.base_id.to_string(),
-                    self.spec.destination_id.to_string(),
-                ])
-                .values(new)
-                .execute(&pool.clone())
-                .await
-                .unwrap();
+            // exec all set_link, an empty `VALUES` is not valid sql
+            if !new.is_empty() {
+                let mut new_st: InsertMany<S, _> = insert_many(
+                    self.spec.conjuction_table.to_string(),
+                );
+
+                new_st
+                    .columns(vec![
+                        self.spec.base_id.to_string(),
+                        self.spec.destination_id.to_string(),
+                    ])
+                    .values(new)
+                    .execute(&mut *conn)
+                    .await?;
+            }
 
             // exec all remove_id
-            let mut rem_st = DeleteSt::init(
-                self.spec.conjuction_table.to_string(),
-            );
-
-            rem_st.where_(or(remove
-                .into_iter()
-                .map(|e| {
-                    col(self.spec.destination_id.to_string())
-                        .eq(e)
-                })
-                .collect()));
-
-            rem_st.execute(&pool).await.unwrap();
+            if !remove.is_empty() {
+                let mut rem_st = DeleteSt::init(
+                    self.spec.conjuction_table.to_string(),
+                );
+
+                rem_st.where_(or(remove
+                    .into_iter()
+                    .map(|e| {
+                        col(self.spec.destination_id.to_string())
+                            .eq(e)
+                    })
+                    .collect()));
+
+                rem_st.execute(&mut *conn).await?;
+            }
 
             // populate more data
             let mut st = SelectSt::init(
@@ -462,14 +513,14 @@ where
             );
 
             let res = st
-                .fetch_all(&pool, |r| {
-                    let r: i64 = r.get(0);
+                .fetch_all(&mut *conn, |r| {
+                    let r: i64 = r.get(self.spec.destination_id.as_str());
                     Ok(r)
                 })
-                .await
-                .unwrap();
+                .await?;
 
-            *&mut data.1 = res
+            *&mut data.1 = res;
+            Ok(())
         }
     }
 
@@ -478,16 +529,20 @@ where
     }
 }
 
-impl<F, T> GetAllWorker for ManyWorker<F, T, ManyToMany>
+impl<F, T, S> GetAllWorker<S> for ManyWorker<F, T, ManyToMany>
 where
-    F: Collection<Sqlite>,
-    T: Collection<Sqlite>,
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    F: Collection<S>,
+    T: Collection<S>,
 {
     type Inner = HashMap<i64, Vec<SimpleOutput<T>>>;
 
     type Output = Vec<SimpleOutput<T>>;
 
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
         let local_id = row.get("local_id");
         data.insert(local_id, vec![]);
     }
@@ -495,8 +550,8 @@ where
     fn sub_op<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
+        pool: Pool<S>,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
         async move {
             let mut st = SelectSt::init(
                 self.spec.conjuction_table.to_string(),
@@ -533,8 +588,7 @@ where
 
             T::on_select(&mut st);
 
-            let res = st
-                .fetch_all(&pool, |r| {
+            st.fetch_all(&pool, |r| {
                     let id = r.get("dest_id");
                     let from_id = r.get("from_id");
                     let attr = T::from_row_scoped(&r);
@@ -544,8 +598,9 @@ where
 
                     Ok(())
                 })
-                .await
-                .unwrap();
+                .await?;
+
+            Ok(())
         }
     }
 
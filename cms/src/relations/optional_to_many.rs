@@ -2,19 +2,24 @@ use std::collections::HashMap;
 
 use case::CaseExt;
 use serde::de::DeserializeOwned;
+use queries_for_sqlx::SupportNamedBind;
 use serde_json::from_value;
 
+use sqlx::{ColumnIndex, Database, Decode, Encode, Executor, Type};
+
 use crate::{
+    backend::Backend,
     dynamic_schema::{
         DynInsertOneWorker, DynamicRelationResult,
     },
-    migration2::DynMigration,
+    migration2::{DynMigration, Events, MigrationCtx},
     operations::{
         delete_one::DynDeleteWorker,
         insert_one::InsertOneWorker, select_many::GetAllWorker,
         update_one::UpdateOneWorker, IdOutput, SimpleOutput,
     },
     queries_bridge::{SelectSt, UpdateSt},
+    queries_for_sqlx_extention::optional_foreign_key,
     relations::ManyWorker,
 };
 
@@ -28,25 +33,6 @@ pub struct OptionalToMany {
     pub foriegn_key: String,
 }
 
-impl DynMigration for (&'static str, OptionalToMany) {
-    fn panic_on_unsafe_schema(&self) {
-        queries_for_sqlx::ident_safety::append_schema(
-            self.0,
-            &[self.1.foriegn_key.as_str()],
-        )
-    }
-    fn migrate(
-        &self,
-        ctx: &mut crate::migration2::MigrationCtx,
-    ) -> Result<(), String> {
-        let name = self.0.to_owned();
-
-        todo!();
-
-        Ok(())
-    }
-}
-
 impl LinkSpec for OptionalToMany {}
 impl LinkSpecCanInsert for OptionalToMany {
     type Input = i64;
@@ -56,37 +42,97 @@ impl LinkSpecCanUpdate for OptionalToMany {
     type Input = Option<i64>;
 }
 
-pub struct OptionalToManyDynamic<From, To> {
+pub struct OptionalToManyDynamic<From, To, S> {
     pub(crate) list_itself_under: String,
     pub(crate) rel_spec: OptionalToMany,
     pub(crate) key: String,
     pub(crate) _pd: PhantomData<(From, To)>,
+    pub(crate) _db: PhantomData<fn() -> S>,
 }
 
-impl<From, To> OptionalToManyDynamic<From, To> {
+impl<From, To, S> OptionalToManyDynamic<From, To, S> {
     pub fn new() -> Self
     where
         From: Linked<To, Spec = OptionalToMany>,
-        From: Collection<Sqlite> + Serialize + 'static,
-        To: Collection<Sqlite>,
+        From: Collection<S> + Serialize + 'static,
+        To: Collection<S>,
     {
         Self {
             list_itself_under: From::table_name().to_string(),
             key: To::table_name().to_snake(),
             rel_spec: From::spec(),
             _pd: PhantomData,
+            _db: PhantomData,
         }
     }
 }
 
-impl<From, To> CompleteRelationForServer
-    for OptionalToManyDynamic<From, To>
+impl<From, To, S> DynMigration<S>
+    for OptionalToManyDynamic<From, To, S>
+where
+    S: Backend,
+    From: Collection<S> + 'static,
+    To: Collection<S> + 'static,
+{
+    fn panic_on_unsafe_schema(&self) {
+        queries_for_sqlx::ident_safety::append_columns(
+            From::table_name(),
+            &[self.rel_spec.foriegn_key.as_str()],
+        )
+    }
+    fn migrate(
+        &self,
+        ctx: &mut MigrationCtx<S>,
+    ) -> Result<(), String> {
+        let table_name = From::table_name();
+        let key = &self.rel_spec.foriegn_key;
+
+        let Some(table) = ctx.store.0.get_mut(table_name) else {
+            // the key goes after the collection's own columns,
+            // let every other migration run first, once
+            let deferred =
+                Events::RelationDeferred(table_name, key.clone());
+            if ctx.events.contains(&deferred) {
+                return Err(format!(
+                    "relation {} is on unknown table {}",
+                    key, table_name
+                ));
+            }
+            ctx.events.push(deferred);
+            ctx.executables.insert(
+                0,
+                Box::new(Self {
+                    list_itself_under: self
+                        .list_itself_under
+                        .clone(),
+                    rel_spec: self.rel_spec.clone(),
+                    key: self.key.clone(),
+                    _pd: PhantomData,
+                    _db: PhantomData,
+                }),
+            );
+            return Ok(());
+        };
+
+        table.column(
+            key,
+            optional_foreign_key::<S>(To::table_name()),
+        );
+
+        Ok(())
+    }
+}
+
+impl<From, To, S> CompleteRelationForServer<S>
+    for OptionalToManyDynamic<From, To, S>
 where
-    From: Collection<Sqlite> + Serialize + 'static,
-    To: Collection<Sqlite>
-        + Serialize
-        + 'static
-        + DeserializeOwned,
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    Option<i64>: for<'e> Encode<'e, S>,
+    From: Collection<S> + Serialize + 'static,
+    To: Collection<S> + Serialize + 'static + DeserializeOwned,
 {
     fn list_iteself_under(&self) -> String {
         self.list_itself_under.to_string()
@@ -96,10 +142,14 @@ where
         self.key.clone()
     }
 
+    fn to(&self) -> String {
+        To::table_name().to_string()
+    }
+
     fn init_on_delete(
         self: Arc<Self>,
         to: &str,
-    ) -> DynamicRelationResult<Box<dyn DynDeleteWorker>> {
+    ) -> DynamicRelationResult<Box<dyn DynDeleteWorker<S>>> {
         if to != self.key {
             return DynamicRelationResult::NotFound;
         }
@@ -108,17 +158,22 @@ where
             spec: OptionalToMany,
         }
 
-        impl DynDeleteWorker for Worker {
+        impl<S> DynDeleteWorker<S> for Worker
+        where
+            S: Database + SupportNamedBind,
+            for<'s> &'s str: ColumnIndex<S::Row>,
+            i64: Type<S> + for<'d> Decode<'d, S>,
+        {
             fn sub_op(
                 &mut self,
-                db: axum::extract::State<Pool<Sqlite>>,
+                db: axum::extract::State<Pool<S>>,
             ) -> std::pin::Pin<
-                Box<dyn std::future::Future<Output = ()> + Send>,
+                Box<dyn std::future::Future<Output = Result<(), sqlx::Error>> + Send>,
             > {
-                Box::pin(async move {})
+                Box::pin(async move { Ok(()) })
             }
 
-            fn from_row(&mut self, r: &SqliteRow) -> Value {
+            fn from_row(&mut self, r: &S::Row) -> Value {
                 let re: Option<i64> =
                     r.get(self.spec.foriegn_key.as_str());
 
@@ -142,7 +197,7 @@ where
         to: &str,
         input: Value,
     ) -> DynamicRelationResult<
-        Box<dyn crate::dynamic_schema::DynUpdateOneWorker>,
+        Box<dyn crate::dynamic_schema::DynUpdateOneWorker<S>>,
     > {
         if to != self.key {
             return DynamicRelationResult::NotFound;
@@ -179,7 +234,7 @@ where
         self: Arc<Self>,
         to: &str,
         input: Value,
-    ) -> DynamicRelationResult<Box<dyn DynInsertOneWorker>> {
+    ) -> DynamicRelationResult<Box<dyn DynInsertOneWorker<S>>> {
         if to != self.key {
             return DynamicRelationResult::NotFound;
         }
@@ -220,7 +275,7 @@ where
         to: &str,
         input: Value,
     ) -> DynamicRelationResult<
-        Box<dyn crate::dynamic_schema::DynGetManyWorker>,
+        Box<dyn crate::dynamic_schema::DynGetManyWorker<S>>,
     > {
         if to != self.key {
             return DynamicRelationResult::NotFound;
@@ -253,7 +308,7 @@ where
         self: Arc<Self>,
         to: &str,
         input: Value,
-    ) -> DynamicRelationResult<Box<dyn DynGetOneWorker>> {
+    ) -> DynamicRelationResult<Box<dyn DynGetOneWorker<S>>> {
         if to != self.key {
             return DynamicRelationResult::NotFound;
         }
@@ -273,7 +328,7 @@ where
         DynamicRelationResult::Ok(Box::new(DynamicWorker {
             rw: Some(RelationWorker {
                 rel_spec: self.rel_spec.clone(),
-                _pd: self._pd,
+                _pd: PhantomData::<(From, To)>,
             }),
             arc: self.clone(),
             inner: Default::default(),
@@ -281,11 +336,15 @@ where
     }
 }
 
-impl<From, To> GetOneWorker
+impl<From, To, S> GetOneWorker<S>
     for RelationWorker<OptionalToMany, From, To>
 where
-    From: Send + Collection<Sqlite>,
-    To: Send + Collection<Sqlite>,
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    From: Send + Collection<S>,
+    To: Send + Collection<S>,
 {
     type Output = Option<SimpleOutput<To>>;
     type Inner = Option<(i64, To)>;
@@ -293,7 +352,7 @@ where
     fn on_select(
         &self,
         data: &mut Self::Inner,
-        st: &mut SelectSt<Sqlite>,
+        st: &mut SelectSt<S>,
     ) {
         st.left_join(join {
             on_table: To::table_name().to_string(),
@@ -307,7 +366,7 @@ where
         To::on_select(st);
     }
 
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
         let id: Option<i64> =
             row.get(self.rel_spec.foriegn_key.as_str());
         if let Some(id) = id {
@@ -319,8 +378,9 @@ where
     async fn sub_op<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) {
+        pool: Pool<S>,
+    ) -> Result<(), sqlx::Error> {
+        Ok(())
     }
 
     fn take(self, data: Self::Inner) -> Self::Output {
@@ -328,11 +388,15 @@ where
     }
 }
 
-impl<B, T> InsertOneWorker
+impl<B, T, S> InsertOneWorker<S>
     for LinkIdWorker<B, T, OptionalToMany, i64>
 where
-    B: Collection<Sqlite>,
-    T: Collection<Sqlite>,
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    B: Collection<S>,
+    T: Collection<S>,
 {
     type Inner = Option<T>;
 
@@ -341,19 +405,19 @@ where
     fn on_insert(
         &self,
         data: &mut Self::Inner,
-        st: &mut stmt::InsertStOne<'_, Sqlite, ()>,
+        st: &mut stmt::InsertStOne<'_, S, ()>,
     ) {
         st.insert(self.spec.foriegn_key.clone(), self.input);
     }
 
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
     }
 
     fn sub_op2<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
+        conn: &'this mut S::Connection,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
         async move {
             let mut st = stmt::SelectSt::init(
                 T::table_name().to_string(),
@@ -364,20 +428,20 @@ where
             let id = self.input;
             st.where_(col("id").eq(id));
 
-            st.fetch_one(&pool, |row| {
+            st.fetch_one(&mut *conn, |row| {
                 *data = Some(T::from_row_scoped(&row));
                 Ok(())
             })
-            .await;
+            .await
         }
     }
 
     fn sub_op1<'this>(
         &'this self,
         data: &'this mut Self::Inner,
-        pool: Pool<Sqlite>,
-    ) -> impl Future<Output = ()> + Send + 'this {
-        async {}
+        conn: &'this mut S::Connection,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send + 'this {
+        async { Ok(()) }
     }
 
     fn take(self, data: Self::Inner) -> Self::Output {
@@ -388,11 +452,14 @@ where
     }
 }
 
-impl<B, T> UpdateOneWorker
+impl<B, T, S> UpdateOneWorker<S>
     for UpdateIdWorker<B, T, OptionalToMany, Option<i64>>
 where
-    B: Collection<Sqlite>,
-    T: Collection<Sqlite>,
+    S: Backend,
+    i64: Type<S> + for<'e> Encode<'e, S>,
+    Option<i64>: for<'e> Encode<'e, S>,
+    B: Collection<S>,
+    T: Collection<S>,
 {
     type Inner = Option<i64>;
 
@@ -401,7 +468,7 @@ where
     fn on_update(
         &self,
         data: &mut Self::Inner,
-        st: &mut UpdateSt<Sqlite>,
+        st: &mut UpdateSt<S>,
     ) {
         let id = self.input.clone();
         st.set(self.spec.foriegn_key.to_string(), id);
@@ -415,10 +482,13 @@ where
     }
 }
 
-impl<F, T> GetAllWorker for ManyWorker<F, T, OptionalToMany>
+impl<F, T, S> GetAllWorker<S> for ManyWorker<F, T, OptionalToMany>
 where
-    F: Collection<Sqlite>,
-    T: Collection<Sqlite>,
+    S: Backend,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S>,
+    F: Collection<S>,
+    T: Collection<S>,
 {
     type Inner = HashMap<i64, SimpleOutput<T>>;
 
@@ -427,7 +497,7 @@ where
     fn on_select(
         &self,
         data: &mut Self::Inner,
-        st: &mut SelectSt<Sqlite>,
+        st: &mut SelectSt<S>,
     ) {
         st.left_join(join {
             on_table: T::table_name().to_string(),
@@ -439,7 +509,7 @@ where
         T::on_select(st);
     }
 
-    fn from_row(&self, data: &mut Self::Inner, row: &SqliteRow) {
+    fn from_row(&self, data: &mut Self::Inner, row: &S::Row) {
         let id: Option<i64> =
             row.get(self.spec.foriegn_key.as_str());
         if let Some(id) = id {
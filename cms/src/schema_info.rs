@@ -2,7 +2,10 @@
 use axum::{routing::post, Json, Router};
 use serde::Serialize;
 
-use crate::dynamic_schema::SubmitDynCollection;
+use crate::{
+    backend::Backend, queries_for_sqlx_extention::ColumnKind,
+    validation::Rule,
+};
 
 #[derive(Serialize)]
 pub enum SupportedType {
@@ -14,6 +17,13 @@ pub enum SupportedType {
 pub struct Field {
     pub name: String,
     pub f_type: SupportedType,
+    /// so forms can be checked before they are sent
+    pub rules: &'static [Rule],
+    /// the values an enum member accepts
+    pub variants: Option<&'static [&'static str]>,
+    /// json documents, dates, uuids and decimals, so they get
+    /// the right input
+    pub kind: ColumnKind,
 }
 
 #[derive(Serialize)]
@@ -23,12 +33,12 @@ pub struct Collection {
     pub fields: Vec<Field>,
 }
 
-#[axum::debug_handler]
-async fn schema_handler_() -> Json<Vec<Collection>> {
+async fn schema_handler_<S: Backend>() -> Json<Vec<Collection>> {
     let mut v = vec![];
 
-    for col in inventory::iter::<SubmitDynCollection> {
-        let obj = (col.obj)();
+    for obj in S::collections().read().await.values() {
+        let rules = obj.field_rules();
+        let variants = obj.field_variants();
         v.push(Collection {
             name: obj.table_name().to_string(),
             fields: obj
@@ -37,6 +47,16 @@ async fn schema_handler_() -> Json<Vec<Collection>> {
                 .map(|e| Field {
                     name: e.to_string(),
                     f_type: SupportedType::Todo,
+                    rules: rules
+                        .iter()
+                        .find(|(name, _)| name == e)
+                        .map(|(_, rules)| *rules)
+                        .unwrap_or_default(),
+                    variants: variants
+                        .iter()
+                        .find(|(name, _)| name == e)
+                        .and_then(|(_, variants)| *variants),
+                    kind: obj.column_kind(e),
                 })
                 .collect(),
         });
@@ -45,8 +65,8 @@ async fn schema_handler_() -> Json<Vec<Collection>> {
     Json(v)
 }
 
-pub fn schema_router() -> Router<()> {
-    let app = Router::new().route("/", post(schema_handler_));
+pub fn schema_router<S: Backend>() -> Router<()> {
+    let app = Router::new().route("/", post(schema_handler_::<S>));
 
     app
 }
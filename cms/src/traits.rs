@@ -1,27 +1,75 @@
-use std::marker::PhantomData;
+use std::{collections::BTreeMap, marker::PhantomData};
 
 use queries_for_sqlx::SupportNamedBind;
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use sqlx::Database;
 
-use crate::queries_bridge::{
-    CreatTableSt, InsertSt, SelectSt, UpdateSt,
+use crate::{
+    dynamic_schema::ValidatedAndTyped,
+    queries_bridge::{
+        CreatTableSt, CreateIndexSt, InsertSt, SelectSt,
+        UpdateSt,
+    },
+    queries_for_sqlx_extention::ColumnKind,
+    validation::Rule,
 };
 
+/// field name -> message, sent to the client as
+/// `UserError.structured_hint`
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+)]
+pub struct FieldErrors(pub BTreeMap<String, String>);
+
+impl FieldErrors {
+    /// the first message added for a field is kept
+    pub fn add(
+        &mut self,
+        field: &str,
+        message: impl Into<String>,
+    ) {
+        self.0
+            .entry(field.to_string())
+            .or_insert_with(|| message.into());
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// `Ok` when nothing was added
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/// collections opt in with `#[cms(validate)]`, both run before
+/// any sql is built and may normalize the input in place. only
+/// the dynamic operations run them, the typed `insert_one` and
+/// `update_one` builders are not supported yet
 pub trait Validate {
-    type Value;
-    type Error: Serialize;
-    fn validate_on_insert(
-        &self,
-        value: &mut Self::Value,
-    ) -> Result<(), Self::Error> {
+    /// what updates are validated against, the partial
+    /// collection that the derive generates
+    type Partial;
+    fn validate_on_insert(&mut self) -> Result<(), FieldErrors> {
         Ok(())
     }
+    /// inserts that leave defaulted members out are validated
+    /// here too, the missing members are `Update::keep`
     fn validate_on_update(
-        &self,
-        value: &mut Self::Value,
-    ) -> Result<(), Self::Error> {
+        partial: &mut Self::Partial,
+    ) -> Result<(), FieldErrors> {
+        let _ = partial;
         Ok(())
     }
 }
@@ -82,6 +130,14 @@ pub trait Collection<S>: Sized + Send + Sync {
     fn on_migrate(stmt: &mut CreatTableSt<S>)
     where
         S: Database + SupportNamedBind;
+    /// indexes are not part of the table definition, they
+    /// are created after the table
+    fn on_index(indexes: &mut Vec<CreateIndexSt<S>>)
+    where
+        S: Database + SupportNamedBind,
+    {
+        let _ = indexes;
+    }
     fn on_update(
         stmt: &mut UpdateSt<S>,
         this: Self::PartailCollection,
@@ -91,16 +147,77 @@ pub trait Collection<S>: Sized + Send + Sync {
     fn on_select(stmt: &mut SelectSt<S>)
     where
         S: Database + SupportNamedBind;
+    /// forwards to `Validate` when the collection opted in
+    fn on_validate_insert(&mut self) -> Result<(), FieldErrors> {
+        Ok(())
+    }
+    fn on_validate_update(
+        this: &mut Self::PartailCollection,
+    ) -> Result<(), FieldErrors> {
+        let _ = this;
+        Ok(())
+    }
     fn on_insert(
         self,
         stmt: &mut InsertSt<S>,
     ) -> Result<(), String>
     where
         S: Database + SupportNamedBind;
+    /// insert from client input, unlike `on_insert` members
+    /// that have a default in the schema can be left out
+    fn on_insert_json(
+        input: Value,
+        stmt: &mut InsertSt<S>,
+    ) -> Result<(), ValidatedAndTyped>
+    where
+        S: Database + SupportNamedBind,
+        Self: DeserializeOwned,
+    {
+        let mut this = serde_json::from_value::<Self>(input)
+            .map_err(ValidatedAndTyped::TypeError)?;
+        this.on_validate_insert()?;
+        this.on_insert(stmt)
+            .map_err(ValidatedAndTyped::ValidationError)
+    }
 
     fn members() -> &'static [&'static str];
     fn members_scoped() -> &'static [&'static str];
     fn table_name() -> &'static str;
+    /// the table name this collection had before, migration
+    /// renames the table instead of dropping it
+    fn renamed_from() -> Option<&'static str> {
+        None
+    }
+    /// `(old, new)` column names, same as `renamed_from`
+    fn members_renamed_from(
+    ) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+    /// rows remember who inserted them in
+    /// `permissions::OWNER_COLUMN`, so roles can be restricted
+    /// to their own rows
+    fn owned() -> bool {
+        false
+    }
+    /// `(member, rules)` declared with `#[cms(min_len = ..)]`
+    /// and friends, listed in the schema
+    fn field_rules() -> &'static [(&'static str, &'static [Rule])]
+    {
+        &[]
+    }
+    /// `(member, variants)`, the variants are `Some` for members
+    /// of a `#[derive(TextEnum)]` type
+    fn field_variants(
+    ) -> &'static [(&'static str, Option<&'static [&'static str]>)]
+    {
+        &[]
+    }
+    /// what a member holds, `Json` for `serde_json::Value` and
+    /// `#[cms(json)]` members
+    fn column_kind(member: &str) -> ColumnKind {
+        let _ = member;
+        ColumnKind::Plain
+    }
 
     fn from_row_noscope(row: &<S as Database>::Row) -> Self
     where
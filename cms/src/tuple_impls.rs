@@ -32,9 +32,9 @@ mod get_one_worker {
             &'a self,
             data: &'a mut Self::Inner,
             pool: Pool<Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send
         {
-            async {}
+            async { Ok(()) }
         }
 
         fn take(self, data: Self::Inner) -> Self::Output {}
@@ -66,7 +66,7 @@ mod get_one_worker {
             &'a self,
             data: &'a mut Self::Inner,
             pool: Pool<Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send
         {
             async { self.0.sub_op(&mut data.0, pool).await }
         }
@@ -105,9 +105,10 @@ mod get_one_worker {
             &'a self,
             data: &'a mut Self::Inner,
             pool: Pool<Sqlite>,
-        ) {
-            self.0.sub_op(&mut data.0, pool.clone()).await;
-            self.1.sub_op(&mut data.1, pool.clone()).await;
+        ) -> Result<(), sqlx::Error> {
+            self.0.sub_op(&mut data.0, pool.clone()).await?;
+            self.1.sub_op(&mut data.1, pool.clone()).await?;
+            Ok(())
         }
 
         fn take(self, data: Self::Inner) -> Self::Output {
@@ -147,10 +148,11 @@ mod get_one_worker {
             &'a self,
             data: &'a mut Self::Inner,
             pool: Pool<Sqlite>,
-        ) {
-            self.0.sub_op(&mut data.0, pool.clone()).await;
-            self.1.sub_op(&mut data.1, pool.clone()).await;
-            self.2.sub_op(&mut data.2, pool.clone()).await;
+        ) -> Result<(), sqlx::Error> {
+            self.0.sub_op(&mut data.0, pool.clone()).await?;
+            self.1.sub_op(&mut data.1, pool.clone()).await?;
+            self.2.sub_op(&mut data.2, pool.clone()).await?;
+            Ok(())
         }
 
         fn take(self, data: Self::Inner) -> Self::Output {
@@ -192,18 +194,18 @@ mod insert_one_worker {
         fn sub_op1<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async {}
+            async { Ok(()) }
         }
         fn sub_op2<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async {}
+            async { Ok(()) }
         }
 
         fn take(self, data: Self::Inner) -> Self::Output {}
@@ -236,18 +238,18 @@ mod insert_one_worker {
         fn sub_op1<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async { self.0.sub_op1(&mut data.0, pool).await }
+            async move { self.0.sub_op1(&mut data.0, conn).await }
         }
         fn sub_op2<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async {}
+            async { Ok(()) }
         }
 
         fn take(self, data: Self::Inner) -> Self::Output {
@@ -285,18 +287,18 @@ mod insert_one_worker {
         fn sub_op1<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async { self.0.sub_op1(&mut data.0, pool).await }
+            async move { self.0.sub_op1(&mut data.0, conn).await }
         }
         fn sub_op2<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async { self.1.sub_op1(&mut data.1, pool).await }
+            async move { self.1.sub_op1(&mut data.1, conn).await }
         }
 
         fn take(self, data: Self::Inner) -> Self::Output {
@@ -337,18 +339,18 @@ mod insert_one_worker {
         fn sub_op1<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async { self.0.sub_op1(&mut data.0, pool).await }
+            async move { self.0.sub_op1(&mut data.0, conn).await }
         }
         fn sub_op2<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async { self.1.sub_op1(&mut data.1, pool).await }
+            async move { self.1.sub_op1(&mut data.1, conn).await }
         }
 
         fn take(self, data: Self::Inner) -> Self::Output {
@@ -398,18 +400,18 @@ mod insert_one_worker {
         fn sub_op1<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async { self.0.sub_op1(&mut data.0, pool).await }
+            async move { self.0.sub_op1(&mut data.0, conn).await }
         }
         fn sub_op2<'this>(
             &'this self,
             data: &'this mut Self::Inner,
-            pool: sqlx::Pool<sqlx::Sqlite>,
-        ) -> impl std::future::Future<Output = ()> + Send + 'this
+            conn: &'this mut sqlx::SqliteConnection,
+        ) -> impl std::future::Future<Output = Result<(), sqlx::Error>> + Send + 'this
         {
-            async { self.1.sub_op1(&mut data.1, pool).await }
+            async move { self.1.sub_op1(&mut data.1, conn).await }
         }
 
         fn take(self, data: Self::Inner) -> Self::Output {
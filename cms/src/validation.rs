@@ -0,0 +1,256 @@
+//! rules declared on the fields of a collection, like
+//! `#[cms(min_len = 1, max_len = 200)]`.
+//!
+//! the derive calls the check functions below from
+//! `on_validate_insert`/`on_validate_update`, they are typed so
+//! a rule on a member of the wrong type fails to compile. the
+//! same rules are listed as `Rule`s in the schema so the admin
+//! ui can check forms before sending them.
+//!
+//! `None` passes every rule, required-ness is up to the type.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+};
+
+use regex::Regex;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum Rule {
+    MinLen { min: usize },
+    MaxLen { max: usize },
+    Range { min: Option<f64>, max: Option<f64> },
+    Regex { pattern: &'static str },
+    Email,
+    Url,
+    OneOf { values: &'static [&'static str] },
+}
+
+pub trait AsText {
+    fn as_text(&self) -> Option<&str>;
+}
+
+impl AsText for String {
+    fn as_text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl AsText for str {
+    fn as_text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl<T: AsText> AsText for Option<T> {
+    fn as_text(&self) -> Option<&str> {
+        self.as_ref().and_then(|e| e.as_text())
+    }
+}
+
+pub trait AsNumber {
+    fn as_number(&self) -> Option<f64>;
+}
+
+macro_rules! as_number {
+    ($($ty:ty)*) => {$(
+        impl AsNumber for $ty {
+            fn as_number(&self) -> Option<f64> {
+                Some(*self as f64)
+            }
+        }
+    )*};
+}
+
+as_number!(i8 i16 i32 i64 u8 u16 u32 u64 f32 f64);
+
+impl<T: AsNumber> AsNumber for Option<T> {
+    fn as_number(&self) -> Option<f64> {
+        self.as_ref().and_then(|e| e.as_number())
+    }
+}
+
+pub fn min_len<V: AsText + ?Sized>(
+    value: &V,
+    min: usize,
+) -> Result<(), String> {
+    match value.as_text() {
+        Some(text) if text.chars().count() < min => {
+            Err(format!("must be at least {min} characters"))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn max_len<V: AsText + ?Sized>(
+    value: &V,
+    max: usize,
+) -> Result<(), String> {
+    match value.as_text() {
+        Some(text) if text.chars().count() > max => {
+            Err(format!("must be at most {max} characters"))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn range<V: AsNumber + ?Sized>(
+    value: &V,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Result<(), String> {
+    let Some(number) = value.as_number() else {
+        return Ok(());
+    };
+    if let Some(min) = min {
+        if number < min {
+            return Err(format!("must be at least {min}"));
+        }
+    }
+    if let Some(max) = max {
+        if number > max {
+            return Err(format!("must be at most {max}"));
+        }
+    }
+    Ok(())
+}
+
+// compiled once per pattern, the derive already checked that
+// the pattern is valid
+static PATTERNS: LazyLock<RwLock<HashMap<&'static str, Regex>>> =
+    LazyLock::new(Default::default);
+
+pub fn regex<V: AsText + ?Sized>(
+    value: &V,
+    pattern: &'static str,
+) -> Result<(), String> {
+    let Some(text) = value.as_text() else {
+        return Ok(());
+    };
+
+    let cached = PATTERNS
+        .read()
+        .unwrap()
+        .get(pattern)
+        .map(|re| re.is_match(text));
+
+    let matched = match cached {
+        Some(matched) => matched,
+        None => {
+            let re =
+                Regex::new(pattern).expect("invalid pattern");
+            let matched = re.is_match(text);
+            PATTERNS.write().unwrap().insert(pattern, re);
+            matched
+        }
+    };
+
+    match matched {
+        true => Ok(()),
+        false => Err(format!("must match {pattern}")),
+    }
+}
+
+pub fn email<V: AsText + ?Sized>(
+    value: &V,
+) -> Result<(), String> {
+    let Some(text) = value.as_text() else {
+        return Ok(());
+    };
+    let valid = match text.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !text.contains(char::is_whitespace)
+        }
+        None => false,
+    };
+    match valid {
+        true => Ok(()),
+        false => Err("must be an email".to_string()),
+    }
+}
+
+pub fn url<V: AsText + ?Sized>(value: &V) -> Result<(), String> {
+    let Some(text) = value.as_text() else {
+        return Ok(());
+    };
+    let host = text
+        .strip_prefix("https://")
+        .or_else(|| text.strip_prefix("http://"))
+        .map(|rest| rest.split(['/', '?', '#']).next().unwrap());
+    match host {
+        Some(host)
+            if !host.is_empty()
+                && !text.contains(char::is_whitespace) =>
+        {
+            Ok(())
+        }
+        _ => Err("must be an http(s) url".to_string()),
+    }
+}
+
+pub fn one_of<V: AsText + ?Sized>(
+    value: &V,
+    values: &[&str],
+) -> Result<(), String> {
+    match value.as_text() {
+        Some(text) if !values.contains(&text) => {
+            Err(format!("must be one of {}", values.join(", ")))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        assert!(min_len("", 1).is_err());
+        assert!(min_len("é", 1).is_ok());
+        assert!(max_len("abc", 2).is_err());
+        assert!(min_len(&None::<String>, 1).is_ok());
+
+        assert!(range(&5_i64, Some(0.), Some(10.)).is_ok());
+        assert!(range(&-1_i32, Some(0.), None).is_err());
+        assert!(range(&11.5_f64, None, Some(10.)).is_err());
+
+        assert!(regex("abc-123", "^[a-z]+-[0-9]+$").is_ok());
+        assert!(regex("abc", "^[a-z]+-[0-9]+$").is_err());
+
+        assert!(email("a@b.co").is_ok());
+        assert!(email("a@b").is_err());
+        assert!(email("a b@c.co").is_err());
+
+        assert!(url("https://example.com/path").is_ok());
+        assert!(url("ftp://example.com").is_err());
+        assert!(url("https://").is_err());
+
+        assert!(one_of("draft", &["draft", "published"]).is_ok());
+        assert!(
+            one_of("other", &["draft", "published"]).is_err()
+        );
+    }
+
+    #[test]
+    fn rules_serialize_for_the_schema() {
+        assert_eq!(
+            serde_json::to_value(Rule::Range {
+                min: Some(0.),
+                max: None
+            })
+            .unwrap(),
+            serde_json::json!({
+                "rule": "range", "min": 0.0, "max": null
+            })
+        );
+    }
+}
@@ -1,35 +1,107 @@
+use std::sync::Arc;
+
 use axum::{
     http::{header, HeaderName, HeaderValue, Method},
     middleware::from_fn,
     response::IntoResponse,
     routing::{self, get},
-    Router,
+    Extension, Router,
 };
 use cms_for_rust::{
     uniform_response_layer::uniform_response_middleware,
-    auth::{auth_router, init_auth},
+    auth::{
+        auth_router, init_auth,
+        keys::{JwtKey, JwtKeys},
+        users::{users_router, PrintMailer},
+    },
     axum_router::collections_router,
-    cms_macros::{relation, standard_collection},
+    backend::Backend,
+    cms_macros::{relation, standard_collection, TextEnum},
     collections_editor::admin_router,
     error::{ClientError, PanicError},
     migration2::run_migration,
     schema_info::schema_router,
+    traits::{FieldErrors, Update, Validate},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::types::{
+    chrono::{DateTime, NaiveDate, Utc},
+    Decimal, Uuid,
+};
+use sqlx::{
+    database::HasArguments, ColumnIndex, Decode, Encode, Executor,
+    IntoArguments, Pool, Postgres, Sqlite, Type,
 };
-use sqlx::{Pool, Sqlite};
 use tower_http::{
     catch_panic::CatchPanicLayer, cors::CorsLayer,
     trace::TraceLayer,
 };
 
+#[derive(TextEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    #[cms(rename = "low")]
+    Low,
+    #[cms(rename = "normal")]
+    Normal,
+    #[cms(rename = "high")]
+    High,
+}
+
+/// kept as JSON, `{"settings.color": "red"}` filters on it
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct TodoSettings {
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
 #[standard_collection]
+#[cms(index(fields = ["done", "title"]), validate)]
 pub struct Todo {
+    #[cms(check = "length(title) > 0", max_len = 200)]
     pub title: String,
+    #[cms(index, default = "false")]
     pub done: bool,
     pub description: Option<String>,
+    #[cms(default = "'normal'")]
+    pub priority: Priority,
+    #[cms(json, default = "'{}'")]
+    pub settings: TodoSettings,
+    /// `{"due": {"$lt": "2024-02-01"}}` filters by date
+    pub due: Option<DateTime<Utc>>,
+}
+
+fn validate_title(title: &mut String, errors: &mut FieldErrors) {
+    *title = title.trim().to_string();
+    if title.is_empty() {
+        errors.add("title", "title can't be empty");
+    }
+}
+
+impl Validate for Todo {
+    type Partial = TodoPartial;
+    fn validate_on_insert(&mut self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::default();
+        validate_title(&mut self.title, &mut errors);
+        errors.into_result()
+    }
+    fn validate_on_update(
+        partial: &mut TodoPartial,
+    ) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::default();
+        if let Update::set(title) = &mut partial.title {
+            validate_title(title, &mut errors);
+        }
+        errors.into_result()
+    }
 }
 
 #[standard_collection]
 pub struct Category {
+    #[cms(unique)]
     pub title: String,
 }
 
@@ -38,15 +110,58 @@ pub struct Tag {
     pub title: String,
 }
 
+/// dates, uuids and decimals are sent as strings
+#[standard_collection]
+pub struct Payment {
+    #[cms(unique)]
+    pub reference: Uuid,
+    pub amount: Decimal,
+    pub paid_on: NaiveDate,
+}
+
+/// profile of the end users
+#[standard_collection]
+#[cms(owned)]
+pub struct Profile {
+    #[cms(min_len = 1, max_len = 50)]
+    pub display_name: String,
+}
+
 relation! { optional_to_many Todo Category }
 relation! { many_to_many Todo Tag }
 
 #[tokio::main]
 async fn main() {
-    let pool = Pool::<Sqlite>::connect("sqlite::memory:")
-        .await
-        .unwrap();
+    tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::DEBUG)
+        .init();
 
+    // the backend is picked by the url, the rest of the app is
+    // the same for every backend
+    let url = std::env::var("DATABASE_URL")
+        .unwrap_or("sqlite::memory:".to_string());
+
+    if url.starts_with("postgres") {
+        let pool = Pool::<Postgres>::connect(&url).await.unwrap();
+        serve(pool).await
+    } else {
+        let pool = Pool::<Sqlite>::connect(&url).await.unwrap();
+        serve(pool).await
+    }
+}
+
+async fn serve<S>(pool: Pool<S>)
+where
+    S: Backend,
+    for<'c> &'c mut S::Connection: Executor<'c, Database = S>,
+    for<'q> <S as HasArguments<'q>>::Arguments:
+        IntoArguments<'q, S>,
+    for<'s> &'s str: ColumnIndex<S::Row>,
+    usize: ColumnIndex<S::Row>,
+    i64: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+    i32: Type<S> + for<'e> Encode<'e, S>,
+    String: Type<S> + for<'d> Decode<'d, S> + for<'e> Encode<'e, S>,
+{
     run_migration(pool.clone()).await.unwrap();
 
     let args: Vec<String> = std::env::args().collect();
@@ -57,11 +172,15 @@ async fn main() {
         }
     }
 
-    init_auth(pool.clone()).await.unwrap();
-
-    tracing_subscriber::FmtSubscriber::builder()
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+    // an Ed25519 key when JWT_KEY_FILE points to one, the
+    // HMAC secret in JWT_SALT otherwise
+    let keys = match std::env::var("JWT_KEY_FILE") {
+        Ok(path) => JwtKeys::new(
+            JwtKey::ed25519_pem_file("main", path).unwrap(),
+        ),
+        Err(_) => JwtKeys::from_env().unwrap(),
+    };
+    init_auth(pool.clone(), &keys).await.unwrap();
 
     let app = Router::new()
         .fallback(routing::any(|| async {
@@ -71,8 +190,10 @@ async fn main() {
         .nest("/collection", collections_router())
         .nest("/admin", admin_router())
         .nest("/auth", auth_router())
+        .nest("/users", users_router::<S, Profile>(PrintMailer))
+        .layer(Extension(Arc::new(keys)))
         .with_state(pool.clone())
-        .nest("/schema", schema_router())
+        .nest("/schema", schema_router::<S>())
         .layer(from_fn(uniform_response_middleware))
         .layer(CatchPanicLayer::custom(|_| {
             // todo
@@ -117,5 +238,13 @@ async fn main() {
         .await
         .unwrap();
 
-    axum::serve(listner, app).await.unwrap();
+    // the client ip is needed to rate limit logins per ip
+    axum::serve(
+        listner,
+        app.into_make_service_with_connect_info::<
+            std::net::SocketAddr,
+        >(),
+    )
+    .await
+    .unwrap();
 }
@@ -0,0 +1,282 @@
+use proc_macro_error::abort;
+use syn::{
+    Attribute, Expr, ExprArray, ExprUnary, Lit, LitInt, LitStr,
+    UnOp,
+};
+
+/// `#[cms(...)]` on a field of a collection
+#[derive(Default)]
+pub struct FieldAttrs {
+    pub renamed_from: Option<String>,
+    pub unique: bool,
+    pub index: bool,
+    /// sql expression, `DEFAULT (..)`
+    pub default: Option<String>,
+    /// sql expression, `CHECK (..)`
+    pub check: Option<String>,
+    /// checked before insert and update, in declaration order
+    pub rules: Vec<RuleAttr>,
+    /// `#[cms(json)]`, stored as JSON through serde
+    pub json: bool,
+}
+
+/// `#[cms(min_len = 1)]`, `#[cms(range(min = 0, max = 100))]`,
+/// `#[cms(regex = "..")]`, `#[cms(email)]`, `#[cms(url)]` and
+/// `#[cms(one_of = ["a", "b"])]`
+pub enum RuleAttr {
+    MinLen(usize),
+    MaxLen(usize),
+    Range(Option<f64>, Option<f64>),
+    Regex(String),
+    Email,
+    Url,
+    OneOf(Vec<String>),
+}
+
+/// `#[cms(...)]` on the collection struct itself
+#[derive(Default)]
+pub struct TableAttrs {
+    pub renamed_from: Option<String>,
+    pub indexes: Vec<IndexAttr>,
+    /// `#[cms(owned)]`, rows remember who inserted them
+    pub owned: bool,
+    /// `#[cms(validate)]`, the collection implements `Validate`
+    pub validate: bool,
+}
+
+/// `#[cms(index(fields = ["a", "b"], unique))]`
+pub struct IndexAttr {
+    pub fields: Vec<String>,
+    pub unique: bool,
+}
+
+fn str_array(array: ExprArray) -> syn::Result<Vec<String>> {
+    array
+        .elems
+        .into_iter()
+        .map(|e| match e {
+            Expr::Lit(syn::ExprLit {
+                lit: Lit::Str(lit),
+                ..
+            }) => Ok(lit.value()),
+            e => Err(syn::Error::new_spanned(
+                e,
+                "expected a string literal",
+            )),
+        })
+        .collect()
+}
+
+fn number(expr: Expr) -> syn::Result<f64> {
+    match expr {
+        Expr::Lit(syn::ExprLit {
+            lit: Lit::Int(lit), ..
+        }) => lit.base10_parse(),
+        Expr::Lit(syn::ExprLit {
+            lit: Lit::Float(lit),
+            ..
+        }) => lit.base10_parse(),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => number(*expr).map(|e| -e),
+        e => {
+            Err(syn::Error::new_spanned(e, "expected a number"))
+        }
+    }
+}
+
+fn cms_attrs(
+    attrs: &[Attribute],
+) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|a| a.path().is_ident("cms"))
+}
+
+pub fn field_attrs(attrs: &[Attribute]) -> FieldAttrs {
+    let mut this = FieldAttrs::default();
+
+    for attr in cms_attrs(attrs) {
+        let res = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("renamed_from") {
+                let lit: LitStr = meta.value()?.parse()?;
+                this.renamed_from = Some(lit.value());
+                return Ok(());
+            }
+            if meta.path.is_ident("unique") {
+                this.unique = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("index") {
+                this.index = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("default") {
+                let lit: LitStr = meta.value()?.parse()?;
+                this.default = Some(lit.value());
+                return Ok(());
+            }
+            if meta.path.is_ident("check") {
+                let lit: LitStr = meta.value()?.parse()?;
+                this.check = Some(lit.value());
+                return Ok(());
+            }
+            if meta.path.is_ident("json") {
+                this.json = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("min_len") {
+                let lit: LitInt = meta.value()?.parse()?;
+                this.rules
+                    .push(RuleAttr::MinLen(lit.base10_parse()?));
+                return Ok(());
+            }
+            if meta.path.is_ident("max_len") {
+                let lit: LitInt = meta.value()?.parse()?;
+                this.rules
+                    .push(RuleAttr::MaxLen(lit.base10_parse()?));
+                return Ok(());
+            }
+            if meta.path.is_ident("range") {
+                let (mut min, mut max) = (None, None);
+                meta.parse_nested_meta(|inner| {
+                    if inner.path.is_ident("min") {
+                        min = Some(number(
+                            inner.value()?.parse()?,
+                        )?);
+                        return Ok(());
+                    }
+                    if inner.path.is_ident("max") {
+                        max = Some(number(
+                            inner.value()?.parse()?,
+                        )?);
+                        return Ok(());
+                    }
+                    Err(inner
+                        .error("unsupported range attribute"))
+                })?;
+                if min.is_none() && max.is_none() {
+                    return Err(
+                        meta.error("range needs min or max")
+                    );
+                }
+                this.rules.push(RuleAttr::Range(min, max));
+                return Ok(());
+            }
+            if meta.path.is_ident("regex") {
+                let lit: LitStr = meta.value()?.parse()?;
+                if let Err(err) = regex::Regex::new(&lit.value())
+                {
+                    return Err(syn::Error::new_spanned(
+                        lit, err,
+                    ));
+                }
+                this.rules.push(RuleAttr::Regex(lit.value()));
+                return Ok(());
+            }
+            if meta.path.is_ident("email") {
+                this.rules.push(RuleAttr::Email);
+                return Ok(());
+            }
+            if meta.path.is_ident("url") {
+                this.rules.push(RuleAttr::Url);
+                return Ok(());
+            }
+            if meta.path.is_ident("one_of") {
+                let array: ExprArray = meta.value()?.parse()?;
+                this.rules
+                    .push(RuleAttr::OneOf(str_array(array)?));
+                return Ok(());
+            }
+
+            Err(meta.error("unsupported field attribute"))
+        });
+
+        if let Err(err) = res {
+            abort!(err.span(), "{}", err);
+        }
+    }
+
+    this
+}
+
+pub fn table_attrs(attrs: &[Attribute]) -> TableAttrs {
+    let mut this = TableAttrs::default();
+
+    for attr in cms_attrs(attrs) {
+        let res = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("renamed_from") {
+                let lit: LitStr = meta.value()?.parse()?;
+                this.renamed_from = Some(lit.value());
+                return Ok(());
+            }
+            if meta.path.is_ident("index") {
+                let mut index = IndexAttr {
+                    fields: vec![],
+                    unique: false,
+                };
+                meta.parse_nested_meta(|inner| {
+                    if inner.path.is_ident("fields") {
+                        let array: ExprArray =
+                            inner.value()?.parse()?;
+                        index.fields = str_array(array)?;
+                        return Ok(());
+                    }
+                    if inner.path.is_ident("unique") {
+                        index.unique = true;
+                        return Ok(());
+                    }
+                    Err(inner
+                        .error("unsupported index attribute"))
+                })?;
+                if index.fields.is_empty() {
+                    return Err(meta.error(
+                        "index needs at least one field",
+                    ));
+                }
+                this.indexes.push(index);
+                return Ok(());
+            }
+            if meta.path.is_ident("owned") {
+                this.owned = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("validate") {
+                this.validate = true;
+                return Ok(());
+            }
+
+            Err(meta.error("unsupported collection attribute"))
+        });
+
+        if let Err(err) = res {
+            abort!(err.span(), "{}", err);
+        }
+    }
+
+    this
+}
+
+/// `#[cms(rename = "..")]` on a variant of a `TextEnum`, the
+/// text stored for it
+pub fn variant_attrs(attrs: &[Attribute]) -> Option<String> {
+    let mut rename = None;
+
+    for attr in cms_attrs(attrs) {
+        let res = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                let lit: LitStr = meta.value()?.parse()?;
+                rename = Some(lit.value());
+                return Ok(());
+            }
+
+            Err(meta.error("unsupported variant attribute"))
+        });
+
+        if let Err(err) = res {
+            abort!(err.span(), "{}", err);
+        }
+    }
+
+    rename
+}
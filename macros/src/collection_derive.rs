@@ -4,6 +4,86 @@ use proc_macro_error::abort;
 use quote::quote;
 use syn::{spanned::Spanned, visit::Visit, DeriveInput};
 
+use crate::collection_attrs::{
+    field_attrs, table_attrs, FieldAttrs, RuleAttr,
+};
+
+/// `Rule` listed in the schema, and the check that runs against
+/// `value`
+fn rule_tokens(rule: &RuleAttr) -> (TokenStream, TokenStream) {
+    let option = |e: &Option<f64>| match e {
+        Some(e) => quote!(Some(#e)),
+        None => quote!(None),
+    };
+    match rule {
+        RuleAttr::MinLen(min) => (
+            quote!(Rule::MinLen { min: #min }),
+            quote!(validation::min_len(value, #min)),
+        ),
+        RuleAttr::MaxLen(max) => (
+            quote!(Rule::MaxLen { max: #max }),
+            quote!(validation::max_len(value, #max)),
+        ),
+        RuleAttr::Range(min, max) => {
+            let (min, max) = (option(min), option(max));
+            (
+                quote!(Rule::Range { min: #min, max: #max }),
+                quote!(validation::range(value, #min, #max)),
+            )
+        }
+        RuleAttr::Regex(pattern) => (
+            quote!(Rule::Regex { pattern: #pattern }),
+            quote!(validation::regex(value, #pattern)),
+        ),
+        RuleAttr::Email => (
+            quote!(Rule::Email),
+            quote!(validation::email(value)),
+        ),
+        RuleAttr::Url => (
+            quote!(Rule::Url),
+            quote!(validation::url(value)),
+        ),
+        RuleAttr::OneOf(values) => (
+            quote!(Rule::OneOf { values: &[#(#values),*] }),
+            quote!(validation::one_of(value, &[#(#values),*])),
+        ),
+    }
+}
+
+enum Storage {
+    AsIs,
+    Json,
+    StoredAs,
+}
+
+/// sqlx can't store `Decimal` in sqlite, it is wrapped in
+/// `DecimalColumn` by its `StoredAs` impl. the type is only known
+/// by its name here
+fn is_decimal(ty: &syn::Type) -> bool {
+    let syn::Type::Path(path) = ty else {
+        return false;
+    };
+    let Some(last) = path.path.segments.last() else {
+        return false;
+    };
+    if last.ident == "Decimal" {
+        return true;
+    }
+    if last.ident != "Option" {
+        return false;
+    }
+    match &last.arguments {
+        syn::PathArguments::AngleBracketed(args) => {
+            matches!(
+                args.args.first(),
+                Some(syn::GenericArgument::Type(inner))
+                    if is_decimal(inner)
+            )
+        }
+        _ => false,
+    }
+}
+
 pub fn main(input: DeriveInput) -> TokenStream {
     let mut ts = quote!();
 
@@ -11,6 +91,7 @@ pub fn main(input: DeriveInput) -> TokenStream {
         ty: &'ast syn::Type,
         name: &'ast Ident,
         name_scoped: String,
+        attrs: FieldAttrs,
     }
 
     struct MainDerive<'ast> {
@@ -32,7 +113,8 @@ pub fn main(input: DeriveInput) -> TokenStream {
                     self.fields.push(Memeber {
                         ty: &field.ty,
                         name: ident,
-                        name_scoped: format!("{}_{}", self.table_lower_case, ident)
+                        name_scoped: format!("{}_{}", self.table_lower_case, ident),
+                        attrs: field_attrs(&field.attrs),
                     })
                 }
                 None => {
@@ -57,7 +139,198 @@ pub fn main(input: DeriveInput) -> TokenStream {
     let m_name = main_derive.fields.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
     let m_name_scoped =
         main_derive.fields.iter().map(|m| m.name_scoped.clone()).collect::<Vec<_>>();
+    // what is stored, `#[cms(json)]` members go through `Json`
+    // and decimals through `StoredAs`
+    let storage = |m: &Memeber| match (m.attrs.json, is_decimal(m.ty)) {
+        (true, _) => Storage::Json,
+        (false, true) => Storage::StoredAs,
+        (false, false) => Storage::AsIs,
+    };
+    let m_col_ty = main_derive.fields.iter().map(|m| {
+        let ty = m.ty;
+        match storage(m) {
+            Storage::Json => quote!(Json<#ty>),
+            Storage::StoredAs => quote!(<#ty as StoredAs>::Column),
+            Storage::AsIs => quote!(#ty),
+        }
+    }).collect::<Vec<_>>();
+    let to_col = |m: &Memeber, val: TokenStream| match storage(m) {
+        Storage::Json => quote!(Json(#val)),
+        Storage::StoredAs => quote!(StoredAs::into_column(#val)),
+        Storage::AsIs => val,
+    };
+    let m_to_col = main_derive.fields.iter().map(|m| {
+        to_col(m, quote!(val))
+    }).collect::<Vec<_>>();
+    let m_insert = main_derive.fields.iter().map(|m| {
+        let name = m.name;
+        to_col(m, quote!(self.#name))
+    }).collect::<Vec<_>>();
+    let from_col = |m: &Memeber, column: TokenStream| match storage(m) {
+        Storage::Json => quote!(row.get::<Json<_>, _>(#column).0),
+        Storage::StoredAs => quote!(StoredAs::from_column(row.get(#column))),
+        Storage::AsIs => quote!(row.get(#column)),
+    };
+    let m_from_row_noscope = main_derive.fields.iter().map(|m| {
+        let name = m.name.to_string();
+        from_col(m, quote!(#name))
+    }).collect::<Vec<_>>();
+    let m_from_row_scoped = main_derive.fields.iter().map(|m| {
+        let name = &m.name_scoped;
+        from_col(m, quote!(#name))
+    }).collect::<Vec<_>>();
+
+    let table_attrs = table_attrs(&input.attrs);
+    let table_renamed_from = match table_attrs.renamed_from {
+        Some(old) => quote!(Some(#old)),
+        None => quote!(None),
+    };
+    let owned = table_attrs.owned;
+
+    let ruled = main_derive.fields.iter().filter(|m| !m.attrs.rules.is_empty());
+    let field_rules = ruled.clone().map(|m| {
+        let name = m.name.to_string();
+        let rules = m.attrs.rules.iter().map(|r| rule_tokens(r).0);
+        quote!((#name, &[#(#rules),*]))
+    });
+    // checks stop at the first failing rule of each member
+    let checks = ruled.clone().map(|m| {
+        let name = m.name.to_string();
+        let checks = m.attrs.rules.iter().map(|r| rule_tokens(r).1);
+        quote!(Ok(())#(.and_then(|()| #checks))*.map_err(|message: String| {
+            errors.add(#name, message)
+        }))
+    }).collect::<Vec<_>>();
+    let ruled_name = ruled.map(|m| m.name).collect::<Vec<_>>();
+
+    // `Validate` runs first as it may normalize the input
+    let (validate_insert, validate_update) = if table_attrs.validate {
+        (
+            quote!(<Self as Validate>::validate_on_insert(self).err()),
+            quote!(<Self as Validate>::validate_on_update(this).err()),
+        )
+    } else {
+        (quote!(None::<FieldErrors>), quote!(None::<FieldErrors>))
+    };
+    let errors = match ruled_name.is_empty() {
+        true => quote!(errors),
+        false => quote!(mut errors),
+    };
+    let on_validate = if table_attrs.validate || !ruled_name.is_empty() {
+        quote!(
+            fn on_validate_insert(&mut self) -> Result<(), FieldErrors> {
+                let #errors = #validate_insert.unwrap_or_default();
+                #(
+                {
+                    let value = &self.#ruled_name;
+                    let _ = #checks;
+                }
+                )*
+                errors.into_result()
+            }
+            fn on_validate_update(
+                this: &mut Self::PartailCollection,
+            ) -> Result<(), FieldErrors> {
+                let #errors = #validate_update.unwrap_or_default();
+                #(
+                if let Update::set(value) = &this.#ruled_name {
+                    let _ = #checks;
+                }
+                )*
+                errors.into_result()
+            }
+        )
+    } else {
+        quote!()
+    };
+    let renamed_members = main_derive.fields.iter().filter_map(|m| {
+        let old = m.attrs.renamed_from.as_ref()?;
+        let new = m.name.to_string();
+        Some(quote!((#old, #new)))
+    });
+    let m_col = main_derive.fields.iter().zip(&m_col_ty).map(|(m, ty)| {
+        let name = m.name.to_string();
+        let mut col = quote!(col_type_of::<#ty>(#name));
+        if let Some(default) = m.attrs.default.as_ref() {
+            col = quote!(all((#col, default_value(#default))));
+        }
+        if let Some(check_) = m.attrs.check.as_ref() {
+            col = quote!(all((#col, check(#check_))));
+        }
+        col
+    }).collect::<Vec<_>>();
+    let insert_ident = Ident::new(&format!("{}Insert", d_ident), proc_macro2::Span::call_site());
+    let on_insert_json = if main_derive.fields.iter().any(|m| m.attrs.default.is_some()) {
+        let input_fields = main_derive.fields.iter().map(|m| {
+            let (name, ty) = (m.name, m.ty);
+            if m.attrs.default.is_some() {
+                quote!(#[serde(default)] pub #name: Option<#ty>,)
+            } else {
+                quote!(pub #name: #ty,)
+            }
+        });
+        // through the partial, so inserts that leave defaulted
+        // members out can be validated
+        let partial_fields = main_derive.fields.iter().map(|m| {
+            let name = m.name;
+            if m.attrs.default.is_some() {
+                quote!(#name: match this.#name {
+                    Some(val) => Update::set(val),
+                    None => Update::keep,
+                },)
+            } else {
+                quote!(#name: Update::set(this.#name),)
+            }
+        });
+        ts.extend(quote!(
+            /// insert input, members with a default can be left out
+            #[derive(::cms_for_rust::macro_prelude::serde::Deserialize)]
+            pub struct #insert_ident {
+                #(#input_fields)*
+            }
+        ));
+        quote!(
+            fn on_insert_json(
+                input: Value,
+                stmt: &mut InsertSt<S>,
+            ) -> Result<(), ValidatedAndTyped>
+            {
+                let this = from_value::<#insert_ident>(input)
+                    .map_err(ValidatedAndTyped::TypeError)?;
+                let mut this = #partial_ident { #(#partial_fields)* };
+                Self::on_validate_update(&mut this)?;
+                #(
+                if let Update::set(val) = this.#m_name {
+                    stmt.insert(stringify!(#m_name).to_owned(), #m_to_col);
+                }
+                )*
+                Ok(())
+            }
+        )
+    } else {
+        quote!()
+    };
+    let unique_members = main_derive.fields.iter().filter(|m| m.attrs.unique).map(|m| m.name.to_string());
 
+    let mut indexes = main_derive.fields.iter().filter(|m| m.attrs.index).map(|m| (vec![m.name.to_string()], false)).collect::<Vec<_>>();
+    for index in table_attrs.indexes {
+        for field in index.fields.iter() {
+            if !m_name.iter().any(|m| m == field) {
+                abort!(d_ident.span(), "index on unknown field `{}`", field);
+            }
+        }
+        indexes.push((index.fields, index.unique));
+    }
+    let index_st = indexes.into_iter().map(|(fields, unique)| {
+        let name = format!("{}_{}_idx", main_derive.table_lower_case, fields.join("_"));
+        let unique = unique.then(|| quote!(index.unique();));
+        quote!({
+            let mut index = CreateIndexSt::init((#name, Self::table_name()));
+            #(index.column(#fields);)*
+            #unique
+            indexes.push(index);
+        })
+    });
 
 
     ts.extend(quote!(
@@ -70,10 +343,16 @@ pub fn main(input: DeriveInput) -> TokenStream {
     ts.extend(quote!( const _: () = {
         use ::cms_for_rust::macro_prelude::derive_collection::*;
 
-        submit! {SubmitDynCollection {
+        submit! {SubmitDynCollection::<Sqlite> {
+            obj: || Box::new(PhantomData::<#d_ident>)
+        }}
+        submit!(SubmitDynMigrate::<Sqlite> {
+            obj: || Box::new(PhantomData::<#d_ident>)
+        });
+        submit! {SubmitDynCollection::<Postgres> {
             obj: || Box::new(PhantomData::<#d_ident>)
         }}
-        submit!(SubmitDynMigrate{
+        submit!(SubmitDynMigrate::<Postgres> {
             obj: || Box::new(PhantomData::<#d_ident>)
         });
 
@@ -82,7 +361,7 @@ pub fn main(input: DeriveInput) -> TokenStream {
         S: Database + SupportNamedBind + SqlxQuery,
         for<'s> &'s str: ColumnIndex<<S as Database>::Row>,
         #(
-            #m_ty: Type<S> + for<'c> Decode<'c, S> + for<'e> Encode<'e, S>,
+            #m_col_ty: Type<S> + for<'c> Decode<'c, S> + for<'e> Encode<'e, S>,
         )*
         {
             type PartailCollection = #partial_ident;
@@ -92,9 +371,15 @@ pub fn main(input: DeriveInput) -> TokenStream {
                 #(
                 stmt.column(
                     stringify!(#m_name),
-                    col_type_check_if_null::<#m_ty>(),
+                    #m_col,
                 );
                 )*
+                #(
+                stmt.constraint(unique(&[#unique_members]));
+                )*
+            }
+            fn on_index(indexes: &mut Vec<CreateIndexSt<S>>) {
+                #(#index_st)*
             }
             fn on_update(
                 stmt: &mut UpdateSt<S>,
@@ -104,7 +389,7 @@ pub fn main(input: DeriveInput) -> TokenStream {
                 #(
                 if let Update::set(val) = this.#m_name {
                     stmt.set(stringify!(#m_name).to_string(), {
-                        val
+                        #m_to_col
                     });
                 };)*
                 Ok(())
@@ -129,25 +414,64 @@ pub fn main(input: DeriveInput) -> TokenStream {
             fn table_name() -> &'static str {
                 stringify!(#d_ident)
             }
+
+            fn renamed_from() -> Option<&'static str> {
+                #table_renamed_from
+            }
+
+            fn members_renamed_from() -> &'static [(&'static str, &'static str)] {
+                &[#(#renamed_members,)*]
+            }
+
+            fn owned() -> bool {
+                #owned
+            }
+
+            fn field_rules() -> &'static [(&'static str, &'static [Rule])] {
+                const RULES: &[(&str, &[Rule])] = &[#(#field_rules,)*];
+                RULES
+            }
+
+            fn field_variants() -> &'static [(&'static str, Option<&'static [&'static str]>)] {
+                const VARIANTS: &[(&str, Option<&[&str]>)] = &[#(
+                    (stringify!(#m_name), <#m_col_ty as IsNull>::VARIANTS),
+                )*];
+                VARIANTS
+            }
+
+            fn column_kind(member: &str) -> ColumnKind {
+                match member {
+                    #(
+                    stringify!(#m_name) => <#m_col_ty as IsNull>::KIND,
+                    )*
+                    _ => ColumnKind::Plain,
+                }
+            }
+
+            #on_validate
         
             fn on_select(stmt: &mut SelectSt<S>)
             {
                 #(
-                   stmt.select(stringify!(#m_name_scoped));
+                   stmt.select_aliased(
+                       stringify!(#d_ident),
+                       stringify!(#m_name),
+                       #m_name_scoped,
+                   );
                 )*
             }
         
             fn from_row_noscope(row: &<S as Database>::Row) -> Self
             {
                 Self { #(
-                    #m_name: row.get(stringify!(#m_name)),
+                    #m_name: #m_from_row_noscope,
                 )*}
             }
         
             fn from_row_scoped(row: &<S as Database>::Row) -> Self
             {
                 Self { #(
-                        #m_name: row.get(#m_name_scoped),
+                        #m_name: #m_from_row_scoped,
                 )*}
             }
         
@@ -158,12 +482,13 @@ pub fn main(input: DeriveInput) -> TokenStream {
             {
                 #(
                     stmt.insert(stringify!(#m_name).to_owned(), {
-                        self.#m_name
+                        #m_insert
                     });
                 )*
                 Ok(())
             }
-            
+
+            #on_insert_json
         }
     };));
 
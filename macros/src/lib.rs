@@ -1,5 +1,6 @@
 use proc_macro::TokenStream;
 use proc_macro_error::proc_macro_error;
+mod collection_attrs;
 mod collection_derive;
 mod entity_derive;
 mod into_mut_argument_derive;
@@ -7,6 +8,7 @@ mod prepared_statement;
 mod relation;
 mod relations_mod;
 mod schema_macro;
+mod text_enum_derive;
 
 // #[proc_macro_derive(IntoMutArguments)]
 // pub fn into_mut_arguments_derive(
@@ -35,7 +37,7 @@ pub fn prepared_statement(input: TokenStream) -> TokenStream {
     prepared_statement::main(input).into()
 }
 
-#[proc_macro_derive(Collection)]
+#[proc_macro_derive(Collection, attributes(cms))]
 #[proc_macro_error]
 pub fn collection(input: TokenStream) -> TokenStream {
     let derive = match syn::parse::<syn::DeriveInput>(input) {
@@ -48,6 +50,20 @@ pub fn collection(input: TokenStream) -> TokenStream {
     collection_derive::main(derive).into()
 }
 
+/// unit enums stored as TEXT, usable as members of a collection
+#[proc_macro_derive(TextEnum, attributes(cms))]
+#[proc_macro_error]
+pub fn text_enum(input: TokenStream) -> TokenStream {
+    let derive = match syn::parse::<syn::DeriveInput>(input) {
+        Ok(data) => data,
+        Err(err) => {
+            return err.to_compile_error().into();
+        }
+    };
+
+    text_enum_derive::main(derive).into()
+}
+
 #[proc_macro_attribute]
 pub fn standard_collection(
     _: TokenStream,
@@ -83,19 +83,37 @@ pub fn many_to_many(rest: TwoIdent) -> TokenStream {
                 }
             }
             submit! {
-                SubmitDynRelation {
+                SubmitDynRelation::<Sqlite> {
                     obj: || {
                         Arc::new(
-                            ManyToManyDynamic::<#to, #from>::new()
+                            ManyToManyDynamic::<#to, #from, Sqlite>::new()
                         )
                     }
                 }
             }
             submit! {
-                SubmitDynRelation {
+                SubmitDynRelation::<Postgres> {
                     obj: || {
                         Arc::new(
-                            ManyToManyDynamic::<#from, #to>::new()
+                            ManyToManyDynamic::<#to, #from, Postgres>::new()
+                        )
+                    }
+                }
+            }
+            submit! {
+                SubmitDynRelation::<Sqlite> {
+                    obj: || {
+                        Arc::new(
+                            ManyToManyDynamic::<#from, #to, Sqlite>::new()
+                        )
+                    }
+                }
+            }
+            submit! {
+                SubmitDynRelation::<Postgres> {
+                    obj: || {
+                        Arc::new(
+                            ManyToManyDynamic::<#from, #to, Postgres>::new()
                         )
                     }
                 }
@@ -127,10 +145,19 @@ pub fn optional_to_many(rest: TwoIdent) -> TokenStream {
                 }
             }
             submit! {
-                SubmitDynRelation {
+                SubmitDynRelation::<Sqlite> {
+                    obj: || {
+                        Arc::new(
+                            OptionalToManyDynamic::<#from, #to, Sqlite>::new()
+                        )
+                    }
+                }
+            }
+            submit! {
+                SubmitDynRelation::<Postgres> {
                     obj: || {
                         Arc::new(
-                            OptionalToManyDynamic::<#from, #to>::new()
+                            OptionalToManyDynamic::<#from, #to, Postgres>::new()
                         )
                     }
                 }
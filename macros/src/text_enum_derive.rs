@@ -0,0 +1,130 @@
+use proc_macro2::TokenStream;
+use proc_macro_error::abort;
+use quote::quote;
+use syn::{spanned::Spanned, Data, DeriveInput, Fields};
+
+use crate::collection_attrs::variant_attrs;
+
+pub fn main(input: DeriveInput) -> TokenStream {
+    let ident = &input.ident;
+
+    if input.generics.lt_token.is_some() {
+        abort!(input.generics.span(), "generics are not supported");
+    }
+
+    let Data::Enum(data) = &input.data else {
+        abort!(ident.span(), "TextEnum can only be derived for enums");
+    };
+
+    let mut variants = vec![];
+    let mut texts = vec![];
+    for variant in data.variants.iter() {
+        if !matches!(variant.fields, Fields::Unit) {
+            abort!(
+                variant.span(),
+                "only unit variants can be stored as text"
+            );
+        }
+        let text = variant_attrs(&variant.attrs)
+            .unwrap_or_else(|| variant.ident.to_string());
+        if texts.contains(&text) {
+            abort!(variant.span(), "`{}` is used twice", text);
+        }
+        variants.push(&variant.ident);
+        texts.push(text);
+    }
+
+    if variants.is_empty() {
+        abort!(ident.span(), "TextEnum needs at least one variant");
+    }
+
+    let enum_name = ident.to_string();
+
+    quote!(const _: () = {
+        use ::cms_for_rust::macro_prelude::text_enum::*;
+
+        impl TextEnum for #ident {
+            const VARIANTS: &'static [&'static str] = &[#(#texts),*];
+            fn as_str(&self) -> &'static str {
+                match self {
+                    #(Self::#variants => #texts,)*
+                }
+            }
+            fn from_text(text: &str) -> Option<Self> {
+                match text {
+                    #(#texts => Some(Self::#variants),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl IsNull for #ident {
+            fn is_null() -> bool {
+                false
+            }
+            const VARIANTS: Option<&'static [&'static str]> =
+                Some(<Self as TextEnum>::VARIANTS);
+        }
+
+        impl<S: Database> Type<S> for #ident
+        where
+            String: Type<S>,
+        {
+            fn type_info() -> S::TypeInfo {
+                <String as Type<S>>::type_info()
+            }
+            fn compatible(ty: &S::TypeInfo) -> bool {
+                <String as Type<S>>::compatible(ty)
+            }
+        }
+
+        impl<'q, S: Database> Encode<'q, S> for #ident
+        where
+            &'q str: Encode<'q, S>,
+        {
+            fn encode_by_ref(
+                &self,
+                buf: &mut <S as HasArguments<'q>>::ArgumentBuffer,
+            ) -> encode::IsNull {
+                <&'q str as Encode<'q, S>>::encode(self.as_str(), buf)
+            }
+        }
+
+        impl<'r, S: Database> Decode<'r, S> for #ident
+        where
+            &'r str: Decode<'r, S>,
+        {
+            fn decode(
+                value: <S as HasValueRef<'r>>::ValueRef,
+            ) -> Result<Self, BoxDynError> {
+                let text = <&'r str as Decode<'r, S>>::decode(value)?;
+                <Self as TextEnum>::from_text(text).ok_or_else(|| {
+                    format!("`{}` is not a {}", text, #enum_name).into()
+                })
+            }
+        }
+
+        impl Serialize for #ident {
+            fn serialize<Se: Serializer>(
+                &self,
+                serializer: Se,
+            ) -> Result<Se::Ok, Se::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for #ident {
+            fn deserialize<D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                let text = String::deserialize(deserializer)?;
+                <Self as TextEnum>::from_text(&text).ok_or_else(|| {
+                    de::Error::unknown_variant(
+                        &text,
+                        <Self as TextEnum>::VARIANTS,
+                    )
+                })
+            }
+        }
+    };)
+}
@@ -0,0 +1,99 @@
+use std::marker::PhantomData;
+
+use crate::{
+    execute_no_cache::ExecuteNoCacheUsingSelectTrait,
+    AcceptColIdent, AcceptTableIdent, IdentSafety, Query,
+    Statement,
+};
+
+pub struct CreateIndexSt<S, Q: Query, I: IdentSafety> {
+    pub(crate) unique: bool,
+    pub(crate) name: String,
+    pub(crate) table: I::Table,
+    pub(crate) columns: Vec<I::Column>,
+    pub(crate) ctx: Q::Context1,
+    pub(crate) _sqlx: PhantomData<S>,
+}
+
+impl<S, Q: Query, I: IdentSafety> ExecuteNoCacheUsingSelectTrait
+    for CreateIndexSt<S, Q, I>
+{
+}
+
+impl<S, Q, I> CreateIndexSt<S, Q, I>
+where
+    Q: Query,
+    I: IdentSafety,
+{
+    /// `(index_name, on_table)`
+    pub fn init<T>(init: (&str, T)) -> Self
+    where
+        I: AcceptTableIdent<T>,
+    {
+        I::check_other(init.0);
+        Self {
+            unique: false,
+            name: init.0.to_string(),
+            table: I::into_table(init.1),
+            columns: Default::default(),
+            ctx: Default::default(),
+            _sqlx: PhantomData,
+        }
+    }
+
+    pub fn unique(&mut self) {
+        self.unique = true;
+    }
+
+    pub fn column<C>(&mut self, column: C)
+    where
+        I: AcceptColIdent<C>,
+    {
+        self.columns.push(I::into_col(column));
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn table_name(&self) -> &str {
+        self.table.as_ref()
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+}
+
+impl<S, Q, I> Statement<S, Q> for CreateIndexSt<S, Q, I>
+where
+    Q: Query,
+    I: IdentSafety,
+{
+    fn deref_ctx(&self) -> &Q::Context1 {
+        &self.ctx
+    }
+    fn deref_mut_ctx(&mut self) -> &mut Q::Context1 {
+        &mut self.ctx
+    }
+    #[track_caller]
+    fn _build(self) -> (String, Q::Output) {
+        if self.columns.is_empty() {
+            panic!("index {} has no columns", self.name);
+        }
+
+        Q::build_query(self.ctx, |_| {
+            format!(
+                "CREATE {}INDEX IF NOT EXISTS {} ON {} ({});",
+                if self.unique { "UNIQUE " } else { "" },
+                self.name,
+                self.table.as_ref(),
+                self.columns
+                    .iter()
+                    .map(|e| e.as_ref())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+}
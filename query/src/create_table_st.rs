@@ -33,12 +33,14 @@ impl Display for CreateTableHeader {
         f: &mut std::fmt::Formatter<'_>,
     ) -> std::fmt::Result {
         match self {
-            CreateTableHeader::Create => write!(f, "CREATE"),
+            CreateTableHeader::Create => {
+                write!(f, "CREATE TABLE")
+            }
             CreateTableHeader::CreateTemp => {
-                write!(f, "CREATE TEMP")
+                write!(f, "CREATE TEMP TABLE")
             }
             CreateTableHeader::CreateTempIfNotExists => {
-                write!(f, "CREATE TEMP IF NOT EXISTS")
+                write!(f, "CREATE TEMP TABLE IF NOT EXISTS")
             }
             CreateTableHeader::IfNotExists => {
                 write!(f, "CREATE TABLE IF NOT EXISTS")
@@ -155,6 +157,27 @@ where
     }
 }
 
+/// read back what was pushed so far, useful for diffing
+/// two schemas without building the statement
+impl<S, Q, I> CreateTableSt<S, Q, I>
+where
+    I: IdentSafety,
+    Q: Query<SqlPart = String>,
+{
+    pub fn table_name(&self) -> &str {
+        self.ident.1.as_ref()
+    }
+    pub fn columns(&self) -> &[(String, String)] {
+        &self.columns
+    }
+    pub fn constraints(&self) -> &[String] {
+        &self.constraints
+    }
+    pub fn verbatims(&self) -> &[String] {
+        &self.verbatim
+    }
+}
+
 // #[cfg(todo)]
 // mod create_table_st {
 //     use sqlx::{Pool, Sqlite};
@@ -6,7 +6,7 @@ use crate::{
     execute_no_cache::ExecuteNoCacheUsingSelectTrait,
     ident_safety::PanicOnUnsafe, returning::ReturningClause,
     AcceptTableIdent, BindItem, IdentSafety, Query,
-    QueryHandlers, Statement, SupportNamedBind,
+    QueryHandlers, Statement,
 };
 
 pub struct DeleteSt<S, Q: Query, I: IdentSafety, R = ()> {
@@ -45,7 +45,7 @@ where
 impl<'q, S, Q, I, R> Statement<S, Q> for DeleteSt<S, Q, I, R>
 where
     R: ReturningClause,
-    S: Database + SupportNamedBind,
+    S: Database,
     Q: Query,
     I: IdentSafety,
 {
@@ -90,7 +90,6 @@ impl<S, Q: Query, I: IdentSafety>
 
 impl< S, Q, R, I> DeleteSt<S, Q, I, R>
 where
-    S: SupportNamedBind,
     S: Database,
     R: ReturningClause,
     I: IdentSafety,
@@ -52,12 +52,12 @@ impl<'s, 'q, S: Database> InnerExecutable<'s, 'q, S> {
     pub fn as_str(&self) -> &str {
         self.stmt
     }
-    pub async fn execute<E>(
+    pub async fn execute<'c, E>(
         self,
         executor: E,
     ) -> Result<S::QueryResult, sqlx::Error>
     where
-        for<'c> E: Executor<'q, Database = S>,
+        E: Executor<'c, Database = S>,
     {
         debug!("execute: {}", self.stmt);
         executor
@@ -80,14 +80,14 @@ impl<'s, 'q, S: Database> InnerExecutable<'s, 'q, S> {
             .await
     }
 
-    pub async fn fetch_one_with<E, O, F>(
+    pub async fn fetch_one_with<'c, E, O, F>(
         self,
         executor: E,
         with: F,
     ) -> Result<O, sqlx::Error>
     where
         F: FnOnce(S::Row) -> Result<O, sqlx::Error>,
-        for<'c> E: Executor<'c, Database = S>,
+        E: Executor<'c, Database = S>,
     {
         debug!("fetch one: {}", self.stmt);
         let execute = InnerExecutable {
@@ -115,14 +115,14 @@ impl<'s, 'q, S: Database> InnerExecutable<'s, 'q, S> {
         }
     }
 
-    pub async fn fetch_all_with<E, O, F>(
+    pub async fn fetch_all_with<'c, E, O, F>(
         self,
         executor: E,
         mut with: F,
     ) -> Result<Vec<O>, sqlx::Error>
     where
         F: FnMut(S::Row) -> Result<O, sqlx::Error>,
-        for<'c> E: Executor<'c, Database = S>,
+        E: Executor<'c, Database = S>,
     {
         debug!("fetch all: {}", self.stmt);
         let execute = InnerExecutable {
@@ -149,14 +149,14 @@ impl<'s, 'q, S: Database> InnerExecutable<'s, 'q, S> {
         })
     }
 
-    pub async fn fetch_optional_with<E, O, F>(
+    pub async fn fetch_optional_with<'c, E, O, F>(
         self,
         executor: E,
         with: F,
     ) -> Result<Option<O>, sqlx::Error>
     where
         F: FnOnce(S::Row) -> Result<O, sqlx::Error>,
-        for<'c> E: Executor<'c, Database = S>,
+        E: Executor<'c, Database = S>,
     {
         debug!("fetch optional: {}", self.stmt);
         let execute = InnerExecutable {
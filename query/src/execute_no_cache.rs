@@ -5,20 +5,20 @@ use crate::{
     executable::InnerExecutable, insert_many_st::InsertMany,
     insert_one_st::InsertStOne, returning::ReturningClause,
     string_query::StringQuery, IntoMutArguments, Query,
-    Statement, SupportNamedBind,
+    BindPlaceholder, Statement,
 };
 
 pub trait ExecuteNoCache<'q, S: Database, Q>: Sized {
     fn build(
         self,
     ) -> (String, <S as HasArguments<'q>>::Arguments);
-    fn execute<E>(
+    fn execute<'c, E>(
         self,
         executor: E,
     ) -> impl Future<Output = Result<S::QueryResult, sqlx::Error>>
            + Send
     where
-        E: for<'e> sqlx::Executor<'e, Database = S>,
+        E: sqlx::Executor<'c, Database = S>,
     {
         let (query, args) = self.build();
         async move {
@@ -31,13 +31,13 @@ pub trait ExecuteNoCache<'q, S: Database, Q>: Sized {
             .await
         }
     }
-    fn fetch_one<E, O, F>(
+    fn fetch_one<'c, E, O, F>(
         self,
         executor: E,
         with: F,
     ) -> impl Future<Output = Result<O, sqlx::Error>> + Send
     where
-        E: for<'e> sqlx::Executor<'e, Database = S>,
+        E: sqlx::Executor<'c, Database = S>,
         F: FnOnce(S::Row) -> Result<O, sqlx::Error> + Send,
     {
         let (query, args) = self.build();
@@ -51,13 +51,13 @@ pub trait ExecuteNoCache<'q, S: Database, Q>: Sized {
             .await
         }
     }
-    fn fetch_optional<E, O, F>(
+    fn fetch_optional<'c, E, O, F>(
         self,
         executor: E,
         with: F,
     ) -> impl Future<Output = Result<Option<O>, sqlx::Error>> + Send
     where
-        E: for<'e> sqlx::Executor<'e, Database = S>,
+        E: sqlx::Executor<'c, Database = S>,
         F: FnOnce(S::Row) -> Result<O, sqlx::Error> + Send,
     {
         let (query, args) = self.build();
@@ -72,13 +72,13 @@ pub trait ExecuteNoCache<'q, S: Database, Q>: Sized {
         }
     }
     #[track_caller]
-    fn fetch_all<E, O, F>(
+    fn fetch_all<'c, E, O, F>(
         self,
         executor: E,
         with: F,
     ) -> impl Future<Output = Result<Vec<O>, sqlx::Error>> + Send
     where
-        E: for<'e> sqlx::Executor<'e, Database = S>,
+        E: sqlx::Executor<'c, Database = S>,
         F: FnMut(S::Row) -> Result<O, sqlx::Error> + Send,
     {
         let (query, args) = self.build();
@@ -203,7 +203,7 @@ impl<'q, S, R> ExecuteNoCache<'q, S, ()>
     for InsertStOne<'q, S, R>
 where
     R: ReturningClause,
-    S: Database + SupportNamedBind,
+    S: Database + BindPlaceholder,
 {
     fn build(
         self,
@@ -216,7 +216,7 @@ impl<'q, S: Database, R> ExecuteNoCache<'q, S, ()>
     for InsertMany<S, <S as HasArguments<'q>>::Arguments, R>
 where
     R: ReturningClause,
-    S: Database,
+    S: Database + BindPlaceholder,
 {
     fn build(
         self,
@@ -111,10 +111,13 @@ where
 
         move |ctx| {
             let ptr = ctx as *mut _;
-            maps.into_iter()
+            // parenthesized, other items are joined with AND
+            let ors = maps
+                .into_iter()
                 .map(|map| map(unsafe { &mut *ptr }))
                 .collect::<Vec<_>>()
-                .join(" OR ")
+                .join(" OR ");
+            format!("({})", ors)
         }
     }
 }
@@ -124,7 +127,9 @@ pub mod schema_items {
 
     use sqlx::{Database, Type};
 
-    use crate::{BindItem, IdentSafety, Query, SchemaColumn};
+    use crate::{
+        BindItem, Constraint, IdentSafety, Query, SchemaColumn,
+    };
 
     pub struct ColumnType<T>(PhantomData<(T)>);
 
@@ -159,6 +164,88 @@ pub mod schema_items {
     pub fn col_type<T>() -> ColumnType<T> {
         ColumnType(PhantomData)
     }
+
+    pub struct Unique(Vec<String>);
+
+    impl Constraint for Unique {}
+
+    impl<S, Q, I> BindItem<S, Q, I> for Unique
+    where
+        Q: Query,
+        I: IdentSafety,
+    {
+        fn bind_item(
+            self,
+            _: &mut Q::Context1,
+        ) -> impl FnOnce(&mut Q::Context2) -> String + 'static
+        {
+            move |_| format!("UNIQUE ({})", self.0.join(", "))
+        }
+    }
+
+    /// like `CreateTableSt::column`, column names are not
+    /// checked, they are expected to come from the schema
+    pub fn unique(columns: &[&str]) -> Unique {
+        Unique(columns.iter().map(|e| e.to_string()).collect())
+    }
+
+    /// `DEFAULT (expr)`, the expression is written as is
+    pub struct DefaultValue(String);
+
+    /// `CHECK (expr)`, the expression is written as is
+    pub struct Check(String);
+
+    impl<S> SchemaColumn<S> for DefaultValue {
+        fn display(&self) -> String {
+            format!("DEFAULT ({})", self.0)
+        }
+    }
+
+    impl<S> SchemaColumn<S> for Check {
+        fn display(&self) -> String {
+            format!("CHECK ({})", self.0)
+        }
+    }
+
+    impl<S, Q, I> BindItem<S, Q, I> for DefaultValue
+    where
+        Q: Query,
+        I: IdentSafety,
+    {
+        fn bind_item(
+            self,
+            _: &mut Q::Context1,
+        ) -> impl FnOnce(&mut Q::Context2) -> String + 'static
+        {
+            move |_| <Self as SchemaColumn<S>>::display(&self)
+        }
+    }
+
+    impl<S, Q, I> BindItem<S, Q, I> for Check
+    where
+        Q: Query,
+        I: IdentSafety,
+    {
+        fn bind_item(
+            self,
+            _: &mut Q::Context1,
+        ) -> impl FnOnce(&mut Q::Context2) -> String + 'static
+        {
+            move |_| <Self as SchemaColumn<S>>::display(&self)
+        }
+    }
+
+    /// the expression is not escaped, it should never come
+    /// from user input
+    pub fn default_value(expr: &str) -> DefaultValue {
+        DefaultValue(expr.to_string())
+    }
+
+    /// the expression is not escaped, it should never come
+    /// from user input
+    pub fn check(expr: &str) -> Check {
+        Check(expr.to_string())
+    }
 }
 
 pub mod schema_items_for_tupe {
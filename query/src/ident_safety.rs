@@ -30,6 +30,39 @@ pub fn define_schema(columns: &[(&str, &[&str])]) {
     }
 }
 
+/// unlike `append_schema`, keeps the columns already defined
+/// on the table
+pub fn append_columns(table: &str, columns: &[&str]) {
+    let mut idents = IDENTS.write().unwrap();
+    idents.0.insert(table.to_string());
+    idents
+        .1
+        .entry(table.to_string())
+        .or_default()
+        .extend(columns.iter().map(|x| x.to_string()));
+}
+
+/// after a rename, the old name is no longer safe to use
+pub fn rename_table(old: &str, new: &str) {
+    let mut idents = IDENTS.write().unwrap();
+    if !idents.0.remove(old) {
+        return;
+    }
+    idents.0.insert(new.to_string());
+    if let Some(columns) = idents.1.remove(old) {
+        idents.1.insert(new.to_string(), columns);
+    }
+}
+
+pub fn rename_column(table: &str, old: &str, new: &str) {
+    let mut idents = IDENTS.write().unwrap();
+    if let Some(columns) = idents.1.get_mut(table) {
+        if columns.remove(old) {
+            columns.insert(new.to_string());
+        }
+    }
+}
+
 impl IdentSafety for PanicOnUnsafe {
     type Table = String;
     type Column = String;
@@ -4,7 +4,7 @@ use sqlx::{database::HasArguments, Database};
 
 use crate::{
     delete_st::DeleteSt, returning::ReturningClause,
-    IntoMutArguments,
+    BindPlaceholder, IntoMutArguments,
 };
 
 pub struct InsertMany<S, B, R = ()> {
@@ -20,14 +20,14 @@ impl<'q, S, R>
     InsertMany<S, <S as HasArguments<'q>>::Arguments, R>
 where
     R: ReturningClause,
-    S: Database,
+    S: Database + BindPlaceholder,
 {
     pub fn _build(
         self,
     ) -> (String, <S as HasArguments<'q>>::Arguments) {
         let column = self.cols.len();
         let str = format!(
-            "INSERT INTO {} ({}) VALUES {} {}",
+            "INSERT INTO {} ({}) VALUES {}{}",
             self.into,
             self.cols.join(", "),
             {
@@ -36,7 +36,7 @@ where
                 // for _ in 0..column {
                 let mut s_inner_inner = Vec::new();
                 for cc in 0..self.argument_count {
-                    s_inner_inner.push(format!("${}", binds));
+                    s_inner_inner.push(S::placeholder(binds));
 
                     binds += 1;
 
@@ -1,12 +1,13 @@
 use std::marker::PhantomData;
 
 use sqlx::{
-    database::HasArguments, Arguments, Database, Encode, Type,
+    database::HasArguments, mysql::MySqlRow, Acquire, Arguments,
+    Database, Encode, MySql, Type,
 };
 
 use crate::{
-    returning::ReturningClause, IntoMutArguments,
-    SupportNamedBind, SupportReturning,
+    returning::{LastInsertId, ReturningClause},
+    BindPlaceholder, IntoMutArguments, SupportReturning,
 };
 
 pub struct InsertStOne<'q, S: Database, R = ()> {
@@ -37,21 +38,21 @@ where
 impl<'q, S, R> InsertStOne<'q, S, R>
 where
     R: ReturningClause,
-    S: Database + SupportNamedBind,
+    S: Database + BindPlaceholder,
 {
     pub fn _build(
         self,
     ) -> (String, <S as HasArguments<'q>>::Arguments) {
         let column = self.input.len();
         let str = format!(
-            "INSERT INTO {} ({}) VALUES ({}){};",
+            "INSERT INTO {} ({}) VALUES ({}){}",
             self.from,
             self.input.join(", "),
             {
                 let mut binds = 1;
                 let mut s_inner = Vec::new();
                 for _ in 0..column {
-                    s_inner.push(format!("${}", binds));
+                    s_inner.push(S::placeholder(binds));
                     binds += 1;
                 }
 
@@ -103,3 +104,78 @@ where
         self.buffer.add(value);
     }
 }
+
+impl<'q> InsertStOne<'q, MySql> {
+    /// emulates `RETURNING` for MySql, `id` should be the
+    /// `AUTO_INCREMENT` column of the table
+    pub fn returning_last_insert_id(
+        self,
+        id: &str,
+        returning: Vec<&str>,
+    ) -> InsertStOne<'q, MySql, LastInsertId> {
+        InsertStOne {
+            input: self.input,
+            output: self.output,
+            from: self.from,
+            buffer: self.buffer,
+            returning: LastInsertId {
+                id: id.to_string(),
+                cols: returning
+                    .into_iter()
+                    .map(|e| e.to_string())
+                    .collect(),
+            },
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<'q> InsertStOne<'q, MySql, LastInsertId> {
+    /// returns the insert statement followed by the select
+    /// that brings the row back
+    pub fn _build(
+        self,
+    ) -> (String, String, <MySql as HasArguments<'q>>::Arguments)
+    {
+        let select_back = self.returning.select_back(&self.from);
+        let (insert, args) = InsertStOne::<'q, MySql> {
+            input: self.input,
+            output: self.output,
+            from: self.from,
+            buffer: self.buffer,
+            returning: (),
+            _pd: PhantomData,
+        }
+        ._build();
+
+        (insert, select_back, args)
+    }
+
+    /// a connection is acquired from `conn` so both
+    /// statements run on the same one, pass a transaction to
+    /// make them atomic
+    pub async fn fetch_one<'c, A, O, F>(
+        self,
+        conn: A,
+        with: F,
+    ) -> Result<O, sqlx::Error>
+    where
+        A: Acquire<'c, Database = MySql>,
+        F: FnOnce(MySqlRow) -> Result<O, sqlx::Error>,
+    {
+        let (insert, select_back, args) = self._build();
+        let mut conn = conn.acquire().await?;
+
+        tracing::debug!("execute: {}", insert);
+        sqlx::query_with(&insert, args)
+            .execute(&mut *conn)
+            .await?;
+
+        tracing::debug!("execute: {}", select_back);
+        let row = sqlx::query(&select_back)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        with(row)
+    }
+}
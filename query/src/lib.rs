@@ -2,6 +2,7 @@
 #[cfg(feature = "flexible_accept_impl")]
 pub mod accept_extra;
 pub mod clonable_query;
+pub mod create_index_st;
 pub mod create_table_st;
 #[cfg(test)]
 pub mod debug_query;
@@ -13,7 +14,6 @@ pub mod ident_safety;
 pub mod impls;
 pub mod insert_many_st;
 pub mod insert_one_st;
-#[cfg(feature = "todo")]
 pub mod positional_query;
 pub mod prepared_statement;
 pub mod quick_query;
@@ -37,6 +37,7 @@ pub mod prelude {
     pub use crate::accept_extra::exports::*;
 
     pub mod stmt {
+        pub use crate::create_index_st::CreateIndexSt;
         pub use crate::create_table_st::CreateTableSt;
         pub use crate::delete_st::DeleteSt;
         pub use crate::insert_many_st::InsertMany;
@@ -76,7 +77,9 @@ use std::{
     marker::PhantomData,
 };
 
-use sqlx::{database::HasArguments, Database, Postgres, Sqlite};
+use sqlx::{
+    database::HasArguments, Database, MySql, Postgres, Sqlite,
+};
 
 #[cfg(not(feature = "support_non_static_args"))]
 pub trait Query: Sized {
@@ -129,6 +132,25 @@ pub trait Statement<S, Q: Query> {
 pub trait SupportNamedBind {}
 pub trait SupportReturning {}
 
+/// statements that don't go through `Query` (`InsertStOne`
+/// and `InsertMany`) render their binds using this, `nth`
+/// starts at 1
+pub trait BindPlaceholder {
+    fn placeholder(nth: usize) -> String;
+}
+
+impl<S: SupportNamedBind> BindPlaceholder for S {
+    fn placeholder(nth: usize) -> String {
+        format!("${}", nth)
+    }
+}
+
+impl BindPlaceholder for MySql {
+    fn placeholder(_: usize) -> String {
+        "?".to_string()
+    }
+}
+
 pub trait IdentSafety: 'static {
     type Table: AsRef<str>;
     type Column: AsRef<str>;
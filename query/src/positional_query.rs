@@ -107,7 +107,7 @@ where
         let ptr = &mut ctx2 as *mut _;
         let str = f(unsafe { &mut *ptr });
         let output = ctx2.arg;
-        return (str, output);
+        (str, output)
     }
 }
 
@@ -169,8 +169,7 @@ where
             let bring_back = ctx2
                 .back
                 .get_mut(len - 1)
-                .map(|e| e.take())
-                .flatten()
+                .and_then(|e| e.take())
                 .expect("should be bound and taken only once");
 
             bring_back.bind(&mut ctx2.arg);
@@ -302,4 +301,80 @@ mod tests {
             vec!["1".to_string(), "2".to_string()]
         );
     }
+
+    #[test]
+    fn mysql_statements_render_positional_binds() {
+        use sqlx::MySql;
+
+        use crate::{
+            delete_st::DeleteSt, insert_many_st::insert_many,
+            insert_one_st::InsertStOne, update_st::UpdateSt,
+        };
+
+        let mut st = SelectSt::<
+            MySql,
+            PositionalQuery<MySql>,
+            NoOpIdentSafety,
+        >::init("Todo");
+        st.select(col("*"));
+        st.limit(10);
+        st.where_(col("id").eq(1));
+
+        assert_eq!(
+            st.build().0,
+            "SELECT * FROM Todo WHERE id = ? LIMIT ?;"
+        );
+
+        let mut st = UpdateSt::<
+            MySql,
+            PositionalQuery<MySql>,
+            NoOpIdentSafety,
+        >::init("Todo");
+        st.set("title", "new".to_string());
+        st.where_(col("id").eq(1));
+
+        assert_eq!(
+            st.build().0,
+            "UPDATE Todo SET title = ? WHERE id = ?;"
+        );
+
+        let mut st = DeleteSt::<
+            MySql,
+            PositionalQuery<MySql>,
+            NoOpIdentSafety,
+        >::init("Todo");
+        st.where_(col("id").eq(1));
+
+        assert_eq!(
+            st.build().0,
+            "DELETE FROM Todo WHERE id = ?;"
+        );
+
+        let mut st =
+            InsertStOne::<MySql>::init("Todo".to_string());
+        st.insert("title".to_string(), "first".to_string());
+        st.insert("done".to_string(), false);
+
+        let (insert, select_back, _) = st
+            .returning_last_insert_id("id", vec!["id", "title"])
+            ._build();
+
+        assert_eq!(
+            insert,
+            "INSERT INTO Todo (title, done) VALUES (?, ?);"
+        );
+        assert_eq!(
+            select_back,
+            "SELECT id, title FROM Todo WHERE id = LAST_INSERT_ID();"
+        );
+
+        let st = insert_many::<MySql>("Todo".to_string())
+            .columns(vec!["title".to_string()])
+            .values(vec![("first",), ("second",)]);
+
+        assert_eq!(
+            st._build().0,
+            "INSERT INTO Todo (title) VALUES (?), (?);"
+        );
+    }
 }
@@ -18,3 +18,22 @@ impl ReturningClause for () {
         format!(";")
     }
 }
+
+/// MySql has no `RETURNING`, the inserted row is selected
+/// back by `LAST_INSERT_ID()`, which is scoped to the
+/// connection, so both statements has to run on the same one
+pub struct LastInsertId {
+    pub(crate) id: String,
+    pub(crate) cols: Vec<String>,
+}
+
+impl LastInsertId {
+    pub fn select_back(&self, table: &str) -> String {
+        format!(
+            "SELECT {} FROM {} WHERE {} = LAST_INSERT_ID();",
+            self.cols.join(", "),
+            table,
+            self.id
+        )
+    }
+}
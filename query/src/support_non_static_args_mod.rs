@@ -124,10 +124,12 @@ mod expression_impls {
 
             move |ctx| {
                 let ptr = ctx as *mut _;
-                maps.into_iter()
+                let ors = maps
+                    .into_iter()
                     .map(|map| map(unsafe { &mut *ptr }))
                     .collect::<Vec<_>>()
-                    .join(" OR ")
+                    .join(" OR ");
+                format!("({})", ors)
             }
         }
     }
@@ -1386,12 +1388,12 @@ pub mod create_table_st {
             f: &mut std::fmt::Formatter<'_>,
         ) -> std::fmt::Result {
             match self {
-                CreateTableHeader::Create => write!(f, "CREATE"),
+                CreateTableHeader::Create => write!(f, "CREATE TABLE"),
                 CreateTableHeader::CreateTemp => {
-                    write!(f, "CREATE TEMP")
+                    write!(f, "CREATE TEMP TABLE")
                 }
                 CreateTableHeader::CreateTempIfNotExists => {
-                    write!(f, "CREATE TEMP IF NOT EXISTS")
+                    write!(f, "CREATE TEMP TABLE IF NOT EXISTS")
                 }
                 CreateTableHeader::IfNotExists => {
                     write!(f, "CREATE TABLE IF NOT EXISTS")
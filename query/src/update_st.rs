@@ -7,7 +7,7 @@ use crate::{
     ident_safety::PanicOnUnsafe, returning::ReturningClause,
     Accept, AcceptColIdent, AcceptTableIdent, BindItem,
     IdentSafety, Query, QueryHandlers, Statement,
-    SupportNamedBind, SupportReturning,
+    SupportReturning,
 };
 
 pub struct UpdateSt<S, Q: Query, I: IdentSafety, R = ()> {
@@ -51,7 +51,7 @@ where
 impl<S, Q, I: IdentSafety, R> Statement<S, Q>
     for UpdateSt<S, Q, I, R>
 where
-    S: Database + SupportNamedBind,
+    S: Database,
     Q: Query,
     R: ReturningClause,
 {
@@ -69,7 +69,6 @@ where
 
 impl<S, R, Q, I> UpdateSt<S, Q, I, R>
 where
-    S: SupportNamedBind,
     S: Database,
     Q: Query,
     I: IdentSafety,
@@ -77,7 +76,7 @@ where
     pub fn build(self) -> (String, Q::Output)
     where
         R: ReturningClause,
-        S: Database + SupportNamedBind,
+        S: Database,
     {
         <Q as Query>::build_query(self.ctx, |ctx| {
             let mut str = String::from("UPDATE ");
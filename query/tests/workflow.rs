@@ -1,426 +1,222 @@
-// #[tokio::test]
-// async fn query_workflow_1() {
-//     tracing_subscriber::fmt()
-//         .with_max_level(Level::DEBUG)
-//         .init();
-//
-//     let conn = sqlx::SqlitePool::connect("sqlite://:memory:")
-//         .await
-//         .unwrap();
-//
-//     let mut users =
-//         stmt::create_table_if_not_exists::<Sqlite>("Users");
-//
-//     users.column("id", primary_key());
-//     users.column::<String>(
-//         "avatar",
-//         default(sanitize("NAN".to_string())).not_null(),
-//     );
-//     users.column::<Option<String>>(
-//         "display_name",
-//         check_if_null(),
-//     );
-//     users.column::<String>("password", check_if_null());
-//
-//     let mut testing_copy = String::new();
-//
-//     users
-//         .execute(DebugSql(&conn, |stmt| {
-//             assert_eq!(
-//                 stmt,
-//                 "
-//                    CREATE TABLE IF NOT EXISTS Users (
-//                        id INTEGER PRIMARY KEY AUTOINCREMENT,
-//                        avatar TEXT DEFAULT `NAN` NOT NULL,
-//                        display_name TEXT,
-//                        password TEXT NOT NULL
-//                    );"
-//                 .un_pretty()
-//                 .to_string()
-//             )
-//         }))
-//         .await
-//         .expect("creating users table failed");
-//
-//     let mut logs = stmt::create_table_if_not_exists("Logs");
-//
-//     logs.column("id", primary_key());
-//     logs.column::<String>("title", ());
-//     logs.column::<i32>("amount", ());
-//
-//     logs.foreign_key(Fk {
-//         not_null: true,
-//         column: "created_by",
-//         refer_table: "Users",
-//         refer_column: "id",
-//     });
-//
-//     logs.foreign_key(Fk {
-//         not_null: false,
-//         column: "category_id",
-//         refer_table: "Categories",
-//         refer_column: "id",
-//     });
-//
-//     logs.execute(DebugSql(&conn, |stmt| {
-//         assert_eq!(
-//             stmt,
-//             "
-//                    CREATE TABLE IF NOT EXISTS Logs (
-//                        id INTEGER PRIMARY KEY AUTOINCREMENT,
-//                        title TEXT NOT NULL,
-//                        amount INTEGER NOT NULL,
-//                        created_by INTEGER NOT NULL,
-//                        category_id INTEGER,
-//                        FOREIGN KEY (created_by)
-//                            REFERENCES Users(id),
-//                        FOREIGN KEY (category_id)
-//                            REFERENCES Categories(id)
-//                            ON DELETE SET NULL
-//                    );"
-//             .un_pretty()
-//             .to_string()
-//         )
-//     }))
-//     .await
-//     .expect("creating logs table failed");
-//     let mut category =
-//         stmt::create_table_if_not_exists("Categories");
-//
-//     category.column("id", primary_key());
-//     category.column::<String>("name", ());
-//
-//     category
-//         .execute(DebugSql(&conn, |stmt| {
-//             assert_eq!(
-//                 stmt,
-//                 "
-//                    CREATE TABLE IF NOT EXISTS Categories (
-//                        id INTEGER PRIMARY KEY AUTOINCREMENT,
-//                        name TEXT NOT NULL
-//                    );"
-//                 .un_pretty()
-//                 .to_string()
-//             )
-//         }))
-//         .await
-//         .expect("creating categories table failed");
-//
-//     #[derive(Query)]
-//     struct Logs {
-//         title: String,
-//         amount: i32,
-//         created_by: i32,
-//         category_id: Option<i32>,
-//     }
-//
-//     #[derive(Query)]
-//     struct Users {
-//         avatar: Option<String>,
-//         display_name: Option<String>,
-//         password: String,
-//     }
-//
-//     #[derive(Query)]
-//     struct Categories {
-//         name: String,
-//     }
-//
-//     #[derive(
-//         Query, FromRow, PartialEq, Eq, PartialOrd, Ord, Debug,
-//     )]
-//     struct ReturningUser {
-//         id: i32,
-//         avatar: String,
-//     }
-//
-//     let user: Vec<ReturningUser> = insert_many_returning(
-//         &conn,
-//         vec![
-//             Users {
-//                 avatar: Some("avatar_01".to_string()),
-//                 display_name: Some("John Doe".to_string()),
-//                 password: "password".to_string(),
-//             },
-//             Users {
-//                 avatar: Some("avatar_02".to_string()),
-//                 display_name: Some("Sarah Miller".to_string()),
-//                 password: "123pass".to_string(),
-//             },
-//         ],
-//     )
-//     .await
-//     .unwrap();
-//
-//     assert_eq!(
-//         user,
-//         vec!(
-//             ReturningUser {
-//                 id: 1,
-//                 avatar: "avatar_01".to_string(),
-//             },
-//             ReturningUser {
-//                 id: 2,
-//                 avatar: "avatar_02".to_string(),
-//             }
-//         )
-//     );
-//
-//     stmt::insert_many(
-//         &conn,
-//         vec![
-//             Categories {
-//                 name: "Food".to_string(),
-//             },
-//             Categories {
-//                 name: "Transport".to_string(),
-//             },
-//         ],
-//     )
-//     .await
-//     .unwrap();
-//
-//     stmt::insert(
-//         &conn,
-//         Logs {
-//             title: "First Log".to_string(),
-//             created_by: 1,
-//             amount: 100,
-//             category_id: Some(1),
-//         },
-//     )
-//     .await
-//     .unwrap();
-//
-//     stmt::insert_many(
-//         &conn,
-//         vec![
-//             Logs {
-//                 title: "Second Log".to_string(),
-//                 created_by: 1,
-//                 amount: 200,
-//                 category_id: None,
-//             },
-//             Logs {
-//                 title: "Third Log".to_string(),
-//                 created_by: 2,
-//                 amount: 300,
-//                 category_id: Some(2),
-//             },
-//         ],
-//     )
-//     .await
-//     .unwrap();
-//
-//     #[derive(
-//         FromRow, Debug, PartialEq, Eq, PartialOrd, Ord, Query,
-//     )]
-//     struct ReturnCategory {
-//         id: i64,
-//         name: String,
-//     }
-//
-//     #[derive(
-//         FromRow, Debug, PartialEq, Eq, PartialOrd, Ord, Query,
-//     )]
-//     struct ReturnUser {
-//         id: i64,
-//         avatar: String,
-//         display_name: Option<String>,
-//         password: String,
-//     }
-//
-//     #[derive(
-//         FromRow, Debug, PartialEq, Eq, PartialOrd, Ord, Query,
-//     )]
-//     struct ReturnLog {
-//         id: i16,
-//         title: String,
-//         amount: i16,
-//         created_by: i16,
-//         category_id: i16,
-//     }
-//
-//     let mut user = stmt::select("Users");
-//
-//     user.select(col("id"));
-//     user.select(col("avatar"));
-//     user.select(col("display_name"));
-//     user.select(col("password"));
-//
-//     let user: Vec<ReturnUser> =
-//         user.fetch_all(&conn).await.unwrap();
-//
-//     assert_eq!(
-//         user,
-//         vec![
-//             ReturnUser {
-//                 id: 1,
-//                 avatar: "avatar_01".to_string(),
-//                 display_name: Some("John Doe".to_string()),
-//                 password: "password".to_string(),
-//             },
-//             ReturnUser {
-//                 id: 2,
-//                 avatar: "avatar_02".to_string(),
-//                 display_name: Some("Sarah Miller".to_string()),
-//                 password: "123pass".to_string(),
-//             }
-//         ]
-//     );
-//
-//     let mut user = stmt::select("Logs");
-//
-//     user.select(col("title"));
-//     user.where_(col("created_by").eq(|| 1));
-//
-//     user.select(ft("Users").col("avatar").alias("user_avatar"));
-//     user.join(Join {
-//         ty: join_type::Left,
-//         on_table: "Users",
-//         on_column: "id",
-//         local_column: "created_by",
-//     });
-//
-//     user.select(
-//         ft("Categories").col("name").alias("category_name"),
-//     );
-//     user.join(Join {
-//         ty: join_type::Left,
-//         on_table: "Categories",
-//         on_column: "id",
-//         local_column: "category_id",
-//     });
-//
-//     user.limit(|| 2);
-//     user.order_by("title", order_by::DESC);
-//
-//     let user: Vec<(String, String, Option<String>)> = user
-//         .fetch_all(DebugSql(&conn, |stmt| {
-//             assert_eq!(
-//                 stmt,
-//                 "
-//      SELECT
-//         title, Users.avatar AS user_avatar, Categories.name AS category_name
-//      FROM Logs
-//      LEFT JOIN Users ON Users.id = Logs.created_by
-//      LEFT JOIN Categories ON Categories.id = Logs.category_id
-//      WHERE created_by = $1
-//      ORDER BY title DESC
-//      LIMIT $2;
-// "
-//                     .un_pretty()
-//                     .to_string()
-//             )
-//         }))
-//         .await
-//         .unwrap();
-//
-//     assert_eq!(
-//         user,
-//         vec![
-//             (
-//                 "Second Log".to_string(),
-//                 "avatar_01".to_string(),
-//                 None,
-//             ),
-//             (
-//                 "First Log".to_string(),
-//                 "avatar_01".to_string(),
-//                 Some("Food".to_string()),
-//             ),
-//         ]
-//     );
-//
-//     let mut delete =
-//         stmt::delete("Categories").returning::<ReturnCategory>();
-//
-//     delete.where_(col("id").eq(|| 2));
-//
-//     let user = delete
-//         .delete(DebugSql(&conn, |stmt| {
-//             assert_eq!(
-//                 stmt,
-//                 "
-//             DELETE FROM
-//                 Categories
-//             WHERE id = $1
-//             RETURNING
-//               id, name;
-// "
-//                 .un_pretty()
-//                 .to_string()
-//             )
-//         }))
-//         .await
-//         .unwrap();
-//
-//     assert_eq!(
-//         user,
-//         ReturnCategory {
-//             id: 2,
-//             name: "Transport".to_string(),
-//         }
-//     );
-//
-//     let mut select_st = stmt::select("Logs");
-//
-//     select_st.select(ft("Categories").col("name"));
-//
-//     select_st.join(Join {
-//         ty: join_type::Left,
-//         on_table: "Categories",
-//         on_column: "id",
-//         local_column: "category_id",
-//     });
-//
-//     let selected: Vec<(Option<String>,)> =
-//         select_st.fetch_all(&conn).await.unwrap();
-//
-//     assert_eq!(
-//         selected,
-//         vec![(Some("Food".to_string()),), (None,), (None,),]
-//     );
-//
-//     let mut update_st = stmt::update("Logs");
-//
-//     update_st.set("title", || "new_title".to_string());
-//     update_st.set("category_id", || 1);
-//     update_st.where_(col("id").eq(|| 2));
-//
-//     update_st
-//         .update(DebugSql(&conn, |stmt| {
-//             assert_eq!(
-//                 stmt,
-//                 "
-//             UPDATE
-//                 Logs
-//             SET
-//                 title = $1,
-//                 category_id = $2
-//             WHERE id = $3;
-// "
-//                 .un_pretty()
-//                 .to_string()
-//             )
-//         }))
-//         .await
-//         .unwrap();
-//
-//     let mut select_st = stmt::select("Logs");
-//
-//     select_st.select(col("title"));
-//     select_st.select(col("category_id"));
-//
-//     let selected: Vec<(String, Option<i32>)> =
-//         select_st.fetch_all(&conn).await.unwrap();
-//
-//     assert_eq!(
-//         selected,
-//         vec![
-//             ("First Log".to_string(), Some(1)),
-//             ("new_title".to_string(), Some(1)),
-//             ("Third Log".to_string(), None),
-//         ]
-//     );
-// }
+use queries_for_sqlx::{
+    create_table_st::CreateTableHeader,
+    ident_safety::{define_schema, PanicOnUnsafe},
+    insert_many_st::insert_many,
+    positional_query::PositionalQuery,
+    prelude::{stmt, *},
+    select_st::join,
+};
+use sqlx::{MySql, Pool, Row, Sqlite};
+
+type SelectSt<S> =
+    stmt::SelectSt<S, PositionalQuery<S>, PanicOnUnsafe>;
+type UpdateSt<S> =
+    stmt::UpdateSt<S, PositionalQuery<S>, PanicOnUnsafe>;
+type DeleteSt<S> =
+    stmt::DeleteSt<S, PositionalQuery<S>, PanicOnUnsafe>;
+type CreateTableSt<S> =
+    stmt::CreateTableSt<S, PositionalQuery<S>, PanicOnUnsafe>;
+
+/// MySql is not available everywhere, set `QUERY_TEST_MYSQL_URL`
+/// to an empty database and run with `--ignored`
+#[tokio::test]
+#[ignore = "needs QUERY_TEST_MYSQL_URL"]
+async fn query_workflow_mysql() {
+    let url = std::env::var("QUERY_TEST_MYSQL_URL")
+        .expect("QUERY_TEST_MYSQL_URL is set");
+
+    let pool = Pool::<MySql>::connect(&url).await.unwrap();
+
+    sqlx::query("DROP TABLE IF EXISTS Logs, Users;")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    define_schema(&[
+        ("Users", &["id", "name"]),
+        ("Logs", &["id", "title", "amount", "created_by"]),
+    ]);
+
+    let mut users = CreateTableSt::<MySql>::init((
+        CreateTableHeader::IfNotExists,
+        "Users",
+    ));
+    users.verbatim("id BIGINT AUTO_INCREMENT PRIMARY KEY");
+    users.verbatim("name TEXT NOT NULL");
+    users.execute(&pool).await.unwrap();
+
+    let mut logs = CreateTableSt::<MySql>::init((
+        CreateTableHeader::IfNotExists,
+        "Logs",
+    ));
+    logs.verbatim("id BIGINT AUTO_INCREMENT PRIMARY KEY");
+    logs.verbatim("title TEXT NOT NULL");
+    logs.verbatim("amount BIGINT NOT NULL");
+    logs.verbatim("created_by BIGINT NOT NULL");
+    logs.execute(&pool).await.unwrap();
+
+    for (name, expected_id) in [("John", 1), ("Sarah", 2)] {
+        let mut st = stmt::InsertStOne::<MySql>::init(
+            "Users".to_string(),
+        );
+        st.insert("name".to_string(), name.to_string());
+
+        let user = st
+            .returning_last_insert_id("id", vec!["id", "name"])
+            .fetch_one(&pool, |r| {
+                Ok((
+                    r.get::<i64, _>("id"),
+                    r.get::<String, _>("name"),
+                ))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(user, (expected_id, name.to_string()));
+    }
+
+    insert_many::<MySql>("Logs".to_string())
+        .columns(vec![
+            "title".to_string(),
+            "amount".to_string(),
+            "created_by".to_string(),
+        ])
+        .values(vec![
+            ("First Log".to_string(), 100_i64, 1_i64),
+            ("Second Log".to_string(), 200_i64, 1_i64),
+            ("Third Log".to_string(), 300_i64, 2_i64),
+        ])
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let mut st = SelectSt::<MySql>::init("Logs");
+    st.select("title");
+    st.select_aliased("Users", "name", "user_name");
+    st.left_join(join {
+        on_table: "Users".to_string(),
+        on_column: "id".to_string(),
+        local_column: "created_by".to_string(),
+    });
+    st.order_by("title", order_by::DESC);
+    // limit is set before where, binds should still follow
+    // the order of the statement
+    st.limit(2_i64);
+    st.where_(col("created_by").eq(1_i64));
+
+    let logs = st
+        .fetch_all(&pool, |r| {
+            Ok((
+                r.get::<String, _>("title"),
+                r.get::<String, _>("user_name"),
+            ))
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        logs,
+        vec![
+            ("Second Log".to_string(), "John".to_string()),
+            ("First Log".to_string(), "John".to_string()),
+        ]
+    );
+
+    let mut st = UpdateSt::<MySql>::init("Logs");
+    st.set("title", "new_title".to_string());
+    st.set("amount", 250_i64);
+    st.where_(col("id").eq(2_i64));
+
+    let res = st.execute(&pool).await.unwrap();
+    assert_eq!(res.rows_affected(), 1);
+
+    let mut st = DeleteSt::<MySql>::init("Logs");
+    st.where_(col("id").eq(3_i64));
+
+    let res = st.execute(&pool).await.unwrap();
+    assert_eq!(res.rows_affected(), 1);
+
+    let mut st = SelectSt::<MySql>::init("Logs");
+    st.select("title");
+    st.select("amount");
+    st.order_by("id", order_by::ASC);
+
+    let logs = st
+        .fetch_all(&pool, |r| {
+            Ok((
+                r.get::<String, _>("title"),
+                r.get::<i64, _>("amount"),
+            ))
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        logs,
+        vec![
+            ("First Log".to_string(), 100),
+            ("new_title".to_string(), 250),
+        ]
+    );
+}
+
+/// Sqlite accepts `?` as well, this runs the positional
+/// statements against a real database when MySql is not around
+#[tokio::test]
+async fn positional_binds_on_sqlite() {
+    let pool = Pool::<Sqlite>::connect("sqlite::memory:")
+        .await
+        .unwrap();
+
+    define_schema(&[("Notes", &["id", "title", "done"])]);
+
+    let mut notes = CreateTableSt::<Sqlite>::init((
+        CreateTableHeader::Create,
+        "Notes",
+    ));
+    notes.verbatim("id INTEGER PRIMARY KEY AUTOINCREMENT");
+    notes.verbatim("title TEXT NOT NULL");
+    notes.verbatim("done BOOLEAN NOT NULL");
+    notes.execute(&pool).await.unwrap();
+
+    for (title, done) in
+        [("first", false), ("second", true), ("third", false)]
+    {
+        let mut st = stmt::InsertStOne::<Sqlite>::init(
+            "Notes".to_string(),
+        );
+        st.insert("title".to_string(), title.to_string());
+        st.insert("done".to_string(), done);
+        st.execute(&pool).await.unwrap();
+    }
+
+    let mut st = UpdateSt::<Sqlite>::init("Notes");
+    st.where_(col("id").eq(1_i64));
+    st.set("done", true);
+
+    let (sql, _) = ExecuteNoCache::build(st);
+    assert_eq!(sql, "UPDATE Notes SET done = ? WHERE id = ?;");
+
+    let mut st = UpdateSt::<Sqlite>::init("Notes");
+    st.where_(col("id").eq(1_i64));
+    st.set("done", true);
+    st.execute(&pool).await.unwrap();
+
+    let mut st = DeleteSt::<Sqlite>::init("Notes");
+    st.where_(col("id").eq(3_i64));
+    st.execute(&pool).await.unwrap();
+
+    let mut st = SelectSt::<Sqlite>::init("Notes");
+    st.select("title");
+    st.offset(0_i64);
+    st.limit(10_i64);
+    st.where_(col("done").eq(true));
+    st.order_by("id", order_by::ASC);
+
+    let notes = st
+        .fetch_all(&pool, |r| Ok(r.get::<String, _>("title")))
+        .await
+        .unwrap();
+
+    assert_eq!(notes, vec!["first", "second"]);
+}